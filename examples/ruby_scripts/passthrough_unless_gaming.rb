@@ -0,0 +1,4 @@
+# take over the key only in the gaming profile, let it through everywhere else
+return :passthrough unless Makita.profile == "gaming"
+
+Makita.press(Makita::KEY_SPACE) if event.key_down?
@@ -6,6 +6,7 @@ class Event
     @timestamp_sec = data['timestamp_sec']
     @timestamp_nsec = data['timestamp_nsec']
     @script = data['script']
+    @device = data['device'] || {}
   end
 
   def key
@@ -40,7 +41,30 @@ class Event
     @script
   end
 
+  def passed_through?
+    @passed_through == true
+  end
+
+  def passed_through!
+    @passed_through = true
+  end
+
+  # Name as reported by the kernel, e.g. "Keychron K2"
+  def device_name
+    @device['name']
+  end
+
+  # Event node, e.g. "/dev/input/event5", which can change across reconnects
+  def device_path
+    @device['path']
+  end
+
+  # "bus:vendor:product" plus the serial number or the physical port, stable across reconnects
+  def device_id
+    @device['id']
+  end
+
   def to_s
-    "Event(type=#{@event_type}, code=#{@code}, value=#{@value}, time=#{@timestamp_sec}.#{@timestamp_nsec}, script=#{@script})"
+    "Event(type=#{@event_type}, code=#{@code}, value=#{@value}, time=#{@timestamp_sec}.#{@timestamp_nsec}, script=#{@script}, device=#{device_name})"
   end
 end
@@ -8,10 +8,23 @@ class Runtime
     @scripts = {}
   end
 
+  def use_library(path)
+    return unless Dir.exist?(path)
+    $LOAD_PATH.unshift(path) unless $LOAD_PATH.include?(path)
+    Dir[File.join(path, "*.rb")].sort.each do |file|
+      require file
+      makita_log("info", "Library loaded: #{File.basename(file)}")
+    rescue ScriptError, StandardError => e
+      makita_log("error", "Failed to load library #{file}: #{e.message}")
+      makita_log("error", "    from #{e.backtrace&.first}")
+    end
+  end
+
   def load_script(name, path)
     begin
       content = File.read(path)
-      @scripts[name] = content
+      # Wrapped in a lambda so that `return` ends the handler and its value tells what to do with the event.
+      @scripts[name] = eval("lambda do |event|\n#{content}\nend", TOPLEVEL_BINDING, path, 0)
       makita_log("info", "Script loaded: #{name}")
     rescue => e
       makita_log("error", "Failed to load script #{name}: #{e.message}")
@@ -41,10 +54,14 @@ class Runtime
           if script = @scripts[script_name]
             event = Event.new(event_data)
             Fiber.schedule do
-              eval(script)
+              started = Process.clock_gettime(Process::CLOCK_MONOTONIC)
+              # Returning :passthrough emits the original event, as if the binding didn't exist.
+              Makita.passthrough(event) if script.call(event) == :passthrough
             rescue => e
               makita_log("error", "Event processing error in #{script_name}: #{e.message}")
               makita_log("error", "    from #{e.backtrace.first}")
+            ensure
+              makita_record_script(script_name, Process.clock_gettime(Process::CLOCK_MONOTONIC) - started) if started
             end
           else
             makita_log("error", "Script not loaded: #{script_name}")
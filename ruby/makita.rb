@@ -10,6 +10,7 @@ module Makita
   # EVENT_TYPE_KEY = defined back in Rust
   # EVENT_TYPE_RELATIVE = defined back in Rust
   # EVENT_TYPE_ABSOLUTE = defined back in Rust
+  # EVENT_TYPE_MISC = defined back in Rust
   # EVENT_TYPE_SWITCH = defined back in Rust
   # EVENT_TYPE_LED = defined back in Rust
   # EVENT_TYPE_SOUND = defined back in Rust
@@ -33,10 +34,109 @@ module Makita
       send_synthetic_event(EVENT_TYPE_KEY, key_code, KEY_VALUE_UP)
     end
 
+    # Absolute events go to the "Makita Virtual Absolute" device: sticks (ABS_X to ABS_RZ) range
+    # from -32768 to 32767, ABS_HAT0X/Y from -1 to 1, ABS_PRESSURE from 0 to 4095 and ABS_TILT_X/Y from -90 to 90.
+    def absolute(axis_code, value)
+      send_synthetic_event(EVENT_TYPE_ABSOLUTE, axis_code, value)
+    end
+
+    # Emits on the given virtual device (:keyboard, :pointer, :absolute or :tablet, which is the absolute device, or
+    # :gamepad) instead of the one that matches the event type, e.g. emit(EVENT_TYPE_RELATIVE, REL_X, 10, to: :pointer)
+    def emit(event_type, code, value, to: nil)
+      if to
+        error = makita_send_synthetic_event_to(event_type, code, value, to.to_s)
+        raise ArgumentError, error if error
+      else
+        send_synthetic_event(event_type, code, value)
+      end
+    end
+
+    # { "keyboard" => "Makita Virtual Keyboard/Mouse", ... }
+    def virtual_devices
+      makita_virtual_devices
+    end
+
+    # Has the device's reader emit the event its [rubies] binding swallowed, only once per event.
+    def passthrough(event)
+      return if event.passed_through?
+      event.passed_through!
+      makita_passthrough(event.device_path.to_s, event.event_type, event.code, event.value)
+    end
+
+    # Webhooks from a key press, e.g. http_post("http://hass.local:8123/api/webhook/lights", json: { on: true }).
+    # Handlers run in their own fiber and the scheduler makes the socket calls non-blocking, so other events
+    # keep flowing while the request is in flight. Returns the Net::HTTPResponse, or nil after logging an error.
+    def http_get(url, headers: {}, timeout: 5)
+      http_request(:get, url, nil, headers, timeout)
+    end
+
+    def http_post(url, body = nil, json: nil, headers: {}, timeout: 5)
+      if json
+        body = JSON.generate(json)
+        headers = { "Content-Type" => "application/json" }.merge(headers)
+      end
+      http_request(:post, url, body, headers, timeout)
+    end
+
     def pass(event)
       send_synthetic_event(event.event_type, event.code, event.value)
     end
 
+    def profile
+      makita_get_profile
+    end
+
+    def profile=(name)
+      makita_set_profile(name.to_s)
+    end
+
+    def setting(name)
+      makita_get_setting(name.to_s)
+    end
+
+    def set_setting(name, value)
+      error = makita_set_setting(name.to_s, value.to_s)
+      raise ArgumentError, error if error
+    end
+
+    # Do-not-disturb: actions and scripts are bypassed, keys still remap, for an hour or the given minutes.
+    # inhibit(0) ends it, from a script that's still running, e.g. sleeping in a loop.
+    def inhibit(minutes = nil)
+      makita_inhibit(minutes&.to_f)
+    end
+
+    def inhibited?
+      makita_inhibited
+    end
+
+    # The lock state Makita tracks, for :num_lock, :caps_lock and :scroll_lock, whatever keyboard is connected.
+    def lock?(name)
+      on = makita_lock(name.to_s)
+      raise ArgumentError, "unknown lock #{name}" if on.nil?
+      on
+    end
+
+    # Taps the lock key on the virtual keyboard if the lock isn't already on or off.
+    def set_lock(name, on)
+      error = makita_set_lock(name.to_s, on ? true : false)
+      raise ArgumentError, error if error
+    end
+
+    def toggle_lock(name)
+      error = makita_set_lock(name.to_s, nil)
+      raise ArgumentError, error if error
+    end
+
+    # A desktop notification, shown like those of LAYER_NOTIFY and DEVICE_NOTIFY.
+    def notify(message)
+      makita_notify(message.to_s)
+    end
+
+    # Invocations, last-fired time (unix milliseconds) and average latency of every binding and script.
+    def stats
+      JSON.parse(makita_get_stats)
+    end
+
     def type_text(string, delay_seconds: 0)
       string.each_char do |char|
         case char_to_keycode(char)
@@ -54,6 +154,20 @@ module Makita
 
     private
 
+    def http_request(method, url, body, headers, timeout)
+      require "net/http"
+      uri = URI(url)
+      request = method == :post ? Net::HTTP::Post.new(uri) : Net::HTTP::Get.new(uri)
+      headers.each { |name, value| request[name.to_s] = value.to_s }
+      request.body = body if body
+      Net::HTTP.start(uri.host, uri.port, use_ssl: uri.scheme == "https", open_timeout: timeout, read_timeout: timeout) do |http|
+        http.request(request)
+      end
+    rescue StandardError => e
+      makita_log("error", "HTTP #{method.to_s.upcase} #{url} failed: #{e.message}")
+      nil
+    end
+
     def send_synthetic_event(event_type, code, value)
       makita_send_synthetic_event(event_type, code, value)
     end
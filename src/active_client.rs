@@ -1,16 +1,22 @@
+use crate::session;
 use crate::udev_monitor::{Client, Environment, Server};
 use crate::Config;
 use serde_json;
+use std::fs;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
 use std::process::{Command, Stdio};
-use swayipc_async::Connection;
+use std::time::Duration;
+use swayipc_async::{Connection, Node};
 use x11rb::protocol::xproto::{get_input_focus, get_property, Atom, AtomEnum};
+use x11rb::rust_connection::{DefaultStream, RustConnection};
 
 pub async fn get_active_window(environment: &Environment, config: &Vec<Config>) -> Client {
   match &environment.server {
     Server::Connected(server) => {
       match server.as_str() {
         "Hyprland" => {
-          let query = Command::new("hyprctl").args(["activewindow", "-j"]).output().unwrap();
+          let query = Command::new("hyprctl").args(["activewindow", "-j"]).envs(session::session_environment(environment)).output().unwrap();
           if let Ok(reply) = serde_json::from_str::<serde_json::Value>(std::str::from_utf8(query.stdout.as_slice()).unwrap()) {
             match_window(config, Client::Class(reply["class"].to_string().replace("\"", "")))
           } else {
@@ -19,8 +25,14 @@ pub async fn get_active_window(environment: &Environment, config: &Vec<Config>)
         }
 
         "sway" => {
-          let mut connection = Connection::new().await.unwrap();
-          let active_window = match connection.get_tree().await.unwrap().find_focused(|window| window.focused) {
+          let tree = match session::session_variable(environment, "SWAYSOCK") {
+            Some(socket) => sway_tree(&socket),
+            None => match Connection::new().await {
+              Ok(mut connection) => connection.get_tree().await.ok(),
+              Err(_) => None,
+            },
+          };
+          let active_window = match tree.and_then(|tree| tree.find_focused(|window| window.focused)) {
             Some(window) => match window.app_id {
               Some(id) => Client::Class(id),
               None => window.window_properties.and_then(|window_properties| window_properties.class).map_or(Client::Default, Client::Class),
@@ -32,7 +44,7 @@ pub async fn get_active_window(environment: &Environment, config: &Vec<Config>)
         }
 
         "niri" => {
-          let query = Command::new("niri").args(["msg", "-j", "focused-window"]).output().unwrap();
+          let query = Command::new("niri").args(["msg", "-j", "focused-window"]).envs(session::session_environment(environment)).output().unwrap();
           if let Ok(reply) = serde_json::from_str::<serde_json::Value>(std::str::from_utf8(query.stdout.as_slice()).unwrap()) {
             match_window(config, Client::Class(reply["app_id"].to_string().replace("\"", "")))
           } else {
@@ -41,20 +53,13 @@ pub async fn get_active_window(environment: &Environment, config: &Vec<Config>)
         }
 
         "KDE" => {
-          let (user, running_as_root) =
-            if let Ok(sudo_user) = environment.sudo_user.clone() {
-              (Some(sudo_user), true)
-            } else if let Ok(user) = environment.user.clone() {
-              (Some(user), false)
-            } else {
-              (None, false)
-            };
+          let running_as_root = nix::unistd::geteuid().is_root();
+          let user = if running_as_root { environment.sudo_user.clone().ok() } else { environment.user.clone().ok() };
 
           let active_window = {
-            if let Some(user) = user {
+            if let Some(user) = user.or_else(|| session::active_user().map(|user| user.name)) {
               let output = if running_as_root {
-                let command = "kdotool getactivewindow getwindowclassname";
-                Command::new("runuser").arg(user).arg("-c").arg(command).output().unwrap()
+                session::desktop_command(environment, "kdotool getactivewindow getwindowclassname").output().unwrap()
               } else {
                 let command = format!("systemd-run --user --scope -M {}@ kdotool getactivewindow getwindowclassname", user);
                 Command::new("sh").arg("-c").arg(command).stderr(Stdio::null()).output().unwrap()
@@ -69,7 +74,13 @@ pub async fn get_active_window(environment: &Environment, config: &Vec<Config>)
         }
 
         "x11" => {
-          let connection = x11rb::connect(None).unwrap().0;
+          let variables = session::session_environment(environment);
+          let variable = |name: &str| variables.iter().find(|(variable, _)| variable == name).map(|(_, value)| value.as_str());
+          let connection = match variable("DISPLAY") {
+            Some(display) => connect_x11(display, variable("XAUTHORITY")),
+            None => RustConnection::connect(None).ok().map(|(connection, _)| connection),
+          };
+          let Some(connection) = connection else { return Client::Default };
           let focused_window = get_input_focus(&connection).unwrap().reply().unwrap().focus;
           let (wm_class, string): (Atom, Atom) = (AtomEnum::WM_CLASS.into(), AtomEnum::STRING.into());
           let class = get_property(&connection, false, focused_window, wm_class, string, 0, u32::MAX)
@@ -95,6 +106,50 @@ pub async fn get_active_window(environment: &Environment, config: &Vec<Config>)
   }
 }
 
+// The tree of the sway instance listening on socket, asked for with GET_TREE: the "i3-ipc" magic, then the length
+// of the payload and the type of the message as native endian u32s, replied to in the same framing.
+fn sway_tree(socket: &str) -> Option<Node> {
+  const GET_TREE: u32 = 4;
+  let mut stream = UnixStream::connect(socket).ok()?;
+  stream.set_read_timeout(Some(Duration::from_secs(1))).ok()?;
+  let mut message = b"i3-ipc".to_vec();
+  message.extend(0u32.to_ne_bytes());
+  message.extend(GET_TREE.to_ne_bytes());
+  stream.write_all(&message).ok()?;
+  let mut header = [0; 14];
+  stream.read_exact(&mut header).ok()?;
+  let mut payload = vec![0; u32::from_ne_bytes(header[6..10].try_into().ok()?) as usize];
+  stream.read_exact(&mut payload).ok()?;
+  serde_json::from_slice(&payload).ok()
+}
+
+// The X server of the active session, with the cookie of its Xauthority file, as x11rb only reads the one of
+// Makita's own environment.
+fn connect_x11(display: &str, xauthority: Option<&str>) -> Option<RustConnection> {
+  let number = display.split(':').nth(1)?.split('.').next()?;
+  let (stream, _) = DefaultStream::from_unix_stream(UnixStream::connect(format!("/tmp/.X11-unix/X{}", number)).ok()?).ok()?;
+  let cookie = xauthority.and_then(|path| fs::read(path).ok()).and_then(|entries| xauthority_cookie(&entries, number));
+  let (name, data) = cookie.unwrap_or_default();
+  RustConnection::connect_to_stream_with_auth_info(stream, 0, name, data).ok()
+}
+
+// The MIT-MAGIC-COOKIE-1 of a display in an Xauthority file, whose entries are a big endian u16 family followed by
+// the address, display number, auth name and auth data, each a big endian u16 length and its bytes.
+fn xauthority_cookie(mut entries: &[u8], number: &str) -> Option<(Vec<u8>, Vec<u8>)> {
+  let field = |entries: &mut &[u8]| -> Option<Vec<u8>> {
+    let length = u16::from_be_bytes(entries.get(..2)?.try_into().ok()?) as usize;
+    let value = entries.get(2..2 + length)?.to_vec();
+    *entries = &entries[2 + length..];
+    Some(value)
+  };
+  while entries.len() > 2 {
+    entries = &entries[2..];
+    let (_address, display, name, data) = (field(&mut entries)?, field(&mut entries)?, field(&mut entries)?, field(&mut entries)?);
+    if display == number.as_bytes() && name == b"MIT-MAGIC-COOKIE-1" { return Some((name, data)); }
+  }
+  None
+}
+
 fn match_window(config: &Vec<Config>, active_window: Client) -> Client {
   if let Some(_) = config.iter().find(|&x| x.associations.client == active_window) {
     active_window
@@ -102,3 +157,24 @@ fn match_window(config: &Vec<Config>, active_window: Client) -> Client {
     Client::Default
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn finds_the_cookie_of_a_display() {
+    let entry = |display: &str, data: &[u8]| {
+      let mut entry = 256u16.to_be_bytes().to_vec();
+      for field in [b"host".as_slice(), display.as_bytes(), b"MIT-MAGIC-COOKIE-1", data] {
+        entry.extend((field.len() as u16).to_be_bytes());
+        entry.extend(field);
+      }
+      entry
+    };
+    let entries = [entry("0", &[1, 2]), entry("1", &[3, 4])].concat();
+    assert_eq!(xauthority_cookie(&entries, "1"), Some((b"MIT-MAGIC-COOKIE-1".to_vec(), vec![3, 4])));
+    assert_eq!(xauthority_cookie(&entries, "2"), None);
+    assert_eq!(xauthority_cookie(&entries[..entries.len() - 1], "1"), None);
+  }
+}
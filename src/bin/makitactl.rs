@@ -0,0 +1,316 @@
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::{env, path::PathBuf, process};
+
+const USAGE: &str = "Usage: makitactl <command> [arguments]
+
+Commands:
+  profile                         Show the active profile
+  profile <name>                  Switch every device to the given profile (\"default\" to clear it)
+  state                           Show every key held on physical and virtual devices, the active layers and the
+                                  last key events, to find out why a key is stuck
+  state modifiers                 Show the modifiers currently held, by name
+  state toggles                   Show the keys latched by toggle() bindings, per device
+  state synthetic                 Show the key codes held down by Ruby scripts and hold() actions
+  bindings [device]               List the bindings of the config each device is using
+  stats [device]                  Show how often each binding and Ruby script fired, and how long it took
+  layouts                         Show the layouts of each device and the active one
+  devices                         Show whether each configured device is connected, awaited or absent
+  get <setting> [device]          Show a setting, as changed at runtime or configured for the device
+  set <setting> <value> [device]  Change a setting on every device, or only on the given one
+  layout <layout> [device]        Switch to a layout by number or LAYOUT_NAMES name, on every device that has it or
+                                  only on the given one
+  disable-device <device>         Stop mapping a device and ungrab it, e.g. to run evtest or a firmware updater
+  enable-device <device>          Map a device stopped by disable-device again
+  disable                         Stop mapping every connected device, until enable
+  enable                          Map every device stopped by disable or disable-device again
+  reload                          Read the config files again and show what changed
+  inhibit [minutes]               Bypass actions and Ruby scripts, keys still remap, for an hour or the given minutes
+  inhibit off                     Run actions and Ruby scripts again
+  locks                           Show whether Num Lock, Caps Lock and Scroll Lock are on
+  lock <lock> [on|off|toggle]     Set num_lock, caps_lock or scroll_lock through the virtual keyboard, toggling it
+                                  by default
+  emit <key>...                   Press and release keys or chords like KEY_LEFTCTRL-KEY_C, one after the other
+  emit '<events>'                 Emit a JSON array of events, e.g. '[{\"event_type\": 2, \"code\": 8, \"value\": -1}]'
+  release-all                     Release every key and modifier Makita holds down, e.g. when one got stuck";
+
+fn socket_path() -> PathBuf {
+  if let Ok(path) = env::var("MAKITA_SOCKET") {
+    return PathBuf::from(path);
+  }
+  match env::var("XDG_RUNTIME_DIR") {
+    Ok(runtime_directory) if PathBuf::from(&runtime_directory).join("makita.sock").exists() => {
+      PathBuf::from(runtime_directory).join("makita.sock")
+    }
+    _ => PathBuf::from("/run/makita.sock"),
+  }
+}
+
+fn build_request(args: &[String]) -> Option<Value> {
+  let args: Vec<&str> = args.iter().map(String::as_str).collect();
+  match args.as_slice() {
+    ["profile"] => Some(json!({ "command": "get_profile" })),
+    ["profile", profile] => Some(json!({ "command": "set_profile", "profile": profile })),
+    ["state"] => Some(json!({ "command": "state", "query": "snapshot" })),
+    ["state", query @ ("modifiers" | "toggles")] => Some(json!({ "command": "state", "query": query })),
+    ["state", "synthetic"] => Some(json!({ "command": "state", "query": "synthetic_keys" })),
+    ["bindings", ..] => Some(json!({ "command": "state", "query": "bindings" })),
+    ["stats", ..] => Some(json!({ "command": "state", "query": "stats" })),
+    ["layouts"] => Some(json!({ "command": "state", "query": "layouts" })),
+    ["devices"] => Some(json!({ "command": "state", "query": "devices" })),
+    // Device names can contain spaces, so every remaining argument is part of it.
+    ["get", setting, device @ ..] => {
+      let device = (!device.is_empty()).then(|| device.join(" "));
+      Some(json!({ "command": "get_setting", "setting": setting, "device": device }))
+    }
+    ["set", setting, value, device @ ..] => {
+      let device = (!device.is_empty()).then(|| device.join(" "));
+      Some(json!({ "command": "set_setting", "setting": setting, "value": value, "device": device }))
+    }
+    // enable and disable are kept as shorter names.
+    [command @ ("enable-device" | "disable-device" | "enable" | "disable"), device @ ..] if !device.is_empty() => {
+      let enabled = command.starts_with("enable");
+      Some(json!({ "command": "set_enabled", "device": device.join(" "), "enabled": enabled }))
+    }
+    [command @ ("enable" | "disable")] => Some(json!({ "command": "set_enabled", "enabled": *command == "enable" })),
+    ["layout", layout, device @ ..] => {
+      let device = (!device.is_empty()).then(|| device.join(" "));
+      Some(json!({ "command": "set_layout", "layout": layout, "device": device }))
+    }
+    ["reload"] => Some(json!({ "command": "reload" })),
+    ["inhibit"] => Some(json!({ "command": "inhibit" })),
+    ["inhibit", "off"] => Some(json!({ "command": "inhibit", "minutes": 0 })),
+    ["inhibit", minutes] => minutes.parse::<f64>().ok().map(|minutes| json!({ "command": "inhibit", "minutes": minutes })),
+    ["locks"] => Some(json!({ "command": "state", "query": "locks" })),
+    ["lock", lock] | ["lock", lock, "toggle"] => Some(json!({ "command": "set_lock", "lock": lock })),
+    ["lock", lock, on @ ("on" | "off")] => Some(json!({ "command": "set_lock", "lock": lock, "on": *on == "on" })),
+    // A JSON array is a batch of raw events, anything else keys to tap.
+    ["emit", batch] if batch.starts_with('[') => {
+      serde_json::from_str::<Value>(batch).ok().map(|events| json!({ "command": "emit", "events": events }))
+    }
+    ["emit", keys @ ..] if !keys.is_empty() => Some(json!({ "command": "emit", "keys": keys })),
+    ["release-all"] => Some(json!({ "command": "release_all" })),
+    _ => None,
+  }
+}
+
+fn print_bindings(bindings: &Value, device: Option<&str>) {
+  let Some(bindings) = bindings.as_object() else { return };
+  let mut devices: Vec<&String> = bindings.keys().filter(|name| device.is_none_or(|device| device == name.as_str())).collect();
+  devices.sort();
+  if devices.is_empty() {
+    println!("No bindings found{}.", device.map(|device| format!(" for {}", device)).unwrap_or_default());
+  }
+
+  for name in devices {
+    let rows: Vec<[String; 4]> = bindings[name].as_array().into_iter().flatten().map(|binding| {
+      let modifiers: Vec<&str> = binding["modifiers"].as_array().into_iter().flatten().filter_map(Value::as_str).collect();
+      [
+        binding["input"].as_str().unwrap_or_default().to_string(),
+        modifiers.join(" + "),
+        binding["output"].as_str().unwrap_or_default().to_string(),
+        binding["description"].as_str().unwrap_or_default().to_string(),
+      ]
+    }).collect();
+    let header = ["INPUT", "MODIFIERS", "OUTPUT", "DESCRIPTION"].map(String::from);
+    let widths: Vec<usize> = (0..3).map(|column| {
+      rows.iter().chain([&header]).map(|row| row[column].len()).max().unwrap_or_default()
+    }).collect();
+
+    println!("{}", name);
+    for row in [&header].into_iter().chain(&rows) {
+      let line = format!("  {:<w0$}  {:<w1$}  {:<w2$}  {}", row[0], row[1], row[2], row[3], w0 = widths[0], w1 = widths[1], w2 = widths[2]);
+      println!("{}", line.trim_end());
+    }
+  }
+}
+
+fn print_table(header: &[&str], rows: &[Vec<String>]) {
+  let widths: Vec<usize> = (0..header.len()).map(|column| {
+    rows.iter().map(|row| row[column].len()).chain([header[column].len()]).max().unwrap_or_default()
+  }).collect();
+  let header: Vec<String> = header.iter().map(|title| title.to_string()).collect();
+  for row in [&header].into_iter().chain(rows) {
+    let cells: Vec<String> = row.iter().zip(&widths).map(|(cell, width)| format!("{:<width$}", cell, width = width)).collect();
+    println!("  {}", cells.join("  ").trim_end());
+  }
+}
+
+fn format_stats(stats: &Value) -> [String; 3] {
+  let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+  let last_fired = match stats["last_fired"].as_u64() {
+    Some(last_fired) => format!("{}s ago", now.saturating_sub(last_fired) / 1000),
+    None => "never".to_string(),
+  };
+  let latency = match stats["average_latency_us"].as_u64() {
+    Some(latency) if latency >= 1000 => format!("{:.1}ms", latency as f64 / 1000.0),
+    Some(latency) => format!("{}µs", latency),
+    None => "-".to_string(),
+  };
+  [stats["count"].as_u64().unwrap_or_default().to_string(), last_fired, latency]
+}
+
+// Bindings that never fired show up as "never", and slow scripts by their average latency.
+fn print_stats(state: &Value, device: Option<&str>) {
+  let bindings = state["bindings"].as_object().cloned().unwrap_or_default();
+  let mut devices: Vec<&String> = bindings.keys().filter(|name| device.is_none_or(|device| device == name.as_str())).collect();
+  devices.sort();
+  if devices.is_empty() {
+    println!("No bindings found{}.", device.map(|device| format!(" for {}", device)).unwrap_or_default());
+  }
+
+  for name in devices {
+    let rows: Vec<Vec<String>> = bindings[name].as_array().into_iter().flatten().map(|binding| {
+      let modifiers: Vec<&str> = binding["modifiers"].as_array().into_iter().flatten().filter_map(Value::as_str).collect();
+      let mut row = vec![
+        binding["table"].as_str().unwrap_or_default().to_string(),
+        binding["input"].as_str().unwrap_or_default().to_string(),
+        modifiers.join(" + "),
+      ];
+      row.extend(format_stats(binding));
+      row
+    }).collect();
+    println!("{}", name);
+    print_table(&["TABLE", "INPUT", "MODIFIERS", "COUNT", "LAST FIRED", "AVG LATENCY"], &rows);
+  }
+
+  let scripts: Vec<Vec<String>> = state["scripts"].as_array().into_iter().flatten().map(|script| {
+    let mut row = vec![script["script"].as_str().unwrap_or_default().to_string()];
+    row.extend(format_stats(script));
+    row
+  }).collect();
+  if device.is_none() && !scripts.is_empty() {
+    println!("Ruby scripts");
+    print_table(&["SCRIPT", "COUNT", "LAST FIRED", "AVG LATENCY"], &scripts);
+  }
+}
+
+fn print_layouts(layouts: &Value) {
+  let Some(layouts) = layouts.as_object() else { return };
+  let mut devices: Vec<&String> = layouts.keys().collect();
+  devices.sort();
+  for name in devices {
+    let active = layouts[name]["active"].as_u64();
+    let rows: Vec<Vec<String>> = layouts[name]["layouts"].as_array().into_iter().flatten().map(|layout| vec![
+      if layout["number"].as_u64() == active { "*".to_string() } else { String::new() },
+      layout["number"].as_u64().unwrap_or_default().to_string(),
+      layout["name"].as_str().unwrap_or_default().to_string(),
+    ]).collect();
+    println!("{}", name);
+    print_table(&["", "LAYOUT", "NAME"], &rows);
+  }
+}
+
+fn print_devices(state: &Value) {
+  let Some(devices) = state["devices"].as_object() else { return };
+  let disabled: Vec<&str> = state["disabled"].as_array().into_iter().flatten().filter_map(Value::as_str).collect();
+  let mut names: Vec<&String> = devices.keys().collect();
+  names.sort();
+  let rows: Vec<Vec<String>> = names.into_iter().map(|name| vec![
+    name.clone(),
+    devices[name].as_str().unwrap_or_default().to_string(),
+    if disabled.contains(&name.as_str()) { "paused".to_string() } else { String::new() },
+    state["problems"][name].as_str().unwrap_or_default().to_string(),
+  ]).collect();
+  print_table(&["DEVICE", "STATUS", "", "PROBLEM"], &rows);
+}
+
+fn print_snapshot(state: &Value) {
+  let list = |value: &Value| {
+    let names: Vec<&str> = value.as_array().into_iter().flatten().filter_map(Value::as_str).collect();
+    if names.is_empty() { "-".to_string() } else { names.join(", ") }
+  };
+  println!("Profile: {}", state["profile"].as_str().unwrap_or("default"));
+  println!("Modifiers: {}", list(&state["modifiers"]));
+  println!("Held on the virtual devices: {}", list(&state["virtual_keys"]));
+  println!("Held by scripts and hold(): {}", list(&state["synthetic_keys"]));
+  let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+  match state["inhibited_until"].as_u64() {
+    Some(until) => println!("Do-not-disturb: on, {} more minute(s)", until.saturating_sub(now).div_ceil(60_000)),
+    None => println!("Do-not-disturb: off"),
+  }
+
+  let mut devices: Vec<&String> = state["layers"].as_object().into_iter().flat_map(|layers| layers.keys())
+    .chain(state["physical_keys"].as_object().into_iter().flat_map(|keys| keys.keys()))
+    .collect();
+  devices.sort();
+  devices.dedup();
+  let rows: Vec<Vec<String>> = devices.into_iter().map(|device| vec![
+    device.clone(),
+    state["layers"][device].as_str().unwrap_or("-").to_string(),
+    list(&state["physical_keys"][device]),
+    list(&state["toggles"][device]),
+  ]).collect();
+  println!("Devices");
+  print_table(&["DEVICE", "LAYER", "HELD", "TOGGLED"], &rows);
+
+  let rows: Vec<Vec<String>> = state["recent_events"].as_array().into_iter().flatten().rev().map(|event| vec![
+    format!("{:.1}s ago", now.saturating_sub(event["time"].as_u64().unwrap_or_default()) as f64 / 1000.0),
+    event["device"].as_str().unwrap_or_default().to_string(),
+    event["key"].as_str().unwrap_or_default().to_string(),
+    if event["value"] == 0 { "released".to_string() } else { "pressed".to_string() },
+  ]).collect();
+  println!("Last key events");
+  print_table(&["WHEN", "DEVICE", "KEY", ""], &rows);
+}
+
+fn main() {
+  let args: Vec<String> = env::args().skip(1).collect();
+  let Some(request) = build_request(&args) else {
+    eprintln!("{}", USAGE);
+    process::exit(1);
+  };
+
+  let path = socket_path();
+  let mut stream = match UnixStream::connect(&path) {
+    Ok(stream) => stream,
+    Err(e) => {
+      eprintln!("Unable to connect to {}: {}. Is Makita running?", path.display(), e);
+      process::exit(1);
+    }
+  };
+
+  if let Err(e) = writeln!(stream, "{}", request) {
+    eprintln!("Unable to send request: {}", e);
+    process::exit(1);
+  }
+
+  let mut reply = String::new();
+  if let Err(e) = BufReader::new(&stream).read_line(&mut reply) {
+    eprintln!("Unable to read reply: {}", e);
+    process::exit(1);
+  }
+
+  match serde_json::from_str::<Value>(&reply) {
+    Ok(reply) if reply["status"] == "error" => {
+      eprintln!("Error: {}", reply["message"].as_str().unwrap_or_default());
+      process::exit(1);
+    }
+    Ok(reply) if args[0] == "bindings" => {
+      let device = (args.len() > 1).then(|| args[1..].join(" "));
+      print_bindings(&reply["state"]["bindings"], device.as_deref());
+    }
+    Ok(reply) if args[0] == "stats" => {
+      let device = (args.len() > 1).then(|| args[1..].join(" "));
+      print_stats(&reply["state"], device.as_deref());
+    }
+    Ok(reply) if args[0] == "layouts" => print_layouts(&reply["state"]["layouts"]),
+    Ok(reply) if args[0] == "devices" => print_devices(&reply["state"]),
+    Ok(reply) if args[0] == "locks" => {
+      for lock in ["num_lock", "caps_lock", "scroll_lock"] {
+        println!("{}: {}", lock, if reply["state"][lock].as_bool().unwrap_or_default() { "on" } else { "off" });
+      }
+    }
+    Ok(reply) if args == ["state"] => print_snapshot(&reply["state"]),
+    Ok(reply) if args[0] == "reload" => {
+      let changes: Vec<&str> = reply["changes"].as_array().into_iter().flatten().filter_map(Value::as_str).collect();
+      match changes.is_empty() {
+        true => println!("No changes."),
+        false => changes.iter().for_each(|change| println!("{}", change)),
+      }
+    }
+    Ok(reply) => println!("{}", serde_json::to_string_pretty(&reply).unwrap_or_default()),
+    Err(_) => print!("{}", reply),
+  }
+}
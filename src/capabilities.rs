@@ -0,0 +1,100 @@
+use crate::config::{Axis, Config, Event};
+use evdev::{AbsoluteAxisType, AttributeSet, Device, Key, RelativeAxisType};
+use std::collections::{BTreeSet, HashMap};
+
+// The events a device reports, which decide the bindings of its configs that can fire.
+pub struct Capabilities {
+  pub keys: AttributeSet<Key>,
+  pub relative_axes: AttributeSet<RelativeAxisType>,
+  pub absolute_axes: AttributeSet<AbsoluteAxisType>,
+}
+
+impl Capabilities {
+  pub fn of(device: &Device) -> Self {
+    let mut capabilities = Self { keys: AttributeSet::new(), relative_axes: AttributeSet::new(), absolute_axes: AttributeSet::new() };
+    for key in device.supported_keys().iter().flat_map(|keys| keys.iter()) { capabilities.keys.insert(key); }
+    for axis in device.supported_relative_axes().iter().flat_map(|axes| axes.iter()) { capabilities.relative_axes.insert(axis); }
+    for axis in device.supported_absolute_axes().iter().flat_map(|axes| axes.iter()) { capabilities.absolute_axes.insert(axis); }
+    capabilities
+  }
+
+  // Axis bindings fire from any of the axes the reader converts them from, see EventReader::start_loop.
+  fn produces_axis(&self, axis: Axis, stadia: bool) -> bool {
+    if let Axis::ABS_MINUS(code) | Axis::ABS_PLUS(code) = axis {
+      return self.absolute_axes.contains(AbsoluteAxisType(code));
+    }
+    let (relative, absolute): (&[RelativeAxisType], &[AbsoluteAxisType]) = match axis {
+      Axis::BTN_DPAD_UP | Axis::BTN_DPAD_DOWN => (&[], &[AbsoluteAxisType::ABS_HAT0Y]),
+      Axis::BTN_DPAD_LEFT | Axis::BTN_DPAD_RIGHT => (&[], &[AbsoluteAxisType::ABS_HAT0X]),
+      Axis::HAT1_UP | Axis::HAT1_DOWN => (&[], &[AbsoluteAxisType::ABS_HAT1Y]),
+      Axis::HAT1_LEFT | Axis::HAT1_RIGHT => (&[], &[AbsoluteAxisType::ABS_HAT1X]),
+      Axis::HAT2_UP | Axis::HAT2_DOWN => (&[], &[AbsoluteAxisType::ABS_HAT2Y]),
+      Axis::HAT2_LEFT | Axis::HAT2_RIGHT => (&[], &[AbsoluteAxisType::ABS_HAT2X]),
+      Axis::HAT3_UP | Axis::HAT3_DOWN => (&[], &[AbsoluteAxisType::ABS_HAT3Y]),
+      Axis::HAT3_LEFT | Axis::HAT3_RIGHT => (&[], &[AbsoluteAxisType::ABS_HAT3X]),
+      Axis::LSTICK_UP | Axis::LSTICK_DOWN => (&[], &[AbsoluteAxisType::ABS_Y]),
+      Axis::LSTICK_LEFT | Axis::LSTICK_RIGHT => (&[], &[AbsoluteAxisType::ABS_X]),
+      Axis::RSTICK_UP | Axis::RSTICK_DOWN if stadia => (&[], &[AbsoluteAxisType::ABS_RZ]),
+      Axis::RSTICK_LEFT | Axis::RSTICK_RIGHT if stadia => (&[], &[AbsoluteAxisType::ABS_Z]),
+      Axis::RSTICK_UP | Axis::RSTICK_DOWN => (&[], &[AbsoluteAxisType::ABS_RY]),
+      Axis::RSTICK_LEFT | Axis::RSTICK_RIGHT => (&[], &[AbsoluteAxisType::ABS_RX]),
+      Axis::BTN_TL2 if stadia => (&[], &[AbsoluteAxisType::ABS_BRAKE]),
+      Axis::BTN_TR2 if stadia => (&[], &[AbsoluteAxisType::ABS_GAS]),
+      Axis::BTN_TL2 => (&[], &[AbsoluteAxisType::ABS_Z]),
+      Axis::BTN_TR2 => (&[], &[AbsoluteAxisType::ABS_RZ]),
+      Axis::SCROLL_WHEEL_UP | Axis::SCROLL_WHEEL_DOWN => (&[RelativeAxisType::REL_WHEEL, RelativeAxisType::REL_WHEEL_HI_RES], &[]),
+      Axis::ABS_WHEEL_CW | Axis::ABS_WHEEL_CCW => (&[], &[AbsoluteAxisType::ABS_WHEEL]),
+      Axis::TILT_UP | Axis::TILT_DOWN => (&[], &[AbsoluteAxisType::ABS_TILT_Y]),
+      Axis::TILT_LEFT | Axis::TILT_RIGHT => (&[], &[AbsoluteAxisType::ABS_TILT_X]),
+      Axis::EDGE_LEFT | Axis::EDGE_RIGHT | Axis::EDGE_TOP | Axis::EDGE_BOTTOM | Axis::CORNER_TOP_LEFT | Axis::CORNER_TOP_RIGHT
+        | Axis::CORNER_BOTTOM_LEFT | Axis::CORNER_BOTTOM_RIGHT => (&[RelativeAxisType::REL_X, RelativeAxisType::REL_Y], &[]),
+      _ => (&[], &[AbsoluteAxisType::ABS_MT_POSITION_X]),
+    };
+    relative.iter().any(|axis| self.relative_axes.contains(*axis)) || absolute.iter().any(|axis| self.absolute_axes.contains(*axis))
+  }
+}
+
+// The inputs a config binds that the device never reports, so their bindings can't fire, e.g. a KEY_VOLUMEUP
+// binding in the config of a mouse. Only inputs are checked, modifiers can be held on any device.
+pub fn unproducible_events(config: &Config, default_settings: &HashMap<String, String>, capabilities: &Capabilities) -> Vec<Event> {
+  let setting = |name: &str| config.settings.get(name).or(default_settings.get(name)).is_some_and(|value| value == "true");
+  let (stadia, macro_pad) = (setting("STADIA"), setting("MACRO_PAD"));
+
+  let bindings = &config.bindings;
+  let mut events: BTreeSet<Event> = BTreeSet::new();
+  events.extend(bindings.remap.keys());
+  events.extend(bindings.overrides.keys());
+  events.extend(bindings.movements.keys());
+  events.extend(bindings.actions.keys());
+  events.extend(bindings.rubies.keys());
+  events.extend(bindings.tap_hold.keys().map(|key| Event::Key(*key)));
+  events.into_iter().filter(|event| match event {
+    // With MACRO_PAD, bindings are on the keys of the macro pad bank rather than those of the device.
+    Event::Key(_) if macro_pad => false,
+    Event::Key(key) => !capabilities.keys.contains(*key),
+    Event::Axis(axis) => !capabilities.produces_axis(*axis, stadia),
+    Event::Hold => false,
+  }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn finds_events_the_device_cannot_produce() {
+    let mut capabilities = Capabilities { keys: AttributeSet::new(), relative_axes: AttributeSet::new(), absolute_axes: AttributeSet::new() };
+    capabilities.keys.insert(Key::BTN_LEFT);
+    capabilities.keys.insert(Key::BTN_SIDE);
+    capabilities.relative_axes.insert(RelativeAxisType::REL_WHEEL);
+    let config = Config::parse(
+      "[remap]\nBTN_SIDE = [\"KEY_B\"]\nctrl-KEY_VOLUMEUP = [\"KEY_C\"]\nSCROLL_WHEEL_UP = [\"KEY_D\"]\n[movements]\nRSTICK_UP = \"CURSOR_UP\"\n",
+      "Test Device".to_string(),
+    ).unwrap();
+    assert_eq!(unproducible_events(&config, &HashMap::new(), &capabilities), vec![Event::Axis(Axis::RSTICK_UP), Event::Key(Key::KEY_VOLUMEUP)]);
+
+    capabilities.absolute_axes.insert(AbsoluteAxisType::ABS_RZ);
+    let stadia = HashMap::from([("STADIA".to_string(), "true".to_string())]);
+    assert_eq!(unproducible_events(&config, &stadia, &capabilities), vec![Event::Key(Key::KEY_VOLUMEUP)]);
+  }
+}
@@ -0,0 +1,176 @@
+// `makita check` validates the config directory without touching any device: every file has to parse, and every
+// key, axis, modifier and setting has to be one Makita knows. `makita lint` warns about bindings that load but don't
+// do what they seem to, this lists what keeps a config from loading at all.
+use crate::config::{Config, ConfigError, RawConfig};
+use crate::reload;
+use crate::schema::check_setting;
+use std::{fs, io, path::Path, str::FromStr};
+
+// Tables whose entries are checked one by one.
+const BINDING_TABLES: [&str; 8] = ["remap", "overrides", "movements", "actions", "rubies", "tap_hold", "axes", "chords"];
+
+// Every error of a config file, as file:line: message when the line is known.
+pub fn check_file(file_name: &str, content: &str) -> Vec<String> {
+  // Only broken TOML stops the check, its errors come with their line.
+  let table: toml::Table = match toml::from_str(content) {
+    Ok(table) => table,
+    Err(e) => return vec![format!("{}: unable to parse: {}", file_name, e.to_string().trim_end())],
+  };
+  let mut errors: Vec<(Option<usize>, String)> = Vec::new();
+
+  let mut context = toml::Table::new();
+  for name in ["vars", "settings"] {
+    if let Some(entries) = table.get(name) {
+      context.insert(name.to_string(), entries.clone());
+    }
+  }
+  // Variables are substituted first.
+  let raw_config = toml::to_string(&context).map_err(|e| e.to_string())
+    .and_then(|context| RawConfig::from_str(&context).map_err(|e| e.to_string()));
+  if let Err(e) = &raw_config {
+    errors.push((None, format!("unable to parse: {}", e)));
+  }
+  if let Ok(raw_config) = raw_config {
+    let mut settings: Vec<(&String, &String)> = raw_config.settings.iter().collect();
+    settings.sort();
+    for (setting, value) in settings {
+      if let Err(e) = check_setting(setting, value) {
+        errors.push((line_of(content, "settings", setting), e));
+      }
+    }
+  }
+
+  let default_settings = Path::new(file_name).file_name().is_some_and(|name| name == reload::DEFAULTS_FILE);
+  if !default_settings {
+    // The parser stops at the first error, so every binding is parsed on its own, along with the variables and the
+    // settings when those are valid.
+    if parse_table(&context).is_err() {
+      context.remove("settings");
+    }
+    let mut names: Vec<&String> = table.keys().filter(|name| *name != "vars" && *name != "settings").collect();
+    names.sort();
+    for name in names {
+      let entries: Vec<(Option<&String>, toml::Value)> = match (&table[name], BINDING_TABLES.contains(&name.as_str())) {
+        (toml::Value::Table(entries), true) => entries.iter()
+          .map(|(entry, value)| (Some(entry), toml::Value::Table(toml::Table::from_iter([(entry.clone(), value.clone())]))))
+          .collect(),
+        (value, _) => vec![(None, value.clone())],
+      };
+      for (entry, value) in entries {
+        let mut snippet = context.clone();
+        snippet.insert(name.clone(), value);
+        if let Err(e) = parse_table(&snippet) {
+          let line = entry.and_then(|entry| line_of(content, name, entry)).or_else(|| error_line(content, &e));
+          errors.push((line, e.to_string().replace('\n', " ")));
+        }
+      }
+    }
+
+    // What only shows with the whole config, e.g. a setting that only fails along with the bindings that use it.
+    if let Err(e) = Config::parse(content, file_name.to_string()) {
+      let line = error_line(content, &e);
+      let error = e.to_string().replace('\n', " ");
+      if !errors.iter().any(|(_, previous)| *previous == error) {
+        errors.push((line, error));
+      }
+    }
+  }
+
+  errors.sort();
+  errors.into_iter()
+    .map(|(line, error)| match line {
+      Some(line) => format!("{}:{}: {}", file_name, line, error),
+      None => format!("{}: {}", file_name, error),
+    })
+    .collect()
+}
+
+fn parse_table(table: &toml::Table) -> Result<Config, ConfigError> {
+  let content = toml::to_string(table).map_err(|e| ConfigError::Toml(e.to_string()))?;
+  Config::parse(&content, String::new())
+}
+
+fn error_line(content: &str, error: &ConfigError) -> Option<usize> {
+  match error {
+    ConfigError::Toml(_) => None,
+    ConfigError::UnknownEvent { binding, .. } | ConfigError::InvalidCondition { binding, .. } => line_of(content, "", binding),
+    ConfigError::InvalidMovement { binding, .. } => line_of(content, "movements", binding),
+    ConfigError::InvalidAction { binding, .. } => line_of(content, "actions", binding),
+    ConfigError::InvalidTapHold { binding, .. } => line_of(content, "tap_hold", binding),
+    ConfigError::InvalidSetting { setting, .. } => line_of(content, "settings", setting),
+    ConfigError::InvalidAxis { axis, .. } => line_of(content, "axes", axis),
+    ConfigError::InvalidChord { chord, .. } => line_of(content, "chords", chord),
+  }
+}
+
+// The line of an entry of the table, or of any table when it's empty. Entries can be tables of their own, e.g.
+// [tap_hold.KEY_CAPSLOCK].
+fn line_of(content: &str, table: &str, key: &str) -> Option<usize> {
+  let mut current = "";
+  for (index, line) in content.lines().enumerate() {
+    let line = line.trim();
+    if let Some(header) = line.strip_prefix('[').and_then(|header| header.split(']').next()) {
+      current = header.trim();
+      let entry = current.split_once('.').map(|(header_table, entry)| (header_table, entry.trim_matches('"')));
+      if entry.is_some_and(|(header_table, entry)| (table.is_empty() || header_table == table) && entry == key) {
+        return Some(index + 1);
+      }
+      continue;
+    }
+    let name = line.split('=').next().unwrap_or_default().trim().trim_matches('"');
+    if (table.is_empty() || current == table) && name == key {
+      return Some(index + 1);
+    }
+  }
+  None
+}
+
+// How many config files the directory has, and their errors.
+pub fn check_directory(config_directory: &str) -> io::Result<(usize, Vec<String>)> {
+  let files = reload::config_files(Path::new(config_directory))?;
+  let mut errors: Vec<String> = Vec::new();
+  for path in &files {
+    let file_name = path.strip_prefix(config_directory).unwrap_or(path).display().to_string();
+    match fs::read_to_string(path) {
+      Ok(content) => errors.extend(check_file(&file_name, &content)),
+      Err(e) => errors.push(format!("{}: unable to read: {}", file_name, e)),
+    }
+  }
+  Ok((files.len(), errors))
+}
+
+pub fn run(config_directory: &str) {
+  let (files, errors) = match check_directory(config_directory) {
+    Ok(checked) => checked,
+    Err(e) => {
+      eprintln!("Unable to read {}: {}", config_directory, e);
+      std::process::exit(1);
+    }
+  };
+
+  for error in &errors {
+    println!("{}", error);
+  }
+  println!("Checked {} config file(s), found {} error(s).", files, errors.len());
+  if !errors.is_empty() {
+    std::process::exit(1);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn checks_every_binding_and_setting_with_its_line() {
+    let content = "[remap]\nctrl-KEY_FOO = [\"KEY_B\"]\nKEY_A = [\"KEY_C\"]\n\n[actions]\nKEY_F1 = \"explode\"\n\n[settings]\nGRAB_DEVICE = \"yes\"\nCURSOR_SPED = \"3\"\nCURSOR_SPEED = \"${speed}\"\n\n[vars]\nspeed = \"fast\"\n";
+    assert_eq!(check_file("Keyboard.toml", content), vec![
+      "Keyboard.toml:2: ctrl-KEY_FOO uses unknown key or axis KEY_FOO",
+      "Keyboard.toml:6: invalid action explode for KEY_F1 in [actions]",
+      "Keyboard.toml:9: invalid value yes for GRAB_DEVICE, use true or false",
+      "Keyboard.toml:10: unknown setting CURSOR_SPED",
+      "Keyboard.toml:11: invalid value fast for CURSOR_SPEED, use an integer",
+    ]);
+    assert!(check_file("Keyboard.toml", "[remap]\nKEY_A = [\"KEY_B\"]\n").is_empty());
+  }
+}
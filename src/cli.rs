@@ -0,0 +1,65 @@
+// The subcommands of the makita binary. Without one, Makita runs, like with `makita run`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+  Run,
+  Check,
+  Doctor,
+  ListDevices,
+  Lint,
+  Schema,
+  Import(Vec<String>),
+  Help,
+  Version,
+}
+
+pub const USAGE: &str = "\
+Usage: makita [command]
+
+Commands:
+  run                                    Remap the devices that have a config (the default)
+  check                                  Validate the config files without touching any device
+  doctor                                 Check that everything Makita needs is set up, with how to fix what isn't
+  list-devices                           List the event devices and the configs they would get
+  lint                                   Warn about bindings that are shadowed, duplicated or unused
+  schema                                 Print a JSON Schema of the config files
+  import --from <keyd|kmonad|xremap> <file>  Convert the config of another remapper
+  help                                   Show this message
+  version                                Show the version
+
+The config directory is $MAKITA_CONFIG, ~/.config/makita by default.";
+
+pub fn parse(args: &[String]) -> Result<Command, String> {
+  let Some(command) = args.first() else { return Ok(Command::Run) };
+  let command = match command.as_str() {
+    "run" => Command::Run,
+    "check" => Command::Check,
+    "doctor" => Command::Doctor,
+    "list-devices" => Command::ListDevices,
+    "lint" => Command::Lint,
+    "schema" => Command::Schema,
+    // The importer checks its own arguments.
+    "import" => return Ok(Command::Import(args[1..].to_vec())),
+    "help" | "-h" | "--help" => Command::Help,
+    "version" | "-V" | "--version" => Command::Version,
+    unknown => return Err(format!("Unknown command {}.", unknown)),
+  };
+  match &args[1..] {
+    [] => Ok(command),
+    extra => Err(format!("Unexpected argument {} for {}.", extra[0], args[0])),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_subcommands() {
+    let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<String>>();
+    assert_eq!(parse(&args(&[])), Ok(Command::Run));
+    assert_eq!(parse(&args(&["list-devices"])), Ok(Command::ListDevices));
+    assert_eq!(parse(&args(&["import", "--from", "keyd", "a.conf"])), Ok(Command::Import(args(&["--from", "keyd", "a.conf"]))));
+    assert!(parse(&args(&["check", "extra"])).is_err());
+    assert!(parse(&args(&["frobnicate"])).is_err());
+  }
+}
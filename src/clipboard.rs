@@ -0,0 +1,48 @@
+use crate::keymap;
+use crate::session;
+use crate::udev_monitor::Environment;
+use crate::virtual_devices::{Target, VirtualDevices};
+use evdev::{EventType, InputEvent};
+use std::{process::Stdio, time::Duration};
+
+// wl-paste on Wayland, xclip on X11, run as the user of the active session when Makita runs as root.
+pub fn read_clipboard(environment: &Environment) -> Option<String> {
+  let commands = if std::env::var("WAYLAND_DISPLAY").is_ok() {
+    ["wl-paste --no-newline", "xclip -selection clipboard -o"]
+  } else {
+    ["xclip -selection clipboard -o", "wl-paste --no-newline"]
+  };
+  for command in commands {
+    let output = session::desktop_command(environment, command).stderr(Stdio::null()).output();
+    if let Ok(output) = output {
+      if output.status.success() {
+        return Some(String::from_utf8_lossy(&output.stdout).to_string());
+      }
+    }
+  }
+  None
+}
+
+// In the layout the session types with when the text is read, looked up on a blocking thread since that asks the
+// compositor or localectl.
+pub async fn type_text(text: String, delay: Duration, virtual_devices: VirtualDevices, environment: Environment) {
+  let keymap = tokio::task::spawn_blocking(move || keymap::session_keymap(&environment)).await.ok().flatten();
+  let mut skipped = 0;
+  for character in text.replace("\r\n", "\n").chars() {
+    let Some((key, modifiers)) = keymap::keys_for_char(keymap.as_deref(), character) else {
+      skipped += 1;
+      continue;
+    };
+    let mut events: Vec<InputEvent> = modifiers.iter().map(|modifier| InputEvent::new_now(EventType::KEY, modifier.code(), 1)).collect();
+    events.push(InputEvent::new_now(EventType::KEY, key.code(), 1));
+    events.push(InputEvent::new_now(EventType::KEY, key.code(), 0));
+    events.extend(modifiers.iter().rev().map(|modifier| InputEvent::new_now(EventType::KEY, modifier.code(), 0)));
+    for event in events {
+      virtual_devices.emit(Target::Keyboard, &[event]);
+    }
+    tokio::time::sleep(delay).await;
+  }
+  if skipped > 0 {
+    println!("[Clipboard] Skipped {} character(s) that can't be typed with the keyboard layout.", skipped);
+  }
+}
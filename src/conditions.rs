@@ -0,0 +1,305 @@
+use crate::config::parse_key;
+use evdev::{Key, LedType};
+use std::str::FromStr;
+
+// What a `when` condition can look at, gathered when the input of a conditional binding is pressed.
+pub struct Context {
+  pub modifier_count: usize,
+  pub layer: String,
+  pub layout: u16,
+  pub profile: String,
+  pub device: String,
+  pub held_keys: Vec<Key>,
+  // The lock LEDs lit on the device.
+  pub leds: Vec<LedType>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+  Bool(bool),
+  Number(f64),
+  Text(String),
+}
+
+impl Value {
+  fn truthy(&self) -> bool {
+    match self {
+      Value::Bool(value) => *value,
+      Value::Number(value) => *value != 0.0,
+      Value::Text(value) => !value.is_empty(),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Variable {
+  ModifierCount,
+  Layer,
+  Layout,
+  Profile,
+  Device,
+  Led(LedType),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+  Equal,
+  NotEqual,
+  Less,
+  LessOrEqual,
+  Greater,
+  GreaterOrEqual,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expression {
+  Literal(Value),
+  Variable(Variable),
+  KeyHeld(Key),
+  Not(Box<Expression>),
+  And(Box<Expression>, Box<Expression>),
+  Or(Box<Expression>, Box<Expression>),
+  Compare(Comparison, Box<Expression>, Box<Expression>),
+}
+
+impl Expression {
+  fn evaluate(&self, context: &Context) -> Value {
+    match self {
+      Expression::Literal(value) => value.clone(),
+      Expression::Variable(Variable::ModifierCount) => Value::Number(context.modifier_count as f64),
+      Expression::Variable(Variable::Layer) => Value::Text(context.layer.clone()),
+      Expression::Variable(Variable::Layout) => Value::Number(context.layout as f64),
+      Expression::Variable(Variable::Profile) => Value::Text(context.profile.clone()),
+      Expression::Variable(Variable::Device) => Value::Text(context.device.clone()),
+      Expression::Variable(Variable::Led(led)) => Value::Bool(context.leds.contains(led)),
+      Expression::KeyHeld(key) => Value::Bool(context.held_keys.contains(key)),
+      Expression::Not(expression) => Value::Bool(!expression.evaluate(context).truthy()),
+      Expression::And(left, right) => Value::Bool(left.evaluate(context).truthy() && right.evaluate(context).truthy()),
+      Expression::Or(left, right) => Value::Bool(left.evaluate(context).truthy() || right.evaluate(context).truthy()),
+      Expression::Compare(comparison, left, right) => {
+        let (left, right) = (left.evaluate(context), right.evaluate(context));
+        // Only numbers are ordered, values of different types are never equal.
+        Value::Bool(match (comparison, &left, &right) {
+          (Comparison::Equal, _, _) => left == right,
+          (Comparison::NotEqual, _, _) => left != right,
+          (_, Value::Number(left), Value::Number(right)) => match comparison {
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            _ => left >= right,
+          },
+          _ => false,
+        })
+      }
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+  Number(f64),
+  Text(String),
+  Name(String),
+  Operator(&'static str),
+  Open,
+  Close,
+}
+
+const OPERATORS: [&str; 9] = ["&&", "||", "==", "!=", "<=", ">=", "<", ">", "!"];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+  let mut tokens = Vec::new();
+  let mut rest = source.trim_start();
+  while let Some(first) = rest.chars().next() {
+    let length = if let Some(operator) = OPERATORS.iter().find(|operator| rest.starts_with(**operator)) {
+      tokens.push(Token::Operator(operator));
+      operator.len()
+    } else if first == '(' || first == ')' {
+      tokens.push(if first == '(' { Token::Open } else { Token::Close });
+      1
+    } else if first == '\'' || first == '"' {
+      let end = rest[1..].find(first).ok_or_else(|| format!("unterminated string {}", rest))?;
+      tokens.push(Token::Text(rest[1..end + 1].to_string()));
+      end + 2
+    } else if first.is_ascii_digit() {
+      let end = rest.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(rest.len());
+      tokens.push(Token::Number(rest[..end].parse().map_err(|_| format!("invalid number {}", &rest[..end]))?));
+      end
+    } else if first.is_ascii_alphabetic() || first == '_' {
+      let end = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+      tokens.push(Token::Name(rest[..end].to_string()));
+      end
+    } else {
+      return Err(format!("unexpected {}", first));
+    };
+    rest = rest[length..].trim_start();
+  }
+  Ok(tokens)
+}
+
+// or := and ("||" and)*, and := not ("&&" not)*, not := "!" not | comparison,
+// comparison := primary (operator primary)?, primary := "(" or ")" | literal | variable | key_held("KEY")
+struct Parser {
+  tokens: Vec<Token>,
+  position: usize,
+}
+
+impl Parser {
+  fn peek(&self) -> Option<&Token> {
+    self.tokens.get(self.position)
+  }
+
+  fn next(&mut self) -> Result<Token, String> {
+    let token = self.tokens.get(self.position).cloned().ok_or("unexpected end")?;
+    self.position += 1;
+    Ok(token)
+  }
+
+  fn expect(&mut self, expected: Token) -> Result<(), String> {
+    match self.next()? {
+      token if token == expected => Ok(()),
+      token => Err(format!("expected {:?}, found {:?}", expected, token)),
+    }
+  }
+
+  fn or(&mut self) -> Result<Expression, String> {
+    let mut expression = self.and()?;
+    while self.peek() == Some(&Token::Operator("||")) {
+      self.position += 1;
+      expression = Expression::Or(Box::new(expression), Box::new(self.and()?));
+    }
+    Ok(expression)
+  }
+
+  fn and(&mut self) -> Result<Expression, String> {
+    let mut expression = self.not()?;
+    while self.peek() == Some(&Token::Operator("&&")) {
+      self.position += 1;
+      expression = Expression::And(Box::new(expression), Box::new(self.not()?));
+    }
+    Ok(expression)
+  }
+
+  fn not(&mut self) -> Result<Expression, String> {
+    if self.peek() == Some(&Token::Operator("!")) {
+      self.position += 1;
+      return Ok(Expression::Not(Box::new(self.not()?)));
+    }
+    self.comparison()
+  }
+
+  fn comparison(&mut self) -> Result<Expression, String> {
+    let left = self.primary()?;
+    let comparison = match self.peek() {
+      Some(Token::Operator("==")) => Comparison::Equal,
+      Some(Token::Operator("!=")) => Comparison::NotEqual,
+      Some(Token::Operator("<")) => Comparison::Less,
+      Some(Token::Operator("<=")) => Comparison::LessOrEqual,
+      Some(Token::Operator(">")) => Comparison::Greater,
+      Some(Token::Operator(">=")) => Comparison::GreaterOrEqual,
+      _ => return Ok(left),
+    };
+    self.position += 1;
+    Ok(Expression::Compare(comparison, Box::new(left), Box::new(self.primary()?)))
+  }
+
+  fn primary(&mut self) -> Result<Expression, String> {
+    match self.next()? {
+      Token::Open => {
+        let expression = self.or()?;
+        self.expect(Token::Close)?;
+        Ok(expression)
+      }
+      Token::Number(number) => Ok(Expression::Literal(Value::Number(number))),
+      Token::Text(text) => Ok(Expression::Literal(Value::Text(text))),
+      Token::Name(name) => match name.as_str() {
+        "true" => Ok(Expression::Literal(Value::Bool(true))),
+        "false" => Ok(Expression::Literal(Value::Bool(false))),
+        "modifier_count" => Ok(Expression::Variable(Variable::ModifierCount)),
+        "layer" => Ok(Expression::Variable(Variable::Layer)),
+        "layout" => Ok(Expression::Variable(Variable::Layout)),
+        "profile" => Ok(Expression::Variable(Variable::Profile)),
+        "device" => Ok(Expression::Variable(Variable::Device)),
+        "num_lock" => Ok(Expression::Variable(Variable::Led(LedType::LED_NUML))),
+        "caps_lock" => Ok(Expression::Variable(Variable::Led(LedType::LED_CAPSL))),
+        "scroll_lock" => Ok(Expression::Variable(Variable::Led(LedType::LED_SCROLLL))),
+        "key_held" => {
+          self.expect(Token::Open)?;
+          let key = match self.next()? {
+            Token::Text(name) => parse_key(&name).ok_or_else(|| format!("unknown key {}", name))?,
+            token => return Err(format!("key_held takes a key name, found {:?}", token)),
+          };
+          self.expect(Token::Close)?;
+          Ok(Expression::KeyHeld(key))
+        }
+        _ => Err(format!("unknown name {}", name)),
+      },
+      token => Err(format!("unexpected {:?}", token)),
+    }
+  }
+}
+
+// The `when` condition of a binding, e.g. "modifier_count == 0 && layer == 'base'" or "key_held('KEY_SPACE')".
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+  expression: Expression,
+}
+
+impl Condition {
+  pub fn holds(&self, context: &Context) -> bool {
+    self.expression.evaluate(context).truthy()
+  }
+}
+
+impl FromStr for Condition {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut parser = Parser { tokens: tokenize(s)?, position: 0 };
+    let expression = parser.or()?;
+    match parser.peek() {
+      Some(token) => Err(format!("unexpected {:?}", token)),
+      None => Ok(Self { expression }),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::config::{parse_binding, Config, ConfigError};
+
+  #[test]
+  fn evaluates_when_conditions() {
+    let config = Config::parse(r#"
+      [remap]
+      KEY_CAPSLOCK = { output = ["KEY_ESC"], when = "modifier_count == 0 && (layer == 'base' || layout > 2)" }
+      KEY_J = { output = ["KEY_DOWN"], when = "key_held('KEY_SPACE') && !(profile != \"default\")" }
+    "#, "Test Device".to_string()).unwrap();
+    let condition = |binding: &str| config.bindings.conditions[&parse_binding(binding).unwrap()].clone();
+    let context = |modifier_count: usize, layer: &str, layout: u16, held_keys: Vec<Key>| Context {
+      modifier_count,
+      layer: layer.to_string(),
+      layout,
+      profile: "default".to_string(),
+      device: "Keyboard".to_string(),
+      held_keys,
+      leds: vec![],
+    };
+    assert!(condition("KEY_CAPSLOCK").holds(&context(0, "base", 0, vec![])));
+    assert!(condition("KEY_CAPSLOCK").holds(&context(0, "3", 3, vec![])));
+    assert!(!condition("KEY_CAPSLOCK").holds(&context(1, "base", 0, vec![])));
+    assert!(condition("KEY_J").holds(&context(0, "base", 0, vec![Key::KEY_SPACE])));
+    assert!(!condition("KEY_J").holds(&context(0, "base", 0, vec![Key::KEY_LEFTSHIFT])));
+    let num_lock: Condition = "!num_lock && !caps_lock".parse().unwrap();
+    assert!(num_lock.holds(&context(0, "base", 0, vec![])));
+    assert!(!num_lock.holds(&Context { leds: vec![LedType::LED_NUML], ..context(0, "base", 0, vec![]) }));
+
+    let parse = |content: &str| Config::parse(content, "Test Device".to_string());
+    assert!(matches!(
+      parse("[remap]\nKEY_A = { output = [\"KEY_B\"], when = \"key_held('KEY_NOPE')\" }\n"),
+      Err(ConfigError::InvalidCondition { .. })
+    ));
+    assert!(parse("[remap]\nKEY_A = { output = [\"KEY_B\"], when = \"layer ==\" }\n").is_err());
+  }
+}
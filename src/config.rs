@@ -1,7 +1,8 @@
+use crate::conditions::Condition;
 use crate::udev_monitor::Client;
-use evdev::Key;
+use evdev::{AbsoluteAxisType, Key, LedType, RelativeAxisType};
 use serde;
-use std::{collections::HashMap, str::FromStr};
+use std::{collections::HashMap, str::FromStr, time::Duration};
 
 #[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy)]
 pub enum Event {
@@ -17,6 +18,18 @@ pub enum Axis {
   BTN_DPAD_DOWN,
   BTN_DPAD_LEFT,
   BTN_DPAD_RIGHT,
+  HAT1_UP,
+  HAT1_DOWN,
+  HAT1_LEFT,
+  HAT1_RIGHT,
+  HAT2_UP,
+  HAT2_DOWN,
+  HAT2_LEFT,
+  HAT2_RIGHT,
+  HAT3_UP,
+  HAT3_DOWN,
+  HAT3_LEFT,
+  HAT3_RIGHT,
   LSTICK_UP,
   LSTICK_DOWN,
   LSTICK_LEFT,
@@ -31,8 +44,117 @@ pub enum Axis {
   BTN_TR2,
   ABS_WHEEL_CW,
   ABS_WHEEL_CCW,
+  TILT_UP,
+  TILT_DOWN,
+  TILT_LEFT,
+  TILT_RIGHT,
+  SWIPE_FROM_LEFT,
+  SWIPE_FROM_RIGHT,
+  SWIPE_FROM_TOP,
+  SWIPE_FROM_BOTTOM,
+  TAP_2_FINGERS,
+  TAP_3_FINGERS,
+  TAP_4_FINGERS,
+  EDGE_LEFT,
+  EDGE_RIGHT,
+  EDGE_TOP,
+  EDGE_BOTTOM,
+  CORNER_TOP_LEFT,
+  CORNER_TOP_RIGHT,
+  CORNER_BOTTOM_LEFT,
+  CORNER_BOTTOM_RIGHT,
+  // Either direction of any other absolute axis by its code, e.g. ABS_THROTTLE_MINUS and ABS_THROTTLE_PLUS.
+  ABS_MINUS(u16),
+  ABS_PLUS(u16),
 }
 
+impl Axis {
+  pub const ALL: [Axis; 49] = [
+    Axis::BTN_DPAD_UP,
+    Axis::BTN_DPAD_DOWN,
+    Axis::BTN_DPAD_LEFT,
+    Axis::BTN_DPAD_RIGHT,
+    Axis::HAT1_UP,
+    Axis::HAT1_DOWN,
+    Axis::HAT1_LEFT,
+    Axis::HAT1_RIGHT,
+    Axis::HAT2_UP,
+    Axis::HAT2_DOWN,
+    Axis::HAT2_LEFT,
+    Axis::HAT2_RIGHT,
+    Axis::HAT3_UP,
+    Axis::HAT3_DOWN,
+    Axis::HAT3_LEFT,
+    Axis::HAT3_RIGHT,
+    Axis::LSTICK_UP,
+    Axis::LSTICK_DOWN,
+    Axis::LSTICK_LEFT,
+    Axis::LSTICK_RIGHT,
+    Axis::RSTICK_UP,
+    Axis::RSTICK_DOWN,
+    Axis::RSTICK_LEFT,
+    Axis::RSTICK_RIGHT,
+    Axis::SCROLL_WHEEL_UP,
+    Axis::SCROLL_WHEEL_DOWN,
+    Axis::BTN_TL2,
+    Axis::BTN_TR2,
+    Axis::ABS_WHEEL_CW,
+    Axis::ABS_WHEEL_CCW,
+    Axis::TILT_UP,
+    Axis::TILT_DOWN,
+    Axis::TILT_LEFT,
+    Axis::TILT_RIGHT,
+    Axis::SWIPE_FROM_LEFT,
+    Axis::SWIPE_FROM_RIGHT,
+    Axis::SWIPE_FROM_TOP,
+    Axis::SWIPE_FROM_BOTTOM,
+    Axis::TAP_2_FINGERS,
+    Axis::TAP_3_FINGERS,
+    Axis::TAP_4_FINGERS,
+    Axis::EDGE_LEFT,
+    Axis::EDGE_RIGHT,
+    Axis::EDGE_TOP,
+    Axis::EDGE_BOTTOM,
+    Axis::CORNER_TOP_LEFT,
+    Axis::CORNER_TOP_RIGHT,
+    Axis::CORNER_BOTTOM_LEFT,
+    Axis::CORNER_BOTTOM_RIGHT,
+  ];
+
+  // The name bindings use, which is the name of the variant except for the generic absolute axes.
+  pub fn name(&self) -> String {
+    match self {
+      Axis::ABS_MINUS(code) => format!("{:?}_MINUS", AbsoluteAxisType(*code)),
+      Axis::ABS_PLUS(code) => format!("{:?}_PLUS", AbsoluteAxisType(*code)),
+      axis => format!("{:?}", axis),
+    }
+  }
+}
+
+// The absolute axes the reader turns into the named events above, which can't be bound as ABS_<NAME>_MINUS/PLUS.
+pub const DEDICATED_ABS_AXES: [AbsoluteAxisType; 16] = [
+  AbsoluteAxisType::ABS_X, AbsoluteAxisType::ABS_Y, AbsoluteAxisType::ABS_Z,
+  AbsoluteAxisType::ABS_RX, AbsoluteAxisType::ABS_RY, AbsoluteAxisType::ABS_RZ,
+  AbsoluteAxisType::ABS_HAT0X, AbsoluteAxisType::ABS_HAT0Y, AbsoluteAxisType::ABS_HAT1X, AbsoluteAxisType::ABS_HAT1Y,
+  AbsoluteAxisType::ABS_HAT2X, AbsoluteAxisType::ABS_HAT2Y, AbsoluteAxisType::ABS_HAT3X, AbsoluteAxisType::ABS_HAT3Y,
+  AbsoluteAxisType::ABS_WHEEL, AbsoluteAxisType::ABS_MISC,
+];
+
+// The directions of each hat as [up, down, left, right], ABS_HAT0X/Y being the D-pad of a controller and
+// ABS_HAT1X/Y to ABS_HAT3X/Y the extra hat switches of flight sticks and HOTAS.
+pub const HAT_DIRECTIONS: [[Axis; 4]; 4] = [
+  [Axis::BTN_DPAD_UP, Axis::BTN_DPAD_DOWN, Axis::BTN_DPAD_LEFT, Axis::BTN_DPAD_RIGHT],
+  [Axis::HAT1_UP, Axis::HAT1_DOWN, Axis::HAT1_LEFT, Axis::HAT1_RIGHT],
+  [Axis::HAT2_UP, Axis::HAT2_DOWN, Axis::HAT2_LEFT, Axis::HAT2_RIGHT],
+  [Axis::HAT3_UP, Axis::HAT3_DOWN, Axis::HAT3_LEFT, Axis::HAT3_RIGHT],
+];
+
+// The borders of the desktop, which the cursor a mouse moves fires as it reaches them.
+pub const POINTER_EDGES: [Axis; 8] = [
+  Axis::EDGE_LEFT, Axis::EDGE_RIGHT, Axis::EDGE_TOP, Axis::EDGE_BOTTOM,
+  Axis::CORNER_TOP_LEFT, Axis::CORNER_TOP_RIGHT, Axis::CORNER_BOTTOM_LEFT, Axis::CORNER_BOTTOM_RIGHT,
+];
+
 impl FromStr for Axis {
   type Err = String;
   fn from_str(s: &str) -> Result<Axis, Self::Err> {
@@ -41,6 +163,18 @@ impl FromStr for Axis {
       "BTN_DPAD_DOWN" => Ok(Axis::BTN_DPAD_DOWN),
       "BTN_DPAD_LEFT" => Ok(Axis::BTN_DPAD_LEFT),
       "BTN_DPAD_RIGHT" => Ok(Axis::BTN_DPAD_RIGHT),
+      "HAT1_UP" => Ok(Axis::HAT1_UP),
+      "HAT1_DOWN" => Ok(Axis::HAT1_DOWN),
+      "HAT1_LEFT" => Ok(Axis::HAT1_LEFT),
+      "HAT1_RIGHT" => Ok(Axis::HAT1_RIGHT),
+      "HAT2_UP" => Ok(Axis::HAT2_UP),
+      "HAT2_DOWN" => Ok(Axis::HAT2_DOWN),
+      "HAT2_LEFT" => Ok(Axis::HAT2_LEFT),
+      "HAT2_RIGHT" => Ok(Axis::HAT2_RIGHT),
+      "HAT3_UP" => Ok(Axis::HAT3_UP),
+      "HAT3_DOWN" => Ok(Axis::HAT3_DOWN),
+      "HAT3_LEFT" => Ok(Axis::HAT3_LEFT),
+      "HAT3_RIGHT" => Ok(Axis::HAT3_RIGHT),
       "LSTICK_UP" => Ok(Axis::LSTICK_UP),
       "LSTICK_DOWN" => Ok(Axis::LSTICK_DOWN),
       "LSTICK_LEFT" => Ok(Axis::LSTICK_LEFT),
@@ -55,7 +189,31 @@ impl FromStr for Axis {
       "BTN_TR2" => Ok(Axis::BTN_TR2),
       "ABS_WHEEL_CW" => Ok(Axis::ABS_WHEEL_CW),
       "ABS_WHEEL_CCW" => Ok(Axis::ABS_WHEEL_CCW),
-      _ => Err(s.to_string()),
+      "TILT_UP" => Ok(Axis::TILT_UP),
+      "TILT_DOWN" => Ok(Axis::TILT_DOWN),
+      "TILT_LEFT" => Ok(Axis::TILT_LEFT),
+      "TILT_RIGHT" => Ok(Axis::TILT_RIGHT),
+      "SWIPE_FROM_LEFT" => Ok(Axis::SWIPE_FROM_LEFT),
+      "SWIPE_FROM_RIGHT" => Ok(Axis::SWIPE_FROM_RIGHT),
+      "SWIPE_FROM_TOP" => Ok(Axis::SWIPE_FROM_TOP),
+      "SWIPE_FROM_BOTTOM" => Ok(Axis::SWIPE_FROM_BOTTOM),
+      "TAP_2_FINGERS" => Ok(Axis::TAP_2_FINGERS),
+      "TAP_3_FINGERS" => Ok(Axis::TAP_3_FINGERS),
+      "TAP_4_FINGERS" => Ok(Axis::TAP_4_FINGERS),
+      "EDGE_LEFT" => Ok(Axis::EDGE_LEFT),
+      "EDGE_RIGHT" => Ok(Axis::EDGE_RIGHT),
+      "EDGE_TOP" => Ok(Axis::EDGE_TOP),
+      "EDGE_BOTTOM" => Ok(Axis::EDGE_BOTTOM),
+      "CORNER_TOP_LEFT" => Ok(Axis::CORNER_TOP_LEFT),
+      "CORNER_TOP_RIGHT" => Ok(Axis::CORNER_TOP_RIGHT),
+      "CORNER_BOTTOM_LEFT" => Ok(Axis::CORNER_BOTTOM_LEFT),
+      "CORNER_BOTTOM_RIGHT" => Ok(Axis::CORNER_BOTTOM_RIGHT),
+      _ => s.strip_suffix("_MINUS").map(|name| (name, false))
+        .or_else(|| s.strip_suffix("_PLUS").map(|name| (name, true)))
+        .and_then(|(name, plus)| Some((AbsoluteAxisType::from_str(name).ok()?, plus)))
+        .filter(|(axis, _)| !DEDICATED_ABS_AXES.contains(axis))
+        .map(|(axis, plus)| if plus { Axis::ABS_PLUS(axis.0) } else { Axis::ABS_MINUS(axis.0) })
+        .ok_or_else(|| s.to_string()),
     }
   }
 }
@@ -64,6 +222,7 @@ impl FromStr for Axis {
 pub enum Relative {
   Cursor(Cursor),
   Scroll(Scroll),
+  Wheel(Scroll),
 }
 
 #[allow(non_camel_case_types)]
@@ -84,6 +243,12 @@ pub enum Scroll {
   SCROLL_RIGHT,
 }
 
+pub const MOVEMENT_NAMES: [&str; 12] = [
+  "CURSOR_UP", "CURSOR_DOWN", "CURSOR_LEFT", "CURSOR_RIGHT",
+  "SCROLL_UP", "SCROLL_DOWN", "SCROLL_LEFT", "SCROLL_RIGHT",
+  "WHEEL_UP", "WHEEL_DOWN", "WHEEL_LEFT", "WHEEL_RIGHT",
+];
+
 impl FromStr for Relative {
   type Err = String;
   fn from_str(s: &str) -> Result<Relative, Self::Err> {
@@ -96,22 +261,282 @@ impl FromStr for Relative {
       "SCROLL_DOWN" => Ok(Relative::Scroll(Scroll::SCROLL_DOWN)),
       "SCROLL_LEFT" => Ok(Relative::Scroll(Scroll::SCROLL_LEFT)),
       "SCROLL_RIGHT" => Ok(Relative::Scroll(Scroll::SCROLL_RIGHT)),
+      "WHEEL_UP" => Ok(Relative::Wheel(Scroll::SCROLL_UP)),
+      "WHEEL_DOWN" => Ok(Relative::Wheel(Scroll::SCROLL_DOWN)),
+      "WHEEL_LEFT" => Ok(Relative::Wheel(Scroll::SCROLL_LEFT)),
+      "WHEEL_RIGHT" => Ok(Relative::Wheel(Scroll::SCROLL_RIGHT)),
+      _ => Err(s.to_string()),
+    }
+  }
+}
+
+// Accepts full names like KEY_LEFTCTRL and BTN_LEFT as well as shorthands like ctrl, a, f5 or mouse1.
+pub fn key_from_alias(name: &str) -> Option<Key> {
+  let lowercase = name.to_lowercase();
+  let alias = match lowercase.as_str() {
+    "esc" | "escape" => "ESC",
+    "ret" | "return" | "enter" => "ENTER",
+    "spc" | "space" => "SPACE",
+    "bspc" | "backspace" => "BACKSPACE",
+    "del" | "delete" => "DELETE",
+    "caps" | "capslock" => "CAPSLOCK",
+    "ctrl" | "control" | "lctl" | "lctrl" | "leftcontrol" | "control_l" => "LEFTCTRL",
+    "rctl" | "rctrl" | "rightcontrol" | "control_r" => "RIGHTCTRL",
+    "shift" | "lsft" | "lshift" | "leftshift" | "shift_l" => "LEFTSHIFT",
+    "rsft" | "rshift" | "rightshift" | "shift_r" => "RIGHTSHIFT",
+    "alt" | "lalt" | "leftalt" | "alt_l" => "LEFTALT",
+    "ralt" | "rightalt" | "alt_r" | "altgr" => "RIGHTALT",
+    "meta" | "super" | "lmet" | "lmeta" | "leftmeta" | "super_l" | "win" => "LEFTMETA",
+    "rmet" | "rmeta" | "rightmeta" | "super_r" => "RIGHTMETA",
+    "pgup" | "pageup" => "PAGEUP",
+    "pgdn" | "pagedown" => "PAGEDOWN",
+    "-" => "MINUS",
+    "=" => "EQUAL",
+    "[" => "LEFTBRACE",
+    "]" => "RIGHTBRACE",
+    ";" => "SEMICOLON",
+    "'" => "APOSTROPHE",
+    "`" | "grv" => "GRAVE",
+    "\\" => "BACKSLASH",
+    "," => "COMMA",
+    "." => "DOT",
+    "/" => "SLASH",
+    "mouse1" => "BTN_LEFT",
+    "mouse2" => "BTN_RIGHT",
+    "mouse3" => "BTN_MIDDLE",
+    "mouse4" => "BTN_SIDE",
+    "mouse5" => "BTN_EXTRA",
+    _ => "",
+  };
+
+  let name = if alias.is_empty() { lowercase.to_uppercase() } else { alias.to_string() };
+  Key::from_str(&format!("KEY_{}", name)).or_else(|_| Key::from_str(&name)).ok()
+}
+
+// The highest key code the kernel knows of.
+pub const KEY_MAX: u16 = 0x2ff;
+
+// Like key_from_alias, but plain numbers are raw key codes, as used in configs.
+pub fn parse_key(name: &str) -> Option<Key> {
+  if let Ok(code) = name.parse::<u16>() {
+    return (code <= KEY_MAX).then_some(Key(code));
+  }
+  key_from_alias(name)
+}
+
+// Shorthands like "ctrl" are shown, and compared, by the key they stand for.
+pub fn canonical_name(name: &str) -> String {
+  match (Axis::from_str(name), parse_key(name)) {
+    (Err(_), Some(key)) => format!("{:?}", key),
+    _ => name.to_string(),
+  }
+}
+
+fn deserialize_key_lists<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<HashMap<String, Vec<Key>>, D::Error> {
+  let raw: HashMap<String, Vec<String>> = serde::Deserialize::deserialize(deserializer)?;
+  raw.into_iter().map(|(input, names)| {
+    let keys = names.iter()
+      .map(|name| parse_key(name).ok_or_else(|| serde::de::Error::custom(format!("unknown key {} in binding {}", name, input))))
+      .collect::<Result<Vec<Key>, D::Error>>()?;
+    Ok((input, keys))
+  }).collect()
+}
+
+// A layout picked by its number, or by its name in LAYOUT_NAMES.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum LayoutTarget {
+  Number(u16),
+  Name(String),
+}
+
+// Whether the remote action starts or stops forwarding to the REMOTE_TARGET machine.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum RemoteCommand {
+  On,
+  Off,
+  Toggle,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Action {
+  Profile(Option<String>),
+  Layout(LayoutTarget),
+  Osc { target: String, address: String, payload: String },
+  Mqtt { broker: String, topic: String, payload: String },
+  PasteClipboard(Duration),
+  CycleCursorSpeed,
+  ToggleRemapping,
+  Remote(RemoteCommand),
+  Inhibit(Option<u64>),
+  Wheel { direction: Scroll, modifiers: Vec<Key> },
+  Toggle(Vec<Key>),
+  Hold(Vec<Key>, Duration),
+  // Turns a lock on or off, or toggles it with None.
+  Lock(LedType, Option<bool>),
+}
+
+impl FromStr for Action {
+  type Err = String;
+  fn from_str(s: &str) -> Result<Action, Self::Err> {
+    let (name, argument) = match s.split_once('(') {
+      Some((name, rest)) => (name.trim(), rest.trim_end().strip_suffix(')').ok_or(s.to_string())?.trim()),
+      None => (s.trim(), ""),
+    };
+    // The payload comes last so that it can contain commas.
+    let mut arguments = argument.splitn(3, ',').map(str::trim);
+    let (first, second, payload) = (arguments.next().unwrap_or(""), arguments.next(), arguments.next().unwrap_or("").to_string());
+    match (name, argument.trim_matches('"')) {
+      ("profile", "" | "default") => Ok(Action::Profile(None)),
+      ("profile", profile) => Ok(Action::Profile(Some(profile.to_string()))),
+      ("layout", "") => Err(s.to_string()),
+      ("layout", name) => Ok(Action::Layout(match argument.parse() {
+        Ok(number) => LayoutTarget::Number(number),
+        Err(_) => LayoutTarget::Name(name.to_string()),
+      })),
+      ("osc", _) if second.is_some_and(|address| address.starts_with('/')) => Ok(Action::Osc {
+        target: first.to_string(),
+        address: second.unwrap().to_string(),
+        payload,
+      }),
+      ("wheel", _) => {
+        let mut arguments = argument.split(',').map(str::trim);
+        let direction = match Relative::from_str(arguments.next().unwrap_or("")) {
+          Ok(Relative::Scroll(direction)) => direction,
+          _ => return Err(s.to_string()),
+        };
+        let modifiers = arguments.map(parse_key).collect::<Option<Vec<Key>>>().ok_or(s.to_string())?;
+        Ok(Action::Wheel { direction, modifiers })
+      }
+      ("hold", _) => {
+        let (keys, duration) = argument.rsplit_once(',').ok_or(s.to_string())?;
+        let duration = duration.trim().parse().map_err(|_| s.to_string())?;
+        let keys = keys.split(',').map(|key| parse_key(key.trim())).collect::<Option<Vec<Key>>>().ok_or(s.to_string())?;
+        Ok(Action::Hold(keys, Duration::from_millis(duration)))
+      }
+      ("toggle", "") => Err(s.to_string()),
+      ("toggle", keys) => keys.split(',').map(|key| parse_key(key.trim())).collect::<Option<Vec<Key>>>()
+        .map(Action::Toggle).ok_or(s.to_string()),
+      ("lock", _) if payload.is_empty() => {
+        let led = crate::state::parse_lock(first).ok_or(s.to_string())?;
+        match second {
+          None | Some("toggle") => Ok(Action::Lock(led, None)),
+          Some("on") => Ok(Action::Lock(led, Some(true))),
+          Some("off") => Ok(Action::Lock(led, Some(false))),
+          _ => Err(s.to_string()),
+        }
+      }
+      ("cycle_cursor_speed", "") => Ok(Action::CycleCursorSpeed),
+      ("toggle_remapping", "") => Ok(Action::ToggleRemapping),
+      ("inhibit", "") => Ok(Action::Inhibit(None)),
+      ("inhibit", minutes) => minutes.parse().map(|minutes| Action::Inhibit(Some(minutes))).map_err(|_| s.to_string()),
+      ("remote", "" | "toggle") => Ok(Action::Remote(RemoteCommand::Toggle)),
+      ("remote", "on") => Ok(Action::Remote(RemoteCommand::On)),
+      ("remote", "off") => Ok(Action::Remote(RemoteCommand::Off)),
+      ("paste_clipboard", "") => Ok(Action::PasteClipboard(Duration::from_millis(10))),
+      ("paste_clipboard", delay) => delay.parse().map(|delay| Action::PasteClipboard(Duration::from_millis(delay))).map_err(|_| s.to_string()),
+      ("mqtt", _) if second.is_some_and(|topic| !topic.is_empty()) => Ok(Action::Mqtt {
+        broker: first.to_string(),
+        topic: second.unwrap().to_string(),
+        payload,
+      }),
+      _ => Err(s.to_string()),
+    }
+  }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum TapHoldStrategy {
+  HoldOnTimeout,
+  PermissiveHold,
+  HoldOnOtherKeyPress,
+  TapOnOtherKeyPress,
+}
+
+pub const TAP_HOLD_STRATEGIES: [&str; 4] = ["hold_on_timeout", "permissive_hold", "hold_on_other_key_press", "tap_on_other_key_press"];
+pub const STICK_FUNCTIONS: [&str; 6] = ["cursor", "scroll", "flick", "bind", "gamepad", "disabled"];
+pub const VIRTUAL_GAMEPAD_MODES: [&str; 2] = ["none", "xpad"];
+pub const MOUSE_STICK_MODES: [&str; 3] = ["none", "left", "right"];
+pub const WHEEL_BINDING_SOURCES: [&str; 3] = ["auto", "low_res", "hi_res"];
+pub const EVENT_TAP_MODES: [&str; 3] = ["off", "bound", "all"];
+pub const LOCK_SCREEN_MODES: [&str; 3] = ["remap", "passthrough", "no_scripts"];
+pub const OUTPUT_BACKENDS: [&str; 2] = ["uinput", "wayland"];
+pub const NOTIFIERS: [&str; 2] = ["dbus", "command"];
+// Percentage of half the range of an absolute axis, for ABS_<NAME>_MINUS/PLUS bindings and [axes] entries.
+pub const DEFAULT_AXIS_DEADZONE: f64 = 10.0;
+pub const AXIS_MODES: [&str; 2] = ["position", "velocity"];
+pub const AXIS_OUTPUTS: [&str; 4] = ["REL_X", "REL_Y", "REL_WHEEL", "REL_HWHEEL"];
+
+impl FromStr for TapHoldStrategy {
+  type Err = String;
+  fn from_str(s: &str) -> Result<TapHoldStrategy, Self::Err> {
+    match s {
+      "hold_on_timeout" => Ok(TapHoldStrategy::HoldOnTimeout),
+      "permissive_hold" => Ok(TapHoldStrategy::PermissiveHold),
+      "hold_on_other_key_press" => Ok(TapHoldStrategy::HoldOnOtherKeyPress),
+      "tap_on_other_key_press" => Ok(TapHoldStrategy::TapOnOtherKeyPress),
       _ => Err(s.to_string()),
     }
   }
 }
 
+// What a tap-hold key runs instead of pressing keys when tapped or held.
+#[derive(Debug, PartialEq, Clone)]
+pub enum TapHoldCommand {
+  Action(Action),
+  Ruby(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct TapHold {
+  pub tap: Key,
+  pub hold: Vec<Key>,
+  pub tap_command: Option<TapHoldCommand>,
+  pub hold_command: Option<TapHoldCommand>,
+  pub tapping_term: Duration,
+  pub strategy: TapHoldStrategy,
+}
+
 #[derive(Debug, PartialEq, Eq, Default, Clone)]
 pub struct Associations {
   pub client: Client,
   pub layout: u16,
+  pub profile: Option<String>,
 }
 
 #[derive(Default, Debug, Clone)]
 pub struct Bindings {
   pub remap: HashMap<Event, HashMap<Vec<Event>, Vec<Key>>>,
+  pub overrides: HashMap<Event, HashMap<Vec<Event>, Vec<Key>>>,
   pub movements: HashMap<Event, HashMap<Vec<Event>, Relative>>,
+  pub actions: HashMap<Event, HashMap<Vec<Event>, Action>>,
   pub rubies: HashMap<Event, HashMap<Vec<Event>, String>>,
+  pub tap_hold: HashMap<Key, TapHold>,
+  pub rate_limits: HashMap<(Event, Vec<Event>), Duration>,
+  pub conditions: HashMap<(Event, Vec<Event>), Condition>,
+  pub chords: HashMap<Vec<Event>, Vec<Key>>,
+}
+
+impl Bindings {
+  // A binding is its input and modifiers, whichever table it's in.
+  fn declares(&self, event: &Event, modifiers: &Vec<Event>) -> bool {
+    fn has<T>(table: &HashMap<Event, HashMap<Vec<Event>, T>>, event: &Event, modifiers: &Vec<Event>) -> bool {
+      table.get(event).is_some_and(|map| map.contains_key(modifiers))
+    }
+    has(&self.remap, event, modifiers)
+      || has(&self.overrides, event, modifiers)
+      || has(&self.movements, event, modifiers)
+      || has(&self.actions, event, modifiers)
+      || has(&self.rubies, event, modifiers)
+  }
+
+  // Whether the input is bound in any table, with any modifiers.
+  pub fn binds(&self, event: &Event) -> bool {
+    self.remap.contains_key(event)
+      || self.overrides.contains_key(event)
+      || self.movements.contains_key(event)
+      || self.actions.contains_key(event)
+      || self.rubies.contains_key(event)
+  }
 }
 
 #[derive(Default, Debug, Clone)]
@@ -122,37 +547,315 @@ pub struct MappedModifiers {
 }
 
 #[derive(serde::Deserialize, Debug, Clone)]
-pub struct RawConfig {
+pub struct RawTapHold {
+  pub tap: Option<Key>,
+  pub hold: Option<Key>,
+  pub tap_action: Option<String>,
+  pub hold_action: Option<String>,
+  pub tap_ruby: Option<String>,
+  pub hold_ruby: Option<String>,
+  pub tapping_term: Option<u64>,
+  pub strategy: Option<String>,
+  pub description: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct RawHidraw {
+  pub device: String,
+  pub report_id: Option<u8>,
   #[serde(default)]
+  pub buttons: HashMap<String, Key>,
+}
+
+#[derive(Debug, Clone)]
+pub struct HidrawButton {
+  pub byte: usize,
+  pub bit: Option<u8>,
+  pub key: Key,
+}
+
+#[derive(Debug, Clone)]
+pub struct Hidraw {
+  pub device: String,
+  pub report_id: Option<u8>,
+  pub buttons: Vec<HidrawButton>,
+}
+
+impl Hidraw {
+  fn from_raw(raw: RawHidraw) -> Self {
+    let mut buttons = Vec::new();
+    for (position, key) in raw.buttons {
+      let parsed = match position.split_once(':') {
+        Some((byte, bit)) => byte.parse().ok().zip(bit.parse().ok().filter(|bit| *bit < 8)).map(|(byte, bit)| (byte, Some(bit))),
+        None => position.parse().ok().map(|byte| (byte, None)),
+      };
+      match parsed {
+        Some((byte, bit)) => buttons.push(HidrawButton { byte, bit, key }),
+        None => println!("[Config] Invalid button position {} in [hidraw.buttons], use \"byte\" or \"byte:bit\", ignoring.", position),
+      }
+    }
+
+    Self {
+      device: raw.device,
+      report_id: raw.report_id,
+      buttons,
+    }
+  }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Merge {
+  pub devices: Vec<String>,
+  #[serde(default)]
+  pub joycon: bool,
+  #[serde(default)]
+  pub rotate: HashMap<String, i32>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct RawAxisMapping {
+  pub output: String,
+  pub scale: Option<f64>,
+  #[serde(default)]
+  pub invert: bool,
+  pub mode: Option<String>,
+  pub deadzone: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AxisMode {
+  Position,
+  Velocity,
+}
+
+// An [axes] entry: the relative axis an absolute one drives, with the sign of scale set by invert.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AxisMapping {
+  pub output: RelativeAxisType,
+  pub scale: f64,
+  pub mode: AxisMode,
+  pub deadzone: f64,
+}
+
+fn parse_axes(raw_axes: HashMap<String, RawAxisMapping>) -> Result<HashMap<u16, AxisMapping>, ConfigError> {
+  let invalid = |axis: &str, reason: String| ConfigError::InvalidAxis { axis: axis.to_string(), reason };
+  let mut axes = HashMap::new();
+  for (name, raw) in raw_axes {
+    let axis = AbsoluteAxisType::from_str(&name).map_err(|_| invalid(&name, "unknown absolute axis".to_string()))?;
+    let output = match AXIS_OUTPUTS.contains(&raw.output.as_str()) {
+      true => RelativeAxisType::from_str(&raw.output).map_err(|_| invalid(&name, format!("unknown output {}", raw.output)))?,
+      false => return Err(invalid(&name, format!("output {} isn't one of {}", raw.output, AXIS_OUTPUTS.join(", ")))),
+    };
+    let mode = match raw.mode.as_deref().unwrap_or("position") {
+      "position" => AxisMode::Position,
+      "velocity" => AxisMode::Velocity,
+      mode => return Err(invalid(&name, format!("mode {} isn't position or velocity", mode))),
+    };
+    let scale = raw.scale.unwrap_or(1.0);
+    axes.insert(axis.0, AxisMapping {
+      output,
+      scale: if raw.invert { -scale } else { scale },
+      mode,
+      deadzone: raw.deadzone.unwrap_or(DEFAULT_AXIS_DEADZONE),
+    });
+  }
+  Ok(axes)
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct RawConfig {
+  #[serde(default, deserialize_with = "deserialize_key_lists")]
   pub remap: HashMap<String, Vec<Key>>,
+  #[serde(default, deserialize_with = "deserialize_key_lists")]
+  pub overrides: HashMap<String, Vec<Key>>,
   #[serde(default)]
   pub movements: HashMap<String, String>,
   #[serde(default)]
+  pub actions: HashMap<String, String>,
+  #[serde(default)]
   pub settings: HashMap<String, String>,
   #[serde(default)]
   pub rubies: HashMap<String, String>,
+  #[serde(default)]
+  pub tap_hold: HashMap<String, RawTapHold>,
+  pub hidraw: Option<RawHidraw>,
+  pub merge: Option<Merge>,
+  #[serde(default)]
+  pub axes: HashMap<String, RawAxisMapping>,
+  #[serde(default, deserialize_with = "deserialize_key_lists")]
+  pub chords: HashMap<String, Vec<Key>>,
+  #[serde(skip)]
+  pub descriptions: HashMap<(String, String), String>,
+  #[serde(skip)]
+  pub rate_limits: HashMap<(String, String), u64>,
+  #[serde(skip)]
+  pub conditions: HashMap<(String, String), String>,
+}
+
+const DESCRIBED_TABLES: [&str; 5] = ["remap", "overrides", "movements", "actions", "rubies"];
+
+// Bindings can also be written as { output = ..., description = "...", rate_limit_ms = ..., when = "..." }, the
+// options are split off here so the tables deserialize as usual.
+#[allow(clippy::type_complexity)]
+fn take_binding_options(value: &mut toml::Value) -> (HashMap<(String, String), String>, HashMap<(String, String), u64>, HashMap<(String, String), String>) {
+  let mut descriptions = HashMap::new();
+  let mut rate_limits = HashMap::new();
+  let mut conditions = HashMap::new();
+  for table in DESCRIBED_TABLES {
+    let Some(entries) = value.get_mut(table).and_then(toml::Value::as_table_mut) else { continue };
+    for (input, entry) in entries.iter_mut() {
+      let Some(output) = entry.get("output").cloned() else { continue };
+      if let Some(description) = entry.get("description").and_then(toml::Value::as_str) {
+        descriptions.insert((table.to_string(), input.clone()), description.to_string());
+      }
+      if let Some(rate_limit) = entry.get("rate_limit_ms").and_then(toml::Value::as_integer) {
+        rate_limits.insert((table.to_string(), input.clone()), rate_limit.max(0) as u64);
+      }
+      if let Some(condition) = entry.get("when").and_then(toml::Value::as_str) {
+        conditions.insert((table.to_string(), input.clone()), condition.to_string());
+      }
+      *entry = output;
+    }
+  }
+  (descriptions, rate_limits, conditions)
+}
+
+const TEMPLATED_TABLES: [&str; 3] = ["actions", "rubies", "settings"];
+
+// Replaces ${name} in action, script and setting values with the variable from [vars], e.g.
+// `broker = "192.168.1.10:1883"` and `KEY_F1 = "mqtt(${broker}, home/lights, on)"`.
+fn substitute_vars(value: &mut toml::Value) -> Result<(), String> {
+  let vars: HashMap<String, String> = match value.as_table_mut().and_then(|table| table.remove("vars")) {
+    Some(toml::Value::Table(vars)) => vars.into_iter()
+      .map(|(name, var)| (name, var.as_str().map(str::to_string).unwrap_or_else(|| var.to_string())))
+      .collect(),
+    Some(_) => return Err("[vars] has to be a table".to_string()),
+    None => return Ok(()),
+  };
+  for table in TEMPLATED_TABLES {
+    let Some(entries) = value.get_mut(table).and_then(toml::Value::as_table_mut) else { continue };
+    for (input, entry) in entries.iter_mut() {
+      let Some(template) = entry.as_str() else { continue };
+      let mut rendered = String::new();
+      let mut rest = template;
+      while let Some(start) = rest.find("${") {
+        let end = rest[start..].find('}').ok_or_else(|| format!("unterminated ${{ in {}.{}", table, input))? + start;
+        let name = &rest[start + 2..end];
+        let var = vars.get(name).ok_or_else(|| format!("unknown variable {} in {}.{}", name, table, input))?;
+        rendered.push_str(&rest[..start]);
+        rendered.push_str(var);
+        rest = &rest[end + 1..];
+      }
+      rendered.push_str(rest);
+      *entry = toml::Value::String(rendered);
+    }
+  }
+  Ok(())
+}
+
+impl FromStr for RawConfig {
+  type Err = toml::de::Error;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut value: toml::Value = toml::from_str(s)?;
+    let (descriptions, rate_limits, conditions) = take_binding_options(&mut value);
+    substitute_vars(&mut value).map_err(<toml::de::Error as serde::de::Error>::custom)?;
+    let mut raw_config: RawConfig = value.try_into()?;
+    raw_config.descriptions = descriptions;
+    raw_config.rate_limits = rate_limits;
+    raw_config.conditions = conditions;
+    Ok(raw_config)
+  }
 }
 
 impl RawConfig {
-  fn new_from_file(file: &str) -> Self {
+  fn new_from_file(file: &str) -> Result<Self, ConfigError> {
     println!("[Config] Parsing config file: {}", file.rsplit_once("/").unwrap().1);
 
-    let file_content: String = std::fs::read_to_string(file).unwrap();
-    let raw_config: RawConfig = toml::from_str(&file_content).expect("Couldn't parse config file.");
-    let remap = raw_config.remap;
-    let movements = raw_config.movements;
-    let settings = raw_config.settings;
-    let rubies = raw_config.rubies;
+    let file_content: String = std::fs::read_to_string(file).map_err(|e| ConfigError::Toml(e.to_string()))?;
+    RawConfig::from_str(&file_content).map_err(|e| ConfigError::Toml(e.to_string()))
+  }
+}
 
-    Self {
-      remap,
-      movements,
-      settings,
-      rubies,
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+  Toml(String),
+  UnknownEvent { binding: String, name: String },
+  InvalidMovement { binding: String, movement: String },
+  InvalidAction { binding: String, action: String },
+  InvalidTapHold { binding: String, reason: String },
+  InvalidSetting { setting: String, value: String },
+  InvalidCondition { binding: String, reason: String },
+  InvalidAxis { axis: String, reason: String },
+  InvalidChord { chord: String, reason: String },
+}
+
+impl std::fmt::Display for ConfigError {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match self {
+      ConfigError::Toml(message) => write!(f, "unable to parse: {}", message.trim_end()),
+      ConfigError::UnknownEvent { binding, name } => write!(f, "{} uses unknown key or axis {}", binding, name),
+      ConfigError::InvalidMovement { binding, movement } => write!(f, "invalid movement {} for {} in [movements]", movement, binding),
+      ConfigError::InvalidAction { binding, action } => write!(f, "invalid action {} for {} in [actions]", action, binding),
+      ConfigError::InvalidTapHold { binding, reason } => write!(f, "invalid [tap_hold] entry {}: {}", binding, reason),
+      ConfigError::InvalidSetting { setting, value } => write!(f, "invalid value {} for {}", value, setting),
+      ConfigError::InvalidCondition { binding, reason } => write!(f, "invalid when condition for {}: {}", binding, reason),
+      ConfigError::InvalidAxis { axis, reason } => write!(f, "invalid [axes] entry {}: {}", axis, reason),
+      ConfigError::InvalidChord { chord, reason } => write!(f, "invalid [chords] entry {}: {}", chord, reason),
     }
   }
 }
 
+impl std::error::Error for ConfigError {}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct BindingInfo {
+  pub table: String,
+  pub input: String,
+  pub modifiers: Vec<String>,
+  pub output: String,
+  pub description: Option<String>,
+}
+
+// Lists the bindings of a config as written, with key shorthands spelled out.
+fn describe_bindings(raw_config: &RawConfig) -> Vec<BindingInfo> {
+  let key_list = |keys: &Vec<Key>| keys.iter().map(|key| format!("{:?}", key)).collect::<Vec<String>>().join(" + ");
+  let mut entries: Vec<(&str, &String, String, Option<String>)> = Vec::new();
+  entries.extend(raw_config.remap.iter().map(|(input, keys)| ("remap", input, key_list(keys), None)));
+  entries.extend(raw_config.overrides.iter().map(|(input, keys)| ("overrides", input, key_list(keys), None)));
+  entries.extend(raw_config.movements.iter().map(|(input, output)| ("movements", input, output.clone(), None)));
+  entries.extend(raw_config.actions.iter().map(|(input, output)| ("actions", input, output.clone(), None)));
+  entries.extend(raw_config.rubies.iter().map(|(input, output)| ("rubies", input, output.clone(), None)));
+  entries.extend(raw_config.tap_hold.iter().map(|(input, tap_hold)| {
+    let side = |key: &Option<Key>, action: &Option<String>, ruby: &Option<String>| match (key, action, ruby) {
+      (Some(key), _, _) => format!("{:?}", key),
+      (None, Some(action), _) => action.clone(),
+      (None, None, Some(ruby)) => format!("ruby {}", ruby),
+      (None, None, None) => "nothing".to_string(),
+    };
+    let output = format!(
+      "tap {}, hold {}",
+      side(&tap_hold.tap, &tap_hold.tap_action, &tap_hold.tap_ruby),
+      side(&tap_hold.hold, &tap_hold.hold_action, &tap_hold.hold_ruby),
+    );
+    ("tap_hold", input, output, tap_hold.description.clone())
+  }));
+  entries.extend(raw_config.chords.iter().map(|(input, keys)| ("chords", input, key_list(keys), None)));
+
+  let mut bindings: Vec<BindingInfo> = entries.into_iter().map(|(table, raw, output, description)| {
+    let (modifiers, input) = raw.rsplit_once('-').unwrap_or(("", raw));
+    BindingInfo {
+      table: table.to_string(),
+      input: input.split('+').map(canonical_name).collect::<Vec<String>>().join("+"),
+      modifiers: modifiers.split('-').filter(|modifier| !modifier.is_empty()).map(canonical_name).collect(),
+      output,
+      description: description.or_else(|| raw_config.descriptions.get(&(table.to_string(), raw.clone())).cloned()),
+    }
+  }).collect();
+  bindings.sort_by(|a, b| (&a.input, &a.modifiers, &a.table).cmp(&(&b.input, &b.modifiers, &b.table)));
+  bindings
+}
+
 #[derive(Debug, Clone)]
 pub struct Config {
   pub name: String,
@@ -160,21 +863,134 @@ pub struct Config {
   pub bindings: Bindings,
   pub settings: HashMap<String, String>,
   pub mapped_modifiers: MappedModifiers,
+  pub hidraw: Option<Hidraw>,
+  pub merge: Option<Merge>,
+  pub axes: HashMap<u16, AxisMapping>,
+  pub binding_list: Vec<BindingInfo>,
 }
 
 impl Config {
-  pub fn new_from_file(file: &str, file_name: String) -> Self {
-    let raw_config = RawConfig::new_from_file(file);
-    let (bindings, settings, mapped_modifiers) = parse_raw_config(raw_config);
+  pub fn new_from_file(file: &str, file_name: String) -> Result<Self, ConfigError> {
+    Self::from_raw(RawConfig::new_from_file(file)?, file_name)
+  }
+
+  // Settings the file doesn't set itself are taken from default_settings.
+  pub fn new_from_file_with_defaults(file: &str, file_name: String, default_settings: &HashMap<String, String>) -> Result<Self, ConfigError> {
+    let mut raw_config = RawConfig::new_from_file(file)?;
+    for (setting, value) in default_settings {
+      raw_config.settings.entry(setting.clone()).or_insert_with(|| value.clone());
+    }
+    Self::from_raw(raw_config, file_name)
+  }
+
+  pub fn parse(content: &str, file_name: String) -> Result<Self, ConfigError> {
+    Self::from_raw(RawConfig::from_str(content).map_err(|e| ConfigError::Toml(e.to_string()))?, file_name)
+  }
+
+  fn from_raw(raw_config: RawConfig, file_name: String) -> Result<Self, ConfigError> {
+    let hidraw = raw_config.hidraw.clone().map(Hidraw::from_raw);
+    let merge = raw_config.merge.clone();
+    let axes = parse_axes(raw_config.axes.clone())?;
+    let binding_list = describe_bindings(&raw_config);
+    let (bindings, settings, mapped_modifiers) = parse_raw_config(raw_config)?;
     let associations = Default::default();
 
-    Self {
+    Ok(Self {
       name: file_name,
       associations,
       bindings,
       settings,
       mapped_modifiers,
+      hidraw,
+      merge,
+      axes,
+      binding_list,
+    })
+  }
+
+  // With INHERIT_BINDINGS, app, layout and profile configs only replace the bindings they declare and take
+  // every other one from the default config. Tap-hold keys and chords always come from the default config.
+  pub fn inherit_bindings(&mut self, default_config: &Config) {
+    fn inherit<T: Clone>(table: &mut HashMap<Event, HashMap<Vec<Event>, T>>, default_table: &HashMap<Event, HashMap<Vec<Event>, T>>, own: &Bindings) {
+      for (event, map) in default_table {
+        for (modifiers, output) in map.iter().filter(|(modifiers, _)| !own.declares(event, modifiers)) {
+          table.entry(*event).or_default().insert(modifiers.clone(), output.clone());
+        }
+      }
     }
+    let own = self.bindings.clone();
+    inherit(&mut self.bindings.remap, &default_config.bindings.remap, &own);
+    inherit(&mut self.bindings.overrides, &default_config.bindings.overrides, &own);
+    inherit(&mut self.bindings.movements, &default_config.bindings.movements, &own);
+    inherit(&mut self.bindings.actions, &default_config.bindings.actions, &own);
+    inherit(&mut self.bindings.rubies, &default_config.bindings.rubies, &own);
+    for ((event, modifiers), rate_limit) in default_config.bindings.rate_limits.iter().filter(|((event, modifiers), _)| !own.declares(event, modifiers)) {
+      self.bindings.rate_limits.insert((*event, modifiers.clone()), *rate_limit);
+    }
+    for ((event, modifiers), condition) in default_config.bindings.conditions.iter().filter(|((event, modifiers), _)| !own.declares(event, modifiers)) {
+      self.bindings.conditions.insert((*event, modifiers.clone()), condition.clone());
+    }
+
+    self.mapped_modifiers.custom.extend(default_config.mapped_modifiers.custom.clone());
+    self.mapped_modifiers.all.extend(default_config.mapped_modifiers.all.clone());
+    self.mapped_modifiers.all.sort();
+    self.mapped_modifiers.all.dedup();
+
+    let sorted = |modifiers: &Vec<String>| { let mut modifiers = modifiers.clone(); modifiers.sort(); modifiers };
+    let inherited: Vec<BindingInfo> = default_config.binding_list.iter()
+      .filter(|binding| binding.table != "tap_hold" && binding.table != "chords")
+      .filter(|binding| !self.binding_list.iter().any(|own| own.input == binding.input && sorted(&own.modifiers) == sorted(&binding.modifiers)))
+      .cloned()
+      .collect();
+    self.binding_list.extend(inherited);
+    self.binding_list.sort_by(|a, b| (&a.input, &a.modifiers, &a.table).cmp(&(&b.input, &b.modifiers, &b.table)));
+  }
+
+  // Rewrites the inputs and modifiers of the bindings, e.g. BTN_SOUTH to the button a controller actually reports
+  // there. Every event is translated at once, so swapped buttons don't translate twice.
+  pub fn translate_events(&mut self, translations: &HashMap<Event, Event>) {
+    fn translate<T>(table: &mut HashMap<Event, HashMap<Vec<Event>, T>>, translations: &HashMap<Event, Event>) {
+      *table = std::mem::take(table).into_iter()
+        .map(|(event, map)| (translated(&event, translations), map.into_iter().map(|(modifiers, output)| (translated_modifiers(&modifiers, translations), output)).collect()))
+        .collect();
+    }
+    fn translated(event: &Event, translations: &HashMap<Event, Event>) -> Event {
+      *translations.get(event).unwrap_or(event)
+    }
+    fn translated_modifiers(modifiers: &[Event], translations: &HashMap<Event, Event>) -> Vec<Event> {
+      let mut modifiers: Vec<Event> = modifiers.iter().map(|modifier| translated(modifier, translations)).collect();
+      modifiers.sort();
+      modifiers.dedup();
+      modifiers
+    }
+    if translations.is_empty() {
+      return;
+    }
+    let bindings = &mut self.bindings;
+    translate(&mut bindings.remap, translations);
+    translate(&mut bindings.overrides, translations);
+    translate(&mut bindings.movements, translations);
+    translate(&mut bindings.actions, translations);
+    translate(&mut bindings.rubies, translations);
+    bindings.tap_hold = std::mem::take(&mut bindings.tap_hold).into_iter()
+      .map(|(key, tap_hold)| match translations.get(&Event::Key(key)) {
+        Some(Event::Key(translated)) => (*translated, tap_hold),
+        _ => (key, tap_hold),
+      })
+      .collect();
+    bindings.rate_limits = std::mem::take(&mut bindings.rate_limits).into_iter()
+      .map(|((event, modifiers), rate_limit)| ((translated(&event, translations), translated_modifiers(&modifiers, translations)), rate_limit))
+      .collect();
+    bindings.conditions = std::mem::take(&mut bindings.conditions).into_iter()
+      .map(|((event, modifiers), condition)| ((translated(&event, translations), translated_modifiers(&modifiers, translations)), condition))
+      .collect();
+    bindings.chords = std::mem::take(&mut bindings.chords).into_iter()
+      .map(|(members, keys)| (translated_modifiers(&members, translations), keys))
+      .collect();
+    let modifiers = &mut self.mapped_modifiers;
+    modifiers.default = translated_modifiers(&modifiers.default, translations);
+    modifiers.custom = translated_modifiers(&modifiers.custom, translations);
+    modifiers.all = translated_modifiers(&modifiers.all, translations);
   }
 
   pub fn new_empty(file_name: String) -> Self {
@@ -184,13 +1000,19 @@ impl Config {
       bindings: Default::default(),
       settings: Default::default(),
       mapped_modifiers: Default::default(),
+      hidraw: None,
+      merge: None,
+      axes: HashMap::new(),
+      binding_list: Vec::new(),
     }
   }
 }
 
-fn parse_raw_config(raw_config: RawConfig) -> (Bindings, HashMap<String, String>, MappedModifiers) {
+fn parse_raw_config(raw_config: RawConfig) -> Result<(Bindings, HashMap<String, String>, MappedModifiers), ConfigError> {
   let remap: HashMap<String, Vec<Key>> = raw_config.remap;
+  let overrides: HashMap<String, Vec<Key>> = raw_config.overrides;
   let movements: HashMap<String, String> = raw_config.movements;
+  let actions: HashMap<String, String> = raw_config.actions;
   let settings: HashMap<String, String> = raw_config.settings;
   let rubies: HashMap<String, String> = raw_config.rubies;
   let mut bindings: Bindings = Default::default();
@@ -208,107 +1030,233 @@ fn parse_raw_config(raw_config: RawConfig) -> (Bindings, HashMap<String, String>
     custom: Vec::new(),
     all: Vec::new(),
   };
-  let custom_modifiers: Vec<Event> = parse_modifiers(&settings, "CUSTOM_MODIFIERS");
-  let lstick_activation_modifiers: Vec<Event> = parse_modifiers(&settings, "LSTICK_ACTIVATION_MODIFIERS");
-  let rstick_activation_modifiers: Vec<Event> = parse_modifiers(&settings, "RSTICK_ACTIVATION_MODIFIERS");
+  let custom_modifiers: Vec<Event> = parse_modifiers(&settings, "CUSTOM_MODIFIERS")?;
+  let lstick_activation_modifiers: Vec<Event> = parse_modifiers(&settings, "LSTICK_ACTIVATION_MODIFIERS")?;
+  let rstick_activation_modifiers: Vec<Event> = parse_modifiers(&settings, "RSTICK_ACTIVATION_MODIFIERS")?;
 
   mapped_modifiers.custom.extend(custom_modifiers);
   mapped_modifiers.custom.extend(lstick_activation_modifiers);
   mapped_modifiers.custom.extend(rstick_activation_modifiers);
 
-  for (input, output) in remap.clone() {
-    let (custom_bindings, custom_modifiers) = get_bindings_and_modifiers(&input, output, &mapped_modifiers);
-    bindings.remap.extend(custom_bindings);
+  for (input, output) in remap {
+    let custom_modifiers = insert_binding(&mut bindings.remap, &input, output, &mapped_modifiers)?;
+    mapped_modifiers.custom.extend(custom_modifiers);
+  }
+
+  for (input, output) in overrides {
+    let custom_modifiers = insert_binding(&mut bindings.overrides, &input, output, &mapped_modifiers)?;
+    mapped_modifiers.custom.extend(custom_modifiers);
+  }
+
+  for (input, output) in rubies {
+    let custom_modifiers = insert_binding(&mut bindings.rubies, &input, output, &mapped_modifiers)?;
     mapped_modifiers.custom.extend(custom_modifiers);
   }
 
-  for (input, output) in rubies.clone() {
-    let (custom_bindings, custom_modifiers) = get_bindings_and_modifiers(&input, output, &mapped_modifiers);
-    bindings.rubies.extend(custom_bindings);
+  for (input, raw_movement) in movements {
+    let output = Relative::from_str(raw_movement.as_str())
+      .map_err(|_| ConfigError::InvalidMovement { binding: input.clone(), movement: raw_movement.clone() })?;
+    let custom_modifiers = insert_binding(&mut bindings.movements, &input, output, &mapped_modifiers)?;
     mapped_modifiers.custom.extend(custom_modifiers);
   }
 
-  for (input, bad_output) in movements.clone() {
-    let output = Relative::from_str(bad_output.as_str()).expect("Invalid movement in [movements].");
-    let (custom_bindings, custom_modifiers) = get_bindings_and_modifiers(&input, output, &mapped_modifiers);
-    bindings.movements.extend(custom_bindings);
+  for (input, raw_action) in actions {
+    let output = Action::from_str(raw_action.as_str())
+      .map_err(|_| ConfigError::InvalidAction { binding: input.clone(), action: raw_action.clone() })?;
+    let custom_modifiers = insert_binding(&mut bindings.actions, &input, output, &mapped_modifiers)?;
     mapped_modifiers.custom.extend(custom_modifiers);
   }
 
+  bindings.tap_hold = parse_tap_hold(raw_config.tap_hold, &settings)?;
+  bindings.chords = parse_chords(raw_config.chords)?;
+
+  for ((_table, input), rate_limit) in raw_config.rate_limits {
+    bindings.rate_limits.insert(parse_binding(&input)?, Duration::from_millis(rate_limit));
+  }
+
+  for ((_table, input), condition) in raw_config.conditions {
+    let condition = Condition::from_str(&condition).map_err(|reason| ConfigError::InvalidCondition { binding: input.clone(), reason })?;
+    bindings.conditions.insert(parse_binding(&input)?, condition);
+  }
+
   mapped_modifiers.all.extend(mapped_modifiers.default.clone());
   mapped_modifiers.all.extend(mapped_modifiers.custom.clone());
   mapped_modifiers.all.sort();
   mapped_modifiers.all.dedup();
 
-  (bindings, settings, mapped_modifiers)
+  Ok((bindings, settings, mapped_modifiers))
 }
 
-pub fn parse_modifiers(settings: &HashMap<String, String>, parameter: &str) -> Vec<Event> {
-  match settings.get(&parameter.to_string()) {
-    Some(modifiers) => {
-      let mut custom_modifiers = Vec::new();
-      let split_modifiers = modifiers.split("-").collect::<Vec<&str>>();
-      for modifier in split_modifiers {
-        if let Ok(key) = Key::from_str(modifier) {
-          custom_modifiers.push(Event::Key(key));
-        } else if let Ok(axis) = Axis::from_str(modifier) {
-          custom_modifiers.push(Event::Axis(axis));
-        } else {
-          println!("[Config] Invalid value used as modifier in {}, ignoring.", parameter);
-        }
-      }
-      custom_modifiers
-    }
-    None => Vec::new(),
-  }
+const AUTOSHIFT_LETTERS: [Key; 26] = [
+  Key::KEY_A, Key::KEY_B, Key::KEY_C, Key::KEY_D, Key::KEY_E, Key::KEY_F, Key::KEY_G, Key::KEY_H, Key::KEY_I,
+  Key::KEY_J, Key::KEY_K, Key::KEY_L, Key::KEY_M, Key::KEY_N, Key::KEY_O, Key::KEY_P, Key::KEY_Q, Key::KEY_R,
+  Key::KEY_S, Key::KEY_T, Key::KEY_U, Key::KEY_V, Key::KEY_W, Key::KEY_X, Key::KEY_Y, Key::KEY_Z,
+];
+
+const AUTOSHIFT_NUMBERS: [Key; 10] = [
+  Key::KEY_1, Key::KEY_2, Key::KEY_3, Key::KEY_4, Key::KEY_5, Key::KEY_6, Key::KEY_7, Key::KEY_8, Key::KEY_9, Key::KEY_0,
+];
+
+const AUTOSHIFT_SYMBOLS: [Key; 11] = [
+  Key::KEY_MINUS, Key::KEY_EQUAL, Key::KEY_LEFTBRACE, Key::KEY_RIGHTBRACE, Key::KEY_SEMICOLON, Key::KEY_APOSTROPHE,
+  Key::KEY_GRAVE, Key::KEY_BACKSLASH, Key::KEY_COMMA, Key::KEY_DOT, Key::KEY_SLASH,
+];
+
+fn invalid_setting(setting: &str, value: &str) -> ConfigError {
+  ConfigError::InvalidSetting { setting: setting.to_string(), value: value.to_string() }
 }
 
-fn get_bindings_and_modifiers<T>(input: &String, output: T, mapped_modifiers: &MappedModifiers) -> (HashMap<Event, HashMap<Vec<Event>, T>>, Vec<Event>) {
-  if let Some((mods, event_string)) = input.rsplit_once("-") {
-    let (modifiers, custom_modifiers) = get_multi_modifiers(mods, &mapped_modifiers);
-    (get_bindings(modifiers, event_string, output), custom_modifiers)
-  } else {
-    (get_bindings(Vec::new(), input.as_str(), output), Vec::new())
+// "BTN_SELECT+BTN_START" = ["KEY_ESC"] presses KEY_ESC when both buttons are pressed together.
+fn parse_chords(raw_chords: HashMap<String, Vec<Key>>) -> Result<HashMap<Vec<Event>, Vec<Key>>, ConfigError> {
+  let mut chords = HashMap::new();
+  for (input, keys) in raw_chords {
+    let mut members = input.split('+')
+      .map(|name| parse_event(name.trim()).ok_or_else(|| ConfigError::UnknownEvent { binding: input.clone(), name: name.trim().to_string() }))
+      .collect::<Result<Vec<Event>, ConfigError>>()?;
+    members.sort();
+    members.dedup();
+    if members.len() < 2 {
+      return Err(ConfigError::InvalidChord { chord: input, reason: "a chord needs two or more different inputs".to_string() });
+    }
+    if keys.is_empty() {
+      return Err(ConfigError::InvalidChord { chord: input, reason: "no output keys".to_string() });
+    }
+    chords.insert(members, keys);
   }
+  Ok(chords)
 }
 
-fn get_multi_modifiers(mods: &str, mapped_modifiers: &MappedModifiers) -> (Vec<Event>, Vec<Event>) {
-  let mut custom_modifiers: Vec<Event> = Vec::new();
-  let str_modifiers = mods.split("-").collect::<Vec<&str>>();
-  let mut modifiers: Vec<Event> = Vec::new();
+fn parse_tap_hold(raw_tap_hold: HashMap<String, RawTapHold>, settings: &HashMap<String, String>) -> Result<HashMap<Key, TapHold>, ConfigError> {
+  let raw_tapping_term = settings.get("TAPPING_TERM").map(String::as_str).unwrap_or("200");
+  let tapping_term: u64 = raw_tapping_term.parse().map_err(|_| invalid_setting("TAPPING_TERM", raw_tapping_term))?;
+  let raw_strategy = settings.get("TAP_HOLD_STRATEGY").map(String::as_str).unwrap_or("permissive_hold");
+  let strategy = TapHoldStrategy::from_str(raw_strategy).map_err(|_| invalid_setting("TAP_HOLD_STRATEGY", raw_strategy))?;
+  let mut tap_hold: HashMap<Key, TapHold> = HashMap::new();
+
+  let autoshift_groups = [
+    ("AUTOSHIFT_LETTERS", AUTOSHIFT_LETTERS.as_slice()),
+    ("AUTOSHIFT_NUMBERS", AUTOSHIFT_NUMBERS.as_slice()),
+    ("AUTOSHIFT_SYMBOLS", AUTOSHIFT_SYMBOLS.as_slice()),
+  ];
+  for (setting, keys) in autoshift_groups {
+    if let Some(timeout) = settings.get(setting) {
+      let timeout: u64 = timeout.parse().map_err(|_| invalid_setting(setting, timeout))?;
+      for &key in keys {
+        tap_hold.insert(key, TapHold {
+          tap: key,
+          hold: vec![Key::KEY_LEFTSHIFT, key],
+          tap_command: None,
+          hold_command: None,
+          tapping_term: Duration::from_millis(timeout),
+          strategy: TapHoldStrategy::TapOnOtherKeyPress,
+        });
+      }
+    }
+  }
 
-  for event in str_modifiers.clone() {
-    if let Ok(axis) = Axis::from_str(event) {
-      modifiers.push(Event::Axis(axis));
-    } else if let Ok(key) = Key::from_str(event) {
-      modifiers.push(Event::Key(key));
+  let home_row_mods = settings.get("HOME_ROW_MODS").map(String::as_str).unwrap_or("false");
+  if home_row_mods.parse().map_err(|_| invalid_setting("HOME_ROW_MODS", home_row_mods))? {
+    let home_row = [
+      (Key::KEY_A, Key::KEY_LEFTMETA),
+      (Key::KEY_S, Key::KEY_LEFTALT),
+      (Key::KEY_D, Key::KEY_LEFTCTRL),
+      (Key::KEY_F, Key::KEY_LEFTSHIFT),
+      (Key::KEY_J, Key::KEY_RIGHTSHIFT),
+      (Key::KEY_K, Key::KEY_RIGHTCTRL),
+      (Key::KEY_L, Key::KEY_LEFTALT),
+      (Key::KEY_SEMICOLON, Key::KEY_RIGHTMETA),
+    ];
+    for (key, hold) in home_row {
+      tap_hold.insert(key, TapHold { tap: key, hold: vec![hold], tap_command: None, hold_command: None, tapping_term: Duration::from_millis(tapping_term), strategy });
     }
   }
 
-  for modifier in &modifiers {
-    if !mapped_modifiers.default.contains(&modifier) { custom_modifiers.push(modifier.clone()) }
+  for (input, raw) in raw_tap_hold {
+    let key = parse_key(&input).ok_or_else(|| ConfigError::UnknownEvent { binding: input.clone(), name: input.clone() })?;
+    let strategy = match raw.strategy {
+      Some(raw_strategy) => TapHoldStrategy::from_str(&raw_strategy)
+        .map_err(|_| ConfigError::InvalidTapHold { binding: input.clone(), reason: format!("unknown strategy {}", raw_strategy) })?,
+      None => strategy,
+    };
+    // Each side either presses a key, runs an action or runs a Ruby script.
+    let side = |name: &str, key: Option<Key>, action: &Option<String>, ruby: &Option<String>| match (key, action, ruby) {
+      (Some(key), None, None) => Ok((Some(key), None)),
+      (None, Some(action), None) => Action::from_str(action)
+        .map(|action| (None, Some(TapHoldCommand::Action(action))))
+        .map_err(|_| ConfigError::InvalidTapHold { binding: input.clone(), reason: format!("invalid {}_action {}", name, action) }),
+      (None, None, Some(ruby)) => Ok((None, Some(TapHoldCommand::Ruby(ruby.clone())))),
+      _ => Err(ConfigError::InvalidTapHold { binding: input.clone(), reason: format!("set one of {0}, {0}_action and {0}_ruby", name) }),
+    };
+    let (tap, tap_command) = side("tap", raw.tap, &raw.tap_action, &raw.tap_ruby)?;
+    let (hold, hold_command) = side("hold", raw.hold, &raw.hold_action, &raw.hold_ruby)?;
+    tap_hold.insert(key, TapHold {
+      tap: tap.unwrap_or(key),
+      hold: hold.into_iter().collect(),
+      tap_command,
+      hold_command,
+      tapping_term: Duration::from_millis(raw.tapping_term.unwrap_or(tapping_term)),
+      strategy,
+    });
   }
-  if str_modifiers[0] == "" { modifiers.push(Event::Hold); }
 
-  (modifiers, custom_modifiers)
+  Ok(tap_hold)
 }
 
-fn get_bindings<T>(modifiers: Vec<Event>, event_string: &str, output: T) -> HashMap<Event, HashMap<Vec<Event>, T>> {
-  let mut bindings: HashMap<Event, HashMap<Vec<Event>, T>> = HashMap::new();
+pub fn parse_modifiers(settings: &HashMap<String, String>, parameter: &str) -> Result<Vec<Event>, ConfigError> {
+  match settings.get(&parameter.to_string()) {
+    Some(modifiers) => modifiers.split("-")
+      .map(|modifier| {
+        parse_key(modifier).map(Event::Key)
+          .or_else(|| Axis::from_str(modifier).ok().map(Event::Axis))
+          .ok_or_else(|| invalid_setting(parameter, modifiers))
+      })
+      .collect(),
+    None => Ok(Vec::new()),
+  }
+}
 
-  if let Ok(event) = Axis::from_str(event_string) { // TODO: refactor
-    if !bindings.contains_key(&Event::Axis(event)) {
-      bindings.insert(Event::Axis(event), HashMap::from([(modifiers, output)]));
-    } else {
-      bindings.get_mut(&Event::Axis(event)).unwrap().insert(modifiers, output);
-    }
-  } else if let Ok(event) = Key::from_str(event_string) {
-    if !bindings.contains_key(&Event::Key(event)) {
-      bindings.insert(Event::Key(event), HashMap::from([(modifiers, output)]));
-    } else {
-      bindings.get_mut(&Event::Key(event)).unwrap().insert(modifiers, output);
-    }
+fn parse_event(name: &str) -> Option<Event> {
+  match Axis::from_str(name) {
+    Ok(axis) => Some(Event::Axis(axis)),
+    Err(_) => parse_key(name).map(Event::Key),
+  }
+}
+
+// Splits "MODIFIER1-MODIFIER2-KEY" into the event and its modifiers, sorted like the held modifiers are at runtime.
+// A leading "-" marks a chained binding, which is looked up with Event::Hold.
+pub fn parse_binding(input: &str) -> Result<(Event, Vec<Event>), ConfigError> {
+  let unknown = |name: &str| ConfigError::UnknownEvent { binding: input.to_string(), name: name.to_string() };
+  // "-" is also the minus key, e.g. "ctrl--", or "--" held.
+  let (mods, event_string) = match input.strip_suffix("--") {
+    Some(mods) => (mods, "-"),
+    None if input == "-" => ("", "-"),
+    None => input.rsplit_once("-").unwrap_or(("", input)),
   };
+  let event = parse_event(event_string).ok_or_else(|| unknown(event_string))?;
 
-  bindings
+  let mut modifiers = mods.split("-")
+    .filter(|modifier| !modifier.is_empty())
+    .map(|modifier| parse_event(modifier).ok_or_else(|| unknown(modifier)))
+    .collect::<Result<Vec<Event>, ConfigError>>()?;
+  modifiers.sort();
+  modifiers.dedup();
+  if input.starts_with("-") && input != "-" { modifiers.push(Event::Hold); }
+
+  Ok((event, modifiers))
+}
+
+// Adds the binding next to the others of the same event and returns the modifiers that aren't default ones.
+fn insert_binding<T>(
+  bindings: &mut HashMap<Event, HashMap<Vec<Event>, T>>,
+  input: &str,
+  output: T,
+  mapped_modifiers: &MappedModifiers,
+) -> Result<Vec<Event>, ConfigError> {
+  let (event, modifiers) = parse_binding(input)?;
+  let custom_modifiers = modifiers.iter()
+    .filter(|modifier| **modifier != Event::Hold && !mapped_modifiers.default.contains(modifier))
+    .cloned()
+    .collect();
+  bindings.entry(event).or_default().insert(modifiers, output);
+  Ok(custom_modifiers)
 }
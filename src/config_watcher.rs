@@ -0,0 +1,93 @@
+use crate::reload;
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, WatchDescriptor};
+use std::{collections::HashMap, fs, path::{Path, PathBuf}, thread};
+use tokio::sync::mpsc;
+use tokio::time::{Duration, Instant};
+
+// Editors save in several steps, e.g. writing a temporary file and moving it over the config, and a change often
+// touches several files, so the reload waits for the directory to settle.
+const SETTLE_TIME: Duration = Duration::from_millis(300);
+
+// Reloads the configs, like makitactl reload, whenever a .toml file of the config directory or of its subdirectories
+// is written, created, moved or deleted.
+pub async fn run(config_directory: String) {
+  let inotify = match Inotify::init(InitFlags::IN_CLOEXEC) {
+    Ok(inotify) => inotify,
+    Err(e) => {
+      eprintln!("[ConfigWatcher] Unable to watch {}, reload the configs with makitactl reload instead: {}", config_directory, e);
+      return;
+    }
+  };
+  let (sender, mut changes) = mpsc::unbounded_channel();
+  thread::spawn(move || watch(inotify, Path::new(&config_directory), sender));
+
+  while changes.recv().await.is_some() {
+    let mut deadline = Instant::now() + SETTLE_TIME;
+    loop {
+      tokio::select! {
+        change = changes.recv() => match change {
+          Some(()) => deadline = Instant::now() + SETTLE_TIME,
+          None => return,
+        },
+        _ = tokio::time::sleep_until(deadline) => break,
+      }
+    }
+    println!("[ConfigWatcher] Config files changed, reloading...");
+    let _ = reload::reload();
+  }
+}
+
+fn watch(inotify: Inotify, config_directory: &Path, sender: mpsc::UnboundedSender<()>) {
+  let mut directories: HashMap<WatchDescriptor, PathBuf> = HashMap::new();
+  add_watches(&inotify, config_directory, &mut directories);
+  println!("[ConfigWatcher] Watching {} for changes.", config_directory.display());
+  loop {
+    let events = match inotify.read_events() {
+      Ok(events) => events,
+      Err(e) => {
+        eprintln!("[ConfigWatcher] Stopped watching {}: {}", config_directory.display(), e);
+        return;
+      }
+    };
+    let mut changed = false;
+    for event in events {
+      if event.mask.contains(AddWatchFlags::IN_IGNORED) {
+        directories.remove(&event.wd);
+        continue;
+      }
+      let Some(name) = event.name.map(|name| name.to_string_lossy().to_string()) else { continue };
+      // Dotfiles and dot directories aren't configs, and editors keep their swap files there.
+      if name.starts_with('.') { continue; }
+      let directory = event.mask.contains(AddWatchFlags::IN_ISDIR);
+      if directory && event.mask.intersects(AddWatchFlags::IN_CREATE | AddWatchFlags::IN_MOVED_TO) {
+        if let Some(parent) = directories.get(&event.wd).cloned() {
+          add_watches(&inotify, &parent.join(&name), &mut directories);
+        }
+      }
+      changed |= directory || name.ends_with(".toml");
+    }
+    if changed && sender.send(()).is_err() {
+      return;
+    }
+  }
+}
+
+// Watches the directory and its subdirectories, but for the dot ones, like reload::config_files reads them.
+fn add_watches(inotify: &Inotify, directory: &Path, directories: &mut HashMap<WatchDescriptor, PathBuf>) {
+  let flags = AddWatchFlags::IN_CLOSE_WRITE | AddWatchFlags::IN_CREATE | AddWatchFlags::IN_DELETE
+    | AddWatchFlags::IN_MOVED_FROM | AddWatchFlags::IN_MOVED_TO;
+  match inotify.add_watch(directory, flags) {
+    Ok(descriptor) => {
+      directories.insert(descriptor, directory.to_path_buf());
+    }
+    Err(e) => {
+      eprintln!("[ConfigWatcher] Unable to watch {}: {}", directory.display(), e);
+      return;
+    }
+  }
+  for entry in fs::read_dir(directory).into_iter().flatten().flatten() {
+    if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) && !entry.file_name().to_string_lossy().starts_with('.') {
+      add_watches(inotify, &entry.path(), directories);
+    }
+  }
+}
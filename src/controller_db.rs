@@ -0,0 +1,155 @@
+use crate::config::{Axis, Event};
+use evdev::{AttributeSetRef, Device, Key};
+use std::{collections::HashMap, env, fs, path::PathBuf, str::FromStr, sync::Mutex};
+
+pub const DATABASE_FILE: &str = "gamecontrollerdb.txt";
+
+lazy_static::lazy_static! {
+  static ref MAPPINGS: Mutex<HashMap<String, Mapping>> = Mutex::new(HashMap::new());
+}
+
+// SDL's names for the buttons of a standard controller, and the events bindings use for them.
+const ELEMENTS: [(&str, Event); 17] = [
+  ("a", Event::Key(Key::BTN_SOUTH)),
+  ("b", Event::Key(Key::BTN_EAST)),
+  ("x", Event::Key(Key::BTN_WEST)),
+  ("y", Event::Key(Key::BTN_NORTH)),
+  ("back", Event::Key(Key::BTN_SELECT)),
+  ("start", Event::Key(Key::BTN_START)),
+  ("guide", Event::Key(Key::BTN_MODE)),
+  ("leftshoulder", Event::Key(Key::BTN_TL)),
+  ("rightshoulder", Event::Key(Key::BTN_TR)),
+  ("leftstick", Event::Key(Key::BTN_THUMBL)),
+  ("rightstick", Event::Key(Key::BTN_THUMBR)),
+  ("lefttrigger", Event::Axis(Axis::BTN_TL2)),
+  ("righttrigger", Event::Axis(Axis::BTN_TR2)),
+  ("dpup", Event::Axis(Axis::BTN_DPAD_UP)),
+  ("dpdown", Event::Axis(Axis::BTN_DPAD_DOWN)),
+  ("dpleft", Event::Axis(Axis::BTN_DPAD_LEFT)),
+  ("dpright", Event::Axis(Axis::BTN_DPAD_RIGHT)),
+];
+
+// A line of the SDL game controller database, e.g.
+// "030000005e0400008e02000014010000,Xbox 360 Controller,a:b0,b:b1,x:b2,y:b3,...,platform:Linux,".
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mapping {
+  pub guid: String,
+  pub name: String,
+  elements: HashMap<String, String>,
+}
+
+impl FromStr for Mapping {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut fields = s.trim().split(',');
+    let guid = fields.next().filter(|guid| guid.len() == 32 && guid.chars().all(|c| c.is_ascii_hexdigit()))
+      .ok_or_else(|| format!("invalid GUID in {}", s))?;
+    let name = fields.next().ok_or_else(|| format!("no name in {}", s))?;
+    let elements = fields.filter_map(|field| field.split_once(':'))
+      .map(|(element, value)| (element.to_string(), value.to_string()))
+      .collect();
+    Ok(Self { guid: normalize_guid(guid), name: name.to_string(), elements })
+  }
+}
+
+impl Mapping {
+  // What the standard buttons are on a controller reporting these keys. SDL numbers buttons in the order of their
+  // codes, starting from BTN_TRIGGER and wrapping around to the codes below it. Only buttons are translated, axes and
+  // hats are read as standard ones already.
+  pub fn translations(&self, keys: &AttributeSetRef<Key>) -> HashMap<Event, Event> {
+    let (joystick, other): (Vec<Key>, Vec<Key>) = keys.iter().partition(|key| key.code() >= Key::BTN_TRIGGER.code());
+    let buttons: Vec<Key> = joystick.into_iter().chain(other).collect();
+    ELEMENTS.iter()
+      .filter_map(|(element, event)| {
+        let index = self.elements.get(*element)?.strip_prefix('b')?.parse::<usize>().ok()?;
+        Some((*event, Event::Key(*buttons.get(index)?)))
+      })
+      .filter(|(event, button)| event != button)
+      .collect()
+  }
+}
+
+// SDL 2.26 and later put a CRC of the name in bytes 2 and 3, which older entries don't have.
+fn normalize_guid(guid: &str) -> String {
+  let guid = guid.to_lowercase();
+  format!("{}0000{}", &guid[..4], &guid[8..])
+}
+
+// The GUID SDL gives a Linux device: its bus type, vendor, product and version as little-endian 16-bit words.
+pub fn guid(device: &Device) -> String {
+  let id = device.input_id();
+  let word = |value: u16| format!("{:02x}{:02x}0000", value & 0xff, value >> 8);
+  format!("{}{}{}{}", word(id.bus_type().0), word(id.vendor()), word(id.product()), word(id.version()))
+}
+
+// Reads gamecontrollerdb.txt from the config directory and the mappings in SDL_GAMECONTROLLERCONFIG, which take
+// precedence like they do in SDL.
+pub fn init(config_directory: &str) {
+  let path = PathBuf::from(config_directory).join(DATABASE_FILE);
+  let mut sources: Vec<String> = fs::read_to_string(&path).into_iter().collect();
+  sources.extend(env::var("SDL_GAMECONTROLLERCONFIG"));
+  let mut mappings = MAPPINGS.lock().unwrap();
+  for line in sources.iter().flat_map(|source| source.lines()) {
+    if line.trim().is_empty() || line.starts_with('#') {
+      continue;
+    }
+    match Mapping::from_str(line) {
+      Ok(mapping) if mapping.elements.get("platform").is_none_or(|platform| platform == "Linux") => {
+        mappings.insert(mapping.guid.clone(), mapping);
+      }
+      Ok(_) => (),
+      Err(e) => eprintln!("[ControllerDb] Skipping a mapping: {}", e),
+    }
+  }
+  if !mappings.is_empty() {
+    println!("[ControllerDb] Loaded {} controller mappings.", mappings.len());
+  }
+}
+
+// The events the bindings of the device are translated with, empty when the database doesn't know it. Entries
+// without a version match every version of the controller.
+pub fn device_translations(device: &Device) -> HashMap<Event, Event> {
+  let guid = guid(device);
+  let mappings = MAPPINGS.lock().unwrap();
+  let mapping = mappings.get(&guid).or_else(|| mappings.get(&format!("{}00000000", &guid[..24])));
+  match (mapping, device.supported_keys()) {
+    (Some(mapping), Some(keys)) => {
+      println!("[ControllerDb] Using the SDL mapping of {} for {}.", mapping.name, device.name().unwrap_or_default());
+      mapping.translations(keys)
+    }
+    _ => HashMap::new(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::config::Config;
+  use evdev::AttributeSet;
+
+  #[test]
+  fn translates_standard_buttons_with_sdl_mappings() {
+    // A generic USB gamepad whose face buttons come as BTN_TRIGGER, BTN_THUMB, BTN_THUMB2 and BTN_TOP.
+    let mapping: Mapping = "03000000790000000600000010010000,Generic USB Joystick,a:b2,b:b1,x:b3,y:b0,back:b8,start:b9,\
+      leftshoulder:b4,rightshoulder:b5,dpup:h0.1,lefttrigger:b6,leftx:a0,platform:Linux,".parse().unwrap();
+    assert_eq!(mapping.name, "Generic USB Joystick");
+    let mut keys = AttributeSet::new();
+    for key in [Key::BTN_TRIGGER, Key::BTN_THUMB, Key::BTN_THUMB2, Key::BTN_TOP, Key::BTN_TOP2, Key::BTN_PINKIE, Key::BTN_BASE, Key::BTN_BASE2, Key::BTN_BASE3, Key::BTN_BASE4] {
+      keys.insert(key);
+    }
+    let translations = mapping.translations(&keys);
+    assert_eq!(translations[&Event::Key(Key::BTN_SOUTH)], Event::Key(Key::BTN_THUMB2));
+    assert_eq!(translations[&Event::Key(Key::BTN_NORTH)], Event::Key(Key::BTN_TRIGGER));
+    assert_eq!(translations[&Event::Axis(Axis::BTN_TL2)], Event::Key(Key::BTN_BASE));
+    assert!(!translations.contains_key(&Event::Axis(Axis::BTN_DPAD_UP)));
+
+    let mut config = Config::parse("[remap]\nBTN_SOUTH = [\"KEY_A\"]\nBTN_TL-BTN_NORTH = [\"KEY_B\"]\nBTN_TL2 = [\"KEY_C\"]\n", "Test Device".to_string()).unwrap();
+    config.translate_events(&translations);
+    let remap = &config.bindings.remap;
+    assert_eq!(remap[&Event::Key(Key::BTN_THUMB2)][&vec![]], vec![Key::KEY_A]);
+    assert_eq!(remap[&Event::Key(Key::BTN_TRIGGER)][&vec![Event::Key(Key::BTN_TOP2)]], vec![Key::KEY_B]);
+    assert_eq!(remap[&Event::Key(Key::BTN_BASE)][&vec![]], vec![Key::KEY_C]);
+    assert!(config.mapped_modifiers.custom.contains(&Event::Key(Key::BTN_TOP2)));
+  }
+}
@@ -0,0 +1,257 @@
+// `makita doctor` checks what Makita needs to remap anything, without touching any device, so that a setup where it
+// starts but silently does nothing shows what's missing, with how to fix it.
+use crate::check;
+use crate::config::Config;
+use crate::reload;
+use crate::ruby_runtime;
+use crate::udev_monitor::{configured_devices, SUPPORTED_COMPOSITORS};
+use nix::unistd::{access, getegid, geteuid, getgroups, AccessFlags, Gid, Group, User};
+use std::{env, fs, io, os::unix::fs::MetadataExt, path::PathBuf, process::Command};
+
+const UINPUT: &str = "/dev/uinput";
+const INPUT_DIRECTORY: &str = "/dev/input";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+  Pass,
+  Warn,
+  Fail,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+  pub check: &'static str,
+  pub status: Status,
+  // What was found, then how to fix it.
+  pub lines: Vec<String>,
+}
+
+impl Report {
+  fn new(check: &'static str, status: Status, line: impl Into<String>) -> Self {
+    Self { check, status, lines: vec![line.into()] }
+  }
+
+  fn line(mut self, line: impl Into<String>) -> Self {
+    self.lines.push(line.into());
+    self
+  }
+}
+
+pub fn run(config_directory: &str, ruby_scripts_directory: &str) {
+  let mut reports: Vec<Report> = Vec::new();
+  let mut report = |report: Report| {
+    let status = match report.status {
+      Status::Pass => "PASS",
+      Status::Warn => "WARN",
+      Status::Fail => "FAIL",
+    };
+    println!("[{}] {}: {}", status, report.check, report.lines[0]);
+    for line in &report.lines[1..] {
+      println!("       {}", line);
+    }
+    reports.push(report);
+  };
+
+  // Parsing logs each file, so before the report starts.
+  let configs = reload::load_configs(config_directory).map(|(configs, _)| configs).unwrap_or_default();
+  report(config_files(config_directory));
+  report(uinput(&configs));
+  report(input_devices(&configs));
+  report(groups());
+  let kdotool = Command::new("kdotool").output().is_ok();
+  report(compositor(|name| env::var(name).ok(), kdotool));
+  // Last, since a broken Ruby installation may take the process down with it.
+  report(ruby(&configs, ruby_scripts_directory));
+
+  let count = |status: Status| reports.iter().filter(|report| report.status == status).count();
+  println!("{} passed, {} warning(s), {} failed.", count(Status::Pass), count(Status::Warn), count(Status::Fail));
+  if count(Status::Fail) > 0 {
+    std::process::exit(1);
+  }
+}
+
+fn config_files(config_directory: &str) -> Report {
+  const CHECK: &str = "Config files";
+  match check::check_directory(config_directory) {
+    Err(e) => Report::new(CHECK, Status::Fail, format!("unable to read {}: {}", config_directory, e))
+      .line("Create it, or point MAKITA_CONFIG to your config directory."),
+    Ok((0, _)) => Report::new(CHECK, Status::Fail, format!("no config files in {}", config_directory))
+      .line("Name a config file after the device it's for, makita list-devices shows their names."),
+    Ok((files, errors)) if !errors.is_empty() => {
+      let report = Report::new(CHECK, Status::Fail, format!("{} error(s) in {} config file(s)", errors.len(), files));
+      errors.into_iter().fold(report, Report::line)
+        .line("Makita skips the config files that have errors.")
+    }
+    Ok((files, _)) => Report::new(CHECK, Status::Pass, format!("{} config file(s) in {} without errors", files, config_directory)),
+  }
+}
+
+fn uinput(configs: &[Config]) -> Report {
+  const CHECK: &str = "uinput";
+  // Like when Makita starts: without configs that emit through uinput, only tablets and gamepads need it.
+  let wayland_only = !configs.is_empty() && configs.iter()
+    .filter(|config| !config.name.contains("::"))
+    .all(|config| config.settings.get("OUTPUT_BACKEND").is_some_and(|backend| backend == "wayland"));
+  let report = match fs::OpenOptions::new().write(true).open(UINPUT) {
+    Ok(_) => return Report::new(CHECK, Status::Pass, format!("{} can be written to", UINPUT)),
+    Err(e) if e.kind() == io::ErrorKind::NotFound => Report::new(CHECK, Status::Fail, format!("{} doesn't exist", UINPUT))
+      .line("Load the uinput module with `sudo modprobe uinput`, and write uinput into /etc/modules-load.d/uinput.conf to load it on boot."),
+    Err(e) if e.kind() == io::ErrorKind::PermissionDenied => Report::new(CHECK, Status::Fail, format!("{} can't be written to", UINPUT))
+      .line("Copy 50-makita.rules into /etc/udev/rules.d/, which gives it to the input group, and join that group, or run Makita as root."),
+    Err(e) => Report::new(CHECK, Status::Fail, format!("{} can't be opened: {}", UINPUT, e)),
+  };
+  match wayland_only {
+    true => Report { status: Status::Warn, ..report }
+      .line("Every config emits through the compositor with OUTPUT_BACKEND, so only tablet and gamepad output is lost."),
+    false => report,
+  }
+}
+
+fn event_devices() -> Vec<PathBuf> {
+  let mut devices: Vec<PathBuf> = fs::read_dir(INPUT_DIRECTORY).into_iter().flatten().flatten()
+    .filter(|entry| entry.file_name().to_string_lossy().starts_with("event"))
+    .map(|entry| entry.path())
+    .collect();
+  devices.sort();
+  devices
+}
+
+fn input_devices(configs: &[Config]) -> Report {
+  const CHECK: &str = "Input devices";
+  let devices = event_devices();
+  let readable = devices.iter().filter(|path| fs::File::open(path).is_ok()).count();
+  if devices.is_empty() {
+    return Report::new(CHECK, Status::Fail, format!("no event devices in {}", INPUT_DIRECTORY));
+  }
+  if readable == 0 {
+    return Report::new(CHECK, Status::Fail, format!("none of the {} event devices in {} can be read", devices.len(), INPUT_DIRECTORY))
+      .line("Join the group that owns them, see Group membership below, or run Makita as root.");
+  }
+
+  let names: Vec<String> = evdev::enumerate().filter_map(|(_, device)| device.name().map(|name| name.replace('/', ""))).collect();
+  let missing: Vec<String> = configured_devices(configs).into_iter().filter(|device| !names.contains(device)).collect();
+  let found = match readable == devices.len() {
+    true => format!("the {} event devices in {} can be read", devices.len(), INPUT_DIRECTORY),
+    false => format!("{} of the {} event devices in {} can be read", readable, devices.len(), INPUT_DIRECTORY),
+  };
+  if missing.is_empty() {
+    let status = if readable == devices.len() { Status::Pass } else { Status::Warn };
+    return Report::new(CHECK, status, format!("{}, every device that has a config is connected", found));
+  }
+  let report = Report::new(CHECK, Status::Warn, format!("{}, but no device among them is named", found));
+  missing.into_iter().fold(report, |report, device| report.line(format!("- {}", device)))
+    .line("Config files are named after their device as makita list-devices shows it. Set WAIT_FOR_DEVICE for devices that connect later.")
+}
+
+fn groups() -> Report {
+  const CHECK: &str = "Group membership";
+  if geteuid().is_root() {
+    return Report::new(CHECK, Status::Pass, "running as root");
+  }
+  let user = User::from_uid(geteuid()).ok().flatten().map_or_else(|| geteuid().to_string(), |user| user.name);
+  let mut groups = getgroups().unwrap_or_default();
+  groups.push(getegid());
+
+  // The groups of the devices the user can't access, access that a uaccess ACL may give without any group.
+  let mut needed: Vec<(Gid, PathBuf)> = Vec::new();
+  let nodes = std::iter::once((PathBuf::from(UINPUT), AccessFlags::W_OK))
+    .chain(event_devices().into_iter().map(|path| (path, AccessFlags::R_OK)));
+  for (path, flags) in nodes {
+    let Ok(metadata) = fs::metadata(&path) else { continue };
+    let gid = Gid::from_raw(metadata.gid());
+    if access(&path, flags).is_err() && gid.as_raw() != 0 && !needed.iter().any(|(needed, _)| *needed == gid) {
+      needed.push((gid, path));
+    }
+  }
+  if needed.is_empty() {
+    return Report::new(CHECK, Status::Pass, format!("{} can access {} and the devices in {}", user, UINPUT, INPUT_DIRECTORY));
+  }
+
+  let mut report = Report::new(CHECK, Status::Fail, format!("{} is missing groups to access the devices", user));
+  for (gid, path) in needed {
+    let group = Group::from_gid(gid).ok().flatten();
+    let name = group.as_ref().map_or_else(|| gid.to_string(), |group| group.name.clone());
+    report = match (groups.contains(&gid), group.is_some_and(|group| group.mem.contains(&user))) {
+      (true, _) => report.line(format!("{} can't access {} though it's in {}, check its permissions.", user, path.display(), name)),
+      (false, true) => report.line(format!("{} was added to {}, which owns {}, after this session started: log out and back in.", user, name, path.display())),
+      (false, false) => report.line(format!("{} owns {}: run `sudo usermod -aG {} {}`, then log out and back in.", name, path.display(), name, user)),
+    };
+  }
+  report
+}
+
+// Makita follows the active window for per application bindings on these, see udev_monitor. The session is read
+// through `var`, which looks an environment variable up.
+fn compositor(var: impl Fn(&str) -> Option<String>, kdotool: bool) -> Report {
+  const CHECK: &str = "Compositor";
+  const SUPPORTED: &str = "Per application bindings work on Hyprland, Sway, Niri, Plasma/KWin and X11.";
+  let session_type = var("XDG_SESSION_TYPE").or_else(|| var("WAYLAND_DISPLAY").map(|_| "wayland".to_string()));
+  let desktop = var("XDG_CURRENT_DESKTOP");
+  match (session_type.as_deref(), desktop.as_deref()) {
+    (Some("wayland"), Some("KDE")) if !kdotool => Report::new(CHECK, Status::Warn, "running on KDE, but kdotool isn't installed")
+      .line("Install kdotool for per application bindings to work."),
+    (Some("wayland"), Some(desktop)) if SUPPORTED_COMPOSITORS.contains(&desktop) => {
+      Report::new(CHECK, Status::Pass, format!("running on {}, per application bindings work", desktop))
+    }
+    (Some("wayland"), Some(desktop)) => Report::new(CHECK, Status::Warn, format!("{} isn't supported, per application bindings won't work", desktop))
+      .line(SUPPORTED),
+    (Some("wayland"), None) => Report::new(CHECK, Status::Warn, "XDG_CURRENT_DESKTOP isn't set, per application bindings won't work")
+      .line(SUPPORTED),
+    (Some("x11"), _) => Report::new(CHECK, Status::Pass, "running on X11, per application bindings work"),
+    (Some(session_type), _) => Report::new(CHECK, Status::Warn, format!("{} sessions aren't supported, per application bindings won't work", session_type))
+      .line(SUPPORTED),
+    (None, _) => Report::new(CHECK, Status::Fail, "neither XDG_SESSION_TYPE nor WAYLAND_DISPLAY is set, Makita exits without them")
+      .line("Run Makita from your session, with `sudo -E` when running it as root, or set User= in its systemd unit."),
+  }
+}
+
+fn ruby(configs: &[Config], ruby_scripts_directory: &str) -> Report {
+  const CHECK: &str = "Ruby runtime";
+  let mut scripts = ruby_runtime::configured_scripts(configs, ruby_scripts_directory);
+  scripts.sort();
+  scripts.dedup();
+  let count = scripts.len();
+  match ruby_runtime::self_test(scripts) {
+    Ok((version, errors)) if errors.is_empty() => {
+      Report::new(CHECK, Status::Pass, format!("Ruby {} boots, {} script(s) in {} compile", version, count, ruby_scripts_directory))
+    }
+    Ok((version, errors)) => {
+      let report = Report::new(CHECK, Status::Fail, format!("Ruby {} boots, but {} of {} script(s) don't load", version, errors.len(), count));
+      errors.into_iter().fold(report, Report::line)
+        .line("Makita exits when a script fails to load.")
+    }
+    // Makita only boots Ruby for scripts.
+    Err(e) if count == 0 => Report::new(CHECK, Status::Warn, format!("Ruby doesn't boot: {}", e))
+      .line("No config binds a Ruby script, so Makita runs without it."),
+    Err(e) => Report::new(CHECK, Status::Fail, format!("Ruby doesn't boot: {}", e)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::collections::HashMap;
+
+  fn session(variables: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+    let variables: HashMap<String, String> = variables.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+    move |name| variables.get(name).cloned()
+  }
+
+  #[test]
+  fn reports_the_compositor() {
+    let wayland = |desktop| [("XDG_SESSION_TYPE", "wayland"), ("XDG_CURRENT_DESKTOP", desktop)];
+    assert_eq!(compositor(session(&wayland("Hyprland")), false).status, Status::Pass);
+    assert_eq!(compositor(session(&[("XDG_SESSION_TYPE", "x11")]), false).status, Status::Pass);
+    assert_eq!(compositor(session(&wayland("KDE")), true).status, Status::Pass);
+    let kde = compositor(session(&wayland("KDE")), false);
+    assert_eq!(kde.status, Status::Warn);
+    assert_eq!(kde.lines, vec!["running on KDE, but kdotool isn't installed", "Install kdotool for per application bindings to work."]);
+    assert_eq!(compositor(session(&wayland("GNOME")), true).status, Status::Warn);
+    assert_eq!(compositor(session(&[("XDG_SESSION_TYPE", "wayland")]), true).status, Status::Warn);
+    assert_eq!(compositor(session(&[("XDG_SESSION_TYPE", "tty")]), true).status, Status::Warn);
+    assert_eq!(compositor(session(&[("XDG_CURRENT_DESKTOP", "sway")]), true).status, Status::Fail);
+    // Without XDG_SESSION_TYPE, as under sudo without -E, a Wayland display is enough.
+    let sway = compositor(session(&[("WAYLAND_DISPLAY", "wayland-1"), ("XDG_CURRENT_DESKTOP", "sway")]), true);
+    assert_eq!(sway.lines, vec!["running on sway, per application bindings work"]);
+  }
+}
@@ -0,0 +1,93 @@
+use crate::config::Event;
+use crate::state_files;
+use lazy_static::lazy_static;
+use serde_json::json;
+use std::env;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, UnixListener};
+use tokio::sync::broadcast;
+
+lazy_static! {
+  static ref EVENTS: broadcast::Sender<String> = broadcast::channel(256).0;
+}
+
+pub fn event_name(event: &Event) -> String {
+  match event {
+    Event::Key(key) => format!("{:?}", key),
+    Event::Axis(axis) => axis.name(),
+    Event::Hold => "HOLD".to_string(),
+  }
+}
+
+// Cheap when nobody is listening, so readers can call it for every event.
+pub fn publish(device: &str, event: &Event, value: i32, modifiers: &[Event], bound: bool) {
+  if EVENTS.receiver_count() == 0 { return; }
+  let timestamp = std::time::SystemTime::now()
+    .duration_since(std::time::UNIX_EPOCH)
+    .unwrap_or_default()
+    .as_millis() as u64;
+  let line = json!({
+    "device": device,
+    "event": event_name(event),
+    "value": value,
+    "modifiers": modifiers.iter().map(event_name).collect::<Vec<String>>(),
+    "bound": bound,
+    "timestamp": timestamp,
+  });
+  let _ = EVENTS.send(line.to_string());
+}
+
+// MAKITA_EVENT_TAP takes either a socket path or "tcp:host:port".
+fn address() -> String {
+  if let Ok(address) = env::var("MAKITA_EVENT_TAP") {
+    return address;
+  }
+  state_files::runtime_path("makita-events.sock").to_string_lossy().to_string()
+}
+
+pub async fn serve() {
+  let address = address();
+  if let Some(tcp_address) = address.strip_prefix("tcp:") {
+    let listener = match TcpListener::bind(tcp_address).await {
+      Ok(listener) => listener,
+      Err(e) => {
+        eprintln!("[EventTap] Unable to listen on {}: {}", tcp_address, e);
+        return;
+      }
+    };
+    println!("[EventTap] Streaming events on tcp://{}.", tcp_address);
+    loop {
+      if let Ok((stream, _)) = listener.accept().await {
+        tokio::spawn(stream_events(stream));
+      }
+    }
+  }
+
+  state_files::remove_stale_socket(&address);
+  let listener = match UnixListener::bind(&address) {
+    Ok(listener) => listener,
+    Err(e) => {
+      eprintln!("[EventTap] Unable to bind event socket {}: {}", address, e);
+      return;
+    }
+  };
+  println!("[EventTap] Streaming events on {}.", address);
+  loop {
+    if let Ok((stream, _)) = listener.accept().await {
+      tokio::spawn(stream_events(stream));
+    }
+  }
+}
+
+async fn stream_events<W: AsyncWriteExt + Unpin>(mut stream: W) {
+  let mut events = EVENTS.subscribe();
+  loop {
+    let line = match events.recv().await {
+      Ok(line) => line,
+      // A slow client misses events rather than holding up the readers.
+      Err(broadcast::error::RecvError::Lagged(_)) => continue,
+      Err(broadcast::error::RecvError::Closed) => return,
+    };
+    if stream.write_all(format!("{}\n", line).as_bytes()).await.is_err() { return; }
+  }
+}
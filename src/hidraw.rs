@@ -0,0 +1,80 @@
+use crate::config::{Config, Hidraw};
+use evdev::{uinput::VirtualDeviceBuilder, AttributeSet, EventType, InputEvent};
+use std::{fs, io::Read, path::PathBuf, thread};
+
+// Devices that don't expose evdev key events are read through hidraw and re-emitted on a virtual
+// device named after their config file, so the regular udev pipeline picks them up like any other device.
+pub fn start_hidraw_bridges(configs: &[Config]) {
+  for config in configs {
+    let Some(hidraw) = config.hidraw.clone() else { continue };
+    let name = config.name.clone();
+
+    match find_hidraw_node(&hidraw.device) {
+      Some(path) => {
+        println!("[Hidraw] Bridging {} as \"{}\".", path.display(), name);
+        thread::spawn(move || {
+          if let Err(e) = run_bridge(path, &name, &hidraw) {
+            eprintln!("[Hidraw] Bridge for \"{}\" stopped: {}", name, e);
+          }
+        });
+      }
+      None => println!("[Hidraw] No hidraw device matching {} found for \"{}\".", hidraw.device, name),
+    }
+  }
+}
+
+// Accepts either a /dev/hidrawN path or a vendor:product pair in hex, e.g. "0fd9:0080".
+fn find_hidraw_node(device: &str) -> Option<PathBuf> {
+  if device.starts_with('/') {
+    return Some(PathBuf::from(device));
+  }
+  let (vendor, product) = device.split_once(':')?;
+  let (vendor, product) = (u32::from_str_radix(vendor, 16).ok()?, u32::from_str_radix(product, 16).ok()?);
+
+  for entry in fs::read_dir("/sys/class/hidraw").ok()?.flatten() {
+    let uevent = fs::read_to_string(entry.path().join("device/uevent")).unwrap_or_default();
+    let hid_id = uevent.lines().find_map(|line| line.strip_prefix("HID_ID="));
+    if let Some(hid_id) = hid_id {
+      let ids: Vec<u32> = hid_id.split(':').filter_map(|id| u32::from_str_radix(id, 16).ok()).collect();
+      if ids.len() == 3 && ids[1] == vendor && ids[2] == product {
+        return Some(PathBuf::from("/dev").join(entry.file_name()));
+      }
+    }
+  }
+  None
+}
+
+fn run_bridge(path: PathBuf, name: &str, hidraw: &Hidraw) -> std::io::Result<()> {
+  let mut keys = AttributeSet::new();
+  for button in &hidraw.buttons { keys.insert(button.key); }
+  let mut virtual_device = VirtualDeviceBuilder::new()?.name(name).with_keys(&keys)?.build()?;
+
+  let mut file = fs::File::open(&path)?;
+  let mut report = [0u8; 1024];
+  let mut pressed = vec![false; hidraw.buttons.len()];
+
+  loop {
+    let length = file.read(&mut report)?;
+    if length == 0 { return Ok(()); }
+    let report = &report[..length];
+    if let Some(report_id) = hidraw.report_id {
+      if report.first() != Some(&report_id) { continue; }
+    }
+
+    let mut events = Vec::new();
+    for (index, button) in hidraw.buttons.iter().enumerate() {
+      let Some(byte) = report.get(button.byte) else { continue };
+      let is_pressed = match button.bit {
+        Some(bit) => byte & (1 << bit) != 0,
+        None => *byte != 0,
+      };
+      if is_pressed != pressed[index] {
+        pressed[index] = is_pressed;
+        events.push(InputEvent::new(EventType::KEY, button.key.code(), is_pressed as i32));
+      }
+    }
+    if !events.is_empty() {
+      virtual_device.emit(&events)?;
+    }
+  }
+}
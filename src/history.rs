@@ -0,0 +1,85 @@
+use crate::state_files;
+use crate::virtual_devices::Target;
+use evdev::{AbsoluteAxisType, EventType, InputEvent, Key, RelativeAxisType};
+use lazy_static::lazy_static;
+use std::{
+  collections::VecDeque,
+  io::{self, Write},
+  path::PathBuf,
+  sync::{Mutex, PoisonError},
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+// How many input and output events are kept, roughly the last few seconds of mouse movement.
+const HISTORY_LENGTH: usize = 2000;
+
+enum Source {
+  Input(String),
+  Output(Target),
+}
+
+struct Entry {
+  time: SystemTime,
+  source: Source,
+  event: InputEvent,
+}
+
+lazy_static! {
+  static ref HISTORY: Mutex<VecDeque<Entry>> = Mutex::new(VecDeque::with_capacity(HISTORY_LENGTH));
+}
+
+// Dumps are written to the state directory, on a panic and when asked to with SIGUSR1.
+pub fn init() {
+  let default_hook = std::panic::take_hook();
+  std::panic::set_hook(Box::new(move |info| {
+    default_hook(info);
+    match dump(&format!("panic: {}", info)) {
+      Ok(path) => eprintln!("[History] Wrote the last events to {}, please attach it to a bug report.", path.display()),
+      Err(e) => eprintln!("[History] Unable to write the last events: {}", e),
+    }
+  }));
+}
+
+fn push(source: Source, event: &InputEvent) {
+  if event.event_type() == EventType::SYNCHRONIZATION { return; }
+  let mut history = HISTORY.lock().unwrap_or_else(PoisonError::into_inner);
+  if history.len() == HISTORY_LENGTH { history.pop_front(); }
+  history.push_back(Entry { time: SystemTime::now(), source, event: *event });
+}
+
+pub fn record_input(device: &str, event: &InputEvent) {
+  push(Source::Input(device.to_string()), event);
+}
+
+pub fn record_output(target: Target, events: &[InputEvent]) {
+  for event in events {
+    push(Source::Output(target), event);
+  }
+}
+
+fn describe(event: &InputEvent) -> String {
+  match event.event_type() {
+    EventType::KEY => format!("{:?}", Key(event.code())),
+    EventType::RELATIVE => format!("{:?}", RelativeAxisType(event.code())),
+    EventType::ABSOLUTE => format!("{:?}", AbsoluteAxisType(event.code())),
+    event_type => format!("{:?} {}", event_type, event.code()),
+  }
+}
+
+// Writes the history to a new file, oldest event first, and returns its path.
+pub fn dump(reason: &str) -> io::Result<PathBuf> {
+  let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+  let (path, file) = state_files::create(&format!("makita-history-{}.log", now.as_secs()))?;
+  let mut file = io::BufWriter::new(file);
+  writeln!(file, "Makita {} event history, {}", env!("CARGO_PKG_VERSION"), reason)?;
+  for entry in HISTORY.lock().unwrap_or_else(PoisonError::into_inner).iter() {
+    let time = entry.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let source = match &entry.source {
+      Source::Input(device) => format!("in  {}", device),
+      Source::Output(target) => format!("out {:?}", target),
+    };
+    writeln!(file, "{}.{:03} {}: {} {}", time.as_secs(), time.subsec_millis(), source, describe(&entry.event), entry.event.value())?;
+  }
+  file.flush()?;
+  Ok(path)
+}
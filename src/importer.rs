@@ -0,0 +1,433 @@
+use crate::config::key_from_alias;
+use evdev::Key;
+use std::collections::HashMap;
+
+#[derive(Default)]
+struct Imported {
+  remap: Vec<(String, Vec<Key>)>,
+  tap_hold: Vec<(Key, Key, Key, Option<u64>)>,
+  skipped: Vec<String>,
+}
+
+pub fn run(args: &[String]) {
+  let (format, file) = match args {
+    [flag, format, file] if flag == "--from" => (format.as_str(), file.as_str()),
+    _ => {
+      eprintln!("Usage: makita import --from <keyd|kmonad|xremap> <file>");
+      std::process::exit(1);
+    }
+  };
+
+  let content = match std::fs::read_to_string(file) {
+    Ok(content) => content,
+    Err(e) => {
+      eprintln!("[Importer] Unable to read {}: {}", file, e);
+      std::process::exit(1);
+    }
+  };
+
+  let imported = match format {
+    "keyd" => import_keyd(&content),
+    "kmonad" => import_kmonad(&content),
+    "xremap" => import_xremap(&content),
+    _ => {
+      eprintln!("[Importer] Unknown format {}, use keyd, kmonad or xremap.", format);
+      std::process::exit(1);
+    }
+  };
+
+  print!("{}", to_toml(&imported, format, file));
+}
+
+fn to_toml(imported: &Imported, format: &str, file: &str) -> String {
+  let mut toml = format!("# Imported from {} config {}\n", format, file);
+  for skipped in &imported.skipped {
+    toml.push_str(&format!("# Skipped: {}\n", skipped));
+  }
+
+  toml.push_str("\n[remap]\n");
+  for (input, output) in &imported.remap {
+    let output = output.iter().map(|key| format!("\"{:?}\"", key)).collect::<Vec<String>>().join(", ");
+    toml.push_str(&format!("{} = [{}]\n", input, output));
+  }
+
+  if !imported.tap_hold.is_empty() {
+    toml.push_str("\n[tap_hold]\n");
+    for (input, tap, hold, tapping_term) in &imported.tap_hold {
+      let tapping_term = tapping_term.map(|ms| format!(", tapping_term = {}", ms)).unwrap_or_default();
+      toml.push_str(&format!("{:?} = {{ tap = \"{:?}\", hold = \"{:?}\"{} }}\n", input, tap, hold, tapping_term));
+    }
+  }
+
+  toml
+}
+
+// Parses outputs like "C-S-a" into the modifiers followed by the key.
+fn keys_from_chord(chord: &str) -> Option<Vec<Key>> {
+  let mut keys = Vec::new();
+  let mut rest = chord;
+  while let Some((modifier, tail)) = rest.split_once('-') {
+    let modifier = match modifier {
+      "C" => Key::KEY_LEFTCTRL,
+      "S" => Key::KEY_LEFTSHIFT,
+      "A" => Key::KEY_LEFTALT,
+      "M" => Key::KEY_LEFTMETA,
+      "G" => Key::KEY_RIGHTALT,
+      _ => break,
+    };
+    if tail.is_empty() { break; }
+    keys.push(modifier);
+    rest = tail;
+  }
+  keys.push(key_from_alias(rest)?);
+  Some(keys)
+}
+
+fn modifier_layer(name: &str) -> Option<Key> {
+  match name {
+    "control" | "ctrl" => Some(Key::KEY_LEFTCTRL),
+    "shift" => Some(Key::KEY_LEFTSHIFT),
+    "alt" => Some(Key::KEY_LEFTALT),
+    "meta" | "super" => Some(Key::KEY_LEFTMETA),
+    "altgr" => Some(Key::KEY_RIGHTALT),
+    _ => None,
+  }
+}
+
+fn binding_name(modifiers: &[Key], key: Key) -> String {
+  let mut name = modifiers.iter().map(|key| format!("{:?}-", key)).collect::<String>();
+  name.push_str(&format!("{:?}", key));
+  name
+}
+
+fn import_keyd(content: &str) -> Imported {
+  let mut imported = Imported::default();
+  let mut section = String::new();
+
+  for line in content.lines().map(str::trim) {
+    if line.is_empty() || line.starts_with('#') { continue; }
+    if line.starts_with('[') && line.ends_with(']') {
+      section = line[1..line.len() - 1].to_string();
+      continue;
+    }
+    if section == "ids" { continue; }
+
+    let Some((input, output)) = line.split_once('=') else {
+      imported.skipped.push(format!("[{}] {}", section, line));
+      continue;
+    };
+    let (input, output) = (input.trim(), output.trim());
+    let layer_modifiers: Vec<Key> = match section.as_str() {
+      "main" => Vec::new(),
+      layer => match layer.split('+').map(modifier_layer).collect::<Option<Vec<Key>>>() {
+        Some(modifiers) => modifiers,
+        None => {
+          imported.skipped.push(format!("[{}] {}", section, line));
+          continue;
+        }
+      },
+    };
+
+    let Some(input_key) = key_from_alias(input) else {
+      imported.skipped.push(format!("[{}] {}", section, line));
+      continue;
+    };
+
+    let arguments = |function: &str| -> Option<Vec<String>> {
+      output.strip_prefix(function)?.strip_prefix('(')?.strip_suffix(')')
+        .map(|args| args.split(',').map(|arg| arg.trim().to_string()).collect())
+    };
+
+    if let (true, Some(args)) = (layer_modifiers.is_empty(), arguments("overload").or_else(|| arguments("lettermod"))) {
+      let hold = args.first().and_then(|layer| modifier_layer(layer));
+      let tap = args.get(1).and_then(|tap| key_from_alias(tap));
+      let tapping_term = args.get(3).and_then(|ms| ms.parse().ok());
+      match (hold, tap) {
+        (Some(hold), Some(tap)) => imported.tap_hold.push((input_key, tap, hold, tapping_term)),
+        _ => imported.skipped.push(format!("[{}] {}", section, line)),
+      }
+    } else if let Some(keys) = keys_from_chord(output) {
+      imported.remap.push((binding_name(&layer_modifiers, input_key), keys));
+    } else {
+      imported.skipped.push(format!("[{}] {}", section, line));
+    }
+  }
+
+  imported
+}
+
+#[derive(Debug, Clone)]
+enum Expression {
+  Atom(String),
+  List(Vec<Expression>),
+}
+
+fn parse_expressions(content: &str) -> Vec<Expression> {
+  let mut cleaned = String::new();
+  let mut in_block_comment = false;
+  for line in content.lines() {
+    let mut line = line;
+    if in_block_comment {
+      match line.split_once("|#") {
+        Some((_, rest)) => { in_block_comment = false; line = rest; }
+        None => continue,
+      }
+    }
+    if let Some((before, _)) = line.split_once("#|") {
+      in_block_comment = true;
+      line = before;
+    }
+    cleaned.push_str(line.split(";;").next().unwrap_or_default());
+    cleaned.push('\n');
+  }
+
+  let tokens = cleaned.replace('(', " ( ").replace(')', " ) ");
+  let mut stack: Vec<Vec<Expression>> = vec![Vec::new()];
+  for token in tokens.split_whitespace() {
+    match token {
+      "(" => stack.push(Vec::new()),
+      ")" if stack.len() > 1 => {
+        let list = stack.pop().unwrap_or_default();
+        if let Some(parent) = stack.last_mut() { parent.push(Expression::List(list)); }
+      }
+      _ => if let Some(current) = stack.last_mut() { current.push(Expression::Atom(token.to_string())); },
+    }
+  }
+  stack.into_iter().next().unwrap_or_default()
+}
+
+fn import_kmonad(content: &str) -> Imported {
+  let mut imported = Imported::default();
+  let mut source: Vec<String> = Vec::new();
+  let mut aliases: HashMap<String, Expression> = HashMap::new();
+  let mut layers: Vec<(String, Vec<Expression>)> = Vec::new();
+
+  for expression in parse_expressions(content) {
+    let Expression::List(items) = expression else { continue };
+    match items.first() {
+      Some(Expression::Atom(head)) if head == "defsrc" => {
+        source = items[1..].iter().filter_map(|item| match item {
+          Expression::Atom(name) => Some(name.clone()),
+          _ => None,
+        }).collect();
+      }
+      Some(Expression::Atom(head)) if head == "defalias" => {
+        for pair in items[1..].chunks(2) {
+          if let [Expression::Atom(name), value] = pair { aliases.insert(name.clone(), value.clone()); }
+        }
+      }
+      Some(Expression::Atom(head)) if head == "deflayer" => {
+        if let Some(Expression::Atom(name)) = items.get(1) { layers.push((name.clone(), items[2..].to_vec())); }
+      }
+      _ => {}
+    }
+  }
+
+  for (name, _) in layers.iter().skip(1) {
+    imported.skipped.push(format!("layer {}, only the first layer is imported", name));
+  }
+  let Some((_, layer)) = layers.first() else { return imported };
+
+  for (input, output) in source.iter().zip(layer) {
+    let Some(input_key) = key_from_alias(input) else {
+      imported.skipped.push(format!("source key {}", input));
+      continue;
+    };
+    let output = match output {
+      Expression::Atom(name) if name.starts_with('@') => aliases.get(&name[1..]).cloned().unwrap_or(output.clone()),
+      _ => output.clone(),
+    };
+
+    match &output {
+      Expression::Atom(name) if name == "_" || name == input => {}
+      Expression::Atom(name) => match keys_from_chord(name) {
+        Some(keys) => imported.remap.push((binding_name(&[], input_key), keys)),
+        None => imported.skipped.push(format!("{} -> {}", input, name)),
+      },
+      Expression::List(items) => match items.as_slice() {
+        [Expression::Atom(function), Expression::Atom(ms), Expression::Atom(tap), Expression::Atom(hold), ..]
+          if function.starts_with("tap-hold") =>
+        {
+          match (key_from_alias(tap), key_from_alias(hold)) {
+            (Some(tap), Some(hold)) => imported.tap_hold.push((input_key, tap, hold, ms.parse().ok())),
+            _ => imported.skipped.push(format!("{} -> {:?}", input, items)),
+          }
+        }
+        _ => imported.skipped.push(format!("{} -> {:?}", input, items)),
+      },
+    }
+  }
+
+  imported
+}
+
+fn flush_tap_hold(pending: &mut Option<(Key, Option<Key>, Option<Key>)>, imported: &mut Imported) {
+  if let Some((input, tap, hold)) = pending.take() {
+    match (tap, hold) {
+      (Some(tap), Some(hold)) => imported.tap_hold.push((input, tap, hold, None)),
+      _ => imported.skipped.push(format!("held/alone binding for {:?}", input)),
+    }
+  }
+}
+
+// Only understands the flat subset of xremap's YAML: modmap/keymap remap entries and held/alone modmaps.
+fn import_xremap(content: &str) -> Imported {
+  let mut imported = Imported::default();
+  let mut section = String::new();
+  let mut in_remap = false;
+  let mut pending_tap_hold: Option<(Key, Option<Key>, Option<Key>)> = None;
+
+  for raw_line in content.lines() {
+    let line = raw_line.split(" #").next().unwrap_or_default().trim_end();
+    if line.trim().is_empty() || line.trim().starts_with('#') { continue; }
+    let indentation = line.len() - line.trim_start().len();
+    let line = line.trim().trim_start_matches("- ");
+
+    if indentation == 0 {
+      flush_tap_hold(&mut pending_tap_hold, &mut imported);
+      section = line.trim_end_matches(':').to_string();
+      in_remap = false;
+      continue;
+    }
+    if line == "remap:" {
+      flush_tap_hold(&mut pending_tap_hold, &mut imported);
+      in_remap = true;
+      continue;
+    }
+    let Some((key, value)) = line.split_once(':') else { continue };
+    let (key, value) = (key.trim(), value.trim());
+
+    if let Some((_, tap, hold)) = &mut pending_tap_hold {
+      match key {
+        "alone" => { *tap = key_from_alias(value); continue; }
+        "held" => { *hold = key_from_alias(value); continue; }
+        _ => flush_tap_hold(&mut pending_tap_hold, &mut imported),
+      }
+    }
+    if !in_remap || key == "name" || key == "application" { continue; }
+
+    match (section.as_str(), value) {
+      ("modmap", "") => match key_from_alias(key) {
+        Some(input) => pending_tap_hold = Some((input, None, None)),
+        None => imported.skipped.push(format!("modmap {}", key)),
+      },
+      ("modmap", value) => match (key_from_alias(key), key_from_alias(value)) {
+        (Some(input), Some(output)) => imported.remap.push((binding_name(&[], input), vec![output])),
+        _ => imported.skipped.push(format!("modmap {}: {}", key, value)),
+      },
+      ("keymap", value) => match (keys_from_chord(key), keys_from_chord(value)) {
+        (Some(input), Some(output)) => {
+          let Some((input_key, modifiers)) = input.split_last() else {
+            imported.skipped.push(format!("keymap {}: {}", key, value));
+            continue;
+          };
+          imported.remap.push((binding_name(modifiers, *input_key), output));
+        }
+        _ => imported.skipped.push(format!("keymap {}: {}", key, value)),
+      },
+      _ => imported.skipped.push(format!("{} {}: {}", section, key, value)),
+    }
+  }
+  flush_tap_hold(&mut pending_tap_hold, &mut imported);
+
+  imported
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn remap(imported: &Imported) -> Vec<(&str, Vec<Key>)> {
+    imported.remap.iter().map(|(input, output)| (input.as_str(), output.clone())).collect()
+  }
+
+  #[test]
+  fn imports_xremap_modmaps_and_keymaps() {
+    let imported = import_xremap("\
+modmap:
+  - name: Global
+    remap:
+      CapsLock: Esc
+      Space:
+        held: Shift_L
+        alone: Space
+      Unknown_Key: Esc
+keymap:
+  - name: Emacs
+    application:
+      only: Firefox
+    remap:
+      C-b: left
+      C-M-f: C-right
+      C-x: launch
+");
+    assert_eq!(remap(&imported), vec![
+      ("KEY_CAPSLOCK", vec![Key::KEY_ESC]),
+      ("KEY_LEFTCTRL-KEY_B", vec![Key::KEY_LEFT]),
+      ("KEY_LEFTCTRL-KEY_LEFTMETA-KEY_F", vec![Key::KEY_LEFTCTRL, Key::KEY_RIGHT]),
+    ]);
+    assert_eq!(imported.tap_hold, vec![(Key::KEY_SPACE, Key::KEY_SPACE, Key::KEY_LEFTSHIFT, None)]);
+    assert_eq!(imported.skipped, vec!["modmap Unknown_Key: Esc", "keymap C-x: launch"]);
+  }
+
+  #[test]
+  fn skips_xremap_tap_holds_missing_a_side() {
+    let imported = import_xremap("modmap:\n  - remap:\n      CapsLock:\n        held: Control_L\n");
+    assert!(imported.tap_hold.is_empty());
+    assert_eq!(imported.skipped, vec!["held/alone binding for KEY_CAPSLOCK"]);
+  }
+
+  #[test]
+  fn imports_keyd_layers_and_overloads() {
+    let imported = import_keyd("\
+[ids]
+*
+
+[main]
+capslock = overload(control, esc)
+a = b
+f1 = command(ls)
+
+[control+shift]
+j = down
+");
+    assert_eq!(remap(&imported), vec![
+      ("KEY_A", vec![Key::KEY_B]),
+      ("KEY_LEFTCTRL-KEY_LEFTSHIFT-KEY_J", vec![Key::KEY_DOWN]),
+    ]);
+    assert_eq!(imported.tap_hold, vec![(Key::KEY_CAPSLOCK, Key::KEY_ESC, Key::KEY_LEFTCTRL, None)]);
+    assert_eq!(imported.skipped, vec!["[main] f1 = command(ls)"]);
+  }
+
+  #[test]
+  fn imports_the_first_kmonad_layer() {
+    let imported = import_kmonad("\
+(defsrc caps a s)
+(defalias cesc (tap-hold 200 esc lctl))
+(deflayer base @cesc b _)
+(deflayer other _ _ _)
+");
+    assert_eq!(remap(&imported), vec![("KEY_A", vec![Key::KEY_B])]);
+    assert_eq!(imported.tap_hold, vec![(Key::KEY_CAPSLOCK, Key::KEY_ESC, Key::KEY_LEFTCTRL, Some(200))]);
+    assert_eq!(imported.skipped, vec!["layer other, only the first layer is imported"]);
+  }
+
+  #[test]
+  fn lists_skipped_entries_at_the_top() {
+    let imported = Imported {
+      remap: vec![("KEY_A".to_string(), vec![Key::KEY_LEFTCTRL, Key::KEY_B])],
+      tap_hold: vec![(Key::KEY_CAPSLOCK, Key::KEY_ESC, Key::KEY_LEFTCTRL, Some(200))],
+      skipped: vec!["keymap C-x: launch".to_string()],
+    };
+    assert_eq!(to_toml(&imported, "xremap", "config.yml"), "\
+# Imported from xremap config config.yml
+# Skipped: keymap C-x: launch
+
+[remap]
+KEY_A = [\"KEY_LEFTCTRL\", \"KEY_B\"]
+
+[tap_hold]
+KEY_CAPSLOCK = { tap = \"KEY_ESC\", hold = \"KEY_LEFTCTRL\", tapping_term = 200 }
+");
+  }
+}
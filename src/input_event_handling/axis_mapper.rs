@@ -0,0 +1,111 @@
+use crate::config::{AxisMapping, AxisMode};
+use evdev::RelativeAxisType;
+use std::{collections::HashMap, time::Duration};
+
+// How often the axes in velocity mode move.
+pub const AXIS_MAPPER_TICK: Duration = Duration::from_millis(10);
+
+// Turns absolute axes into relative motion for the [axes] section. In position mode, moving the axis across its whole
+// range moves by scale, e.g. a touch strip that scrolls as the finger slides along it. In velocity mode, the axis
+// moves by up to scale every tick while it's away from its center, like a stick set to "cursor" does.
+// Wheel motion is in notches, emitted as high resolution units.
+pub struct AxisMapper {
+  mappings: HashMap<u16, AxisMapping>,
+  ranges: HashMap<u16, (i32, i32)>,
+  values: HashMap<u16, i32>,
+  remainders: HashMap<u16, f64>,
+}
+
+impl AxisMapper {
+  pub fn new(mappings: HashMap<u16, AxisMapping>, ranges: HashMap<u16, (i32, i32)>) -> Self {
+    Self { mappings, ranges, values: HashMap::new(), remainders: HashMap::new() }
+  }
+
+  pub fn maps(&self, axis: u16) -> bool {
+    self.mappings.contains_key(&axis)
+  }
+
+  // Whether an axis in velocity mode is away from its center, so that ticks move.
+  pub fn moving(&self) -> bool {
+    self.mappings.iter().any(|(axis, mapping)| mapping.mode == AxisMode::Velocity && self.deflection(*axis, mapping) != 0.0)
+  }
+
+  // The motion an event of an axis in position mode makes. Jumps of more than half the range, e.g. lifting the finger
+  // off a touch strip and touching it elsewhere, don't move.
+  pub fn push(&mut self, axis: u16, value: i32) -> Option<(RelativeAxisType, i32)> {
+    let mapping = *self.mappings.get(&axis)?;
+    let previous = self.values.insert(axis, value)?;
+    let (minimum, maximum) = self.range(axis);
+    let fraction = (value - previous) as f64 / (maximum - minimum) as f64;
+    match mapping.mode == AxisMode::Position && maximum > minimum && fraction.abs() <= 0.5 {
+      true => self.motion(&mapping, fraction),
+      false => None,
+    }
+  }
+
+  // The motion of the axes in velocity mode for one tick.
+  pub fn tick(&mut self) -> Vec<(RelativeAxisType, i32)> {
+    let deflections: Vec<(AxisMapping, f64)> = self.mappings.iter()
+      .filter(|(_, mapping)| mapping.mode == AxisMode::Velocity)
+      .map(|(axis, mapping)| (*mapping, self.deflection(*axis, mapping)))
+      .filter(|(_, deflection)| *deflection != 0.0)
+      .collect();
+    deflections.into_iter().filter_map(|(mapping, deflection)| self.motion(&mapping, deflection)).collect()
+  }
+
+  // How far the axis is from its center, from -1.0 to 1.0, past its deadzone.
+  fn deflection(&self, axis: u16, mapping: &AxisMapping) -> f64 {
+    let (minimum, maximum) = self.range(axis);
+    let half = (maximum - minimum) as f64 / 2.0;
+    let (Some(value), true) = (self.values.get(&axis), half > 0.0) else { return 0.0 };
+    let position = (*value as f64 - minimum as f64 - half) / half;
+    let deadzone = mapping.deadzone / 100.0;
+    match position.abs() <= deadzone {
+      true => 0.0,
+      false => position.signum() * (position.abs() - deadzone) / (1.0 - deadzone),
+    }
+  }
+
+  // Scales a fraction of the range to whole units of the output, carrying the rest over to the next motion.
+  fn motion(&mut self, mapping: &AxisMapping, fraction: f64) -> Option<(RelativeAxisType, i32)> {
+    let units = match mapping.output {
+      RelativeAxisType::REL_WHEEL | RelativeAxisType::REL_HWHEEL => 120.0,
+      _ => 1.0,
+    };
+    let remainder = self.remainders.entry(mapping.output.0).or_default();
+    let motion = fraction * mapping.scale * units + *remainder;
+    *remainder = motion.fract();
+    (motion.trunc() != 0.0).then_some((mapping.output, motion.trunc() as i32))
+  }
+
+  fn range(&self, axis: u16) -> (i32, i32) {
+    self.ranges.get(&axis).copied().unwrap_or_default()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::config::Config;
+  use evdev::AbsoluteAxisType;
+
+  #[test]
+  fn maps_axes_to_relative_motion() {
+    let config = Config::parse(
+      "[axes]\nABS_RX = { output = \"REL_WHEEL\", scale = 10, invert = true }\nABS_THROTTLE = { output = \"REL_X\", mode = \"velocity\", scale = 8 }\n",
+      "Test Device".to_string(),
+    ).unwrap();
+    let ranges = HashMap::from([(AbsoluteAxisType::ABS_RX.0, (0, 1000)), (AbsoluteAxisType::ABS_THROTTLE.0, (0, 255))]);
+    let mut mapper = AxisMapper::new(config.axes.clone(), ranges);
+
+    // The first event only sets where the strip is, a tenth of the range is a notch, inverted.
+    assert_eq!(mapper.push(AbsoluteAxisType::ABS_RX.0, 100), None);
+    assert_eq!(mapper.push(AbsoluteAxisType::ABS_RX.0, 200), Some((RelativeAxisType::REL_WHEEL, -120)));
+    assert_eq!(mapper.push(AbsoluteAxisType::ABS_RX.0, 900), None);
+
+    assert!(!mapper.moving());
+    assert_eq!(mapper.push(AbsoluteAxisType::ABS_THROTTLE.0, 255), None);
+    assert!(mapper.moving());
+    assert_eq!(mapper.tick(), vec![(RelativeAxisType::REL_X, 8)]);
+  }
+}
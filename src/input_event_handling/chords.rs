@@ -0,0 +1,161 @@
+use crate::config::Event;
+use crate::input_event_handling::event_buffer::EventBuffer;
+use evdev::{InputEvent, Key};
+use std::{collections::HashMap, time::Duration};
+use tokio::time::Instant;
+
+// What goes on after the chord engine: an event converted as usual, or the keys of a chord pressed (1) or
+// released (0).
+#[derive(Debug, Clone)]
+pub enum ChordStep {
+  Event(InputEvent, Event, i32, bool),
+  Keys(Vec<Key>, i32),
+}
+
+struct Pending {
+  default_event: InputEvent,
+  event: Event,
+  send_zero: bool,
+}
+
+pub struct ChordEngine {
+  chords: HashMap<Vec<Event>, Vec<Key>>,
+  pending: EventBuffer<Pending>,
+  active: Vec<(Vec<Event>, Vec<Key>)>,
+  // Members still held after their chord was released by another one.
+  spent: Vec<Event>,
+}
+
+impl ChordEngine {
+  pub fn new(chords: HashMap<Vec<Event>, Vec<Key>>) -> Self {
+    Self { chords, pending: EventBuffer::default(), active: Vec::new(), spent: Vec::new() }
+  }
+
+  pub fn deadline(&self, timeout: Duration) -> Option<Instant> {
+    self.pending.first_at().map(|pressed_at| pressed_at + timeout)
+  }
+
+  // Presses of chord members are held back until the chord is complete, or replayed once it can't be anymore,
+  // so a member pressed alone does what it's bound to on its own, just later.
+  pub fn process(&mut self, default_event: InputEvent, event: Event, value: i32, send_zero: bool) -> Vec<ChordStep> {
+    if self.chords.is_empty() {
+      return vec![ChordStep::Event(default_event, event, value, send_zero)];
+    }
+    if let Some(index) = self.spent.iter().position(|spent| *spent == event) {
+      if value == 0 { self.spent.remove(index); }
+      return Vec::new();
+    }
+    if let Some(index) = self.active.iter().position(|(members, _)| members.contains(&event)) {
+      if value != 0 { return Vec::new(); }
+      let (members, keys) = self.active.remove(index);
+      self.spent.extend(members.into_iter().filter(|member| *member != event));
+      return vec![ChordStep::Keys(keys, 0)];
+    }
+
+    let member = self.chords.keys().any(|members| members.contains(&event));
+    let pending = self.pending.iter().any(|pending| pending.event == event);
+    match (value, member, pending) {
+      (1, true, false) => {
+        self.pending.push(Pending { default_event, event, send_zero }, Instant::now());
+        let held: Vec<Event> = self.pending.iter().map(|pending| pending.event).collect();
+        // The longest chord wins when several complete at once.
+        let completed = self.chords.iter()
+          .filter(|(members, _)| members.iter().all(|member| held.contains(member)))
+          .max_by_key(|(members, _)| (members.len(), (*members).clone()))
+          .map(|(members, keys)| (members.clone(), keys.clone()));
+        let Some((members, keys)) = completed else { return Vec::new() };
+        self.pending.retain(|pending| !members.contains(&pending.event));
+        let mut steps = self.flush(self.pending.len());
+        steps.push(ChordStep::Keys(keys.clone(), 1));
+        self.active.push((members, keys));
+        steps
+      }
+      (2, _, true) => Vec::new(),
+      // Other presses and the release of a held back member end the wait, keeping the order of events.
+      (1, false, _) | (0, _, true) => {
+        let mut steps = self.flush(self.pending.len());
+        steps.push(ChordStep::Event(default_event, event, value, send_zero));
+        steps
+      }
+      _ => vec![ChordStep::Event(default_event, event, value, send_zero)],
+    }
+  }
+
+  // Replays the presses that waited for their chord longer than the timeout.
+  pub fn timeout(&mut self, timeout: Duration) -> Vec<ChordStep> {
+    let expired = self.pending.waited(timeout, Instant::now());
+    self.flush(expired)
+  }
+
+  // Forgets the presses held back and returns the keys of the chords that are down.
+  pub fn reset(&mut self) -> Vec<Key> {
+    self.pending.clear();
+    self.spent.clear();
+    self.active.drain(..).flat_map(|(_, keys)| keys.into_iter().rev()).collect()
+  }
+
+  fn flush(&mut self, count: usize) -> Vec<ChordStep> {
+    self.pending.take_first(count).into_iter()
+      .map(|(pending, _)| ChordStep::Event(pending.default_event, pending.event, 1, pending.send_zero))
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::config::Axis;
+  use evdev::EventType;
+
+  #[tokio::test(start_paused = true)]
+  async fn replays_members_that_waited_past_the_timeout_in_order() {
+    let (select, start, south) = (Event::Key(Key::BTN_SELECT), Event::Key(Key::BTN_START), Event::Key(Key::BTN_SOUTH));
+    let mut engine = ChordEngine::new(HashMap::from([(vec![select, start], vec![Key::KEY_ESC]), (vec![select, south], vec![Key::KEY_TAB])]));
+    let timeout = Duration::from_millis(50);
+    let replayed = |steps: Vec<ChordStep>| -> Vec<Event> {
+      steps.into_iter().filter_map(|step| match step {
+        ChordStep::Event(_, event, 1, _) => Some(event),
+        _ => None,
+      }).collect()
+    };
+
+    // Neither completes a chord, so both wait, each for as long as it was pressed for.
+    let pressed_at = Instant::now();
+    assert!(engine.process(InputEvent::new(EventType::KEY, 0, 1), south, 1, false).is_empty());
+    tokio::time::advance(Duration::from_millis(30)).await;
+    assert!(engine.process(InputEvent::new(EventType::KEY, 0, 1), start, 1, false).is_empty());
+    assert_eq!(engine.deadline(timeout), Some(pressed_at + timeout));
+    assert!(replayed(engine.timeout(timeout)).is_empty());
+    tokio::time::advance(Duration::from_millis(20)).await;
+    assert_eq!(replayed(engine.timeout(timeout)), vec![south]);
+    tokio::time::advance(Duration::from_millis(30)).await;
+    assert_eq!(replayed(engine.timeout(timeout)), vec![start]);
+    assert_eq!(engine.deadline(timeout), None);
+  }
+
+  #[test]
+  fn presses_chords_of_gamepad_buttons() {
+    let (select, start, trigger) = (Event::Key(Key::BTN_SELECT), Event::Key(Key::BTN_START), Event::Axis(Axis::BTN_TL2));
+    let mut engine = ChordEngine::new(HashMap::from([
+      (vec![select, start], vec![Key::KEY_ESC]),
+      (vec![trigger, Event::Axis(Axis::BTN_TR2)], vec![Key::KEY_LEFTCTRL, Key::KEY_S]),
+    ]));
+    let mut press = |event: Event, value: i32| -> Vec<(Option<Event>, Vec<Key>, i32)> {
+      engine.process(InputEvent::new(EventType::KEY, 0, value), event, value, false).into_iter().map(|step| match step {
+        ChordStep::Event(_, event, value, _) => (Some(event), Vec::new(), value),
+        ChordStep::Keys(keys, value) => (None, keys, value),
+      }).collect()
+    };
+
+    // Members are held back until the chord completes, then only the chord's keys go out.
+    assert_eq!(press(select, 1), vec![]);
+    assert_eq!(press(start, 1), vec![(None, vec![Key::KEY_ESC], 1)]);
+    assert_eq!(press(select, 0), vec![(None, vec![Key::KEY_ESC], 0)]);
+    assert_eq!(press(start, 0), vec![]);
+
+    // Alone, a member still does what it does on its own, axes included.
+    assert_eq!(press(trigger, 1), vec![]);
+    assert_eq!(press(trigger, 0), vec![(Some(trigger), Vec::new(), 1), (Some(trigger), Vec::new(), 0)]);
+    assert_eq!(press(Event::Key(Key::BTN_SOUTH), 1), vec![(Some(Event::Key(Key::BTN_SOUTH)), Vec::new(), 1)]);
+  }
+}
@@ -0,0 +1,83 @@
+use evdev::{EventType, InputEvent};
+use std::{collections::HashMap, time::Duration};
+use tokio::time::Instant;
+
+// Worn mouse switches bounce: a single click comes out as a release and a press a few milliseconds apart, i.e. a
+// double click. Releases of the buttons in BUTTON_DEBOUNCE wait for their window, and a press of the same button
+// within it drops both, so the button just stays down.
+pub struct Debouncer {
+  pending: Vec<(InputEvent, Instant)>,
+}
+
+impl Debouncer {
+  pub fn new() -> Self {
+    Self { pending: Vec::new() }
+  }
+
+  pub fn deadline(&self) -> Option<Instant> {
+    self.pending.iter().map(|(_, deadline)| *deadline).min()
+  }
+
+  pub fn process(&mut self, event: InputEvent, windows: &HashMap<u16, Duration>) -> Option<InputEvent> {
+    if event.event_type() != EventType::KEY {
+      return Some(event);
+    }
+    let Some(window) = windows.get(&event.code()) else { return Some(event) };
+    match event.value() {
+      0 => {
+        self.pending.push((event, Instant::now() + *window));
+        None
+      }
+      1 => match self.pending.iter().position(|(release, _)| release.code() == event.code()) {
+        Some(index) => {
+          self.pending.remove(index);
+          None
+        }
+        None => Some(event),
+      },
+      _ => Some(event),
+    }
+  }
+
+  // The releases whose window passed without a press.
+  pub fn expire(&mut self) -> Vec<InputEvent> {
+    let now = Instant::now();
+    let (expired, pending) = self.pending.drain(..).partition(|(_, deadline)| *deadline <= now);
+    self.pending = pending;
+    expired.into_iter().map(|(release, _)| release).collect()
+  }
+
+  // Held keys are being released anyway.
+  pub fn clear(&mut self) {
+    self.pending.clear();
+  }
+}
+
+impl Default for Debouncer {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use evdev::Key;
+
+  #[test]
+  fn merges_bouncing_clicks() {
+    let key = |key: Key, value: i32| InputEvent::new(EventType::KEY, key.code(), value);
+    let windows = HashMap::from([(Key::BTN_LEFT.code(), Duration::from_secs(60)), (Key::BTN_RIGHT.code(), Duration::ZERO)]);
+    let mut debouncer = Debouncer::new();
+    assert!(debouncer.process(key(Key::BTN_LEFT, 1), &windows).is_some());
+    assert!(debouncer.process(key(Key::BTN_LEFT, 0), &windows).is_none());
+    // Pressed again within the window, the button stays down.
+    assert!(debouncer.process(key(Key::BTN_LEFT, 1), &windows).is_none());
+    assert_eq!(debouncer.deadline(), None);
+    assert!(debouncer.process(key(Key::BTN_MIDDLE, 0), &windows).is_some());
+
+    assert!(debouncer.process(key(Key::BTN_RIGHT, 0), &windows).is_none());
+    let releases: Vec<(u16, i32)> = debouncer.expire().iter().map(|event| (event.code(), event.value())).collect();
+    assert_eq!(releases, vec![(Key::BTN_RIGHT.code(), 0)]);
+  }
+}
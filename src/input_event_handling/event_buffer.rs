@@ -0,0 +1,93 @@
+use evdev::{EventType, InputEvent};
+use std::time::Duration;
+use tokio::time::Instant;
+
+// Events swallowed while a timing-based binding (a tap-hold key or a chord) is unresolved, to be replayed in order
+// once it is. Each event keeps the time it arrived at, so that bindings among the replayed events are timed
+// from when they were really pressed rather than from the replay. The replay itself isn't spaced out: the events
+// are emitted back to back, only their order is kept.
+pub struct EventBuffer<T = InputEvent> {
+  events: Vec<(T, Instant)>,
+}
+
+impl<T> Default for EventBuffer<T> {
+  fn default() -> Self {
+    Self { events: Vec::new() }
+  }
+}
+
+impl<T> EventBuffer<T> {
+  pub fn push(&mut self, event: T, at: Instant) {
+    self.events.push((event, at));
+  }
+
+  pub fn len(&self) -> usize {
+    self.events.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.events.is_empty()
+  }
+
+  // When the oldest event arrived.
+  pub fn first_at(&self) -> Option<Instant> {
+    self.events.first().map(|(_, at)| *at)
+  }
+
+  // How many of the oldest events have waited for the timeout by now.
+  pub fn waited(&self, timeout: Duration, now: Instant) -> usize {
+    self.events.iter().take_while(|(_, at)| *at + timeout <= now).count()
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = &T> {
+    self.events.iter().map(|(event, _)| event)
+  }
+
+  pub fn retain(&mut self, mut keep: impl FnMut(&T) -> bool) {
+    self.events.retain(|(event, _)| keep(event));
+  }
+
+  // The oldest events, in the order they arrived.
+  pub fn take_first(&mut self, count: usize) -> Vec<(T, Instant)> {
+    self.events.drain(..count).collect()
+  }
+
+  pub fn clear(&mut self) {
+    self.events.clear();
+  }
+
+  pub fn take(&mut self) -> Vec<(T, Instant)> {
+    std::mem::take(&mut self.events)
+  }
+}
+
+impl EventBuffer<InputEvent> {
+  // Whether the key was pressed since buffering began.
+  pub fn pressed(&self, code: u16) -> bool {
+    self.iter().any(|event| event.event_type() == EventType::KEY && event.code() == code && event.value() == 1)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn keeps_the_order_and_times_events_arrived_at() {
+    let start = Instant::now();
+    let mut buffer = EventBuffer::default();
+    buffer.push(InputEvent::new(EventType::KEY, 30, 1), start);
+    buffer.push(InputEvent::new(EventType::KEY, 31, 1), start + Duration::from_millis(50));
+    buffer.push(InputEvent::new(EventType::KEY, 30, 0), start + Duration::from_millis(120));
+    assert!(buffer.pressed(31));
+    assert!(!buffer.pressed(32));
+    assert_eq!(buffer.first_at(), Some(start));
+    assert_eq!(buffer.waited(Duration::from_millis(100), start + Duration::from_millis(150)), 2);
+
+    let first = buffer.take_first(1);
+    assert_eq!((first[0].0.code(), first[0].1), (30, start));
+    let rest: Vec<(u16, Instant)> = buffer.take().into_iter().map(|(event, at)| (event.code(), at)).collect();
+    assert_eq!(rest, vec![(31, start + Duration::from_millis(50)), (30, start + Duration::from_millis(120))]);
+    assert!(buffer.is_empty());
+  }
+}
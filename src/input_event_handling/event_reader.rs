@@ -1,59 +1,411 @@
 use crate::active_client::*;
-use crate::config::{Associations, Axis, Cursor, Event, Relative, Scroll};
-use crate::ruby_runtime::{RubyService};
-use crate::udev_monitor::Environment;
-use crate::virtual_devices::VirtualDevices;
+use crate::clipboard;
+use crate::conditions::{Condition, Context};
+use crate::config::{parse_modifiers, Action, Associations, Axis, Cursor, Event, LayoutTarget, Relative, Scroll, TapHoldCommand, DEFAULT_AXIS_DEADZONE, POINTER_EDGES, EVENT_TAP_MODES, HAT_DIRECTIONS, MOUSE_STICK_MODES, WHEEL_BINDING_SOURCES};
+use crate::controller_db;
+use crate::event_tap;
+use crate::history;
+use crate::live_settings;
+use crate::notifier;
+use crate::outputs;
+use crate::input_event_handling::axis_mapper::{AxisMapper, AXIS_MAPPER_TICK};
+use crate::input_event_handling::chords::{ChordEngine, ChordStep};
+use crate::input_event_handling::debounce::Debouncer;
+use crate::input_event_handling::event_sender::{modifier_events, release_synthetic_keys};
+use crate::input_event_handling::flick_stick::{normalize_axis, FlickStick};
+use crate::input_event_handling::grab_health::{Check, GrabHealth};
+use crate::input_event_handling::mouse_stick::{MouseStick, MOUSE_STICK_TICK};
+use crate::input_event_handling::pen::{Pen, STYLUS_BUTTONS, TABLET_TOOLS};
+use crate::input_event_handling::pointer_edges::{self, PointerEdges};
+use crate::input_event_handling::tap_hold::TapHoldEngine;
+use crate::input_event_handling::touch::Touchscreen;
+use crate::layouts;
+use crate::profiles;
+use crate::reload;
+use crate::remote;
+use crate::ruby_runtime::{self, DeviceIdentity, RubyService};
+use crate::session;
+use crate::state;
+use crate::stats;
+use crate::udev_monitor::{device_configs, Client, Environment, Server};
+use crate::virtual_devices::{self, Target, VirtualDevices};
 use crate::Config;
-use evdev::{AbsoluteAxisType, EventStream, EventType, InputEvent, Key, RelativeAxisType};
+use evdev::{AbsoluteAxisType, Device, EventStream, EventType, InputEvent, Key, LedType, RelativeAxisType};
 use std::{
+  collections::{HashMap, HashSet, VecDeque},
   future::Future,
   option::Option,
+  os::fd::AsRawFd,
   pin::Pin,
   str::FromStr,
   sync::Arc,
   sync::Mutex,
+  time::Duration,
 };
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::time::Instant;
 use tokio_stream::StreamExt;
 
 struct Stick {
   function: String,
+  sensitivity: u64,
   deadzone: i32,
+  activation_modifiers: Vec<Event>,
 }
 
 struct Settings {
   lstick: Stick,
   rstick: Stick,
+  invert_cursor_axis: bool,
+  invert_scroll_axis: bool,
   axis_16_bit: bool,
+  stadia: bool,
+  cursor_speed: i32,
+  cursor_accel: f64,
+  scroll_speed: i32,
+  scroll_accel: f64,
   chain_only: bool,
+  macro_pad: bool,
+  flick_stick_full_turn: f64,
+  flick_stick_threshold: f64,
   layout_switcher: Key,
+  layout_names: Vec<String>,
+  grab_device: bool,
+  idle_timeout: Option<Duration>,
+  idle_ungrab: bool,
+  modifier_timeout: Option<Duration>,
+  movement_timeout: Duration,
+  chord_timeout: Duration,
+  lock_screen_mode: String,
+  event_tap: String,
+  cursor_speed_presets: Vec<f64>,
+  cursor_speed_notify: bool,
+  device_notify: bool,
+  layer_notify: bool,
+  osd_command: Option<String>,
+  layout_led: Option<String>,
+  layout_led_brightness: Vec<u32>,
+  xkb_layouts: Vec<u32>,
+  wheel_movement_sensitivity: f64,
+  wheel_movement_accel: f64,
+  wheel_invert: bool,
+  wheel_multiplier: f64,
+  wheel_binding_source: String,
+  pen_tilt_threshold: f64,
+  screen_size: Option<(f64, f64)>,
+  mouse_stick: String,
+  mouse_stick_sensitivity: f64,
+  mouse_stick_decay: Duration,
+  axis_deadzones: HashMap<u16, f64>,
+  button_debounce: HashMap<u16, Duration>,
+  grab_check_interval: Option<Duration>,
+}
+
+impl Settings {
+  fn new(settings: &HashMap<String, String>) -> Self {
+    let lstick_function = settings.get("LSTICK").unwrap_or(&"cursor".to_string()).to_string();
+    let lstick_sensitivity: u64 = settings.get("LSTICK_SENSITIVITY").unwrap_or(&"0".to_string()).parse::<u64>().expect("Invalid LSTICK_SENSITIVITY, use a positive integer.");
+    let lstick_deadzone: i32 = settings.get("LSTICK_DEADZONE").unwrap_or(&"5".to_string()).parse::<i32>().expect("Invalid LSTICK_DEADZONE, use integer 0 to 128.");
+    let mut lstick_activation_modifiers: Vec<Event> = parse_modifiers(settings, "LSTICK_ACTIVATION_MODIFIERS").expect("Invalid LSTICK_ACTIVATION_MODIFIERS, use keys separated by -.");
+    lstick_activation_modifiers.sort();
+    let lstick = Stick {
+      function: lstick_function,
+      sensitivity: lstick_sensitivity,
+      deadzone: lstick_deadzone,
+      activation_modifiers: lstick_activation_modifiers,
+    };
+
+    let rstick_function: String = settings.get("RSTICK").unwrap_or(&"scroll".to_string()).to_string();
+    let rstick_sensitivity: u64 = settings.get("RSTICK_SENSITIVITY").unwrap_or(&"0".to_string()).parse::<u64>().expect("Invalid RSTICK_SENSITIVITY, use a positive integer.");
+    let rstick_deadzone: i32 = settings.get("RSTICK_DEADZONE").unwrap_or(&"5".to_string()).parse::<i32>().expect("Invalid RSTICK_DEADZONE, use integer 0 to 128.");
+    let mut rstick_activation_modifiers: Vec<Event> = parse_modifiers(settings, "RSTICK_ACTIVATION_MODIFIERS").expect("Invalid RSTICK_ACTIVATION_MODIFIERS, use keys separated by -.");
+    rstick_activation_modifiers.sort();
+    let rstick = Stick {
+      function: rstick_function,
+      sensitivity: rstick_sensitivity,
+      deadzone: rstick_deadzone,
+      activation_modifiers: rstick_activation_modifiers,
+    };
+
+    let invert_cursor_axis: bool = settings.get("INVERT_CURSOR_AXIS").unwrap_or(&"false".to_string()).parse().expect("Invalid INVERT_CURSOR_AXIS use true/false.");
+    let invert_scroll_axis: bool = settings.get("INVERT_SCROLL_AXIS").unwrap_or(&"false".to_string()).parse().expect("Invalid INVERT_SCROLL_AXIS use true/false.");
+    let axis_16_bit: bool = settings.get("16_BIT_AXIS").unwrap_or(&"false".to_string()).parse().expect("Invalid 16_BIT_AXIS use true/false.");
+    let stadia: bool = settings.get("STADIA").unwrap_or(&"false".to_string()).parse().expect("Invalid STADIA use true/false.");
+
+    let cursor_speed: i32 = settings.get("CURSOR_SPEED").unwrap_or(&"0".to_string()).parse().expect("Invalid CURSOR_SPEED, use an integer.");
+    let cursor_accel: f64 = settings.get("CURSOR_ACCEL").unwrap_or(&"1.0".to_string()).parse().expect("Invalid CURSOR_ACCEL, use a float between 0.0 and 1.0.");
+    let scroll_speed: i32 = settings.get("SCROLL_SPEED").unwrap_or(&"0".to_string()).parse().expect("Invalid SCROLL_SPEED, use an integer.");
+    let scroll_accel: f64 = settings.get("SCROLL_ACCEL").unwrap_or(&"1.0".to_string()).parse().expect("Invalid SCROLL_ACCEL, use a float between 0.0 and 1.0.");
+    let chain_only: bool = settings.get("CHAIN_ONLY").unwrap_or(&"true".to_string()).parse().expect("Invalid CHAIN_ONLY use true/false.");
+    let macro_pad: bool = settings.get("MACRO_PAD").unwrap_or(&"false".to_string()).parse().expect("Invalid MACRO_PAD, use true/false.");
+    let flick_stick_full_turn: f64 = settings.get("FLICK_STICK_FULL_TURN").unwrap_or(&"3000".to_string()).parse().expect("Invalid FLICK_STICK_FULL_TURN, use a number.");
+    let flick_stick_threshold: f64 = settings.get("FLICK_STICK_THRESHOLD").unwrap_or(&"0.9".to_string()).parse().expect("Invalid FLICK_STICK_THRESHOLD, use a float between 0.0 and 1.0.");
+
+    let layout_switcher: Key = Key::from_str(settings.get("LAYOUT_SWITCHER").unwrap_or(&"BTN_0".to_string())).expect("LAYOUT_SWITCHER is not a valid Key.");
+    let layout_names: Vec<String> = settings.get("LAYOUT_NAMES").map(|names| names.split(',').map(|name| name.trim().to_string()).collect()).unwrap_or_default();
+
+    let grab_device: bool = settings.get("GRAB_DEVICE").unwrap_or(&"true".to_string()).parse().unwrap_or(false);
+    let idle_timeout: Option<Duration> = settings.get("IDLE_TIMEOUT")
+      .map(|minutes| Duration::from_secs_f64(minutes.parse::<f64>().expect("Invalid IDLE_TIMEOUT, use a number of minutes.") * 60.0));
+    let grab_check_interval: Option<Duration> = match settings.get("GRAB_CHECK_INTERVAL").unwrap_or(&"5".to_string()).parse::<u64>().expect("Invalid GRAB_CHECK_INTERVAL, use a number of seconds.") {
+      0 => None,
+      seconds => Some(Duration::from_secs(seconds)),
+    };
+    let idle_ungrab: bool = settings.get("IDLE_UNGRAB").unwrap_or(&"false".to_string()).parse().expect("Invalid IDLE_UNGRAB use true/false.");
+    let modifier_timeout: Option<Duration> = settings.get("MODIFIER_TIMEOUT")
+      .map(|seconds| Duration::from_secs_f64(seconds.parse::<f64>().expect("Invalid MODIFIER_TIMEOUT, use a number of seconds.")));
+    let movement_timeout = Duration::from_millis(settings.get("MOVEMENT_TIMEOUT").unwrap_or(&"250".to_string()).parse().expect("Invalid MOVEMENT_TIMEOUT, use a number of milliseconds."));
+    let chord_timeout = Duration::from_millis(settings.get("CHORD_TIMEOUT").unwrap_or(&"50".to_string()).parse().expect("Invalid CHORD_TIMEOUT, use a number of milliseconds."));
+
+    let lock_screen_mode: String = settings.get("LOCK_SCREEN_MODE").unwrap_or(&"remap".to_string()).to_string();
+
+    let event_tap: String = settings.get("EVENT_TAP").unwrap_or(&"off".to_string()).to_string();
+    if !EVENT_TAP_MODES.contains(&event_tap.as_str()) {
+      panic!("Invalid EVENT_TAP, use off/bound/all.");
+    }
+
+    let cursor_speed_presets: Vec<f64> = settings.get("CURSOR_SPEED_PRESETS").unwrap_or(&"1.0".to_string())
+      .split(',')
+      .map(|preset| preset.trim().parse().expect("Invalid CURSOR_SPEED_PRESETS, use comma separated floats."))
+      .collect();
+    let cursor_speed_notify: bool = settings.get("CURSOR_SPEED_NOTIFY").unwrap_or(&"false".to_string()).parse().expect("Invalid CURSOR_SPEED_NOTIFY use true/false.");
+    let device_notify: bool = settings.get("DEVICE_NOTIFY").unwrap_or(&"false".to_string()).parse().expect("Invalid DEVICE_NOTIFY use true/false.");
+    let layer_notify: bool = settings.get("LAYER_NOTIFY").unwrap_or(&"false".to_string()).parse().expect("Invalid LAYER_NOTIFY use true/false.");
+    let osd_command: Option<String> = settings.get("OSD_COMMAND").cloned();
+    let layout_led: Option<String> = settings.get("LAYOUT_LED").cloned();
+    let layout_led_brightness: Vec<u32> = settings.get("LAYOUT_LED_BRIGHTNESS").unwrap_or(&"0,1,1,1".to_string())
+      .split(',')
+      .map(|brightness| brightness.trim().parse().expect("Invalid LAYOUT_LED_BRIGHTNESS, use comma separated integers."))
+      .collect();
+    let xkb_layouts: Vec<u32> = settings.get("XKB_LAYOUTS").map(|indices| indices.split(',')
+      .map(|index| index.trim().parse().expect("Invalid XKB_LAYOUTS, use comma separated integers."))
+      .collect()).unwrap_or_default();
+    let wheel_movement_sensitivity: f64 = settings.get("WHEEL_MOVEMENT_SENSITIVITY").unwrap_or(&"1.0".to_string()).parse().expect("Invalid WHEEL_MOVEMENT_SENSITIVITY, use a float.");
+    let wheel_movement_accel: f64 = settings.get("WHEEL_MOVEMENT_ACCEL").unwrap_or(&"0.0".to_string()).parse().expect("Invalid WHEEL_MOVEMENT_ACCEL, use a float.");
+    let wheel_invert: bool = settings.get("WHEEL_INVERT").unwrap_or(&"false".to_string()).parse().expect("Invalid WHEEL_INVERT, use true/false.");
+    let wheel_multiplier: f64 = settings.get("WHEEL_MULTIPLIER").unwrap_or(&"1.0".to_string()).parse().expect("Invalid WHEEL_MULTIPLIER, use a float.");
+    let wheel_binding_source: String = settings.get("WHEEL_BINDING_SOURCE").unwrap_or(&"auto".to_string()).to_string();
+    if !WHEEL_BINDING_SOURCES.contains(&wheel_binding_source.as_str()) {
+      panic!("Invalid WHEEL_BINDING_SOURCE, use auto/low_res/hi_res.");
+    }
+    let screen_size: Option<(f64, f64)> = settings.get("SCREEN_SIZE").map(|size| {
+      size.split_once('x')
+        .and_then(|(width, height)| Some((width.trim().parse().ok()?, height.trim().parse().ok()?)))
+        .expect("Invalid SCREEN_SIZE, use <width>x<height>, e.g. 2560x1440.")
+    });
+    let pen_tilt_threshold: f64 = settings.get("PEN_TILT_THRESHOLD").unwrap_or(&"0.5".to_string()).parse().ok()
+      .filter(|threshold| *threshold > 0.0 && *threshold <= 1.0)
+      .expect("Invalid PEN_TILT_THRESHOLD, use a float between 0.0 and 1.0.");
+    let mouse_stick: String = settings.get("MOUSE_STICK").unwrap_or(&"none".to_string()).to_string();
+    if !MOUSE_STICK_MODES.contains(&mouse_stick.as_str()) {
+      panic!("Invalid MOUSE_STICK, use none/left/right.");
+    }
+    let mouse_stick_sensitivity: f64 = settings.get("MOUSE_STICK_SENSITIVITY").unwrap_or(&"1.0".to_string()).parse().expect("Invalid MOUSE_STICK_SENSITIVITY, use a float.");
+    let mouse_stick_decay = Duration::from_millis(settings.get("MOUSE_STICK_DECAY").unwrap_or(&"50".to_string()).parse().expect("Invalid MOUSE_STICK_DECAY, use a number of milliseconds."));
+    let axis_deadzones: HashMap<u16, f64> = settings.get("AXIS_DEADZONES").map(String::as_str).unwrap_or_default()
+      .split(',')
+      .filter(|entry| !entry.trim().is_empty())
+      .map(|entry| {
+        let (axis, deadzone) = entry.split_once(':')
+          .and_then(|(axis, deadzone)| Some((AbsoluteAxisType::from_str(axis.trim()).ok()?, deadzone.trim().parse::<f64>().ok()?)))
+          .expect("Invalid AXIS_DEADZONES, use comma separated ABS_<NAME>:<percent> pairs.");
+        (axis.0, deadzone)
+      })
+      .collect();
+    let button_debounce: HashMap<u16, Duration> = settings.get("BUTTON_DEBOUNCE").map(String::as_str).unwrap_or_default()
+      .split(',')
+      .filter(|entry| !entry.trim().is_empty())
+      .map(|entry| {
+        let (key, window) = entry.split_once(':')
+          .and_then(|(key, window)| Some((Key::from_str(key.trim()).ok()?, window.trim().parse::<u64>().ok()?)))
+          .expect("Invalid BUTTON_DEBOUNCE, use comma separated BTN_<NAME>:<milliseconds> pairs.");
+        (key.code(), Duration::from_millis(window))
+      })
+      .collect();
+
+    Self {
+      lstick,
+      rstick,
+      invert_cursor_axis,
+      invert_scroll_axis,
+      axis_16_bit,
+      stadia,
+      cursor_speed,
+      cursor_accel,
+      scroll_speed,
+      scroll_accel,
+      chain_only,
+      macro_pad,
+      flick_stick_full_turn,
+      flick_stick_threshold,
+      layout_switcher,
+      layout_names,
+      grab_device,
+      idle_timeout,
+      idle_ungrab,
+      modifier_timeout,
+      movement_timeout,
+      chord_timeout,
+      lock_screen_mode,
+      event_tap,
+      cursor_speed_presets,
+      cursor_speed_notify,
+      device_notify,
+      layer_notify,
+      osd_command,
+      layout_led,
+      layout_led_brightness,
+      xkb_layouts,
+      wheel_movement_sensitivity,
+      wheel_movement_accel,
+      wheel_invert,
+      wheel_multiplier,
+      wheel_binding_source,
+      pen_tilt_threshold,
+      screen_size,
+      mouse_stick,
+      mouse_stick_sensitivity,
+      mouse_stick_decay,
+      axis_deadzones,
+      button_debounce,
+      grab_check_interval,
+    }
+  }
+}
+
+struct WheelMovement {
+  last_detent: Instant,
+  streak: u32,
+  cursor_remainder: (f64, f64),
+  scroll_remainder: (i32, i32),
+}
+
+// App and layout configs inherit the settings they don't set from the default config,
+// and settings changed at runtime apply on top of both. Config files are checked when they are reloaded, and the
+// settings changed at runtime are checked again here, so that one the readers can't parse is reported instead of
+// panicking.
+fn resolve_settings(config: &[Config]) -> Result<Vec<Arc<Settings>>, String> {
+  let default_config = config.iter().find(|&x| x.associations == Associations::default()).unwrap();
+  let overrides = live_settings::overrides_for(&default_config.name);
+  for (setting, value) in &overrides {
+    live_settings::validate(setting, value)?;
+  }
+  Ok(merge_settings(config, &overrides))
+}
+
+fn merge_settings(config: &[Config], overrides: &HashMap<String, String>) -> Vec<Arc<Settings>> {
+  let default_config = config.iter().find(|&x| x.associations == Associations::default()).unwrap();
+  config.iter().map(|x| {
+    let mut merged = default_config.settings.clone();
+    merged.extend(x.settings.clone());
+    merged.extend(overrides.clone());
+    Arc::new(Settings::new(&merged))
+  }).collect()
+}
+
+// -1 or 1 when the axis is further from the center of its range than the deadzone, a percentage of half the range.
+fn absolute_direction(value: i32, (minimum, maximum): (i32, i32), deadzone: f64) -> i32 {
+  let half = (maximum - minimum) as f64 / 2.0;
+  if half <= 0.0 {
+    return 0;
+  }
+  let position = (value as f64 - minimum as f64 - half) / half;
+  match position * 100.0 {
+    position if position < -deadzone => -1,
+    position if position > deadzone => 1,
+    _ => 0,
+  }
+}
+
+// The held modifiers that are down on the virtual keyboard too, i.e. all but the custom ones, which only reach it
+// when tapped. Those are the ones bindings that ignore modifiers lift and press again.
+fn emitted_modifiers(modifiers: &[Event], config: &Config) -> Vec<Event> {
+  modifiers.iter()
+    .filter(|modifier| matches!(modifier, Event::Key(_)) && !config.mapped_modifiers.custom.contains(modifier))
+    .copied()
+    .collect()
+}
+
+// The keys of an active override, and the modifiers it lifted off the virtual keyboard.
+type ActiveOverride = (Vec<Key>, Vec<Event>);
+
+// Stadia controllers report the right stick on ABS_Z/ABS_RZ and the triggers on ABS_BRAKE/ABS_GAS.
+fn stadia_event(event: InputEvent) -> InputEvent {
+  let axis = match (event.event_type(), AbsoluteAxisType(event.code())) {
+    (EventType::ABSOLUTE, AbsoluteAxisType::ABS_Z) => AbsoluteAxisType::ABS_RX,
+    (EventType::ABSOLUTE, AbsoluteAxisType::ABS_RZ) => AbsoluteAxisType::ABS_RY,
+    (EventType::ABSOLUTE, AbsoluteAxisType::ABS_BRAKE) => AbsoluteAxisType::ABS_Z,
+    (EventType::ABSOLUTE, AbsoluteAxisType::ABS_GAS) => AbsoluteAxisType::ABS_RZ,
+    _ => return event,
+  };
+  InputEvent::new(EventType::ABSOLUTE, axis.0, event.value())
+}
+
+// With MACRO_PAD, the keyboard keys of the device are given the keys of the macro pad bank in order of their codes,
+// so each of them stays the same key across restarts.
+fn macro_pad_keys(name: &str, device: &Device) -> HashMap<Key, Key> {
+  let Some(supported) = device.supported_keys() else { return HashMap::new() };
+  let keys: Vec<Key> = supported.iter().filter(|key| key.code() < Key::BTN_0.code()).collect();
+  let bank = virtual_devices::macro_pad_bank();
+  if keys.len() > bank.len() {
+    println!("[EventReader] {} has {} keys, only the first {} can be turned into macro keys.", name, keys.len(), bank.len());
+  }
+  keys.into_iter().zip(bank).collect()
 }
 
 pub struct EventReader {
-  config: Vec<Config>,
+  config: Mutex<Vec<Config>>,
   physical_input_stream: Arc<Mutex<EventStream>>,
-  virtual_devices: Arc<Mutex<VirtualDevices>>,
+  virtual_devices: VirtualDevices,
   lstick_position: Arc<Mutex<Vec<i32>>>,
   rstick_position: Arc<Mutex<Vec<i32>>>,
   cursor_movement: Arc<Mutex<(i32, i32)>>,
   scroll_movement: Arc<Mutex<(i32, i32)>>,
+  movement_keys: Mutex<HashMap<Key, (Relative, Instant)>>,
+  last_fired: Mutex<HashMap<(Event, Vec<Event>), Instant>>,
+  rate_limited: Mutex<HashSet<Event>>,
+  unmet_conditions: Mutex<HashMap<Event, Vec<Vec<Event>>>>,
+  flick_stick: Arc<Mutex<FlickStick>>,
+  mouse_stick: Mutex<MouseStick>,
+  chords: Mutex<ChordEngine>,
+  debouncer: Mutex<Debouncer>,
+  cursor_speed_preset: Arc<Mutex<usize>>,
+  pointer_remainder: Arc<Mutex<(f64, f64)>>,
+  wheel_remainders: Mutex<HashMap<u16, f64>>,
+  pointer_edges: Arc<Mutex<PointerEdges>>,
+  wheel_movement: Arc<Mutex<WheelMovement>>,
+  macro_pad_keys: HashMap<Key, Key>,
+  pen: Option<Mutex<Pen>>,
+  touchscreen: Option<Mutex<Touchscreen>>,
   modifiers: Arc<Mutex<Vec<Event>>>,
   modifier_was_activated: Arc<Mutex<bool>>,
+  active_overrides: Arc<Mutex<HashMap<Event, ActiveOverride>>>,
+  active_toggles: Arc<Mutex<Vec<Key>>>,
   active_layout: Arc<Mutex<u16>>,
+  active_profile: Arc<Mutex<Option<String>>>,
   current_config: Arc<Mutex<Config>>,
   environment: Environment,
-  settings: Settings,
+  settings: Mutex<Arc<Settings>>,
+  config_settings: Mutex<(u64, Vec<Arc<Settings>>)>,
+  config_index: Mutex<usize>,
+  active_client: Arc<Mutex<Client>>,
+  last_client_check: Arc<Mutex<Instant>>,
   ruby_service: Option<Arc<Mutex<RubyService>>>,
+  device_identity: DeviceIdentity,
+  passthrough: Mutex<Option<UnboundedReceiver<InputEvent>>>,
 }
 
 impl EventReader {
+  #[allow(clippy::too_many_arguments)]
   pub fn new(
     config: Vec<Config>,
-    virtual_devices: Arc<Mutex<VirtualDevices>>,
+    virtual_devices: VirtualDevices,
     physical_input_stream: Arc<Mutex<EventStream>>,
     modifiers: Arc<Mutex<Vec<Event>>>,
     modifier_was_activated: Arc<Mutex<bool>>,
     environment: Environment,
     ruby_service: Option<Arc<Mutex<RubyService>>>,
+    device_path: &str,
   ) -> Self {
     let mut position_vector: Vec<i32> = Vec::new();
     for i in [0, 0] {
@@ -63,74 +415,128 @@ impl EventReader {
     let rstick_position = Arc::new(Mutex::new(position_vector.clone()));
     let cursor_movement = Arc::new(Mutex::new((0, 0)));
     let scroll_movement = Arc::new(Mutex::new((0, 0)));
+    let cursor_speed_preset = Arc::new(Mutex::new(0));
+    let pointer_remainder = Arc::new(Mutex::new((0.0, 0.0)));
+    let wheel_movement = Arc::new(Mutex::new(WheelMovement {
+      last_detent: Instant::now(),
+      streak: 0,
+      cursor_remainder: (0.0, 0.0),
+      scroll_remainder: (0, 0),
+    }));
+    let active_overrides = Arc::new(Mutex::new(HashMap::new()));
+    let active_toggles = Arc::new(Mutex::new(Vec::new()));
     let active_layout: Arc<Mutex<u16>> = Arc::new(Mutex::new(0));
+    let active_profile: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
+    let active_client: Arc<Mutex<Client>> = Arc::new(Mutex::new(Client::Default));
+    let last_client_check: Arc<Mutex<Instant>> = Arc::new(Mutex::new(Instant::now() - Duration::from_secs(1)));
 
     let current_config: Arc<Mutex<Config>> = Arc::new(Mutex::new(
       config.iter().find(|&x| x.associations == Associations::default()).unwrap().clone()
     ));
-    let settings = config.iter().find(|&x| x.associations == Associations::default()).unwrap().settings.clone();
+    let flick_stick = Arc::new(Mutex::new(FlickStick::default()));
 
-    let lstick_function = settings.get("LSTICK").unwrap_or(&"cursor".to_string()).to_string();
-    let lstick_deadzone: i32 = settings.get("LSTICK_DEADZONE").unwrap_or(&"5".to_string()).parse::<i32>().expect("Invalid LSTICK_DEADZONE, use integer 0 to 128.");
-    let lstick = Stick {
-      function: lstick_function,
-      deadzone: lstick_deadzone,
+    let device_name = current_config.lock().unwrap().name.clone();
+    let pen = Pen::new(&device_name, physical_input_stream.lock().unwrap().device()).map(Mutex::new);
+    let macro_pad_keys = macro_pad_keys(&device_name, physical_input_stream.lock().unwrap().device());
+    let device_identity = DeviceIdentity::new(physical_input_stream.lock().unwrap().device(), device_path);
+    let touchscreen = match pen {
+      Some(_) => None,
+      None => Touchscreen::new(&device_name, physical_input_stream.lock().unwrap().device()).map(Mutex::new),
     };
 
-    let rstick_function: String = settings.get("RSTICK").unwrap_or(&"scroll".to_string()).to_string();
-    let rstick_deadzone: i32 = settings.get("RSTICK_DEADZONE").unwrap_or(&"5".to_string()).parse::<i32>().expect("Invalid RSTICK_DEADZONE, use integer 0 to 128.");
-    let rstick = Stick {
-      function: rstick_function,
-      deadzone: rstick_deadzone,
-    };
-
-    let axis_16_bit: bool = settings.get("16_BIT_AXIS").unwrap_or(&"false".to_string()).parse().expect("Invalid 16_BIT_AXIS use true/false.");
-    let chain_only: bool = settings.get("CHAIN_ONLY").unwrap_or(&"true".to_string()).parse().expect("Invalid CHAIN_ONLY use true/false.");
-
-    let layout_switcher: Key = Key::from_str(settings.get("LAYOUT_SWITCHER").unwrap_or(&"BTN_0".to_string())).expect("LAYOUT_SWITCHER is not a valid Key.");
-
-    let settings = Settings {
-      lstick,
-      rstick,
-      axis_16_bit,
-      chain_only,
-      layout_switcher,
-    };
+    let chords = Mutex::new(ChordEngine::new(current_config.lock().unwrap().bindings.chords.clone()));
+    let config_settings = resolve_settings(&config).unwrap_or_else(|e| {
+      println!("[EventReader] Ignoring the settings changed at runtime for {}: {}", device_name, e);
+      merge_settings(&config, &HashMap::new())
+    });
+    let config_index = config.iter().position(|x| x.associations == Associations::default()).unwrap();
+    let settings = Mutex::new(config_settings[config_index].clone());
+    let config_settings = Mutex::new((live_settings::generation(), config_settings));
+    let config_index = Mutex::new(config_index);
 
     Self {
-      config,
+      config: Mutex::new(config),
       physical_input_stream,
       virtual_devices,
       lstick_position,
       rstick_position,
       cursor_movement,
       scroll_movement,
+      movement_keys: Mutex::new(HashMap::new()),
+      last_fired: Mutex::new(HashMap::new()),
+      rate_limited: Mutex::new(HashSet::new()),
+      unmet_conditions: Mutex::new(HashMap::new()),
+      flick_stick,
+      mouse_stick: Mutex::new(MouseStick::new()),
+      chords,
+      debouncer: Mutex::new(Debouncer::new()),
+      cursor_speed_preset,
+      pointer_remainder,
+      wheel_remainders: Mutex::new(HashMap::new()),
+      pointer_edges: Arc::new(Mutex::new(PointerEdges::new())),
+      wheel_movement,
+      macro_pad_keys,
+      pen,
+      touchscreen,
       modifiers,
       modifier_was_activated,
+      active_overrides,
+      active_toggles,
       active_layout,
+      active_profile,
       current_config,
       environment,
       settings,
+      config_settings,
+      config_index,
+      active_client,
+      last_client_check,
       ruby_service,
+      passthrough: Mutex::new(Some(ruby_runtime::register_passthrough(device_path))),
+      device_identity,
     }
   }
 
   pub fn start(&self) {
     println!("[EventReader] {} detected, reading events.", self.current_config.lock().unwrap().name);
-    self.event_loop();
+    self.restore_layout();
+    self.publish_bindings();
+    self.publish_layouts();
+    self.run();
   }
 
+  // The stick and movement loops only live as long as the device's event loop.
   #[tokio::main]
+  async fn run(&self) {
+    tokio::select! {
+      _ = self.event_loop() => {}
+      _ = self.loop_2d(true) => {}
+      _ = self.loop_2d(false) => {}
+      _ = self.key_loop_2d() => {}
+    }
+  }
+
   pub async fn event_loop(&self) {
     let (
-      mut dpad_values,
+      mut hat_values,
       mut lstick_values,
       mut rstick_values,
       mut triggers_values,
       mut abs_wheel_position,
-    ) = ((0, 0), (0, 0), (0, 0), (0, 0), 0);
+    ) = ([0; 8], (0, 0), (0, 0), (0, 0), 0);
     let mut stream = self.physical_input_stream.lock().unwrap();
     let mut max_abs_wheel = 0;
+    // Devices that only report a high resolution wheel fire wheel bindings from it.
+    let low_res_wheel = stream.device().supported_relative_axes().is_some_and(|axes| axes.contains(RelativeAxisType::REL_WHEEL));
+    let mut hi_res_wheel_remainder = 0;
+    let mut tap_hold = self.tap_hold_engine();
+    let abs_ranges: HashMap<u16, (i32, i32)> = match (stream.device().supported_absolute_axes(), stream.device().get_abs_state()) {
+      (Some(axes), Ok(abs_state)) => axes.iter().map(|axis| (axis.0, (abs_state[axis.0 as usize].minimum, abs_state[axis.0 as usize].maximum))).collect(),
+      _ => HashMap::new(),
+    };
+    let mut abs_directions: HashMap<u16, i32> = HashMap::new();
+    let mut axis_mapper = self.axis_mapper(&abs_ranges);
+    let mut axis_scroll_remainder = (0, 0);
     if let Ok(abs_state) = stream.device().get_abs_state() {
       for state in abs_state {
         if state.maximum > max_abs_wheel {
@@ -139,26 +545,262 @@ impl EventReader {
       }
     }
 
+    let mut passthrough = self.passthrough.lock().unwrap().take().expect("event loop started twice");
+    self.set_layout_led();
+    // Until Makita grabbed it, the compositor kept the lock LEDs of the device up to date, which only matters for
+    // the first device though: the others, and this one when it connects again, show the lock state Makita tracks.
+    let supported_leds: Vec<LedType> = stream.device().supported_leds().map(|leds| leds.iter().collect()).unwrap_or_default();
+    let device_leds: Vec<LedType> = stream.device().get_led_state().map(|leds| leds.iter().collect()).unwrap_or_default();
+    let locks = match supported_leds.is_empty() {
+      true => state::locks(),
+      false => state::sync_locks(device_leds.clone()),
+    };
+    let changed: Vec<InputEvent> = supported_leds.iter().filter(|led| device_leds.contains(led) != locks.contains(led))
+      .map(|led| InputEvent::new(EventType::LED, led.0, locks.contains(led) as i32))
+      .collect();
+    if self.settings().grab_device && !changed.is_empty() {
+      if let Err(e) = stream.device_mut().send_events(&changed) {
+        eprintln!("[EventReader] Unable to set the lock LEDs of {}: {}", self.current_config.lock().unwrap().name, e);
+      }
+    }
+    let mut lock_key_presses = state::lock_key_presses();
+    let mut last_event = Instant::now();
+    let mut idle = false;
+    let mut release_requests = state::release_requests();
+    let mut layout_requests = state::layout_requests();
+    let device = self.current_config.lock().unwrap().name.split("::").next().unwrap_or_default().to_string();
+    let mut enabled_changes = state::enabled_changes();
+    let mut reloads = reload::reloads();
+    let mut enabled = true;
+    let mut grab_health = GrabHealth::new();
+    let mut last_grab_check = Instant::now();
+    // Modifiers held while paused, which Makita doesn't track then, to recognize the binding that resumes it.
+    let mut paused_modifiers: Vec<Event> = Vec::new();
+    let mut mouse_stick_tick = tokio::time::interval(MOUSE_STICK_TICK);
+    mouse_stick_tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut axis_mapper_tick = tokio::time::interval(AXIS_MAPPER_TICK);
+    axis_mapper_tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    if !state::device_enabled(&device) {
+      enabled = false;
+      self.pause(&mut stream, &mut tap_hold).await;
+    }
+    let mut queued: VecDeque<InputEvent> = VecDeque::new();
+
     loop {
-      let event = match stream.next().await {
-        Some(Ok(event)) => event,
-        Some(Err(e)) => {
-          eprintln!("[EventReader] Error reading event: {}", e);
-          continue;
+      // The events the tap-hold engine let through go down the pipeline one by one before more are read.
+      let Some(event) = queued.pop_front() else {
+        let deadline = tap_hold.deadline();
+        let chord_deadline = self.chords.lock().unwrap().deadline(self.settings().chord_timeout);
+        let debounce_deadline = self.debouncer.lock().unwrap().deadline();
+        let idle_deadline = self.settings().idle_timeout.map(|timeout| last_event + timeout);
+        let grab_check_deadline = self.settings().grab_check_interval.map(|interval| last_grab_check + interval);
+        let modifier_deadline = self.settings().modifier_timeout
+          .filter(|_| !self.modifiers.lock().unwrap().is_empty())
+          .map(|timeout| Instant::from_std(state::last_input()) + timeout);
+        let movement_deadline = self.movement_keys.lock().unwrap().values()
+          .map(|(_, refreshed)| *refreshed + self.settings().movement_timeout)
+          .min();
+        let mouse_stick_tilted = !self.mouse_stick.lock().unwrap().centered();
+        let axes_moving = axis_mapper.moving();
+        let events = tokio::select! {
+          event = stream.next() => match event {
+            Some(Ok(event)) => {
+              last_event = Instant::now();
+              grab_health.read();
+              state::touch_input();
+              history::record_input(&device, &event);
+              if event.event_type() == EventType::KEY {
+                state::record_physical_key(&device, Key(event.code()), event.value());
+              }
+              if event.event_type() == EventType::LED {
+                state::set_lock(LedType(event.code()), event.value() != 0);
+                continue;
+              }
+              if !enabled {
+                self.process_paused_event(event, &device, &mut paused_modifiers);
+                continue;
+              }
+              if idle {
+                idle = false;
+                if self.settings().idle_ungrab && self.settings().grab_device {
+                  println!("[EventReader] Activity on {}, grabbing device again.", self.current_config.lock().unwrap().name);
+                  if let Err(e) = stream.device_mut().grab() {
+                    eprintln!("[EventReader] Unable to grab device again: {}", e);
+                  }
+                }
+              }
+              let Some(event) = self.debouncer.lock().unwrap().process(event, &self.settings().button_debounce) else { continue };
+              tap_hold.process(event)
+            }
+            Some(Err(e)) => {
+              eprintln!("[EventReader] Error reading event: {}", e);
+              grab_health.read_failed(&e);
+              continue;
+            }
+            None => {
+              println!("[EventReader] Event stream ended");
+              break;
+            }
+          },
+          Some(event) = passthrough.recv() => {
+            self.emit_default_event(event).await;
+            continue;
+          }
+          _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => tap_hold.timeout(),
+          _ = tokio::time::sleep_until(chord_deadline.unwrap_or_else(Instant::now)), if chord_deadline.is_some() => {
+            let steps = self.chords.lock().unwrap().timeout(self.settings().chord_timeout);
+            self.run_chord_steps(steps).await;
+            Vec::new()
+          }
+          _ = tokio::time::sleep_until(debounce_deadline.unwrap_or_else(Instant::now)), if debounce_deadline.is_some() => {
+            let releases = self.debouncer.lock().unwrap().expire();
+            releases.into_iter().flat_map(|release| tap_hold.process(release)).collect()
+          }
+          _ = tokio::time::sleep_until(idle_deadline.unwrap_or_else(Instant::now)), if idle_deadline.is_some() && !idle => {
+            idle = true;
+            println!("[EventReader] {} is idle, releasing held keys.", self.current_config.lock().unwrap().name);
+            self.release_held_keys(tap_hold.reset()).await;
+            if self.settings().idle_ungrab && self.settings().grab_device {
+              if let Err(e) = stream.device_mut().ungrab() {
+                eprintln!("[EventReader] Unable to ungrab idle device: {}", e);
+              }
+            }
+            Vec::new()
+          }
+          _ = tokio::time::sleep_until(grab_check_deadline.unwrap_or_else(Instant::now)), if grab_check_deadline.is_some() => {
+            last_grab_check = Instant::now();
+            let grabbed = self.settings().grab_device && enabled && !(idle && self.settings().idle_ungrab);
+            match grab_health.check(&self.device_identity.path, stream.device().as_raw_fd(), grabbed) {
+              Check::Healthy => self.report_problem(&device, None),
+              Check::Regrabbed => {
+                println!("[EventReader] {} lost its grab, grabbed it again.", device);
+                self.report_problem(&device, None);
+              }
+              Check::Degraded(problem) => self.report_problem(&device, Some(problem)),
+            }
+            Vec::new()
+          }
+          // Modifiers that stay held without any other input most likely missed their release event.
+          _ = tokio::time::sleep_until(modifier_deadline.unwrap_or_else(Instant::now)), if modifier_deadline.is_some() => {
+            if state::last_input().elapsed() >= self.settings().modifier_timeout.unwrap_or_default() {
+              println!("[EventReader] Modifiers held for too long on {}, releasing them.", self.current_config.lock().unwrap().name);
+              self.release_modifiers();
+            }
+            Vec::new()
+          }
+          _ = tokio::time::sleep_until(movement_deadline.unwrap_or_else(Instant::now)), if movement_deadline.is_some() => {
+            self.check_movement_keys(&stream).await;
+            Vec::new()
+          }
+          // Eases the mouse stick back to the center while the mouse is still.
+          _ = mouse_stick_tick.tick(), if mouse_stick_tilted => {
+            let values = self.mouse_stick.lock().unwrap().tick(self.settings().mouse_stick_decay);
+            self.emit_mouse_stick(values);
+            Vec::new()
+          }
+          // Keeps moving while an [axes] entry in velocity mode is away from its center.
+          _ = axis_mapper_tick.tick(), if axes_moving => {
+            let motions = axis_mapper.tick();
+            self.emit_axis_motion(&motions, &mut axis_scroll_remainder);
+            Vec::new()
+          }
+          Ok(()) = enabled_changes.recv() => {
+            if state::device_enabled(&device) != enabled {
+              enabled = !enabled;
+              if enabled {
+                println!("[EventReader] Resuming {}.", device);
+                if self.settings().grab_device {
+                  if let Err(e) = stream.device_mut().grab() {
+                    eprintln!("[EventReader] Unable to grab device again: {}", e);
+                  }
+                }
+              } else {
+                paused_modifiers.clear();
+                self.pause(&mut stream, &mut tap_hold).await;
+              }
+            }
+            Vec::new()
+          }
+          Ok(()) = reloads.recv() => {
+            if !self.reload(&device, stream.device(), &mut tap_hold).await {
+              break;
+            }
+            axis_mapper = self.axis_mapper(&abs_ranges);
+            Vec::new()
+          }
+          // Ungrabbed, the compositor sets the LEDs itself.
+          Ok(led) = lock_key_presses.recv(), if !supported_leds.is_empty() => {
+            if supported_leds.contains(&led) && enabled && self.settings().grab_device && !(idle && self.settings().idle_ungrab) {
+              let on = state::lock_on(led);
+              if let Err(e) = stream.device_mut().send_events(&[InputEvent::new(EventType::LED, led.0, on as i32)]) {
+                eprintln!("[EventReader] Unable to set {:?} on {}: {}", led, device, e);
+              }
+            }
+            Vec::new()
+          }
+          Ok((requested, target)) = layout_requests.recv() => {
+            if requested == device && enabled {
+              self.jump_to_layout(&target).await;
+            }
+            Vec::new()
+          }
+          Ok(()) = release_requests.recv() => {
+            println!("[EventReader] Releasing every key held for {}.", self.current_config.lock().unwrap().name);
+            self.release_held_keys(tap_hold.reset()).await;
+            release_synthetic_keys(&self.virtual_devices);
+            Vec::new()
+          }
+        };
+
+        for (key, command, value) in tap_hold.take_commands() {
+          self.run_tap_hold_command(key, &command, value).await;
         }
-        None => {
-          println!("[EventReader] Event stream ended");
-          break;
+        queued.extend(events);
+        continue;
+      };
+      if let Some(pen) = &self.pen {
+        self.process_pen_event(pen, event).await;
+        continue;
+      }
+      if let Some(touchscreen) = &self.touchscreen {
+        let gestures = touchscreen.lock().unwrap().push(event);
+        for gesture in gestures {
+          self.convert_event(event, Event::Axis(gesture), 1, true).await;
         }
+        continue;
+      }
+      let event = match self.settings().stadia {
+        true => stadia_event(event),
+        false => event,
       };
-
       match (event.event_type(), RelativeAxisType(event.code()), AbsoluteAxisType(event.code()), false) {
+        // The layout switcher only switches on devices that have more than one layout.
+        (EventType::KEY, _, _, _) if Key(event.code()) == self.settings().layout_switcher && self.layouts().len() > 1 => {
+          if event.value() == 1 { self.change_active_layout().await; }
+        }
         (EventType::KEY, _, _, _) => self.convert_event(event, Event::Key(Key(event.code())), event.value(), false).await,
-        (EventType::RELATIVE, RelativeAxisType::REL_WHEEL | RelativeAxisType::REL_WHEEL_HI_RES, _, _, ) => match event.value() {
-          -1 => self.convert_event(event, Event::Axis(Axis::SCROLL_WHEEL_DOWN), 1, true).await,
-          1 => self.convert_event(event, Event::Axis(Axis::SCROLL_WHEEL_UP), 1, true).await,
-          _ => {}
+        (EventType::RELATIVE, RelativeAxisType::REL_X | RelativeAxisType::REL_Y, _, _) => match self.settings().mouse_stick.as_str() {
+          "none" => self.emit_pointer_motion(event).await,
+          _ => {
+            let settings = self.settings();
+            let values = self.mouse_stick.lock().unwrap().push(RelativeAxisType(event.code()), event.value(), settings.mouse_stick_sensitivity, settings.mouse_stick_decay);
+            self.emit_mouse_stick(values);
+          }
         },
+        (EventType::RELATIVE, RelativeAxisType::REL_WHEEL | RelativeAxisType::REL_WHEEL_HI_RES, _, _, ) => {
+          let hi_res_bindings = match self.settings().wheel_binding_source.as_str() {
+            "low_res" => false,
+            "hi_res" => true,
+            _ => !low_res_wheel,
+          };
+          self.process_wheel(event, hi_res_bindings, &mut hi_res_wheel_remainder).await;
+        }
+        // [axes] entries take over the axis from whatever else it's used for.
+        (EventType::ABSOLUTE, _, axis, _) if axis_mapper.maps(axis.0) => {
+          if let Some(motion) = axis_mapper.push(axis.0, event.value()) {
+            self.emit_axis_motion(&[motion], &mut axis_scroll_remainder);
+          }
+        }
         (EventType::ABSOLUTE, _, AbsoluteAxisType::ABS_WHEEL, _) => {
           let value = event.value();
           if value != 0 && abs_wheel_position != 0 {
@@ -182,56 +824,39 @@ impl EventReader {
             self.emit_default_event(event).await;
           }
         }
-        (_, _, AbsoluteAxisType::ABS_HAT0X, _) => {
+        (EventType::ABSOLUTE, _, axis, _) if (AbsoluteAxisType::ABS_HAT0X.0..=AbsoluteAxisType::ABS_HAT3Y.0).contains(&axis.0) => {
+          let index = (axis.0 - AbsoluteAxisType::ABS_HAT0X.0) as usize;
+          let [up, down, left, right] = HAT_DIRECTIONS[index / 2];
+          let (negative, positive) = if index % 2 == 1 { (up, down) } else { (left, right) };
           match event.value() {
             -1 => {
-              self.convert_event(event, Event::Axis(Axis::BTN_DPAD_LEFT), 1, false).await;
-              dpad_values.0 = -1;
+              self.convert_event(event, Event::Axis(negative), 1, false).await;
+              hat_values[index] = -1;
             }
             1 => {
-              self.convert_event(event, Event::Axis(Axis::BTN_DPAD_RIGHT), 1, false).await;
-              dpad_values.0 = 1;
+              self.convert_event(event, Event::Axis(positive), 1, false).await;
+              hat_values[index] = 1;
             }
             0 => {
-              match dpad_values.0 {
-                -1 => self.convert_event(event, Event::Axis(Axis::BTN_DPAD_LEFT), 0, false).await,
-                1 => self.convert_event(event, Event::Axis(Axis::BTN_DPAD_RIGHT), 0, false).await,
+              match hat_values[index] {
+                -1 => self.convert_event(event, Event::Axis(negative), 0, false).await,
+                1 => self.convert_event(event, Event::Axis(positive), 0, false).await,
                 _ => {}
               }
-              dpad_values.0 = 0;
+              hat_values[index] = 0;
             }
             _ => {}
           };
         }
-        (_, _, AbsoluteAxisType::ABS_HAT0Y, _) => {
-          match event.value() {
-            -1 => {
-              self.convert_event(event, Event::Axis(Axis::BTN_DPAD_UP), 1, false).await;
-              dpad_values.1 = -1;
-            }
-            1 => {
-              self.convert_event(event, Event::Axis(Axis::BTN_DPAD_DOWN), 1, false).await;
-              dpad_values.1 = 1;
-            }
-            0 => {
-              match dpad_values.1 {
-                -1 => self.convert_event(event, Event::Axis(Axis::BTN_DPAD_UP), 0, false).await,
-                1 => self.convert_event(event, Event::Axis(Axis::BTN_DPAD_DOWN), 0, false).await,
-                _ => {}
-              }
-              dpad_values.1 = 0;
-            }
-            _ => {}
-          };
-        }
-        (EventType::ABSOLUTE, _, AbsoluteAxisType::ABS_X | AbsoluteAxisType::ABS_Y, false) => match self.settings.lstick.function.as_str() {
+        (EventType::ABSOLUTE, _, AbsoluteAxisType::ABS_X | AbsoluteAxisType::ABS_Y, false) => match self.settings().lstick.function.as_str() {
+          "gamepad" => self.emit_gamepad_axis(event),
           "cursor" | "scroll" => {
-            let axis_value = self.get_axis_value(&event, &self.settings.lstick.deadzone).await;
+            let axis_value = self.get_axis_value(&event, &self.settings().lstick.deadzone).await;
             let mut lstick_position = self.lstick_position.lock().unwrap();
             lstick_position[event.code() as usize] = axis_value;
           }
           "bind" => {
-            let axis_value = self.get_axis_value(&event, &self.settings.lstick.deadzone).await;
+            let axis_value = self.get_axis_value(&event, &self.settings().lstick.deadzone).await;
             let direction = if axis_value < 0 {
               -1
             } else if axis_value > 0 {
@@ -289,14 +914,28 @@ impl EventReader {
           }
           _ => {}
         },
-        (EventType::ABSOLUTE, _, AbsoluteAxisType::ABS_RX | AbsoluteAxisType::ABS_RY, false) => match self.settings.rstick.function.as_str() {
+        (EventType::ABSOLUTE, _, AbsoluteAxisType::ABS_RX | AbsoluteAxisType::ABS_RY, false) => match self.settings().rstick.function.as_str() {
+          "gamepad" => self.emit_gamepad_axis(event),
           "cursor" | "scroll" => {
-            let axis_value = self.get_axis_value(&event, &self.settings.rstick.deadzone).await;
+            let axis_value = self.get_axis_value(&event, &self.settings().rstick.deadzone).await;
             let mut rstick_position = self.rstick_position.lock().unwrap();
             rstick_position[event.code() as usize - 3] = axis_value;
           }
+          "flick" => {
+            let settings = self.settings();
+            let value = normalize_axis(event.value(), settings.axis_16_bit);
+            let (full_turn, threshold) = (settings.flick_stick_full_turn, settings.flick_stick_threshold);
+            let counts = match AbsoluteAxisType(event.code()) {
+              AbsoluteAxisType::ABS_RX => self.flick_stick.lock().unwrap().update(Some(value), None, full_turn, threshold),
+              _ => self.flick_stick.lock().unwrap().update(None, Some(value), full_turn, threshold),
+            };
+            if counts != 0 {
+              let virtual_event = InputEvent::new_now(EventType::RELATIVE, RelativeAxisType::REL_X.0, counts);
+              self.virtual_devices.emit_motion(&[virtual_event]);
+            }
+          }
           "bind" => {
-            let axis_value = self.get_axis_value(&event, &self.settings.rstick.deadzone).await;
+            let axis_value = self.get_axis_value(&event, &self.settings().rstick.deadzone).await;
             let direction = if axis_value < 0 {
               -1
             } else if axis_value > 0 {
@@ -384,13 +1023,37 @@ impl EventReader {
             _ => {}
           }
         }
+        (EventType::ABSOLUTE, _, axis, _) if self.binds_absolute_axis(axis) => {
+          let deadzone = self.settings().axis_deadzones.get(&axis.0).copied().unwrap_or(DEFAULT_AXIS_DEADZONE);
+          let direction = absolute_direction(event.value(), abs_ranges.get(&axis.0).copied().unwrap_or_default(), deadzone);
+          let previous = abs_directions.insert(axis.0, direction).unwrap_or(0);
+          if direction != previous {
+            for (direction, value) in [(previous, 0), (direction, 1)] {
+              match direction {
+                -1 => self.convert_event(event, Event::Axis(Axis::ABS_MINUS(axis.0)), value, false).await,
+                1 => self.convert_event(event, Event::Axis(Axis::ABS_PLUS(axis.0)), value, false).await,
+                _ => {}
+              }
+            }
+          }
+        }
         _ => self.emit_default_event(event).await,
       }
     }
 
+    self.release_toggles();
+    release_synthetic_keys(&self.virtual_devices);
+    state::set_bindings(self.current_config.lock().unwrap().name.split("::").next().unwrap_or_default(), &[]);
+    state::set_layouts(&device, None);
+    state::clear_physical_keys(&device);
+    state::set_device_problem(&device, None);
     println!("[EventReader] Disconnected device \"{}\".", self.current_config.lock().unwrap().name);
+    if self.settings().device_notify {
+      notifier::notify(&self.environment, &format!("{} disconnected", self.current_config.lock().unwrap().name));
+    }
   }
 
+  // Members of [chords] entries go through the chord engine before they're converted.
   async fn convert_event(
     &self,
     default_event: InputEvent,
@@ -398,28 +1061,66 @@ impl EventReader {
     value: i32,
     send_zero: bool,
   ) {
-    // if value == 1 { self.update_config().await; };
+    let steps = self.chords.lock().unwrap().process(default_event, event, value, send_zero);
+    self.run_chord_steps(steps).await;
+  }
+
+  async fn run_chord_steps(&self, steps: Vec<ChordStep>) {
+    for step in steps {
+      match step {
+        ChordStep::Event(default_event, event, value, send_zero) => self.convert_single_event(default_event, event, value, send_zero).await,
+        ChordStep::Keys(keys, value) => self.emit_chord(&keys, value),
+      }
+    }
+  }
+
+  fn emit_chord(&self, keys: &[Key], value: i32) {
+    let virtual_devices = &self.virtual_devices;
+    let keys: Vec<&Key> = match value {
+      0 => keys.iter().rev().collect(),
+      _ => keys.iter().collect(),
+    };
+    for key in keys {
+      virtual_devices.emit(Target::Keyboard, &[InputEvent::new_now(EventType::KEY, key.code(), value)]);
+    }
+  }
+
+  async fn convert_single_event(
+    &self,
+    default_event: InputEvent,
+    event: Event,
+    value: i32,
+    send_zero: bool,
+  ) {
+    let started = Instant::now();
+    self.update_config().await;
+    if self.settings().event_tap != "off" { self.publish_event(&event, value); }
 
-    // Send physical event to Ruby for async processing
-    if let Some(ruby) = &self.ruby_service {
+    let locked = session::is_locked();
+    if locked && self.settings().lock_screen_mode == "passthrough" {
+      self.emit_default_event(default_event).await;
+      return;
+    }
+
+    if self.rate_limited(&event, value) { return; }
+    let inhibited = state::inhibited();
+    let unmet = self.unmet_conditions(&event, value);
+    let holds = |modifiers: &Vec<Event>| !unmet.contains(modifiers);
+
+    // Send physical event to Ruby for async processing. With scripts off on the lock screen, the event goes on like
+    // one without a script.
+    let scripts_locked = locked && self.settings().lock_screen_mode == "no_scripts";
+    if let Some(ruby) = self.ruby_service.as_ref().filter(|_| !inhibited && !scripts_locked) {
       let config = self.current_config.lock().unwrap();
       let modifiers = self.modifiers.lock().unwrap().clone();
 
       // Check if there's a Ruby script configured for this event
       if let Some(map) = config.bindings.rubies.get(&event) {
-        if map.get(&modifiers).is_some() {
+        if map.get(&modifiers).is_some() && holds(&modifiers) {
           let script = map.get(&modifiers).unwrap();
           // println!("[EventReader] Sending event to Ruby: {:?}; event_type: {:?}, code: {}, value: {}; script: {}", event, default_event.event_type(), default_event.code(), value, script);
-          let physical_event = crate::ruby_runtime::PhysicalEvent {
-            script: script.to_string(),
-            event_type: default_event.event_type().0,
-            code: default_event.code(),
-            value,
-            timestamp_sec: default_event.timestamp().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_secs(),
-            timestamp_nsec: default_event.timestamp().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().subsec_nanos(),
-          };
-
-          ruby.lock().unwrap().send_event(physical_event);
+          ruby.lock().unwrap().send_event(self.physical_event(script, default_event, value));
+          self.record_binding(&config, "rubies", &event, &modifiers, value, started);
 
           return;
         }
@@ -429,8 +1130,63 @@ impl EventReader {
     let config = self.current_config.lock().unwrap();
     let modifiers = self.modifiers.lock().unwrap().clone();
 
+    let active_override = match value {
+      1 => config.bindings.overrides.get(&event).and_then(|map| map.get(&modifiers)).filter(|_| holds(&modifiers))
+        .map(|event_list| (event_list.clone(), emitted_modifiers(&modifiers, &config))),
+      _ => self.active_overrides.lock().unwrap().get(&event).cloned(),
+    };
+    if let Some((event_list, lifted)) = active_override {
+      match value {
+        1 => { self.active_overrides.lock().unwrap().insert(event, (event_list.clone(), lifted.clone())); }
+        0 => { self.active_overrides.lock().unwrap().remove(&event); }
+        _ => {}
+      }
+      self.emit_event(&event_list, value, &modifiers, &config, false, true).await;
+      // Only the modifiers the override lifted and that are still held go back down.
+      if value == 0 {
+        let lifted: Vec<Event> = lifted.into_iter().filter(|modifier| modifiers.contains(modifier)).collect();
+        self.restore_modifiers(&lifted).await;
+      }
+      self.record_binding(&config, "overrides", &event, &modifiers, value, started);
+      return;
+    }
+
+    if let Some(map) = config.bindings.actions.get(&event) {
+      // Only the inhibit action still runs during do-not-disturb, so it can be ended again.
+      if let Some(action) = map.get(&modifiers).filter(|action| holds(&modifiers) && (!inhibited || matches!(action, Action::Inhibit(_)))) {
+        if value != 2 { self.run_action(action, &config.name, &event, value).await; }
+        self.record_binding(&config, "actions", &event, &modifiers, value, started);
+        return;
+      }
+    }
+
+    // Wheel targets emit one notch per input event instead of a continuous movement.
+    if let Some(Relative::Wheel(direction)) = config.bindings.movements.get(&event).and_then(|map| map.get(&modifiers)).filter(|_| holds(&modifiers)) {
+      if value == 1 {
+        // Lift held modifiers so that e.g. SHIFT-SCROLL_WHEEL_UP reaches applications as a plain horizontal notch.
+        self.emit_event(&Vec::new(), 0, &modifiers, &config, false, true).await;
+        self.emit_wheel(direction, &[]).await;
+        self.restore_modifiers(&emitted_modifiers(&modifiers, &config)).await;
+      }
+      self.record_binding(&config, "movements", &event, &modifiers, value, started);
+      return;
+    }
+
+    // Wheels and tablet rings only report detents, so their cursor and scroll movements are emitted per detent.
+    if matches!(event, Event::Axis(Axis::SCROLL_WHEEL_UP | Axis::SCROLL_WHEEL_DOWN | Axis::ABS_WHEEL_CW | Axis::ABS_WHEEL_CCW)) {
+      if let Some(movement) = config.bindings.movements.get(&event).and_then(|map| map.get(&modifiers)).filter(|_| holds(&modifiers)) {
+        if value == 1 {
+          self.emit_event(&Vec::new(), 0, &modifiers, &config, false, true).await;
+          self.emit_wheel_movement(movement).await;
+          self.restore_modifiers(&emitted_modifiers(&modifiers, &config)).await;
+        }
+        self.record_binding(&config, "movements", &event, &modifiers, value, started);
+        return;
+      }
+    }
+
     if let Some(map) = config.bindings.remap.get(&event) {
-      if let Some(event_list) = map.get(&modifiers) {
+      if let Some(event_list) = map.get(&modifiers).filter(|_| holds(&modifiers)) {
         self.emit_event(
           event_list,
           value,
@@ -450,29 +1206,34 @@ impl EventReader {
             !modifiers.is_empty(),
           ).await;
         }
+        self.record_binding(&config, "remap", &event, &modifiers, value, started);
         return;
       }
 
-      if let Some(event_list) = map.get(&vec![Event::Hold]) {
-        if !modifiers.is_empty() || self.settings.chain_only == false {
+      if let Some(event_list) = map.get(&vec![Event::Hold]).filter(|_| holds(&vec![Event::Hold])) {
+        if !modifiers.is_empty() || self.settings().chain_only == false {
           self.emit_event(event_list, value, &modifiers, &config, false, false).await;
+          self.record_binding(&config, "remap", &event, &[], value, started);
           return;
         }
       }
+    }
 
-      if let Some(map) = config.bindings.movements.get(&event) {
-        if let Some(movement) = map.get(&modifiers) {
-          if value <= 1 { self.emit_movement(movement, value).await; }
-          return;
-        };
-      }
+    if let Some(movement) = config.bindings.movements.get(&event).and_then(|map| map.get(&modifiers)).filter(|_| holds(&modifiers)) {
+      if value <= 1 { self.emit_movement(movement, value).await; }
+      if let Event::Key(key) = event { self.track_movement_key(key, movement, value); }
+      self.record_binding(&config, "movements", &event, &modifiers, value, started);
+      return;
+    }
 
-      if let Some(event_list) = map.get(&Vec::new()) {
+    if let Some(map) = config.bindings.remap.get(&event) {
+      if let Some(event_list) = map.get(&Vec::new()).filter(|_| holds(&Vec::new())) {
         self.emit_event(event_list, value, &modifiers, &config, true, false).await;
         if send_zero {
           let modifiers = self.modifiers.lock().unwrap().clone();
           self.emit_event(event_list, 0, &modifiers, &config, true, false).await;
         }
+        self.record_binding(&config, "remap", &event, &[], value, started);
         return;
       }
     }
@@ -480,6 +1241,108 @@ impl EventReader {
     self.emit_nonmapped_event(default_event, event, value, &modifiers, &config).await;
   }
 
+  // The event a Ruby script of this device is handed, with the device and time it came from.
+  fn physical_event(&self, script: &str, default_event: InputEvent, value: i32) -> crate::ruby_runtime::PhysicalEvent {
+    crate::ruby_runtime::PhysicalEvent {
+      script: script.to_string(),
+      device: self.device_identity.clone(),
+      event_type: default_event.event_type().0,
+      code: default_event.code(),
+      value,
+      timestamp_sec: default_event.timestamp().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_secs(),
+      timestamp_nsec: default_event.timestamp().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().subsec_nanos(),
+    }
+  }
+
+  // Tap-hold commands run like [actions] and [rubies] bindings of the tap-hold key.
+  async fn run_tap_hold_command(&self, key: Key, command: &TapHoldCommand, value: i32) {
+    let locked = session::is_locked();
+    if locked && self.settings().lock_screen_mode == "passthrough" { return; }
+    let inhibited = state::inhibited();
+    match command {
+      TapHoldCommand::Action(action) if inhibited && !matches!(action, Action::Inhibit(_)) => {}
+      TapHoldCommand::Action(action) => {
+        let device = self.current_config.lock().unwrap().name.clone();
+        self.run_action(action, &device, &Event::Key(key), value).await;
+      }
+      TapHoldCommand::Ruby(script) => {
+        if inhibited || (locked && self.settings().lock_screen_mode == "no_scripts") { return; }
+        match &self.ruby_service {
+          Some(ruby) => ruby.lock().unwrap().send_event(self.physical_event(script, InputEvent::new_now(EventType::KEY, key.code(), value), value)),
+          None => println!("[EventReader] Unable to run {} for {:?}, the Ruby runtime isn't running.", script, key),
+        }
+      }
+    }
+  }
+
+  // A binding with rate_limit_ms ignores presses that come sooner than that after the last one it fired for,
+  // along with their repeats and release.
+  fn rate_limited(&self, event: &Event, value: i32) -> bool {
+    let mut rate_limited = self.rate_limited.lock().unwrap();
+    if value != 1 {
+      return match value {
+        0 => rate_limited.remove(event),
+        _ => rate_limited.contains(event),
+      };
+    }
+    rate_limited.remove(event);
+    let modifiers = self.modifiers.lock().unwrap().clone();
+    let Some(rate_limit) = self.current_config.lock().unwrap().bindings.rate_limits.get(&(*event, modifiers.clone())).copied() else {
+      return false;
+    };
+    let mut last_fired = self.last_fired.lock().unwrap();
+    if last_fired.get(&(*event, modifiers.clone())).is_some_and(|fired| fired.elapsed() < rate_limit) {
+      rate_limited.insert(*event);
+      return true;
+    }
+    last_fired.insert((*event, modifiers), Instant::now());
+    false
+  }
+
+  // The modifiers of the event's bindings whose `when` condition didn't hold when it was pressed. Like rate-limited
+  // presses, their repeats and release skip those bindings too, even once the condition holds.
+  fn unmet_conditions(&self, event: &Event, value: i32) -> Vec<Vec<Event>> {
+    let mut unmet_conditions = self.unmet_conditions.lock().unwrap();
+    match value {
+      0 => return unmet_conditions.remove(event).unwrap_or_default(),
+      1 => {}
+      _ => return unmet_conditions.get(event).cloned().unwrap_or_default(),
+    }
+    let config = self.current_config.lock().unwrap();
+    let modifiers = self.modifiers.lock().unwrap().clone();
+    // The bindings convert_event looks up: with the held modifiers, without any and the chained one.
+    let conditions: Vec<(Vec<Event>, &Condition)> = [modifiers.clone(), Vec::new(), vec![Event::Hold]].into_iter()
+      .filter_map(|modifiers| config.bindings.conditions.get(&(*event, modifiers.clone())).map(|condition| (modifiers, condition)))
+      .collect();
+    let unmet: Vec<Vec<Event>> = match conditions.is_empty() {
+      true => Vec::new(),
+      false => {
+        let active_layout = *self.active_layout.lock().unwrap();
+        let context = Context {
+          modifier_count: modifiers.len(),
+          layer: self.layout_name(active_layout),
+          layout: active_layout,
+          profile: profiles::active_profile().unwrap_or("default".to_string()),
+          device: config.name.split("::").next().unwrap_or_default().to_string(),
+          held_keys: state::held_physical_keys(),
+          leds: state::locks(),
+        };
+        conditions.into_iter().filter(|(_, condition)| !condition.holds(&context)).map(|(modifiers, _)| modifiers).collect()
+      }
+    };
+    match unmet.is_empty() {
+      true => unmet_conditions.remove(event),
+      false => unmet_conditions.insert(*event, unmet.clone()),
+    };
+    unmet
+  }
+
+  // Counts presses of a binding for `makitactl stats`, along with the time it took to handle them.
+  fn record_binding(&self, config: &Config, table: &str, event: &Event, modifiers: &[Event], value: i32, started: Instant) {
+    if value != 1 { return; }
+    stats::record_binding(config.name.split("::").next().unwrap_or_default(), table, event, modifiers, started.elapsed());
+  }
+
   async fn emit_event(
     &self,
     event_list: &Vec<Key>,
@@ -489,7 +1352,7 @@ impl EventReader {
     release_keys: bool,
     ignore_modifiers: bool,
   ) {
-    let mut virtual_devices = self.virtual_devices.lock().unwrap();
+    let virtual_devices = &self.virtual_devices;
     let mut modifier_was_activated = self.modifier_was_activated.lock().unwrap();
     if release_keys && value != 2 {
       let released_keys: Vec<Key> = self.released_keys(&modifiers, &config).await;
@@ -497,16 +1360,12 @@ impl EventReader {
         if config.mapped_modifiers.all.contains(&Event::Key(key)) {
           self.toggle_modifiers(Event::Key(key), 0, &config).await;
           let virtual_event: InputEvent = InputEvent::new_now(EventType::KEY, key.code(), 0);
-          virtual_devices.keys.emit(&[virtual_event]).unwrap();
+          virtual_devices.emit(Target::Keyboard, &[virtual_event]);
         }
       }
     } else if ignore_modifiers {
-      for key in modifiers.iter() {
-        if let Event::Key(key) = key {
-          let virtual_event: InputEvent = InputEvent::new_now(EventType::KEY, key.code(), 0);
-          virtual_devices.keys.emit(&[virtual_event]).unwrap();
-        }
-      }
+      let released = modifier_events(&emitted_modifiers(modifiers, config), 0);
+      if !released.is_empty() { virtual_devices.emit(Target::Keyboard, &released); }
     }
     for key in event_list {
       if release_keys && value != 2 {
@@ -515,21 +1374,190 @@ impl EventReader {
       if config.mapped_modifiers.custom.contains(&Event::Key(*key)) {
         if value == 0 && !*modifier_was_activated {
           let virtual_event: InputEvent = InputEvent::new_now(EventType::KEY, key.code(), 1);
-          virtual_devices.keys.emit(&[virtual_event]).unwrap();
+          virtual_devices.emit(Target::Keyboard, &[virtual_event]);
           let virtual_event: InputEvent = InputEvent::new_now(EventType::KEY, key.code(), 0);
-          virtual_devices.keys.emit(&[virtual_event]).unwrap();
+          virtual_devices.emit(Target::Keyboard, &[virtual_event]);
           *modifier_was_activated = true;
         } else if value == 1 {
           *modifier_was_activated = false;
         }
       } else {
         let virtual_event: InputEvent = InputEvent::new_now(EventType::KEY, key.code(), value);
-        virtual_devices.keys.emit(&[virtual_event]).unwrap();
+        virtual_devices.emit(Target::Keyboard, &[virtual_event]);
         *modifier_was_activated = true;
       }
     }
   }
 
+  // Pressing a toggle() binding holds its keys down until it's pressed again.
+  async fn toggle_keys(&self, device: &str, keys: &[Key]) {
+    let mut active_toggles = self.active_toggles.lock().unwrap();
+    let virtual_devices = &self.virtual_devices;
+    if keys.iter().all(|key| active_toggles.contains(key)) {
+      for key in keys.iter().rev() {
+        virtual_devices.emit(Target::Keyboard, &[InputEvent::new_now(EventType::KEY, key.code(), 0)]);
+      }
+      active_toggles.retain(|key| !keys.contains(key));
+    } else {
+      let pressed: Vec<Key> = keys.iter().filter(|key| !active_toggles.contains(key)).copied().collect();
+      for key in &pressed {
+        virtual_devices.emit(Target::Keyboard, &[InputEvent::new_now(EventType::KEY, key.code(), 1)]);
+      }
+      active_toggles.extend(pressed);
+    }
+    state::set_toggles(device, &active_toggles);
+  }
+
+  fn publish_bindings(&self) {
+    let current_config = self.current_config.lock().unwrap();
+    state::set_bindings(current_config.name.split("::").next().unwrap_or_default(), &current_config.binding_list);
+  }
+
+  fn publish_layouts(&self) {
+    let device = self.current_config.lock().unwrap().name.split("::").next().unwrap_or_default().to_string();
+    let layouts = self.layouts().into_iter().map(|number| state::Layout { number, name: self.layout_name(number) }).collect();
+    let active = *self.active_layout.lock().unwrap();
+    state::set_layouts(&device, Some(state::DeviceLayouts { active, layouts }));
+  }
+
+  fn release_toggles(&self) {
+    let virtual_devices = &self.virtual_devices;
+    for key in self.active_toggles.lock().unwrap().drain(..).rev() {
+      virtual_devices.emit(Target::Keyboard, &[InputEvent::new_now(EventType::KEY, key.code(), 0)]);
+    }
+    state::set_toggles(&self.current_config.lock().unwrap().name, &[]);
+  }
+
+  async fn release_held_keys(&self, extra_keys: Vec<Key>) {
+    let mut keys: Vec<Key> = self.modifiers.lock().unwrap().drain(..).filter_map(|event| match event {
+      Event::Key(key) => Some(key),
+      _ => None,
+    }).collect();
+    keys.extend(self.active_overrides.lock().unwrap().drain().flat_map(|(_, (event_list, _))| event_list));
+    keys.extend(extra_keys);
+    keys.extend(self.chords.lock().unwrap().reset());
+    self.debouncer.lock().unwrap().clear();
+    keys.extend(self.active_toggles.lock().unwrap().drain(..));
+    *self.lstick_position.lock().unwrap() = vec![0, 0];
+    *self.rstick_position.lock().unwrap() = vec![0, 0];
+    *self.cursor_movement.lock().unwrap() = (0, 0);
+    *self.scroll_movement.lock().unwrap() = (0, 0);
+    let movements: Vec<Relative> = self.movement_keys.lock().unwrap().drain().map(|(_, (movement, _))| movement).collect();
+    for movement in movements {
+      self.emit_movement(&movement, 0).await;
+    }
+    state::set_toggles(&self.current_config.lock().unwrap().name, &[]);
+
+    let virtual_devices = &self.virtual_devices;
+    for key in keys {
+      let virtual_event: InputEvent = InputEvent::new_now(EventType::KEY, key.code(), 0);
+      virtual_devices.emit(Target::Keyboard, &[virtual_event]);
+    }
+  }
+
+  fn tap_hold_engine(&self) -> TapHoldEngine {
+    TapHoldEngine::new(
+      self.config.lock().unwrap().iter().find(|&x| x.associations == Associations::default()).unwrap().bindings.tap_hold.clone()
+    )
+  }
+
+  // Swaps in the reloaded configs of the device, staying on the active layout if it still has one. Returns false when
+  // the reader has to stop instead, for the device to be let go of or connected again with its new settings.
+  async fn reload(&self, device: &str, physical: &Device, tap_hold: &mut TapHoldEngine) -> bool {
+    let mut config = device_configs(&reload::configs(), device);
+    if config.is_empty() || reload::relaunching(device) {
+      println!("[EventReader] Stopping {} to apply its new configs.", device);
+      self.release_held_keys(tap_hold.reset()).await;
+      return false;
+    }
+    let translations = controller_db::device_translations(physical);
+    for config in config.iter_mut() {
+      config.translate_events(&translations);
+    }
+    self.release_held_keys(tap_hold.reset()).await;
+    let config_settings = resolve_settings(&config).unwrap_or_else(|e| {
+      println!("[EventReader] Ignoring the settings changed at runtime for {}: {}", device, e);
+      merge_settings(&config, &HashMap::new())
+    });
+    *self.config_settings.lock().unwrap() = (live_settings::generation(), config_settings);
+    *self.config.lock().unwrap() = config;
+    *tap_hold = self.tap_hold_engine();
+    *self.chords.lock().unwrap() = ChordEngine::new(
+      self.config.lock().unwrap().iter().find(|&x| x.associations == Associations::default()).unwrap().bindings.chords.clone()
+    );
+    if !self.layouts().contains(&*self.active_layout.lock().unwrap()) {
+      *self.active_layout.lock().unwrap() = 0;
+    }
+    let (client, profile) = (self.active_client.lock().unwrap().clone(), self.active_profile.lock().unwrap().clone());
+    self.select_config(&client, &profile);
+    self.publish_layouts();
+    println!("[EventReader] Reloaded the configs of {}.", device);
+    true
+  }
+
+  // Logs, and notifies with DEVICE_NOTIFY, when the device gets a problem or gets over it. makitactl devices lists it.
+  fn report_problem(&self, device: &str, problem: Option<String>) {
+    if !state::set_device_problem(device, problem.clone()) { return; }
+    match problem {
+      Some(problem) => {
+        eprintln!("[EventReader] {} {}.", device, problem);
+        if self.settings().device_notify {
+          notifier::notify(&self.environment, &format!("{} {}", device, problem));
+        }
+      }
+      None => println!("[EventReader] {} works again.", device),
+    }
+  }
+
+  // Lets go of everything the config holds down and ungrabs the device, so that its input goes through as is.
+  async fn pause(&self, stream: &mut EventStream, tap_hold: &mut TapHoldEngine) {
+    println!("[EventReader] Pausing {}.", self.current_config.lock().unwrap().name);
+    self.release_held_keys(tap_hold.reset()).await;
+    let values = self.mouse_stick.lock().unwrap().reset();
+    self.emit_mouse_stick(values);
+    if self.settings().grab_device {
+      if let Err(e) = stream.device_mut().ungrab() {
+        eprintln!("[EventReader] Unable to ungrab paused device: {}", e);
+      }
+    }
+  }
+
+  // Only a toggle_remapping binding does anything while the device is paused.
+  fn process_paused_event(&self, event: InputEvent, device: &str, paused_modifiers: &mut Vec<Event>) {
+    if event.event_type() != EventType::KEY { return; }
+    let config = self.current_config.lock().unwrap();
+    let key = Event::Key(Key(event.code()));
+    if config.mapped_modifiers.all.contains(&key) {
+      paused_modifiers.retain(|modifier| *modifier != key);
+      if event.value() == 1 {
+        paused_modifiers.push(key);
+        paused_modifiers.sort();
+      }
+      return;
+    }
+    let action = config.bindings.actions.get(&key).and_then(|map| map.get(paused_modifiers));
+    if event.value() == 1 && action == Some(&Action::ToggleRemapping) {
+      state::set_device_enabled(device, true);
+    }
+  }
+
+  fn release_modifiers(&self) {
+    let modifiers: Vec<Event> = self.modifiers.lock().unwrap().drain(..).collect();
+    let released = modifier_events(&modifiers, 0);
+    if !released.is_empty() { self.virtual_devices.emit(Target::Keyboard, &released); }
+  }
+
+  // Presses the modifiers lifted by emitted_modifiers again.
+  async fn restore_modifiers(&self, modifiers: &[Event]) {
+    let virtual_devices = &self.virtual_devices;
+    for key in modifiers.iter() {
+      if let Event::Key(key) = key {
+        let virtual_event: InputEvent = InputEvent::new_now(EventType::KEY, key.code(), 1);
+        virtual_devices.emit(Target::Keyboard, &[virtual_event]);
+      }
+    }
+  }
+
   async fn emit_nonmapped_event(
     &self,
     default_event: InputEvent,
@@ -538,42 +1566,226 @@ impl EventReader {
     modifiers: &Vec<Event>,
     config: &Config,
   ) {
-    let mut virtual_devices = self.virtual_devices.lock().unwrap();
+    let virtual_devices = &self.virtual_devices;
     let mut modifier_was_activated = self.modifier_was_activated.lock().unwrap();
     if config.mapped_modifiers.all.contains(&event) && value != 2 {
       let released_keys: Vec<Key> = self.released_keys(&modifiers, &config).await;
       for key in released_keys {
         self.toggle_modifiers(Event::Key(key), 0, &config).await;
         let virtual_event: InputEvent = InputEvent::new_now(EventType::KEY, key.code(), 0);
-        virtual_devices.keys.emit(&[virtual_event]).unwrap()
+        virtual_devices.emit(Target::Keyboard, &[virtual_event])
       }
     }
     self.toggle_modifiers(event, value, &config).await;
     if config.mapped_modifiers.custom.contains(&event) {
       if value == 0 && !*modifier_was_activated {
         let virtual_event: InputEvent = InputEvent::new_now(default_event.event_type(), default_event.code(), 1);
-        virtual_devices.keys.emit(&[virtual_event]).unwrap();
+        virtual_devices.emit(Target::Keyboard, &[virtual_event]);
         let virtual_event: InputEvent = InputEvent::new_now(default_event.event_type(), default_event.code(), 0);
-        virtual_devices.keys.emit(&[virtual_event]).unwrap();
+        virtual_devices.emit(Target::Keyboard, &[virtual_event]);
         *modifier_was_activated = true;
       } else if value == 1 {
         *modifier_was_activated = false;
       }
     } else {
       *modifier_was_activated = true;
-      match default_event.event_type() {
-        EventType::KEY => virtual_devices.keys.emit(&[default_event]).unwrap(),
-        EventType::RELATIVE => virtual_devices.axis.emit(&[default_event]).unwrap(),
-        _ => {}
+      // Only the transitions of triggers get here, so they're passed through fully pressed or released.
+      let default_event = match event {
+        Event::Axis(Axis::BTN_TL2 | Axis::BTN_TR2) => InputEvent::new_now(EventType::ABSOLUTE, default_event.code(), value * 255),
+        Event::Key(key) if self.settings().macro_pad && self.macro_pad_keys.contains_key(&key) => {
+          InputEvent::new_now(EventType::KEY, self.macro_pad_keys[&key].code(), default_event.value())
+        }
+        _ => default_event,
+      };
+      let Some(default_event) = self.scale_wheel(default_event) else { return };
+      if let Some(target) = virtual_devices.passthrough_target(&default_event) {
+        virtual_devices.emit(target, &[default_event]);
       }
     }
   }
 
+  // Passes a stick through to the virtual gamepad, rescaled to the range of an Xbox 360 controller.
+  fn emit_gamepad_axis(&self, event: InputEvent) {
+    let value = (normalize_axis(event.value(), self.settings().axis_16_bit) * 32767.0) as i32;
+    let virtual_event = InputEvent::new_now(EventType::ABSOLUTE, event.code(), value);
+    self.virtual_devices.emit(Target::Gamepad, &[virtual_event]);
+  }
+
+  // Emits the mouse stick's position on the stick MOUSE_STICK picks, on the virtual gamepad.
+  fn emit_mouse_stick(&self, values: Option<(i32, i32)>) {
+    let Some((x, y)) = values else { return };
+    let (x_axis, y_axis) = match self.settings().mouse_stick.as_str() {
+      "left" => (AbsoluteAxisType::ABS_X, AbsoluteAxisType::ABS_Y),
+      _ => (AbsoluteAxisType::ABS_RX, AbsoluteAxisType::ABS_RY),
+    };
+    self.virtual_devices.emit(Target::Gamepad, &[
+      InputEvent::new_now(EventType::ABSOLUTE, x_axis.0, x),
+      InputEvent::new_now(EventType::ABSOLUTE, y_axis.0, y),
+    ]);
+  }
+
   async fn emit_default_event(&self, event: InputEvent) {
-    match event.event_type() {
-      EventType::KEY => self.virtual_devices.lock().unwrap().keys.emit(&[event]).unwrap(),
-      EventType::RELATIVE => self.virtual_devices.lock().unwrap().axis.emit(&[event]).unwrap(),
-      _ => {}
+    let Some(event) = self.scale_wheel(event) else { return };
+    let virtual_devices = &self.virtual_devices;
+    if let Some(target) = virtual_devices.passthrough_target(&event) {
+      virtual_devices.emit(target, &[event]);
+    }
+  }
+
+  // Scales pointer motion by the active CURSOR_SPEED_PRESETS entry, carrying the fractions over to the next event.
+  async fn emit_pointer_motion(&self, event: InputEvent) {
+    let multiplier = self.settings().cursor_speed_presets.get(*self.cursor_speed_preset.lock().unwrap()).copied().unwrap_or(1.0);
+    if multiplier == 1.0 {
+      self.emit_default_event(event).await;
+      self.track_pointer(event).await;
+      return;
+    }
+    let scaled = {
+      let mut pointer_remainder = self.pointer_remainder.lock().unwrap();
+      let remainder = match RelativeAxisType(event.code()) {
+        RelativeAxisType::REL_X => &mut pointer_remainder.0,
+        _ => &mut pointer_remainder.1,
+      };
+      let scaled = event.value() as f64 * multiplier + *remainder;
+      *remainder = scaled.fract();
+      scaled.trunc()
+    };
+    if scaled != 0.0 {
+      let virtual_event = InputEvent::new_now(EventType::RELATIVE, event.code(), scaled as i32);
+      self.virtual_devices.emit(Target::Pointer, &[virtual_event]);
+      self.track_pointer(virtual_event).await;
+    }
+  }
+
+  // Fires the EDGE_* and CORNER_* bindings the cursor reaches. Only then is its position followed, with the
+  // compositor's help on Hyprland.
+  async fn track_pointer(&self, motion: InputEvent) {
+    let bound = {
+      let config = self.current_config.lock().unwrap();
+      POINTER_EDGES.iter().any(|edge| config.bindings.binds(&Event::Axis(*edge)))
+    };
+    if !bound {
+      return;
+    }
+    let (dx, dy) = match RelativeAxisType(motion.code()) {
+      RelativeAxisType::REL_X => (motion.value() as f64, 0.0),
+      _ => (0.0, motion.value() as f64),
+    };
+    let hyprland = matches!(&self.environment.server, Server::Connected(server) if server == "Hyprland");
+    let entered = {
+      let mut edges = self.pointer_edges.lock().unwrap();
+      if hyprland && edges.query_due() {
+        let pointer_edges = self.pointer_edges.clone();
+        tokio::task::spawn_blocking(move || {
+          if let Some((position, desktop)) = pointer_edges::query_hyprland() {
+            pointer_edges.lock().unwrap().sync(position, desktop);
+          }
+        });
+      }
+      edges.moved(dx, dy, self.settings().screen_size)
+    };
+    if let Some(edge) = entered {
+      self.convert_event(InputEvent::new(EventType::SYNCHRONIZATION, 0, 0), Event::Axis(edge), 1, true).await;
+    }
+  }
+
+  // Applies WHEEL_INVERT and WHEEL_MULTIPLIER to the wheels of the device, which app-specific configs can set for
+  // a single application, carrying the fractions over to the next event of the same axis.
+  fn scale_wheel(&self, event: InputEvent) -> Option<InputEvent> {
+    let wheels = [RelativeAxisType::REL_WHEEL, RelativeAxisType::REL_WHEEL_HI_RES, RelativeAxisType::REL_HWHEEL, RelativeAxisType::REL_HWHEEL_HI_RES];
+    if event.event_type() != EventType::RELATIVE || !wheels.contains(&RelativeAxisType(event.code())) {
+      return Some(event);
+    }
+    let settings = self.settings();
+    let factor = if settings.wheel_invert { -settings.wheel_multiplier } else { settings.wheel_multiplier };
+    if factor == 1.0 {
+      return Some(event);
+    }
+    let mut wheel_remainders = self.wheel_remainders.lock().unwrap();
+    let remainder = wheel_remainders.entry(event.code()).or_default();
+    let scaled = event.value() as f64 * factor + *remainder;
+    *remainder = scaled.fract();
+    (scaled.trunc() != 0.0).then(|| InputEvent::new_now(EventType::RELATIVE, event.code(), scaled.trunc() as i32))
+  }
+
+  // Presses the modifiers around a single wheel notch, e.g. Ctrl + wheel to zoom.
+  async fn emit_wheel(&self, direction: &Scroll, modifiers: &[Key]) {
+    let (axis, hi_res_axis, value) = match direction {
+      Scroll::SCROLL_UP => (RelativeAxisType::REL_WHEEL, RelativeAxisType::REL_WHEEL_HI_RES, 1),
+      Scroll::SCROLL_DOWN => (RelativeAxisType::REL_WHEEL, RelativeAxisType::REL_WHEEL_HI_RES, -1),
+      Scroll::SCROLL_LEFT => (RelativeAxisType::REL_HWHEEL, RelativeAxisType::REL_HWHEEL_HI_RES, -1),
+      Scroll::SCROLL_RIGHT => (RelativeAxisType::REL_HWHEEL, RelativeAxisType::REL_HWHEEL_HI_RES, 1),
+    };
+    let virtual_devices = &self.virtual_devices;
+    let presses: Vec<InputEvent> = modifiers.iter().map(|key| InputEvent::new_now(EventType::KEY, key.code(), 1)).collect();
+    let releases: Vec<InputEvent> = modifiers.iter().rev().map(|key| InputEvent::new_now(EventType::KEY, key.code(), 0)).collect();
+    if !presses.is_empty() { virtual_devices.emit(Target::Keyboard, &presses); }
+    virtual_devices.emit(Target::Pointer, &[
+      InputEvent::new_now(EventType::RELATIVE, axis.0, value),
+      InputEvent::new_now(EventType::RELATIVE, hi_res_axis.0, value * 120),
+    ]);
+    if !releases.is_empty() { virtual_devices.emit(Target::Keyboard, &releases); }
+  }
+
+  // Detents that follow each other within 150ms build up a streak, which WHEEL_MOVEMENT_ACCEL turns into speed.
+  async fn emit_wheel_movement(&self, movement: &Relative) {
+    let settings = self.settings();
+    let mut guard = self.wheel_movement.lock().unwrap();
+    let wheel = &mut *guard;
+    wheel.streak = if wheel.last_detent.elapsed() < Duration::from_millis(150) { (wheel.streak + 1).min(10) } else { 0 };
+    wheel.last_detent = Instant::now();
+    let distance = settings.wheel_movement_sensitivity * (1.0 + settings.wheel_movement_accel * wheel.streak as f64);
+
+    let mut events = Vec::new();
+    match movement {
+      // One detent moves the cursor 10 pixels at sensitivity 1.0.
+      Relative::Cursor(direction) => {
+        let (axis, sign, remainder) = match direction {
+          Cursor::CURSOR_UP => (RelativeAxisType::REL_Y, -1.0, &mut wheel.cursor_remainder.1),
+          Cursor::CURSOR_DOWN => (RelativeAxisType::REL_Y, 1.0, &mut wheel.cursor_remainder.1),
+          Cursor::CURSOR_LEFT => (RelativeAxisType::REL_X, -1.0, &mut wheel.cursor_remainder.0),
+          Cursor::CURSOR_RIGHT => (RelativeAxisType::REL_X, 1.0, &mut wheel.cursor_remainder.0),
+        };
+        let scaled = distance * 10.0 * sign + *remainder;
+        *remainder = scaled.fract();
+        if scaled.trunc() != 0.0 {
+          events.push(InputEvent::new_now(EventType::RELATIVE, axis.0, scaled.trunc() as i32));
+        }
+      }
+      // One detent scrolls one notch at sensitivity 1.0, smaller steps only go out as high resolution scrolling.
+      Relative::Scroll(direction) => {
+        let (axis, hi_res_axis, sign, remainder) = match direction {
+          Scroll::SCROLL_UP => (RelativeAxisType::REL_WHEEL, RelativeAxisType::REL_WHEEL_HI_RES, 1, &mut wheel.scroll_remainder.1),
+          Scroll::SCROLL_DOWN => (RelativeAxisType::REL_WHEEL, RelativeAxisType::REL_WHEEL_HI_RES, -1, &mut wheel.scroll_remainder.1),
+          Scroll::SCROLL_LEFT => (RelativeAxisType::REL_HWHEEL, RelativeAxisType::REL_HWHEEL_HI_RES, -1, &mut wheel.scroll_remainder.0),
+          Scroll::SCROLL_RIGHT => (RelativeAxisType::REL_HWHEEL, RelativeAxisType::REL_HWHEEL_HI_RES, 1, &mut wheel.scroll_remainder.0),
+        };
+        let hi_res = (distance * 120.0).round() as i32 * sign;
+        *remainder += hi_res;
+        events.push(InputEvent::new_now(EventType::RELATIVE, hi_res_axis.0, hi_res));
+        if *remainder / 120 != 0 {
+          events.push(InputEvent::new_now(EventType::RELATIVE, axis.0, *remainder / 120));
+          *remainder %= 120;
+        }
+      }
+      Relative::Wheel(_) => {}
+    }
+    match movement {
+      _ if events.is_empty() => {}
+      // Like the motion of the sticks, it goes to the flat pointer when there is one.
+      Relative::Cursor(_) => self.virtual_devices.emit_motion(&events),
+      _ => self.virtual_devices.emit(Target::Pointer, &events),
+    }
+  }
+
+  async fn cycle_cursor_speed(&self, device: &str) {
+    let mut preset = self.cursor_speed_preset.lock().unwrap();
+    *preset = (*preset + 1) % self.settings().cursor_speed_presets.len();
+    *self.pointer_remainder.lock().unwrap() = (0.0, 0.0);
+    let message = format!("Cursor speed {}/{}: {}x", *preset + 1, self.settings().cursor_speed_presets.len(), self.settings().cursor_speed_presets[*preset]);
+    println!("[EventReader] {} on {}.", message, device);
+    if self.settings().cursor_speed_notify {
+      notifier::notify(&self.environment, &message);
     }
   }
 
@@ -589,11 +1801,157 @@ impl EventReader {
       Relative::Scroll(Scroll::SCROLL_DOWN) => scroll_movement.1 = value,
       Relative::Scroll(Scroll::SCROLL_LEFT) => scroll_movement.0 = -value,
       Relative::Scroll(Scroll::SCROLL_RIGHT) => scroll_movement.0 = value,
+      Relative::Wheel(_) => {}
     };
   }
 
+  // Keys driving a continuous movement are refreshed by their presses and autorepeat.
+  fn track_movement_key(&self, key: Key, movement: &Relative, value: i32) {
+    let mut movement_keys = self.movement_keys.lock().unwrap();
+    match value {
+      0 => { movement_keys.remove(&key); }
+      _ => { movement_keys.insert(key, (*movement, Instant::now())); }
+    }
+  }
+
+  // A movement whose key hasn't been refreshed within MOVEMENT_TIMEOUT keeps going only as long as the kernel
+  // still reports the key as held, so that a lost release event doesn't leave the cursor drifting.
+  async fn check_movement_keys(&self, stream: &EventStream) {
+    let timeout = self.settings().movement_timeout;
+    let held = stream.device().get_key_state().ok();
+    let mut stopped = Vec::new();
+    self.movement_keys.lock().unwrap().retain(|key, (movement, refreshed)| {
+      if refreshed.elapsed() < timeout {
+        return true;
+      }
+      if held.as_ref().is_some_and(|held| held.contains(*key)) {
+        *refreshed = Instant::now();
+        return true;
+      }
+      stopped.push((*key, *movement));
+      false
+    });
+    for (key, movement) in stopped {
+      println!("[EventReader] {:?} is no longer held on {}, stopping its movement.", key, self.current_config.lock().unwrap().name);
+      self.emit_movement(&movement, 0).await;
+    }
+  }
+
+  // Moves the cursor or scrolls with a stick set to "cursor" or "scroll" once every LSTICK_SENSITIVITY or
+  // RSTICK_SENSITIVITY milliseconds, while its activation modifiers are held.
+  async fn loop_2d(&self, left: bool) {
+    let mut scroll_remainder = (0, 0);
+    loop {
+      let settings = self.settings();
+      let stick = if left { &settings.lstick } else { &settings.rstick };
+      if stick.sensitivity == 0 || !matches!(stick.function.as_str(), "cursor" | "scroll") {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        continue;
+      }
+      let position = match left {
+        true => self.lstick_position.lock().unwrap().clone(),
+        false => self.rstick_position.lock().unwrap().clone(),
+      };
+      if position[0] != 0 || position[1] != 0 {
+        let modifiers = self.modifiers.lock().unwrap().clone();
+        if stick.activation_modifiers.is_empty() || stick.activation_modifiers == modifiers {
+          // Using the stick counts as a keystroke, so custom activation modifiers don't emit their key on release.
+          *self.modifier_was_activated.lock().unwrap() = true;
+          match stick.function.as_str() {
+            "cursor" => {
+              let sign = if settings.invert_cursor_axis { -1 } else { 1 };
+              self.emit_cursor(position[0] * sign, position[1] * sign);
+            }
+            _ => {
+              let sign = if settings.invert_scroll_axis { -1 } else { 1 };
+              self.emit_scroll(position[0] * sign, -position[1] * sign, &mut scroll_remainder);
+            }
+          }
+        }
+      }
+      tokio::time::sleep(Duration::from_millis(stick.sensitivity)).await;
+    }
+  }
+
+  // Moves the cursor or scrolls every 5ms while a [movements] binding is held, speeding up by CURSOR_ACCEL and
+  // SCROLL_ACCEL of CURSOR_SPEED and SCROLL_SPEED every time until it reaches them.
+  async fn key_loop_2d(&self) {
+    let (mut cursor_fraction, mut scroll_fraction): (f64, f64) = (0.0, 0.0);
+    let mut scroll_remainder = (0, 0);
+    let mut interval = tokio::time::interval(Duration::from_millis(5));
+    loop {
+      interval.tick().await;
+      let settings = self.settings();
+      let cursor_movement = *self.cursor_movement.lock().unwrap();
+      let scroll_movement = *self.scroll_movement.lock().unwrap();
+
+      if cursor_movement == (0, 0) || settings.cursor_speed == 0 {
+        cursor_fraction = 0.0;
+      } else {
+        cursor_fraction = (cursor_fraction + settings.cursor_accel).min(1.0);
+        let speed = (settings.cursor_speed as f64 * cursor_fraction).round() as i32;
+        self.emit_cursor(cursor_movement.0 * speed, cursor_movement.1 * speed);
+      }
+
+      if scroll_movement == (0, 0) || settings.scroll_speed == 0 {
+        scroll_fraction = 0.0;
+      } else {
+        scroll_fraction = (scroll_fraction + settings.scroll_accel).min(1.0);
+        let speed = (settings.scroll_speed as f64 * scroll_fraction).round() as i32;
+        self.emit_scroll(scroll_movement.0 * speed, -scroll_movement.1 * speed, &mut scroll_remainder);
+      }
+    }
+  }
+
+  fn axis_mapper(&self, ranges: &HashMap<u16, (i32, i32)>) -> AxisMapper {
+    let config = self.config.lock().unwrap();
+    let axes = config.iter().find(|&x| x.associations == Associations::default()).unwrap().axes.clone();
+    AxisMapper::new(axes, ranges.clone())
+  }
+
+  fn emit_axis_motion(&self, motions: &[(RelativeAxisType, i32)], scroll_remainder: &mut (i32, i32)) {
+    for (axis, value) in motions {
+      match *axis {
+        RelativeAxisType::REL_X => self.emit_cursor(*value, 0),
+        RelativeAxisType::REL_Y => self.emit_cursor(0, *value),
+        RelativeAxisType::REL_HWHEEL => self.emit_scroll(*value, 0, scroll_remainder),
+        _ => self.emit_scroll(0, *value, scroll_remainder),
+      }
+    }
+  }
+
+  fn emit_cursor(&self, x: i32, y: i32) {
+    let events: Vec<InputEvent> = [(RelativeAxisType::REL_X, x), (RelativeAxisType::REL_Y, y)].iter()
+      .filter(|(_, value)| *value != 0)
+      .map(|(axis, value)| InputEvent::new_now(EventType::RELATIVE, axis.0, *value))
+      .collect();
+    if !events.is_empty() {
+      self.virtual_devices.emit_motion(&events);
+    }
+  }
+
+  // Scrolls in high resolution units, with a regular notch for every 120 of them for applications that only read those.
+  fn emit_scroll(&self, x: i32, y: i32, remainder: &mut (i32, i32)) {
+    let mut events = Vec::new();
+    for (axis, hi_res_axis, value, remainder) in [
+      (RelativeAxisType::REL_HWHEEL, RelativeAxisType::REL_HWHEEL_HI_RES, x, &mut remainder.0),
+      (RelativeAxisType::REL_WHEEL, RelativeAxisType::REL_WHEEL_HI_RES, y, &mut remainder.1),
+    ] {
+      if value == 0 { continue; }
+      events.push(InputEvent::new_now(EventType::RELATIVE, hi_res_axis.0, value));
+      *remainder += value;
+      if *remainder / 120 != 0 {
+        events.push(InputEvent::new_now(EventType::RELATIVE, axis.0, *remainder / 120));
+        *remainder %= 120;
+      }
+    }
+    if !events.is_empty() {
+      self.virtual_devices.emit(Target::Pointer, &events);
+    }
+  }
+
   async fn get_axis_value(&self, event: &InputEvent, deadzone: &i32) -> i32 {
-    let distance_from_center: i32 = match self.settings.axis_16_bit {
+    let distance_from_center: i32 = match self.settings().axis_16_bit {
       false => (event.value() - 128) * 200,
       _ => event.value(),
     };
@@ -604,6 +1962,21 @@ impl EventReader {
     }
   }
 
+  // Absolute axes without a dedicated event go through as they are, unless a config of the device binds them.
+  fn binds_absolute_axis(&self, axis: AbsoluteAxisType) -> bool {
+    let events = [Event::Axis(Axis::ABS_MINUS(axis.0)), Event::Axis(Axis::ABS_PLUS(axis.0))];
+    self.config.lock().unwrap().iter().any(|config| {
+      let bindings = &config.bindings;
+      events.iter().any(|event| {
+        bindings.remap.contains_key(event)
+          || bindings.overrides.contains_key(event)
+          || bindings.movements.contains_key(event)
+          || bindings.actions.contains_key(event)
+          || bindings.rubies.contains_key(event)
+      })
+    })
+  }
+
   async fn toggle_modifiers(&self, modifier: Event, value: i32, config: &Config) {
     let mut modifiers = self.modifiers.lock().unwrap();
     if config.mapped_modifiers.all.contains(&modifier) {
@@ -629,20 +2002,328 @@ impl EventReader {
     released_keys
   }
 
+  // Stylus buttons are only taken away from the virtual tablet when they're bound, with any modifiers.
+  // Tools used as modifiers in a binding, e.g. BTN_TOOL_RUBBER-BTN_STYLUS, count as held while in proximity.
+  async fn process_pen_event(&self, pen: &Mutex<Pen>, event: InputEvent) {
+    let stylus_button = Event::Key(Key(event.code()));
+    if event.event_type() == EventType::KEY && TABLET_TOOLS.contains(&Key(event.code())) {
+      let config = self.current_config.lock().unwrap().clone();
+      if config.mapped_modifiers.custom.contains(&stylus_button) {
+        self.toggle_modifiers(stylus_button, event.value(), &config).await;
+      }
+    }
+    if event.event_type() == EventType::KEY && STYLUS_BUTTONS.contains(&Key(event.code())) && self.has_binding(&stylus_button) {
+      self.convert_event(event, stylus_button, event.value(), false).await;
+      return;
+    }
+
+    let tilt_changes = {
+      let mut pen = pen.lock().unwrap();
+      pen.push(event, &self.virtual_devices);
+      match event.event_type() {
+        EventType::ABSOLUTE => pen.tilt_changes(&event, self.settings().pen_tilt_threshold),
+        _ => Vec::new(),
+      }
+    };
+    for (axis, value) in tilt_changes {
+      self.convert_event(event, Event::Axis(axis), value, false).await;
+    }
+  }
+
+  // Mice with a high resolution wheel send both REL_WHEEL and REL_WHEEL_HI_RES, and only one of the two fires
+  // wheel bindings. The other one is swallowed while a binding fires, and passed through for regular scrolling.
+  async fn process_wheel(&self, event: InputEvent, hi_res_bindings: bool, hi_res_remainder: &mut i32) {
+    if event.value() == 0 { return; }
+    let direction = Event::Axis(if event.value() > 0 { Axis::SCROLL_WHEEL_UP } else { Axis::SCROLL_WHEEL_DOWN });
+    let hi_res = RelativeAxisType(event.code()) == RelativeAxisType::REL_WHEEL_HI_RES;
+    let bound = self.fires_binding(&direction);
+    if hi_res != hi_res_bindings {
+      if !bound { self.emit_default_event(event).await; }
+      return;
+    }
+    if !bound {
+      self.convert_event(event, direction, 1, true).await;
+      return;
+    }
+
+    // A binding fires once per detent, which is 120 high resolution units.
+    let detents = match hi_res {
+      true => {
+        if (*hi_res_remainder > 0) != (event.value() > 0) { *hi_res_remainder = 0; }
+        *hi_res_remainder += event.value();
+        let detents = *hi_res_remainder / 120;
+        *hi_res_remainder %= 120;
+        detents
+      }
+      false => event.value(),
+    };
+    for _ in 0..detents.abs() {
+      self.convert_event(event, direction, 1, true).await;
+    }
+  }
+
+  // Whether the event fires a binding with the modifiers held right now.
+  fn fires_binding(&self, event: &Event) -> bool {
+    let config = self.current_config.lock().unwrap();
+    let modifiers = self.modifiers.lock().unwrap().clone();
+    config.bindings.remap.get(event).is_some_and(|map| map.contains_key(&modifiers) || map.contains_key(&Vec::new()))
+      || config.bindings.overrides.get(event).is_some_and(|map| map.contains_key(&modifiers))
+      || config.bindings.actions.get(event).is_some_and(|map| map.contains_key(&modifiers))
+      || config.bindings.movements.get(event).is_some_and(|map| map.contains_key(&modifiers))
+      || config.bindings.rubies.get(event).is_some_and(|map| map.contains_key(&modifiers))
+  }
+
+  fn has_binding(&self, event: &Event) -> bool {
+    let config = self.current_config.lock().unwrap();
+    config.bindings.remap.contains_key(event)
+      || config.bindings.overrides.contains_key(event)
+      || config.bindings.actions.contains_key(event)
+      || config.bindings.movements.contains_key(event)
+      || config.bindings.rubies.contains_key(event)
+  }
+
+  fn publish_event(&self, event: &Event, value: i32) {
+    let config = self.current_config.lock().unwrap();
+    let modifiers = self.modifiers.lock().unwrap().clone();
+    let bound = [&config.bindings.remap, &config.bindings.overrides]
+      .iter()
+      .any(|bindings| bindings.get(event).is_some_and(|map| map.contains_key(&modifiers)))
+      || config.bindings.actions.get(event).is_some_and(|map| map.contains_key(&modifiers))
+      || config.bindings.movements.get(event).is_some_and(|map| map.contains_key(&modifiers))
+      || config.bindings.rubies.get(event).is_some_and(|map| map.contains_key(&modifiers));
+    if bound || self.settings().event_tap == "all" {
+      event_tap::publish(&config.name, event, value, &modifiers, bound);
+    }
+  }
+
+  async fn run_action(&self, action: &Action, device: &str, event: &Event, value: i32) {
+    match action {
+      Action::Profile(profile) => if value == 1 { profiles::set_active_profile(profile.clone()) },
+      Action::Layout(target) => if value == 1 { self.jump_to_layout(target).await },
+      Action::Wheel { direction, modifiers } => if value == 1 { self.emit_wheel(direction, modifiers).await },
+      Action::Hold(keys, duration) => if value == 1 {
+        // Timed on its own task, so the physical key can be released right away.
+        let (keys, duration, virtual_devices) = (keys.clone(), *duration, self.virtual_devices.clone());
+        tokio::spawn(async move {
+          for key in &keys {
+            virtual_devices.emit(Target::Keyboard, &[InputEvent::new_now(EventType::KEY, key.code(), 1)]);
+            state::set_synthetic_key(*key, 1);
+          }
+          tokio::time::sleep(duration).await;
+          for key in keys.iter().rev() {
+            virtual_devices.emit(Target::Keyboard, &[InputEvent::new_now(EventType::KEY, key.code(), 0)]);
+            state::set_synthetic_key(*key, 0);
+          }
+        });
+      },
+      Action::Toggle(keys) => if value == 1 { self.toggle_keys(device, keys).await },
+      Action::Lock(led, on) => if value == 1 {
+        if let Some(key) = state::lock_tap(*led, *on) {
+          let virtual_devices = &self.virtual_devices;
+          virtual_devices.emit(Target::Keyboard, &[InputEvent::new_now(EventType::KEY, key.code(), 1)]);
+          virtual_devices.emit(Target::Keyboard, &[InputEvent::new_now(EventType::KEY, key.code(), 0)]);
+        }
+      },
+      Action::CycleCursorSpeed => if value == 1 { self.cycle_cursor_speed(device).await },
+      Action::ToggleRemapping => if value == 1 {
+        let device = device.split("::").next().unwrap_or_default();
+        state::set_device_enabled(device, !state::device_enabled(device));
+      },
+      Action::Inhibit(minutes) => if value == 1 {
+        state::inhibit(if state::inhibited() { Some(0.0) } else { minutes.map(|minutes| minutes as f64) });
+      },
+      Action::Remote(command) => if value == 1 { remote::set_forwarding(*command, &self.virtual_devices) },
+      Action::PasteClipboard(delay) => if value == 1 {
+        match clipboard::read_clipboard(&self.environment) {
+          Some(text) => { tokio::spawn(clipboard::type_text(text, *delay, self.virtual_devices.clone(), self.environment.clone())); }
+          None => println!("[Clipboard] Unable to read the clipboard, install wl-clipboard or xclip."),
+        }
+      },
+      Action::Osc { target, address, payload } => {
+        let (target, address, payload) = (target.clone(), outputs::render(address, device, event, value), outputs::render(payload, device, event, value));
+        tokio::spawn(async move {
+          if let Err(e) = outputs::send_osc(&target, &address, &payload).await {
+            eprintln!("[Outputs] Unable to send OSC message to {}: {}", target, e);
+          }
+        });
+      }
+      Action::Mqtt { broker, topic, payload } => {
+        let (broker, topic, payload) = (broker.clone(), outputs::render(topic, device, event, value), outputs::render(payload, device, event, value));
+        tokio::spawn(async move {
+          if let Err(e) = outputs::publish_mqtt(&broker, &topic, &payload).await {
+            eprintln!("[Outputs] Unable to publish MQTT message to {}: {}", topic, e);
+          }
+        });
+      }
+    }
+  }
+
+  // Picks up settings changed at runtime through IPC or Ruby.
+  fn settings(&self) -> Arc<Settings> {
+    let mut config_settings = self.config_settings.lock().unwrap();
+    let generation = live_settings::generation();
+    if config_settings.0 != generation {
+      match resolve_settings(&self.config.lock().unwrap()) {
+        Ok(settings) => {
+          *config_settings = (generation, settings);
+          *self.settings.lock().unwrap() = config_settings.1[*self.config_index.lock().unwrap()].clone();
+        }
+        // Keeps the settings in use, and only reports it once per change.
+        Err(e) => {
+          println!("[EventReader] Ignoring the settings changed at runtime: {}", e);
+          config_settings.0 = generation;
+        }
+      }
+    }
+    self.settings.lock().unwrap().clone()
+  }
+
+  // Resolves the config, and with it the settings, for the active layout, application and profile.
+  async fn update_config(&self) {
+    let profile = profiles::active_profile();
+    let has_app_configs = self.config.lock().unwrap().iter().any(|x| x.associations.client != Client::Default);
+    // Looking up the focused window can spawn a process, so it's checked at most every 200ms.
+    let client = if has_app_configs && self.last_client_check.lock().unwrap().elapsed() >= Duration::from_millis(200) {
+      *self.last_client_check.lock().unwrap() = Instant::now();
+      let config = self.config.lock().unwrap().clone();
+      get_active_window(&self.environment, &config).await
+    } else {
+      self.active_client.lock().unwrap().clone()
+    };
+    let profile_changed = *self.active_profile.lock().unwrap() != profile;
+    if !profile_changed && *self.active_client.lock().unwrap() == client { return; }
+
+    if profile_changed { self.release_toggles(); }
+    self.select_config(&client, &profile);
+    *self.active_profile.lock().unwrap() = profile;
+    *self.active_client.lock().unwrap() = client;
+    if profile_changed { self.show_layer(); }
+  }
+
+  // The config for the active layout, falling back to the default client and profile.
+  fn select_config(&self, client: &Client, profile: &Option<String>) {
+    let active_layout = *self.active_layout.lock().unwrap();
+    let config = self.config.lock().unwrap();
+    let find_config = |client: &Client, profile: &Option<String>| config.iter().position(|x| {
+      x.associations.layout == active_layout && x.associations.client == *client && x.associations.profile == *profile
+    });
+    let index = find_config(client, profile)
+      .or_else(|| find_config(client, &None))
+      .or_else(|| find_config(&Client::Default, profile))
+      .or_else(|| find_config(&Client::Default, &None));
+    if let Some(index) = index {
+      *self.current_config.lock().unwrap() = config[index].clone();
+      drop(config);
+      *self.settings.lock().unwrap() = self.config_settings.lock().unwrap().1[index].clone();
+      *self.config_index.lock().unwrap() = index;
+      self.publish_bindings();
+    }
+  }
+
+  // The layouts declared by the device's configs, in order.
+  fn layouts(&self) -> Vec<u16> {
+    let mut layouts: Vec<u16> = self.config.lock().unwrap().iter().map(|x| x.associations.layout).collect();
+    layouts.sort();
+    layouts.dedup();
+    layouts
+  }
+
+  fn layout_name(&self, layout: u16) -> String {
+    self.settings().layout_names.get(layout as usize).filter(|name| !name.is_empty()).cloned().unwrap_or(layout.to_string())
+  }
+
+  // The brightness list has one entry per layout, the last one is used for the layouts after it.
+  fn set_layout_led(&self) {
+    let settings = self.settings();
+    let Some(path) = settings.layout_led.as_deref() else { return };
+    let layout = *self.active_layout.lock().unwrap() as usize;
+    if let Some(brightness) = settings.layout_led_brightness.get(layout).or(settings.layout_led_brightness.last()) {
+      outputs::set_led(path, *brightness);
+    }
+  }
+
+  // Keeps the XKB layout of the compositor in lockstep with the layouts, the last entry of XKB_LAYOUTS is used for the
+  // layouts after it.
+  fn switch_xkb_layout(&self) {
+    let settings = self.settings();
+    let layout = *self.active_layout.lock().unwrap() as usize;
+    if let Some(index) = settings.xkb_layouts.get(layout).or(settings.xkb_layouts.last()) {
+      notifier::switch_xkb_layout(&self.environment, *index);
+    }
+  }
+
+  fn show_layer(&self) {
+    let settings = self.settings();
+    if !settings.layer_notify && settings.osd_command.is_none() { return; }
+    let device = self.current_config.lock().unwrap().name.split("::").next().unwrap_or_default().to_string();
+    let profile = self.active_profile.lock().unwrap().clone().unwrap_or("default".to_string());
+    let layout = self.layout_name(*self.active_layout.lock().unwrap());
+    notifier::show_layer(&self.environment, settings.layer_notify, settings.osd_command.as_deref(), &device, &layout, &profile);
+  }
+
+  // Cycles to the next layout that has a config for the focused window, or a default one to fall back to.
   async fn change_active_layout(&self) {
-    let mut active_layout = self.active_layout.lock().unwrap();
-    let active_window = get_active_window(&self.environment, &self.config).await;
-    loop {
-      if *active_layout == 3 {
-        *active_layout = 0
-      } else {
-        *active_layout += 1
-      };
-      if let Some(_) = self.config.iter().find(|&x| {
-        x.associations.layout == *active_layout && x.associations.client == active_window
-      }) {
-        break;
-      };
+    let config = self.config.lock().unwrap().clone();
+    let active_window = get_active_window(&self.environment, &config).await;
+    let available: Vec<u16> = self.layouts().into_iter().filter(|layout| config.iter().any(|x| {
+      x.associations.layout == *layout && (x.associations.client == active_window || x.associations.client == Client::Default)
+    })).collect();
+    let current = *self.active_layout.lock().unwrap();
+    let Some(next) = available.iter().find(|layout| **layout > current).or(available.first()).copied().filter(|next| *next != current) else {
+      println!("[EventReader] No other layout to switch to on {}, staying on layout {}.", self.current_config.lock().unwrap().name, self.layout_name(current));
+      return;
+    };
+    self.set_active_layout(next, active_window);
+  }
+
+  // For layout() actions, which pick the layout by number or by name.
+  async fn jump_to_layout(&self, target: &LayoutTarget) {
+    let layouts = self.layouts();
+    let layout = match target {
+      LayoutTarget::Number(number) => layouts.iter().find(|layout| *layout == number),
+      LayoutTarget::Name(name) => layouts.iter().find(|layout| self.layout_name(**layout) == *name),
+    };
+    match layout {
+      Some(layout) if *layout == *self.active_layout.lock().unwrap() => {}
+      Some(layout) => {
+        let config = self.config.lock().unwrap().clone();
+        self.set_active_layout(*layout, get_active_window(&self.environment, &config).await)
+      }
+      None => println!("[EventReader] {} has no layout {:?}.", self.current_config.lock().unwrap().name, target),
     }
   }
+
+  // Picks up the layout the device was on when Makita last ran, if its configs still have it.
+  fn restore_layout(&self) {
+    let device = self.current_config.lock().unwrap().name.split("::").next().unwrap_or_default().to_string();
+    let Some(layout) = layouts::saved_layout(&device).filter(|layout| self.layouts().contains(layout)) else { return };
+    println!("[EventReader] Restoring layout {} on {}.", self.layout_name(layout), device);
+    *self.active_layout.lock().unwrap() = layout;
+    self.select_config(&Client::Default, &profiles::active_profile());
+  }
+
+  fn set_active_layout(&self, layout: u16, active_window: Client) {
+    *self.active_layout.lock().unwrap() = layout;
+    layouts::save_layout(self.current_config.lock().unwrap().name.split("::").next().unwrap_or_default(), layout);
+    println!("[EventReader] Switched {} to layout {}.", self.current_config.lock().unwrap().name, self.layout_name(layout));
+    let profile = self.active_profile.lock().unwrap().clone();
+    self.select_config(&active_window, &profile);
+    *self.active_client.lock().unwrap() = active_window;
+    self.publish_layouts();
+    self.set_layout_led();
+    self.switch_xkb_layout();
+    self.show_layer();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn lifts_only_the_modifiers_the_virtual_keyboard_holds() {
+    let config = Config::parse("[overrides]\n\"KEY_CAPSLOCK-KEY_LEFTCTRL-KEY_J\" = [\"KEY_DOWN\"]", String::new()).unwrap();
+    let modifiers = vec![Event::Key(Key::KEY_CAPSLOCK), Event::Key(Key::KEY_LEFTCTRL), Event::Axis(Axis::BTN_TL2)];
+    assert_eq!(emitted_modifiers(&modifiers, &config), vec![Event::Key(Key::KEY_LEFTCTRL)]);
+  }
 }
@@ -1,36 +1,127 @@
+use crate::config::Event;
 use crate::ruby_runtime::SyntheticEvent;
-use crate::virtual_devices::VirtualDevices;
-use evdev::{EventType, InputEvent};
-use std::sync::{Arc, Mutex};
-use std::thread::sleep;
-use std::time::Duration;
+use crate::state;
+use crate::virtual_devices::{Target, VirtualDevices};
+use evdev::{EventType, InputEvent, Key};
+use std::thread::{self, JoinHandle};
 use crossbeam_channel::Receiver;
 
+#[derive(Default)]
+struct Frame {
+  keys: Vec<InputEvent>,
+  axis: Vec<InputEvent>,
+  abs: Vec<InputEvent>,
+  gamepad: Vec<InputEvent>,
+}
+
+impl Frame {
+  fn is_empty(&self) -> bool {
+    self.keys.is_empty() && self.axis.is_empty() && self.abs.is_empty() && self.gamepad.is_empty()
+  }
+
+  fn push(&mut self, target: Target, event: InputEvent) {
+    match target {
+      Target::Keyboard => self.keys.push(event),
+      Target::Pointer => self.axis.push(event),
+      Target::Absolute => self.abs.push(event),
+      Target::Gamepad => self.gamepad.push(event),
+    }
+  }
+}
+
+// Emits the events sent by Ruby scripts. It blocks on the channel, so it gets a thread of its own
+// instead of a tokio worker.
 pub struct EventSender {
   synthetic_event_receiver: Receiver<SyntheticEvent>,
-  virtual_devices: Arc<Mutex<VirtualDevices>>,
+  virtual_devices: VirtualDevices,
+  release_modifiers: bool,
+}
+
+// Key events for the modifier keys among the held modifiers, e.g. to release them on the virtual keyboard.
+pub fn modifier_events(modifiers: &[Event], value: i32) -> Vec<InputEvent> {
+  modifiers.iter()
+    .filter_map(|modifier| match modifier {
+      Event::Key(key) => Some(InputEvent::new_now(EventType::KEY, key.code(), value)),
+      _ => None,
+    })
+    .collect()
 }
 
 impl EventSender {
-  pub fn new(synthetic_event_receiver: Receiver<SyntheticEvent>, virtual_devices: Arc<Mutex<VirtualDevices>>) -> Self {
-    Self { synthetic_event_receiver, virtual_devices }
+  pub fn new(synthetic_event_receiver: Receiver<SyntheticEvent>, virtual_devices: VirtualDevices) -> Self {
+    Self { synthetic_event_receiver, virtual_devices, release_modifiers: false }
   }
 
-  pub fn start(&self) -> Result<(), Box<dyn std::error::Error>> {
-    loop {
-      println!("[EventSender] Waiting for synthetic events");
-      let event = self.synthetic_event_receiver.recv().unwrap();
-      let input_event = InputEvent::new(EventType(event.event_type), event.code, event.value);
+  // Lifts the physically held modifiers around every frame of key events from a script, so that e.g. a
+  // script bound to ctrl-KEY_J can type text without ctrl, and presses them again afterwards.
+  pub fn release_modifiers(mut self, release_modifiers: bool) -> Self {
+    self.release_modifiers = release_modifiers;
+    self
+  }
 
-      let mut virtual_devices = self.virtual_devices.lock().unwrap();
+  pub fn spawn(self) -> JoinHandle<()> {
+    thread::spawn(move || {
+      if let Err(e) = self.start() {
+        eprintln!("[EventSender] Error: {}", e);
+      }
+    })
+  }
 
-      match EventType(event.event_type) {
-        EventType::KEY | EventType::SWITCH => virtual_devices.keys.emit(&[input_event]).unwrap(),
-        EventType::RELATIVE => virtual_devices.axis.emit(&[input_event]).unwrap(),
-        _ => virtual_devices.keys.emit(&[input_event]).unwrap(),
+  // Events are collected until a SYN_REPORT, or until the channel is drained, and emitted together so
+  // that a frame reaches applications at once. Returns when every sender is gone.
+  pub fn start(&self) -> Result<(), Box<dyn std::error::Error>> {
+    let mut frame = Frame::default();
+    while let Ok(first) = self.synthetic_event_receiver.recv() {
+      let mut next = Some(first);
+      while let Some(event) = next {
+        let input_event = InputEvent::new(EventType(event.event_type), event.code, event.value);
+        match input_event.event_type() {
+          EventType::SYNCHRONIZATION => self.flush(&mut frame),
+          event_type => frame.push(event.target.unwrap_or(Target::for_event_type(event_type)), input_event),
+        }
+        next = self.synthetic_event_receiver.try_recv().ok();
       }
+      self.flush(&mut frame);
+    }
+    Ok(())
+  }
 
-      sleep(Duration::from_micros(10));
+  // VirtualDevice::emit appends its own SYN_REPORT.
+  fn flush(&self, frame: &mut Frame) {
+    if frame.is_empty() { return; }
+    let virtual_devices = &self.virtual_devices;
+    if !frame.keys.is_empty() {
+      // Modifiers the script pressed itself stay as they are.
+      let held = |value| -> Vec<InputEvent> {
+        if !self.release_modifiers { return Vec::new(); }
+        let synthetic_keys = state::synthetic_keys();
+        modifier_events(&state::held_modifiers(), value).into_iter()
+          .filter(|event| !synthetic_keys.contains(&Key(event.code())))
+          .collect()
+      };
+      let released = held(0);
+      if !released.is_empty() { virtual_devices.emit(Target::Keyboard, &released); }
+      virtual_devices.emit(Target::Keyboard, &frame.keys);
+      for event in frame.keys.iter().filter(|event| event.event_type() == EventType::KEY) {
+        state::set_synthetic_key(Key(event.code()), event.value());
+      }
+      let restored = held(1);
+      if !restored.is_empty() { virtual_devices.emit(Target::Keyboard, &restored); }
     }
+    if !frame.axis.is_empty() { virtual_devices.emit(Target::Pointer, &frame.axis); }
+    if !frame.abs.is_empty() { virtual_devices.emit(Target::Absolute, &frame.abs); }
+    if !frame.gamepad.is_empty() { virtual_devices.emit(Target::Gamepad, &frame.gamepad); }
+    *frame = Frame::default();
+  }
+}
+
+// Lets go of every key scripts and hold() actions still hold, e.g. when a device disconnects or Makita stops.
+pub fn release_synthetic_keys(virtual_devices: &VirtualDevices) {
+  let releases: Vec<InputEvent> = state::take_synthetic_keys().iter().rev()
+    .map(|key| InputEvent::new_now(EventType::KEY, key.code(), 0))
+    .collect();
+  if !releases.is_empty() {
+    println!("[EventSender] Releasing {} key(s) held by scripts.", releases.len());
+    virtual_devices.emit(Target::Keyboard, &releases);
   }
 }
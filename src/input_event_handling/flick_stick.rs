@@ -0,0 +1,54 @@
+use std::f64::consts::PI;
+
+#[derive(Default)]
+pub struct FlickStick {
+  position: (f64, f64),
+  last_angle: Option<f64>,
+  remainder: f64,
+}
+
+impl FlickStick {
+  // Takes a stick position normalized to -1.0..1.0 and returns the REL_X counts to emit.
+  // The first update past the threshold flicks by the stick's angle from "up",
+  // every following one rotates by the angle travelled since the previous update.
+  pub fn update(&mut self, x: Option<f64>, y: Option<f64>, full_turn: f64, threshold: f64) -> i32 {
+    if let Some(x) = x { self.position.0 = x; }
+    if let Some(y) = y { self.position.1 = y; }
+
+    let (x, y) = self.position;
+    if (x * x + y * y).sqrt() < threshold {
+      self.last_angle = None;
+      self.remainder = 0.0;
+      return 0;
+    }
+
+    let angle = x.atan2(-y);
+    let rotation = match self.last_angle {
+      None => angle,
+      Some(last_angle) => wrap_angle(angle - last_angle),
+    };
+    self.last_angle = Some(angle);
+
+    let counts = rotation / (2.0 * PI) * full_turn + self.remainder;
+    self.remainder = counts.fract();
+    counts.trunc() as i32
+  }
+}
+
+pub fn normalize_axis(value: i32, axis_16_bit: bool) -> f64 {
+  let normalized = match axis_16_bit {
+    true => value as f64 / 32768.0,
+    false => (value - 128) as f64 / 128.0,
+  };
+  normalized.clamp(-1.0, 1.0)
+}
+
+fn wrap_angle(angle: f64) -> f64 {
+  if angle > PI {
+    angle - 2.0 * PI
+  } else if angle < -PI {
+    angle + 2.0 * PI
+  } else {
+    angle
+  }
+}
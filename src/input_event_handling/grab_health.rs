@@ -0,0 +1,99 @@
+use nix::errno::Errno;
+use std::{fs::File, io, os::fd::{AsRawFd, RawFd}};
+
+nix::ioctl_write_int!(eviocgrab, b'E', 0x90);
+
+pub enum Check {
+  Healthy,
+  Regrabbed,
+  Degraded(String),
+}
+
+// Checks every GRAB_CHECK_INTERVAL that the device still only goes to Makita and that its events still come in.
+// evdev remembers having grabbed the device, so a grab lost behind its back, e.g. to another program ungrabbing
+// and grabbing the node, or to a driver reset, would otherwise go unnoticed while the input doubles up.
+pub struct GrabHealth {
+  stream_error: Option<String>,
+  read_since_check: bool,
+}
+
+impl GrabHealth {
+  pub fn new() -> Self {
+    Self { stream_error: None, read_since_check: false }
+  }
+
+  pub fn read(&mut self) {
+    self.read_since_check = true;
+  }
+
+  pub fn read_failed(&mut self, error: &io::Error) {
+    self.stream_error = Some(error.to_string());
+  }
+
+  // grabbed is whether Makita means to hold the grab right now, i.e. not while paused or ungrabbed for being idle.
+  pub fn check(&mut self, path: &str, fd: RawFd, grabbed: bool) -> Check {
+    let read = std::mem::take(&mut self.read_since_check);
+    if let Some(error) = self.stream_error.take().filter(|_| !read) {
+      return Check::Degraded(format!("can't be read anymore: {}", error));
+    }
+    if !grabbed {
+      return Check::Healthy;
+    }
+    match grab_held(path) {
+      Ok(true) => Check::Healthy,
+      Ok(false) => match unsafe { eviocgrab(fd, 1) } {
+        Ok(_) => Check::Regrabbed,
+        Err(Errno::EBUSY) => Check::Degraded("lost its grab to another program".to_string()),
+        Err(e) => Check::Degraded(format!("lost its grab and can't grab it again: {}", e)),
+      },
+      Err(e) => Check::Degraded(format!("can't have its grab checked: {}", e)),
+    }
+  }
+}
+
+impl Default for GrabHealth {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+// Whether any descriptor of the device holds its grab: grabbing it through another one fails then. When none does,
+// the probe's grab is released right away.
+fn grab_held(path: &str) -> io::Result<bool> {
+  let file = File::open(path)?;
+  match unsafe { eviocgrab(file.as_raw_fd(), 1) } {
+    Ok(_) => {
+      let _ = unsafe { eviocgrab(file.as_raw_fd(), 0) };
+      Ok(false)
+    }
+    Err(Errno::EBUSY) => Ok(true),
+    Err(e) => Err(e.into()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::state;
+
+  #[test]
+  fn reports_devices_that_stopped_being_read_or_grabbed() {
+    let mut health = GrabHealth::new();
+    health.read_failed(&io::Error::from_raw_os_error(19));
+    assert!(matches!(health.check("/dev/input/none", -1, false), Check::Degraded(problem) if problem.starts_with("can't be read anymore")));
+    health.read_failed(&io::Error::from_raw_os_error(19));
+    health.read();
+    assert!(matches!(health.check("/dev/input/none", -1, false), Check::Healthy));
+    assert!(matches!(health.check("/dev/input/none", -1, true), Check::Degraded(problem) if problem.starts_with("can't have its grab checked")));
+  }
+
+  #[test]
+  fn reports_a_problem_once_until_it_changes() {
+    let _state = state::isolated();
+    assert!(state::set_device_problem("Keyboard", Some("lost its grab".to_string())));
+    assert!(!state::set_device_problem("Keyboard", Some("lost its grab".to_string())));
+    assert!(state::set_device_problem("Keyboard", Some("can't be read anymore".to_string())));
+    assert!(state::set_device_problem("Keyboard", None));
+    assert!(!state::set_device_problem("Keyboard", None));
+  }
+}
@@ -1,2 +1,13 @@
+pub mod axis_mapper;
+pub mod chords;
+pub mod debounce;
+pub mod event_buffer;
 pub mod event_reader;
 pub mod event_sender;
+pub mod flick_stick;
+pub mod grab_health;
+pub mod mouse_stick;
+pub mod pen;
+pub mod pointer_edges;
+pub mod tap_hold;
+pub mod touch;
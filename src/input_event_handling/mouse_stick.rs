@@ -0,0 +1,75 @@
+use evdev::RelativeAxisType;
+use std::time::Duration;
+use tokio::time::Instant;
+
+// How often a tilted stick is eased back towards the center while the mouse is still.
+pub const MOUSE_STICK_TICK: Duration = Duration::from_millis(8);
+
+// Turns relative mouse motion into a stick position: every count tilts the stick further in its direction,
+// and the tilt decays back to the center over time, so the stick's deflection follows the mouse's speed.
+pub struct MouseStick {
+  position: (f64, f64),
+  emitted: (i32, i32),
+  updated: Instant,
+}
+
+impl MouseStick {
+  pub fn new() -> Self {
+    Self {
+      position: (0.0, 0.0),
+      emitted: (0, 0),
+      updated: Instant::now(),
+    }
+  }
+
+  // At sensitivity 1.0, 100 counts tilt the stick all the way. Returns the stick values to emit, if they changed.
+  pub fn push(&mut self, axis: RelativeAxisType, counts: i32, sensitivity: f64, half_life: Duration) -> Option<(i32, i32)> {
+    self.decay(half_life);
+    let position = match axis {
+      RelativeAxisType::REL_X => &mut self.position.0,
+      _ => &mut self.position.1,
+    };
+    *position = (*position + counts as f64 * sensitivity / 100.0).clamp(-1.0, 1.0);
+    self.values()
+  }
+
+  pub fn tick(&mut self, half_life: Duration) -> Option<(i32, i32)> {
+    self.decay(half_life);
+    self.values()
+  }
+
+  pub fn centered(&self) -> bool {
+    self.emitted == (0, 0)
+  }
+
+  pub fn reset(&mut self) -> Option<(i32, i32)> {
+    self.position = (0.0, 0.0);
+    self.values()
+  }
+
+  // Halves the tilt every half life.
+  fn decay(&mut self, half_life: Duration) {
+    let elapsed = self.updated.elapsed();
+    self.updated = Instant::now();
+    let factor = match half_life.is_zero() {
+      true => 0.0,
+      false => 0.5f64.powf(elapsed.as_secs_f64() / half_life.as_secs_f64()),
+    };
+    self.position = (self.position.0 * factor, self.position.1 * factor);
+  }
+
+  fn values(&mut self) -> Option<(i32, i32)> {
+    let values = ((self.position.0 * 32767.0) as i32, (self.position.1 * 32767.0) as i32);
+    if values == self.emitted {
+      return None;
+    }
+    self.emitted = values;
+    Some(values)
+  }
+}
+
+impl Default for MouseStick {
+  fn default() -> Self {
+    Self::new()
+  }
+}
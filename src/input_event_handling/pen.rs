@@ -0,0 +1,127 @@
+use crate::config::Axis;
+use crate::virtual_devices::{self, Target, VirtualDevices};
+use evdev::{uinput::VirtualDevice, AbsoluteAxisType, Device, EventType, InputEvent, Key};
+
+// BTN_STYLUS3 (0x149) isn't named by evdev yet.
+pub const STYLUS_BUTTONS: [Key; 3] = [Key::BTN_STYLUS, Key::BTN_STYLUS2, Key(0x149)];
+
+// The tools of a graphics tablet. BTN_TOOL_FINGER and friends are left out, they belong to touchpads.
+pub const TABLET_TOOLS: [Key; 7] = [
+  Key::BTN_TOOL_PEN, Key::BTN_TOOL_RUBBER, Key::BTN_TOOL_BRUSH, Key::BTN_TOOL_PENCIL,
+  Key::BTN_TOOL_AIRBRUSH, Key::BTN_TOOL_MOUSE, Key::BTN_TOOL_LENS,
+];
+
+// Pens get a virtual tablet of their own, a copy of the physical one, so that position, pressure and
+// tilt reach applications untouched while stylus buttons and tilt directions can be bound.
+// When the copy can't be made, they go through the virtual absolute device, rescaled to its axis ranges.
+pub struct Pen {
+  device: Option<VirtualDevice>,
+  ranges: Vec<(AbsoluteAxisType, i32, i32)>,
+  frame: Vec<InputEvent>,
+  tilt_range: (i32, i32),
+  tilt: (i32, i32),
+}
+
+impl Pen {
+  pub fn new(name: &str, device: &Device) -> Option<Self> {
+    if !device.supported_keys().is_some_and(|keys| TABLET_TOOLS.iter().any(|tool| keys.contains(*tool))) {
+      return None;
+    }
+    let virtual_device = match virtual_devices::copy_of(&format!("Makita Virtual Pen ({})", name), device) {
+      Ok(virtual_device) => {
+        println!("[Pen] Passing the pen of {} through a virtual tablet.", name);
+        Some(virtual_device)
+      }
+      Err(e) => {
+        eprintln!("[Pen] Unable to create a virtual tablet for {}, using the virtual absolute device: {}", name, e);
+        None
+      }
+    };
+
+    let mut ranges = Vec::new();
+    let mut tilt_range = (0, 0);
+    if let (Some(axes), Ok(state)) = (device.supported_absolute_axes(), device.get_abs_state()) {
+      for axis in axes.iter() {
+        let info = state[axis.0 as usize];
+        ranges.push((axis, info.minimum, info.maximum));
+      }
+      tilt_range = (
+        state[AbsoluteAxisType::ABS_TILT_X.0 as usize].maximum,
+        state[AbsoluteAxisType::ABS_TILT_Y.0 as usize].maximum,
+      );
+    }
+
+    Some(Self {
+      device: virtual_device,
+      ranges,
+      frame: Vec::new(),
+      tilt_range,
+      tilt: (0, 0),
+    })
+  }
+
+  // Events are held back until the end of their frame, so that e.g. X and Y arrive together along with the
+  // BTN_TOOL_* and MSC_SERIAL events that tell applications which tool they come from.
+  pub fn push(&mut self, event: InputEvent, virtual_devices: &VirtualDevices) {
+    if event.event_type() != EventType::SYNCHRONIZATION {
+      let event = match self.device {
+        Some(_) => event,
+        None => self.rescale(event),
+      };
+      self.frame.push(event);
+      return;
+    }
+    if self.frame.is_empty() {
+      return;
+    }
+    match self.device.as_mut() {
+      Some(device) => { let _ = device.emit(&self.frame); }
+      None => virtual_devices.emit(Target::Absolute, &self.frame),
+    }
+    self.frame.clear();
+  }
+
+  fn rescale(&self, event: InputEvent) -> InputEvent {
+    if event.event_type() != EventType::ABSOLUTE {
+      return event;
+    }
+    let axis = AbsoluteAxisType(event.code());
+    let range = self.ranges.iter().find(|(candidate, _, _)| *candidate == axis);
+    match (range, virtual_devices::absolute_range(axis)) {
+      (Some((_, minimum, maximum)), Some((target_minimum, target_maximum))) if maximum > minimum => {
+        let position = (event.value() - minimum) as f64 / (maximum - minimum) as f64;
+        let value = target_minimum as f64 + position * (target_maximum - target_minimum) as f64;
+        InputEvent::new(EventType::ABSOLUTE, event.code(), value.round() as i32)
+      }
+      _ => event,
+    }
+  }
+
+  // Returns the tilt directions that were entered (1) or left (0), with the threshold as a
+  // fraction of the axis range.
+  pub fn tilt_changes(&mut self, event: &InputEvent, threshold: f64) -> Vec<(Axis, i32)> {
+    let (range, current, axes) = match AbsoluteAxisType(event.code()) {
+      AbsoluteAxisType::ABS_TILT_X => (self.tilt_range.0, &mut self.tilt.0, (Axis::TILT_LEFT, Axis::TILT_RIGHT)),
+      AbsoluteAxisType::ABS_TILT_Y => (self.tilt_range.1, &mut self.tilt.1, (Axis::TILT_UP, Axis::TILT_DOWN)),
+      _ => return Vec::new(),
+    };
+    let limit = range as f64 * threshold;
+    let direction = if (event.value() as f64) <= -limit {
+      -1
+    } else if (event.value() as f64) >= limit {
+      1
+    } else {
+      0
+    };
+    if range == 0 || direction == *current {
+      return Vec::new();
+    }
+
+    let axis_for = |direction: i32| if direction < 0 { axes.0 } else { axes.1 };
+    let mut changes = Vec::new();
+    if *current != 0 { changes.push((axis_for(*current), 0)); }
+    if direction != 0 { changes.push((axis_for(direction), 1)); }
+    *current = direction;
+    changes
+  }
+}
@@ -0,0 +1,112 @@
+use crate::config::Axis;
+use std::process::Command;
+use tokio::time::{Duration, Instant};
+
+// Pixels from the border of the desktop that count as touching it.
+const EDGE_MARGIN: f64 = 2.0;
+// How often the compositor is asked where the cursor is while the mouse moves.
+const QUERY_INTERVAL: Duration = Duration::from_millis(100);
+
+type Bounds = ((f64, f64), (f64, f64));
+
+// Where the cursor is, to fire EDGE_* and CORNER_* bindings as it reaches a border of the desktop. Without a
+// compositor that tells, it's the sum of the mouse's motion, which ignores pointer acceleration and drifts, but
+// stops at the borders like the cursor does, so pushing against one lines it up again.
+pub struct PointerEdges {
+  position: Option<(f64, f64)>,
+  // The compositor's layout, SCREEN_SIZE otherwise.
+  desktop: Option<Bounds>,
+  touching: Option<Axis>,
+  last_query: Option<Instant>,
+}
+
+impl PointerEdges {
+  pub fn new() -> Self {
+    Self { position: None, desktop: None, touching: None, last_query: None }
+  }
+
+  // Returns the edge or corner the motion reached, if it wasn't there already.
+  pub fn moved(&mut self, dx: f64, dy: f64, screen_size: Option<(f64, f64)>) -> Option<Axis> {
+    let ((left, top), (right, bottom)) = self.desktop.or(screen_size.map(|size| ((0.0, 0.0), size)))?;
+    // Starts in the middle, as far from every edge as it can be.
+    let (x, y) = self.position.unwrap_or(((left + right) / 2.0, (top + bottom) / 2.0));
+    let (x, y) = ((x + dx).clamp(left, right), (y + dy).clamp(top, bottom));
+    self.position = Some((x, y));
+    let horizontal = if x <= left + EDGE_MARGIN { Some(false) } else if x >= right - EDGE_MARGIN { Some(true) } else { None };
+    let vertical = if y <= top + EDGE_MARGIN { Some(false) } else if y >= bottom - EDGE_MARGIN { Some(true) } else { None };
+    let touching = match (horizontal, vertical) {
+      (Some(false), Some(false)) => Some(Axis::CORNER_TOP_LEFT),
+      (Some(true), Some(false)) => Some(Axis::CORNER_TOP_RIGHT),
+      (Some(false), Some(true)) => Some(Axis::CORNER_BOTTOM_LEFT),
+      (Some(true), Some(true)) => Some(Axis::CORNER_BOTTOM_RIGHT),
+      (Some(false), None) => Some(Axis::EDGE_LEFT),
+      (Some(true), None) => Some(Axis::EDGE_RIGHT),
+      (None, Some(false)) => Some(Axis::EDGE_TOP),
+      (None, Some(true)) => Some(Axis::EDGE_BOTTOM),
+      (None, None) => None,
+    };
+    let entered = touching.filter(|_| touching != self.touching);
+    self.touching = touching;
+    entered
+  }
+
+  // Whether to ask the compositor again, at most every QUERY_INTERVAL.
+  pub fn query_due(&mut self) -> bool {
+    if self.last_query.is_some_and(|last_query| last_query.elapsed() < QUERY_INTERVAL) {
+      return false;
+    }
+    self.last_query = Some(Instant::now());
+    true
+  }
+
+  pub fn sync(&mut self, position: (f64, f64), desktop: Bounds) {
+    self.position = Some(position);
+    self.desktop = Some(desktop);
+  }
+}
+
+impl Default for PointerEdges {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+// The cursor position and the bounding box of the monitors, in Hyprland's layout coordinates.
+pub fn query_hyprland() -> Option<((f64, f64), Bounds)> {
+  let query = |arguments: &[&str]| -> Option<serde_json::Value> {
+    let output = Command::new("hyprctl").args(arguments).output().ok()?;
+    serde_json::from_slice(&output.stdout).ok()
+  };
+  let cursor = query(&["cursorpos", "-j"])?;
+  let position = (cursor["x"].as_f64()?, cursor["y"].as_f64()?);
+  let monitors = query(&["monitors", "-j"])?;
+  let desktop = monitors.as_array()?.iter().filter_map(|monitor| {
+    let scale = monitor["scale"].as_f64().filter(|scale| *scale > 0.0).unwrap_or(1.0);
+    // Rotated monitors swap their width and height.
+    let rotated = monitor["transform"].as_i64().is_some_and(|transform| transform % 2 == 1);
+    let (width, height) = (monitor["width"].as_f64()? / scale, monitor["height"].as_f64()? / scale);
+    let (width, height) = if rotated { (height, width) } else { (width, height) };
+    let (x, y) = (monitor["x"].as_f64()?, monitor["y"].as_f64()?);
+    Some(((x, y), (x + width - 1.0, y + height - 1.0)))
+  }).reduce(|((left, top), (right, bottom)), ((x, y), (x2, y2))| ((left.min(x), top.min(y)), (right.max(x2), bottom.max(y2))))?;
+  Some((position, desktop))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn fires_pointer_edges_once_reached() {
+    let mut edges = PointerEdges::new();
+    assert_eq!(edges.moved(-10.0, 0.0, None), None);
+    // From the middle of the screen, the position stops at the borders like the cursor does.
+    let screen = Some((1000.0, 500.0));
+    assert_eq!(edges.moved(-400.0, 0.0, screen), None);
+    assert_eq!(edges.moved(-400.0, 0.0, screen), Some(Axis::EDGE_LEFT));
+    assert_eq!(edges.moved(-50.0, 0.0, screen), None);
+    assert_eq!(edges.moved(0.0, -1000.0, screen), Some(Axis::CORNER_TOP_LEFT));
+    assert_eq!(edges.moved(20.0, 20.0, screen), None);
+    assert_eq!(edges.moved(-20.0, -20.0, screen), Some(Axis::CORNER_TOP_LEFT));
+  }
+}
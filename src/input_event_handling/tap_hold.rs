@@ -0,0 +1,256 @@
+use crate::config::{TapHold, TapHoldCommand, TapHoldStrategy};
+use crate::input_event_handling::event_buffer::EventBuffer;
+use evdev::{EventType, InputEvent, Key};
+use std::collections::HashMap;
+use tokio::time::Instant;
+
+struct Pending {
+  key: Key,
+  tap_hold: TapHold,
+  deadline: Instant,
+  buffer: EventBuffer,
+}
+
+pub struct TapHoldEngine {
+  bindings: HashMap<Key, TapHold>,
+  pending: Option<Pending>,
+  held: HashMap<Key, Vec<Key>>,
+  held_commands: HashMap<Key, TapHoldCommand>,
+  commands: Vec<(Key, TapHoldCommand, i32)>,
+}
+
+impl TapHoldEngine {
+  pub fn new(bindings: HashMap<Key, TapHold>) -> Self {
+    Self {
+      bindings,
+      pending: None,
+      held: HashMap::new(),
+      held_commands: HashMap::new(),
+      commands: Vec::new(),
+    }
+  }
+
+  // The commands of the keys resolved since the last call, pressed (1) or released (0), to run before
+  // the events returned along with them.
+  pub fn take_commands(&mut self) -> Vec<(Key, TapHoldCommand, i32)> {
+    std::mem::take(&mut self.commands)
+  }
+
+  pub fn deadline(&self) -> Option<Instant> {
+    self.pending.as_ref().map(|pending| pending.deadline)
+  }
+
+  // Returns the events that should go through the rest of the pipeline, with tap-hold keys
+  // replaced by their tap or hold key once resolved. Events are held back while unresolved.
+  pub fn process(&mut self, event: InputEvent) -> Vec<InputEvent> {
+    self.process_at(event, Instant::now())
+  }
+
+  // Replayed events are processed with the time they first arrived at.
+  fn process_at(&mut self, event: InputEvent, at: Instant) -> Vec<InputEvent> {
+    if event.event_type() != EventType::KEY {
+      return vec![event];
+    }
+    let key = Key(event.code());
+
+    if let Some(pending) = &mut self.pending {
+      if key == pending.key {
+        return match event.value() {
+          0 => self.resolve_tap(Some(event)),
+          _ => Vec::new(),
+        };
+      }
+
+      match (event.value(), pending.tap_hold.strategy) {
+        (1, TapHoldStrategy::TapOnOtherKeyPress) => {
+          pending.buffer.push(event, at);
+          self.resolve_tap(None)
+        }
+        (1, TapHoldStrategy::HoldOnOtherKeyPress) => {
+          pending.buffer.push(event, at);
+          self.resolve_hold()
+        }
+        (0, TapHoldStrategy::PermissiveHold) if pending.buffer.pressed(event.code()) => {
+          pending.buffer.push(event, at);
+          self.resolve_hold()
+        }
+        _ => {
+          pending.buffer.push(event, at);
+          Vec::new()
+        }
+      }
+    } else if let Some(hold) = self.held.get(&key) {
+      match event.value() {
+        0 => {
+          let events = hold.iter().rev().map(|hold| with_code(&event, hold.code())).collect();
+          self.held.remove(&key);
+          if let Some(command) = self.held_commands.remove(&key) {
+            self.commands.push((key, command, 0));
+          }
+          events
+        }
+        _ => Vec::new(),
+      }
+    } else if let Some(tap_hold) = self.bindings.get(&key) {
+      match event.value() {
+        1 => {
+          self.pending = Some(Pending {
+            key,
+            tap_hold: tap_hold.clone(),
+            deadline: at + tap_hold.tapping_term,
+            buffer: EventBuffer::default(),
+          });
+          Vec::new()
+        }
+        _ => Vec::new(),
+      }
+    } else {
+      vec![event]
+    }
+  }
+
+  // Forgets every pending and held tap-hold key, returning the keys that are still held down.
+  pub fn reset(&mut self) -> Vec<Key> {
+    self.pending = None;
+    self.held_commands.clear();
+    self.commands.clear();
+    self.held.drain().flat_map(|(_, hold)| hold).collect()
+  }
+
+  pub fn timeout(&mut self) -> Vec<InputEvent> {
+    self.resolve_hold()
+  }
+
+  // Without a release event the tap is emitted right away and the physical release is swallowed later.
+  // Tap commands are run and finished at once either way.
+  fn resolve_tap(&mut self, release: Option<InputEvent>) -> Vec<InputEvent> {
+    let Some(pending) = self.pending.take() else { return Vec::new() };
+    if let Some(command) = pending.tap_hold.tap_command {
+      self.commands.push((pending.key, command.clone(), 1));
+      self.commands.push((pending.key, command, 0));
+      if release.is_none() { self.held.insert(pending.key, Vec::new()); }
+      return self.replay(pending.buffer);
+    }
+    let tap = pending.tap_hold.tap.code();
+    let mut events = vec![InputEvent::new(EventType::KEY, tap, 1)];
+    match release {
+      Some(release) => {
+        events.extend(self.replay(pending.buffer));
+        events.push(with_code(&release, tap));
+      }
+      None => {
+        events.push(InputEvent::new(EventType::KEY, tap, 0));
+        self.held.insert(pending.key, Vec::new());
+        events.extend(self.replay(pending.buffer));
+      }
+    }
+    events
+  }
+
+  fn resolve_hold(&mut self) -> Vec<InputEvent> {
+    let Some(pending) = self.pending.take() else { return Vec::new() };
+    if let Some(command) = pending.tap_hold.hold_command {
+      self.commands.push((pending.key, command.clone(), 1));
+      self.held_commands.insert(pending.key, command);
+    }
+    let hold = pending.tap_hold.hold;
+    let mut events: Vec<InputEvent> = hold.iter().map(|hold| InputEvent::new(EventType::KEY, hold.code(), 1)).collect();
+    self.held.insert(pending.key, hold);
+    events.extend(self.replay(pending.buffer));
+    events
+  }
+
+  // Replayed events are emitted back to back. A tap-hold key among them may already be past its tapping term,
+  // in which case the reader resolves it as soon as it checks the deadline.
+  fn replay(&mut self, mut buffer: EventBuffer) -> Vec<InputEvent> {
+    buffer.take().into_iter().flat_map(|(event, at)| self.process_at(event, at)).collect()
+  }
+}
+
+fn with_code(event: &InputEvent, code: u16) -> InputEvent {
+  let mut raw = *event.as_ref();
+  raw.code = code;
+  InputEvent::from(raw)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::time::Duration;
+
+  const TAP_HOLD: Key = Key::KEY_A;
+  const OTHER: Key = Key::KEY_J;
+
+  fn engine(strategy: TapHoldStrategy) -> TapHoldEngine {
+    let tap_hold = TapHold {
+      tap: Key::KEY_A,
+      hold: vec![Key::KEY_LEFTCTRL],
+      tap_command: None,
+      hold_command: None,
+      tapping_term: Duration::from_millis(200),
+      strategy,
+    };
+    TapHoldEngine::new(HashMap::from([(TAP_HOLD, tap_hold)]))
+  }
+
+  fn keys(engine: &mut TapHoldEngine, events: &[(Key, i32)]) -> Vec<(Key, i32)> {
+    events.iter()
+      .flat_map(|(key, value)| engine.process(InputEvent::new(EventType::KEY, key.code(), *value)))
+      .map(|event| (Key(event.code()), event.value()))
+      .collect()
+  }
+
+  #[test]
+  fn taps_when_released_within_the_tapping_term() {
+    let mut engine = engine(TapHoldStrategy::HoldOnTimeout);
+    assert_eq!(keys(&mut engine, &[(TAP_HOLD, 1), (OTHER, 1), (OTHER, 0)]), vec![]);
+    assert!(engine.deadline().is_some());
+    assert_eq!(keys(&mut engine, &[(TAP_HOLD, 0)]), vec![(Key::KEY_A, 1), (OTHER, 1), (OTHER, 0), (Key::KEY_A, 0)]);
+    assert!(engine.deadline().is_none());
+  }
+
+  #[test]
+  fn holds_once_the_tapping_term_passes() {
+    let mut engine = engine(TapHoldStrategy::HoldOnTimeout);
+    assert_eq!(keys(&mut engine, &[(TAP_HOLD, 1), (OTHER, 1)]), vec![]);
+    let events: Vec<(Key, i32)> = engine.timeout().iter().map(|event| (Key(event.code()), event.value())).collect();
+    assert_eq!(events, vec![(Key::KEY_LEFTCTRL, 1), (OTHER, 1)]);
+    assert_eq!(keys(&mut engine, &[(OTHER, 0), (TAP_HOLD, 0)]), vec![(OTHER, 0), (Key::KEY_LEFTCTRL, 0)]);
+  }
+
+  #[test]
+  fn permissive_hold_holds_when_another_key_is_tapped() {
+    let mut engine = engine(TapHoldStrategy::PermissiveHold);
+    assert_eq!(keys(&mut engine, &[(TAP_HOLD, 1), (OTHER, 1)]), vec![]);
+    assert_eq!(keys(&mut engine, &[(OTHER, 0)]), vec![(Key::KEY_LEFTCTRL, 1), (OTHER, 1), (OTHER, 0)]);
+    assert_eq!(keys(&mut engine, &[(TAP_HOLD, 0)]), vec![(Key::KEY_LEFTCTRL, 0)]);
+
+    // A key pressed before the tap-hold key is released after it doesn't count.
+    let mut engine = self::engine(TapHoldStrategy::PermissiveHold);
+    assert_eq!(keys(&mut engine, &[(OTHER, 1), (TAP_HOLD, 1), (OTHER, 0)]), vec![(OTHER, 1)]);
+    assert_eq!(keys(&mut engine, &[(TAP_HOLD, 0)]), vec![(Key::KEY_A, 1), (OTHER, 0), (Key::KEY_A, 0)]);
+  }
+
+  #[test]
+  fn hold_on_other_key_press_holds_right_away() {
+    let mut engine = engine(TapHoldStrategy::HoldOnOtherKeyPress);
+    assert_eq!(keys(&mut engine, &[(TAP_HOLD, 1), (OTHER, 1)]), vec![(Key::KEY_LEFTCTRL, 1), (OTHER, 1)]);
+    assert_eq!(keys(&mut engine, &[(TAP_HOLD, 0), (OTHER, 0)]), vec![(Key::KEY_LEFTCTRL, 0), (OTHER, 0)]);
+  }
+
+  #[test]
+  fn tap_on_other_key_press_taps_right_away() {
+    let mut engine = engine(TapHoldStrategy::TapOnOtherKeyPress);
+    assert_eq!(keys(&mut engine, &[(TAP_HOLD, 1), (OTHER, 1)]), vec![(Key::KEY_A, 1), (Key::KEY_A, 0), (OTHER, 1)]);
+    // The release of the tapped key is swallowed.
+    assert_eq!(keys(&mut engine, &[(TAP_HOLD, 0), (OTHER, 0)]), vec![(OTHER, 0)]);
+  }
+
+  #[test]
+  fn reset_returns_the_held_keys() {
+    let mut engine = engine(TapHoldStrategy::HoldOnOtherKeyPress);
+    keys(&mut engine, &[(TAP_HOLD, 1), (OTHER, 1)]);
+    assert_eq!(engine.reset(), vec![Key::KEY_LEFTCTRL]);
+    assert_eq!(keys(&mut engine, &[(TAP_HOLD, 0)]), vec![]);
+  }
+}
@@ -0,0 +1,154 @@
+use crate::config::Axis;
+use crate::virtual_devices;
+use evdev::{uinput::VirtualDevice, AbsoluteAxisType, Device, EventType, InputEvent, PropType};
+use std::collections::HashMap;
+use tokio::time::{Duration, Instant};
+
+// Fractions of the screen size.
+const EDGE_MARGIN: f64 = 0.05;
+const SWIPE_DISTANCE: f64 = 0.15;
+const TAP_MOVEMENT: f64 = 0.03;
+const TAP_TIME: Duration = Duration::from_millis(250);
+
+struct Contact {
+  start: (i32, i32),
+  position: (i32, i32),
+}
+
+// Follows the contacts of a slotted (type B) multitouch device and recognizes gestures once every finger is lifted.
+pub struct MultitouchDecoder {
+  range: ((i32, i32), (i32, i32)),
+  slot: usize,
+  contacts: HashMap<usize, Contact>,
+  finished: Vec<Contact>,
+  gesture_start: Option<Instant>,
+  max_fingers: usize,
+}
+
+impl MultitouchDecoder {
+  pub fn new(device: &Device) -> std::io::Result<Self> {
+    let state = device.get_abs_state()?;
+    let x = state[AbsoluteAxisType::ABS_MT_POSITION_X.0 as usize];
+    let y = state[AbsoluteAxisType::ABS_MT_POSITION_Y.0 as usize];
+    Ok(Self {
+      range: ((x.minimum, x.maximum), (y.minimum, y.maximum)),
+      slot: 0,
+      contacts: HashMap::new(),
+      finished: Vec::new(),
+      gesture_start: None,
+      max_fingers: 0,
+    })
+  }
+
+  // Returns the gestures completed by this event, which only happens on SYN_REPORT.
+  pub fn process(&mut self, event: &InputEvent) -> Vec<Axis> {
+    match (event.event_type(), AbsoluteAxisType(event.code())) {
+      (EventType::ABSOLUTE, AbsoluteAxisType::ABS_MT_SLOT) => self.slot = event.value().max(0) as usize,
+      (EventType::ABSOLUTE, AbsoluteAxisType::ABS_MT_TRACKING_ID) => {
+        if event.value() < 0 {
+          if let Some(contact) = self.contacts.remove(&self.slot) { self.finished.push(contact); }
+        } else {
+          self.contacts.insert(self.slot, Contact { start: (-1, -1), position: (-1, -1) });
+          self.gesture_start.get_or_insert_with(Instant::now);
+          self.max_fingers = self.max_fingers.max(self.contacts.len());
+        }
+      }
+      (EventType::ABSOLUTE, axis @ (AbsoluteAxisType::ABS_MT_POSITION_X | AbsoluteAxisType::ABS_MT_POSITION_Y)) => {
+        if let Some(contact) = self.contacts.get_mut(&self.slot) {
+          let (start, position) = match axis {
+            AbsoluteAxisType::ABS_MT_POSITION_X => (&mut contact.start.0, &mut contact.position.0),
+            _ => (&mut contact.start.1, &mut contact.position.1),
+          };
+          if *start < 0 { *start = event.value(); }
+          *position = event.value();
+        }
+      }
+      (EventType::SYNCHRONIZATION, _) if self.contacts.is_empty() && self.gesture_start.is_some() => {
+        let gesture = self.gesture();
+        self.finished.clear();
+        self.gesture_start = None;
+        self.max_fingers = 0;
+        return gesture.into_iter().collect();
+      }
+      _ => {}
+    }
+    Vec::new()
+  }
+
+  fn gesture(&self) -> Option<Axis> {
+    let ((min_x, max_x), (min_y, max_y)) = self.range;
+    let (width, height) = ((max_x - min_x).max(1) as f64, (max_y - min_y).max(1) as f64);
+    let relative = |(x, y): (i32, i32)| ((x - min_x) as f64 / width, (y - min_y) as f64 / height);
+    let elapsed = self.gesture_start?.elapsed();
+
+    if self.max_fingers >= 2 {
+      let still = self.finished.iter().all(|contact| {
+        let (start, end) = (relative(contact.start), relative(contact.position));
+        (end.0 - start.0).abs() < TAP_MOVEMENT && (end.1 - start.1).abs() < TAP_MOVEMENT
+      });
+      return match self.max_fingers {
+        2 if still && elapsed < TAP_TIME => Some(Axis::TAP_2_FINGERS),
+        3 if still && elapsed < TAP_TIME => Some(Axis::TAP_3_FINGERS),
+        4 if still && elapsed < TAP_TIME => Some(Axis::TAP_4_FINGERS),
+        _ => None,
+      };
+    }
+
+    let contact = self.finished.first()?;
+    let (start, end) = (relative(contact.start), relative(contact.position));
+    if start.0 < EDGE_MARGIN && end.0 - start.0 > SWIPE_DISTANCE {
+      Some(Axis::SWIPE_FROM_LEFT)
+    } else if start.0 > 1.0 - EDGE_MARGIN && start.0 - end.0 > SWIPE_DISTANCE {
+      Some(Axis::SWIPE_FROM_RIGHT)
+    } else if start.1 < EDGE_MARGIN && end.1 - start.1 > SWIPE_DISTANCE {
+      Some(Axis::SWIPE_FROM_TOP)
+    } else if start.1 > 1.0 - EDGE_MARGIN && start.1 - end.1 > SWIPE_DISTANCE {
+      Some(Axis::SWIPE_FROM_BOTTOM)
+    } else {
+      None
+    }
+  }
+}
+
+// Touchscreens are passed through a virtual copy, while their gestures can be bound on top.
+// Touchpads (without INPUT_PROP_DIRECT) are left to the regular pipeline.
+pub struct Touchscreen {
+  device: VirtualDevice,
+  frame: Vec<InputEvent>,
+  decoder: MultitouchDecoder,
+}
+
+impl Touchscreen {
+  pub fn new(name: &str, device: &Device) -> Option<Self> {
+    let multitouch = device.supported_absolute_axes().is_some_and(|axes| {
+      axes.contains(AbsoluteAxisType::ABS_MT_SLOT) && axes.contains(AbsoluteAxisType::ABS_MT_POSITION_X)
+    });
+    if !multitouch || !device.properties().contains(PropType::DIRECT) {
+      return None;
+    }
+    let touchscreen = virtual_devices::copy_of(&format!("Makita Virtual Touchscreen ({})", name), device)
+      .and_then(|virtual_device| Ok(Self { device: virtual_device, frame: Vec::new(), decoder: MultitouchDecoder::new(device)? }));
+    match touchscreen {
+      Ok(touchscreen) => {
+        println!("[Touch] Passing {} through a virtual touchscreen.", name);
+        Some(touchscreen)
+      }
+      Err(e) => {
+        eprintln!("[Touch] Unable to create a virtual touchscreen for {}: {}", name, e);
+        None
+      }
+    }
+  }
+
+  pub fn push(&mut self, event: InputEvent) -> Vec<Axis> {
+    if event.event_type() == EventType::SYNCHRONIZATION {
+      if !self.frame.is_empty() {
+        let _ = self.device.emit(&self.frame);
+        self.frame.clear();
+      }
+    } else {
+      self.frame.push(event);
+    }
+    self.decoder.process(&event)
+  }
+}
@@ -0,0 +1,174 @@
+use crate::config::parse_key;
+use crate::live_settings;
+use crate::profiles;
+use crate::reload;
+use crate::ruby_runtime::{self, SyntheticEvent};
+use crate::state::{self, DeviceStatus, StateQuery, StateResponse};
+use crate::state_files;
+use evdev::{EventType, Key};
+use serde::{Deserialize, Serialize};
+use std::{env, path::PathBuf};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+  GetProfile,
+  SetProfile { profile: Option<String> },
+  State { query: StateQuery },
+  GetSetting { setting: String, device: Option<String> },
+  SetSetting { setting: String, value: String, device: Option<String> },
+  ReleaseAll,
+  // Every connected device without a device.
+  SetEnabled { device: Option<String>, enabled: bool },
+  // A layout by number or by name, on the device or on every device that has it.
+  SetLayout { layout: String, device: Option<String> },
+  Reload,
+  Inhibit { minutes: Option<f64> },
+  // A lock turned on or off, or toggled without `on`.
+  SetLock { lock: String, on: Option<bool> },
+  // Keys or chords to tap, then raw events, like ydotool.
+  Emit {
+    #[serde(default)]
+    keys: Vec<String>,
+    #[serde(default)]
+    events: Vec<SyntheticEvent>,
+  },
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Response {
+  Ok,
+  Profile { profile: Option<String> },
+  State { state: StateResponse },
+  Setting { setting: String, value: Option<String> },
+  Reloaded { changes: Vec<String> },
+  Error { message: String },
+}
+
+// Each chord, e.g. KEY_LEFTCTRL-KEY_C, is pressed and then released in frames of its own.
+fn tap_events(chords: &[String]) -> Result<Vec<SyntheticEvent>, String> {
+  let event = |event_type: EventType, code: u16, value: i32| SyntheticEvent { event_type: event_type.0, code, value, target: None };
+  let mut events = Vec::new();
+  for chord in chords {
+    let keys = chord.split('-')
+      .map(|name| parse_key(name).ok_or_else(|| format!("unknown key {}", name)))
+      .collect::<Result<Vec<Key>, String>>()?;
+    events.extend(keys.iter().map(|key| event(EventType::KEY, key.code(), 1)));
+    events.push(event(EventType::SYNCHRONIZATION, 0, 0));
+    events.extend(keys.iter().rev().map(|key| event(EventType::KEY, key.code(), 0)));
+    events.push(event(EventType::SYNCHRONIZATION, 0, 0));
+  }
+  Ok(events)
+}
+
+pub fn socket_path() -> PathBuf {
+  match env::var("MAKITA_SOCKET") {
+    Ok(path) => PathBuf::from(path),
+    Err(_) => state_files::runtime_path("makita.sock"),
+  }
+}
+
+pub async fn serve() {
+  let path = socket_path();
+  state_files::remove_stale_socket(&path);
+  let listener = match UnixListener::bind(&path) {
+    Ok(listener) => listener,
+    Err(e) => {
+      eprintln!("[Ipc] Unable to bind control socket {}: {}", path.display(), e);
+      return;
+    }
+  };
+  // Started through sudo, the user can still use makitactl without it.
+  state_files::give_to_user(&path);
+  println!("[Ipc] Listening on {}.", path.display());
+
+  loop {
+    match listener.accept().await {
+      Ok((stream, _)) => { tokio::spawn(handle_connection(stream)); }
+      Err(e) => eprintln!("[Ipc] Failed to accept connection: {}", e),
+    }
+  }
+}
+
+async fn handle_connection(stream: UnixStream) {
+  let (reader, mut writer) = stream.into_split();
+  let mut lines = BufReader::new(reader).lines();
+
+  while let Ok(Some(line)) = lines.next_line().await {
+    let response = match serde_json::from_str::<Request>(&line) {
+      Ok(request) => handle_request(request),
+      Err(e) => Response::Error { message: format!("invalid request: {}", e) },
+    };
+    let mut reply = serde_json::to_string(&response).unwrap_or_default();
+    reply.push('\n');
+    if writer.write_all(reply.as_bytes()).await.is_err() { break; }
+  }
+}
+
+fn handle_request(request: Request) -> Response {
+  match request {
+    Request::GetProfile => Response::Profile { profile: profiles::active_profile() },
+    Request::SetProfile { profile } => {
+      profiles::set_active_profile(profile);
+      Response::Ok
+    }
+    Request::State { query } => Response::State { state: state::query(query) },
+    Request::GetSetting { setting, device } => Response::Setting {
+      value: live_settings::get(device.as_deref(), &setting),
+      setting,
+    },
+    Request::SetSetting { setting, value, device } => match live_settings::set(device, &setting, &value) {
+      Ok(()) => Response::Ok,
+      Err(message) => Response::Error { message },
+    },
+    // Devices are looked up in the device registry, so that those without bindings can be paused too.
+    Request::SetEnabled { device: None, enabled } => {
+      for device in state::connected_devices() {
+        state::set_device_enabled(&device, enabled);
+      }
+      Response::Ok
+    }
+    Request::SetEnabled { device: Some(device), enabled } => match state::device_status(&device) {
+      Some(DeviceStatus::Connected) => {
+        state::set_device_enabled(&device, enabled);
+        Response::Ok
+      }
+      Some(_) => Response::Error { message: format!("{} isn't connected", device) },
+      None => Response::Error { message: format!("no config for a device named {}", device) },
+    },
+    Request::SetLayout { layout, device } => match state::request_layout(device.as_deref(), &layout) {
+      Ok(()) => Response::Ok,
+      Err(message) => Response::Error { message },
+    },
+    Request::Reload => match reload::reload() {
+      Ok(changes) => Response::Reloaded { changes },
+      Err(message) => Response::Error { message },
+    },
+    Request::Inhibit { minutes } => {
+      state::inhibit(minutes);
+      Response::Ok
+    }
+    Request::SetLock { lock, on } => match state::parse_lock(&lock) {
+      Some(led) => {
+        ruby_runtime::set_lock(led, on);
+        Response::Ok
+      }
+      None => Response::Error { message: format!("unknown lock {}, use num_lock, caps_lock or scroll_lock", lock) },
+    },
+    // Nothing is emitted if any key is unknown.
+    Request::Emit { keys, events } => match tap_events(&keys) {
+      Ok(taps) => {
+        ruby_runtime::send_synthetic_events(taps.into_iter().chain(events));
+        Response::Ok
+      }
+      Err(message) => Response::Error { message },
+    },
+    Request::ReleaseAll => match state::request_release_all() {
+      0 => Response::Error { message: "no device is connected".to_string() },
+      _ => Response::Ok,
+    },
+  }
+}
@@ -0,0 +1,304 @@
+// The keys to press for a character of text, looked up in the XKB layout of the session through libxkbcommon,
+// which is loaded when needed so that Makita still runs without it, falling back to a US layout then.
+use crate::session;
+use crate::udev_monitor::{Environment, Server};
+use evdev::Key;
+use nix::libc::{c_char, c_int, c_void, dlopen, dlsym, RTLD_LOCAL, RTLD_NOW};
+use std::{
+  collections::HashMap,
+  env,
+  ffi::CString,
+  process::Stdio,
+  ptr,
+  sync::atomic::{AtomicUsize, Ordering},
+  sync::{Arc, Mutex},
+};
+
+// A layout and variant, with their keymap unless libxkbcommon couldn't be loaded.
+type CompiledLayout = (String, String, Option<Arc<Keymap>>);
+
+lazy_static::lazy_static! {
+  // The layout last compiled, compiled again when the session switches to another one.
+  static ref KEYMAP: Mutex<Option<CompiledLayout>> = Mutex::new(None);
+}
+
+// The index of the layout XKB_LAYOUTS last switched the compositor to, for compositors that can't be asked for it.
+static SWITCHED_LAYOUT: AtomicUsize = AtomicUsize::new(0);
+
+#[repr(C)]
+struct RuleNames {
+  rules: *const c_char,
+  model: *const c_char,
+  layout: *const c_char,
+  variant: *const c_char,
+  options: *const c_char,
+}
+
+struct Library {
+  context_new: unsafe extern "C" fn(c_int) -> *mut c_void,
+  context_unref: unsafe extern "C" fn(*mut c_void),
+  keymap_new_from_names: unsafe extern "C" fn(*mut c_void, *const RuleNames, c_int) -> *mut c_void,
+  keymap_unref: unsafe extern "C" fn(*mut c_void),
+  min_keycode: unsafe extern "C" fn(*mut c_void) -> u32,
+  max_keycode: unsafe extern "C" fn(*mut c_void) -> u32,
+  num_levels_for_key: unsafe extern "C" fn(*mut c_void, u32, u32) -> u32,
+  key_get_syms_by_level: unsafe extern "C" fn(*mut c_void, u32, u32, u32, *mut *const u32) -> c_int,
+  key_get_mods_for_level: unsafe extern "C" fn(*mut c_void, u32, u32, u32, *mut u32, usize) -> usize,
+  mod_get_index: unsafe extern "C" fn(*mut c_void, *const c_char) -> u32,
+  keysym_to_utf32: unsafe extern "C" fn(u32) -> u32,
+}
+
+// The function of that name, with the signature it has in xkbcommon.h.
+// SAFETY: T has to be the `unsafe extern "C" fn` type of the symbol as xkbcommon.h declares it, since nothing checks
+// the signature of what dlsym returns. The library is never closed, so the pointer stays valid for the process.
+unsafe fn symbol<T>(handle: *mut c_void, name: &str) -> Option<T> {
+  let name = CString::new(name).ok()?;
+  let symbol = dlsym(handle, name.as_ptr());
+  (!symbol.is_null()).then(|| std::mem::transmute_copy(&symbol))
+}
+
+impl Library {
+  fn open() -> Option<Self> {
+    // SAFETY: the name is a NUL terminated literal. Opening the library again only bumps its reference count.
+    let handle = unsafe { dlopen(c"libxkbcommon.so.0".as_ptr(), RTLD_NOW | RTLD_LOCAL) };
+    if handle.is_null() {
+      return None;
+    }
+    // SAFETY: every field's type is the signature of its function in xkbcommon.h, see symbol.
+    unsafe {
+      Some(Self {
+        context_new: symbol(handle, "xkb_context_new")?,
+        context_unref: symbol(handle, "xkb_context_unref")?,
+        keymap_new_from_names: symbol(handle, "xkb_keymap_new_from_names")?,
+        keymap_unref: symbol(handle, "xkb_keymap_unref")?,
+        min_keycode: symbol(handle, "xkb_keymap_min_keycode")?,
+        max_keycode: symbol(handle, "xkb_keymap_max_keycode")?,
+        num_levels_for_key: symbol(handle, "xkb_keymap_num_levels_for_key")?,
+        key_get_syms_by_level: symbol(handle, "xkb_keymap_key_get_syms_by_level")?,
+        // Only in libxkbcommon 1.0 and later.
+        key_get_mods_for_level: symbol(handle, "xkb_keymap_key_get_mods_for_level")?,
+        mod_get_index: symbol(handle, "xkb_keymap_mod_get_index")?,
+        keysym_to_utf32: symbol(handle, "xkb_keysym_to_utf32")?,
+      })
+    }
+  }
+}
+
+// The key and the modifiers that type each character of a layout.
+pub struct Keymap {
+  pub layout: String,
+  characters: HashMap<char, (Key, Vec<Key>)>,
+}
+
+impl Keymap {
+  // Compiles the layout and variant like the compositor does, an empty layout being XKB_DEFAULT_LAYOUT or us.
+  pub fn compile(layout: &str, variant: &str) -> Option<Self> {
+    let library = Library::open()?;
+    let (layout_name, variant_name) = (CString::new(layout).ok()?, CString::new(variant).ok()?);
+    let names = RuleNames {
+      rules: ptr::null(),
+      model: ptr::null(),
+      layout: if layout.is_empty() { ptr::null() } else { layout_name.as_ptr() },
+      variant: if layout.is_empty() || variant.is_empty() { ptr::null() } else { variant_name.as_ptr() },
+      options: ptr::null(),
+    };
+    // SAFETY: the names point into CStrings that outlive the call that reads them. The context and keymap are
+    // checked for null, and each is unreferenced once, after its last use.
+    unsafe {
+      let context = (library.context_new)(0);
+      if context.is_null() {
+        return None;
+      }
+      let keymap = (library.keymap_new_from_names)(context, &names, 0);
+      (library.context_unref)(context);
+      if keymap.is_null() {
+        return None;
+      }
+      let characters = characters(&library, keymap);
+      (library.keymap_unref)(keymap);
+      Some(Self { layout: if layout.is_empty() { "default".to_string() } else { layout.to_string() }, characters })
+    }
+  }
+
+  pub fn keys_for(&self, character: char) -> Option<(Key, Vec<Key>)> {
+    self.characters.get(&character).cloned()
+  }
+}
+
+// Every level of the first layout group that a combination of Shift and AltGr (Mod5) reaches, the one with the
+// fewest modifiers winning for characters several keys type.
+// SAFETY: keymap has to be a valid keymap of the library. The keysyms a level returns belong to the keymap and are
+// only read when there is exactly one, and the modifier masks are written into a buffer of the length passed along.
+unsafe fn characters(library: &Library, keymap: *mut c_void) -> HashMap<char, (Key, Vec<Key>)> {
+  let index = |name: &str| CString::new(name).map(|name| (library.mod_get_index)(keymap, name.as_ptr())).unwrap_or(u32::MAX);
+  let modifiers: Vec<(u32, Key)> = [("Shift", Key::KEY_LEFTSHIFT), ("Mod5", Key::KEY_RIGHTALT)].iter()
+    .filter(|(name, _)| index(name) < 32)
+    .map(|(name, key)| (1 << index(name), *key))
+    .collect();
+  let allowed: u32 = modifiers.iter().map(|(mask, _)| mask).sum();
+  let mut characters: HashMap<char, (Key, Vec<Key>)> = HashMap::new();
+  for keycode in (library.min_keycode)(keymap)..=(library.max_keycode)(keymap) {
+    let Some(key) = keycode.checked_sub(8).map(|code| Key(code as u16)) else { continue };
+    for level in 0..(library.num_levels_for_key)(keymap, keycode, 0) {
+      let mut syms: *const u32 = ptr::null();
+      if (library.key_get_syms_by_level)(keymap, keycode, 0, level, &mut syms) != 1 {
+        continue;
+      }
+      let Some(character) = char::from_u32((library.keysym_to_utf32)(*syms)).filter(|character| *character != '\0') else { continue };
+      let mut masks = [0u32; 8];
+      let count = (library.key_get_mods_for_level)(keymap, keycode, 0, level, masks.as_mut_ptr(), masks.len());
+      let Some(mask) = masks[..count.min(masks.len())].iter().filter(|mask| *mask & !allowed == 0).min_by_key(|mask| mask.count_ones()) else { continue };
+      let held: Vec<Key> = modifiers.iter().filter(|(bit, _)| mask & bit != 0).map(|(_, key)| *key).collect();
+      if characters.get(&character).is_none_or(|(_, existing)| held.len() < existing.len()) {
+        characters.insert(character, (key, held));
+      }
+    }
+  }
+  characters
+}
+
+pub fn layout_switched(index: u32) {
+  SWITCHED_LAYOUT.store(index as usize, Ordering::Relaxed);
+}
+
+// The keymap of the layout the session types with right now, compiled again whenever that changes. None when
+// libxkbcommon can't be loaded.
+pub fn session_keymap(environment: &Environment) -> Option<Arc<Keymap>> {
+  let (layouts, variants) = session_layouts(environment);
+  let index = layout_index(environment).unwrap_or_else(|| SWITCHED_LAYOUT.load(Ordering::Relaxed));
+  let (layout, variant) = pick_layout(&layouts, &variants, index);
+  let mut cached = KEYMAP.lock().unwrap();
+  if let Some((_, _, keymap)) = cached.as_ref().filter(|(cached_layout, cached_variant, _)| *cached_layout == layout && *cached_variant == variant) {
+    return keymap.clone();
+  }
+  let keymap = Keymap::compile(&layout, &variant).map(Arc::new);
+  match &keymap {
+    Some(keymap) => println!("[Keymap] Typing text with the {} layout.", keymap.layout),
+    None => println!("[Keymap] Unable to load libxkbcommon, typing text with a US layout."),
+  }
+  *cached = Some((layout, variant, keymap.clone()));
+  keymap
+}
+
+// The comma separated layouts and variants of the session: those of XKB_DEFAULT_LAYOUT and XKB_DEFAULT_VARIANT,
+// which the Wayland output uses too, otherwise Hyprland's or those localectl reports.
+fn session_layouts(environment: &Environment) -> (String, String) {
+  if let Ok(layouts) = env::var("XKB_DEFAULT_LAYOUT") {
+    return (layouts, env::var("XKB_DEFAULT_VARIANT").unwrap_or_default());
+  }
+  if matches!(&environment.server, Server::Connected(server) if server == "Hyprland") {
+    let option = |name: &str| output(environment, &format!("hyprctl getoption {} -j", name))
+      .and_then(|reply| serde_json::from_str::<serde_json::Value>(&reply).ok())
+      .and_then(|reply| reply["str"].as_str().map(str::to_string));
+    if let Some(layouts) = option("input:kb_layout").filter(|layouts| !layouts.is_empty()) {
+      return (layouts, option("input:kb_variant").unwrap_or_default());
+    }
+  }
+  output(environment, "localectl status").map(|status| localectl_layouts(&status)).unwrap_or_default()
+}
+
+fn localectl_layouts(status: &str) -> (String, String) {
+  let field = |name: &str| status.lines()
+    .find_map(|line| line.trim().strip_prefix(name).and_then(|rest| rest.strip_prefix(':')))
+    .map(|value| value.trim().to_string())
+    .unwrap_or_default();
+  (field("X11 Layout"), field("X11 Variant"))
+}
+
+// Which of its layouts the compositor types with, where it can be asked.
+fn layout_index(environment: &Environment) -> Option<usize> {
+  let Server::Connected(server) = &environment.server else { return None };
+  let command = match server.as_str() {
+    "sway" => "swaymsg -t get_inputs --raw",
+    "niri" => "niri msg -j keyboard-layouts",
+    "KDE" => "dbus-send --session --print-reply=literal --dest=org.kde.keyboard /Layouts org.kde.KeyboardLayouts.getLayout",
+    _ => return None,
+  };
+  parse_layout_index(server, &output(environment, command)?)
+}
+
+fn parse_layout_index(server: &str, reply: &str) -> Option<usize> {
+  let index = match server {
+    "sway" => serde_json::from_str::<serde_json::Value>(reply).ok()?.as_array()?.iter()
+      .find(|input| input["type"] == "keyboard")?["xkb_active_layout_index"].as_u64()?,
+    "niri" => serde_json::from_str::<serde_json::Value>(reply).ok()?["current_idx"].as_u64()?,
+    // e.g. "   uint32 1"
+    "KDE" => reply.split_whitespace().last()?.parse().ok()?,
+    _ => return None,
+  };
+  Some(index as usize)
+}
+
+// The layout and variant at that index of the lists, the first ones when there are fewer.
+fn pick_layout(layouts: &str, variants: &str, index: usize) -> (String, String) {
+  let entries = |list: &str| list.split(',').map(|entry| entry.trim().to_string()).collect::<Vec<String>>();
+  let (layouts, variants) = (entries(layouts), entries(variants));
+  let index = if index < layouts.len() { index } else { 0 };
+  (layouts[index].clone(), variants.get(index).cloned().unwrap_or_default())
+}
+
+fn output(environment: &Environment, command: &str) -> Option<String> {
+  let output = session::desktop_command(environment, command).stderr(Stdio::null()).output().ok()?;
+  output.status.success().then(|| String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+// The keys for a character in the keymap, or in a US layout without one.
+pub fn keys_for_char(keymap: Option<&Keymap>, character: char) -> Option<(Key, Vec<Key>)> {
+  match character {
+    ' ' => return Some((Key::KEY_SPACE, Vec::new())),
+    '\n' => return Some((Key::KEY_ENTER, Vec::new())),
+    '\t' => return Some((Key::KEY_TAB, Vec::new())),
+    _ => {}
+  }
+  match keymap {
+    Some(keymap) => keymap.keys_for(character),
+    None => us_keys_for_char(character),
+  }
+}
+
+pub fn us_keys_for_char(character: char) -> Option<(Key, Vec<Key>)> {
+  let unshifted = "`1234567890-=qwertyuiop[]\\asdfghjkl;'zxcvbnm,./";
+  let shifted = "~!@#$%^&*()_+QWERTYUIOP{}|ASDFGHJKL:\"ZXCVBNM<>?";
+  let keys = [
+    Key::KEY_GRAVE, Key::KEY_1, Key::KEY_2, Key::KEY_3, Key::KEY_4, Key::KEY_5, Key::KEY_6, Key::KEY_7, Key::KEY_8,
+    Key::KEY_9, Key::KEY_0, Key::KEY_MINUS, Key::KEY_EQUAL, Key::KEY_Q, Key::KEY_W, Key::KEY_E, Key::KEY_R, Key::KEY_T,
+    Key::KEY_Y, Key::KEY_U, Key::KEY_I, Key::KEY_O, Key::KEY_P, Key::KEY_LEFTBRACE, Key::KEY_RIGHTBRACE,
+    Key::KEY_BACKSLASH, Key::KEY_A, Key::KEY_S, Key::KEY_D, Key::KEY_F, Key::KEY_G, Key::KEY_H, Key::KEY_J, Key::KEY_K,
+    Key::KEY_L, Key::KEY_SEMICOLON, Key::KEY_APOSTROPHE, Key::KEY_Z, Key::KEY_X, Key::KEY_C, Key::KEY_V, Key::KEY_B,
+    Key::KEY_N, Key::KEY_M, Key::KEY_COMMA, Key::KEY_DOT, Key::KEY_SLASH,
+  ];
+  unshifted.chars().position(|c| c == character).map(|index| (keys[index], Vec::new()))
+    .or_else(|| shifted.chars().position(|c| c == character).map(|index| (keys[index], vec![Key::KEY_LEFTSHIFT])))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn types_text_with_the_keyboard_layout() {
+    assert_eq!(us_keys_for_char('A'), Some((Key::KEY_A, vec![Key::KEY_LEFTSHIFT])));
+    // libxkbcommon is loaded at runtime, without it text is typed with the US layout.
+    if let Some(german) = Keymap::compile("de", "") {
+      assert_eq!(german.keys_for('z'), Some((Key::KEY_Y, vec![])));
+      assert_eq!(german.keys_for('"'), Some((Key::KEY_2, vec![Key::KEY_LEFTSHIFT])));
+      assert_eq!(german.keys_for('@'), Some((Key::KEY_Q, vec![Key::KEY_RIGHTALT])));
+      assert_eq!(german.keys_for('ä'), Some((Key::KEY_APOSTROPHE, vec![])));
+    }
+  }
+
+  #[test]
+  fn resolves_the_layout_the_session_types_with() {
+    let status = "   System Locale: LANG=de_DE.UTF-8\n       VC Keymap: de\n      X11 Layout: de,us\n     X11 Variant: nodeadkeys,\n";
+    assert_eq!(localectl_layouts(status), ("de,us".to_string(), "nodeadkeys,".to_string()));
+    assert_eq!(pick_layout("de,us", "nodeadkeys,", 1), ("us".to_string(), "".to_string()));
+    assert_eq!(pick_layout("de,us", "nodeadkeys,", 0), ("de".to_string(), "nodeadkeys".to_string()));
+    assert_eq!(pick_layout("de", "", 3), ("de".to_string(), "".to_string()));
+    assert_eq!(pick_layout("", "", 0), ("".to_string(), "".to_string()));
+
+    assert_eq!(parse_layout_index("sway", r#"[{"type": "pointer"}, {"type": "keyboard", "xkb_active_layout_index": 1}]"#), Some(1));
+    assert_eq!(parse_layout_index("niri", r#"{"names": ["German", "English (US)"], "current_idx": 1}"#), Some(1));
+    assert_eq!(parse_layout_index("KDE", "   uint32 2\n"), Some(2));
+    assert_eq!(parse_layout_index("Hyprland", "{}"), None);
+  }
+}
@@ -0,0 +1,37 @@
+use crate::state_files;
+use std::{collections::HashMap, path::PathBuf, sync::atomic::{AtomicBool, Ordering}, sync::Mutex};
+
+lazy_static::lazy_static! {
+  static ref ACTIVE_LAYOUTS: Mutex<HashMap<String, u16>> = Mutex::new(HashMap::new());
+}
+
+static PERSIST: AtomicBool = AtomicBool::new(false);
+
+// Layouts are per device, so they're saved as a JSON object of device names to layout numbers.
+pub fn init(config_directory: &str) {
+  if let Some((layout_file, contents)) = state_files::read("layouts.json", Some(&PathBuf::from(config_directory).join(".layouts"))) {
+    match serde_json::from_str::<HashMap<String, u16>>(&contents) {
+      Ok(layouts) => *ACTIVE_LAYOUTS.lock().unwrap() = layouts,
+      Err(e) => eprintln!("[Layouts] Ignoring {}: {}", layout_file.display(), e),
+    }
+  }
+  PERSIST.store(true, Ordering::Relaxed);
+}
+
+pub fn saved_layout(device: &str) -> Option<u16> {
+  ACTIVE_LAYOUTS.lock().unwrap().get(device).copied()
+}
+
+pub fn save_layout(device: &str, layout: u16) {
+  let mut layouts = ACTIVE_LAYOUTS.lock().unwrap();
+  match layout {
+    0 => layouts.remove(device),
+    _ => layouts.insert(device.to_string(), layout),
+  };
+
+  if PERSIST.load(Ordering::Relaxed) {
+    if let Err(e) = state_files::write("layouts.json", &serde_json::to_string(&*layouts).unwrap_or_default()) {
+      eprintln!("[Layouts] Unable to save the active layouts to {}: {}", state_files::state_directory().display(), e);
+    }
+  }
+}
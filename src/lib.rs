@@ -0,0 +1,63 @@
+//! The mapping engine behind the `makita` binary, for tools that want to embed it, e.g. GUIs, tests or
+//! other frontends.
+//!
+//! - [`config`] parses config files into [`Config`]s: bindings, settings and the modifiers they use.
+//! - [`udev_monitor`] finds the devices that have a config, grabs them and starts an
+//!   [`EventReader`](input_event_handling::event_reader::EventReader) for each.
+//! - [`input_event_handling`] turns physical events into virtual ones according to the bindings.
+//! - [`virtual_devices`] holds the uinput devices every reader emits to.
+//!
+//! A minimal frontend does what the `makita` binary does without the optional services:
+//!
+//! ```no_run
+//! use makita_core::{udev_monitor::start_monitoring_udev, virtual_devices::VirtualDevices, Config};
+//!
+//! # async fn run() {
+//! let configs = vec![Config::new_from_file("/home/user/.config/makita/My Mouse.toml", "My Mouse".to_string()).unwrap()];
+//! let virtual_devices = VirtualDevices::new();
+//! start_monitoring_udev(configs, virtual_devices, None).await;
+//! # }
+//! ```
+//!
+//! The IPC, event tap, scheduler and Ruby services are started separately, see `main.rs`.
+
+pub mod active_client;
+pub mod capabilities;
+pub mod check;
+pub mod cli;
+pub mod clipboard;
+pub mod conditions;
+pub mod config;
+pub mod config_watcher;
+pub mod controller_db;
+pub mod doctor;
+pub mod event_tap;
+pub mod hidraw;
+pub mod history;
+pub mod importer;
+pub mod keymap;
+pub mod ipc;
+pub mod layouts;
+pub mod lint;
+pub mod live_settings;
+pub mod merge;
+pub mod notifier;
+pub mod outputs;
+pub mod profiles;
+pub mod reload;
+pub mod remote;
+pub mod ruby_runtime;
+pub mod scheduler;
+pub mod schema;
+pub mod session;
+pub mod state;
+pub mod state_files;
+pub mod stats;
+#[cfg(feature = "test-harness")]
+pub mod test_harness;
+pub mod udev_monitor;
+pub mod virtual_devices;
+pub mod wayland_output;
+pub mod input_event_handling;
+
+pub use config::Config;
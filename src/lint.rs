@@ -0,0 +1,159 @@
+use crate::config::{canonical_name, parse_key, Axis, RawConfig};
+use crate::reload;
+use std::{collections::HashMap, fs, path::Path, str::FromStr};
+
+const DEFAULT_MODIFIERS: [&str; 7] = [
+  "KEY_LEFTSHIFT", "KEY_LEFTCTRL", "KEY_LEFTALT", "KEY_RIGHTSHIFT", "KEY_RIGHTCTRL", "KEY_RIGHTALT", "KEY_LEFTMETA",
+];
+
+// In the order convert_event looks them up, so the first table of a duplicate is the one that fires.
+const TABLES: [&str; 5] = ["rubies", "overrides", "actions", "remap", "movements"];
+
+struct Binding {
+  table: &'static str,
+  raw: String,
+  input: String,
+  modifiers: Vec<String>,
+  chained: bool,
+  target: String,
+}
+
+fn is_event(name: &str) -> bool {
+  Axis::from_str(name).is_ok() || parse_key(name).is_some()
+}
+
+fn parse_binding(table: &'static str, raw: &str, target: String) -> Binding {
+  let (modifiers, input) = raw.rsplit_once('-').unwrap_or(("", raw));
+  let chained = raw.starts_with('-');
+  let mut modifiers: Vec<String> = modifiers.split('-').filter(|modifier| !modifier.is_empty()).map(canonical_name).collect();
+  modifiers.sort();
+  Binding { table, raw: raw.to_string(), input: canonical_name(input), modifiers, chained, target }
+}
+
+fn lint_config(file_name: &str, raw_config: &RawConfig, scripts_directory: &str) -> Vec<String> {
+  let mut warnings = Vec::new();
+  let mut bindings = Vec::new();
+  for table in TABLES {
+    let entries: Vec<(String, String)> = match table {
+      "rubies" => raw_config.rubies.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+      "overrides" => raw_config.overrides.iter().map(|(k, v)| (k.clone(), format!("{:?}", v))).collect(),
+      "actions" => raw_config.actions.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+      "remap" => raw_config.remap.iter().map(|(k, v)| (k.clone(), format!("{:?}", v))).collect(),
+      _ => raw_config.movements.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+    };
+    let mut entries = entries;
+    entries.sort();
+    bindings.extend(entries.into_iter().map(|(raw, target)| parse_binding(table, &raw, target)));
+  }
+
+  for binding in &bindings {
+    for name in binding.modifiers.iter().chain([&binding.input]) {
+      if !is_event(name) {
+        warnings.push(format!("{}: [{}] {} uses unknown key or axis {}, the config file is skipped", file_name, binding.table, binding.raw, name));
+      }
+    }
+  }
+
+  // Duplicates, including the same combination written with modifiers in another order.
+  let mut seen: HashMap<(String, Vec<String>, bool), &Binding> = HashMap::new();
+  for binding in &bindings {
+    let key = (binding.input.clone(), binding.modifiers.clone(), binding.chained);
+    match seen.get(&key) {
+      Some(first) => warnings.push(format!(
+        "{}: [{}] {} duplicates [{}] {}, only the [{}] one takes effect",
+        file_name, binding.table, binding.raw, first.table, first.raw, first.table
+      )),
+      None => { seen.insert(key, binding); }
+    }
+  }
+
+  // A key whose own press is consumed by a script, override or action never becomes a held modifier.
+  for binding in bindings.iter().filter(|binding| !binding.modifiers.is_empty()) {
+    for modifier in &binding.modifiers {
+      let consumer = bindings.iter().find(|other| {
+        ["rubies", "overrides", "actions"].contains(&other.table) && &other.input == modifier && other.modifiers.is_empty() && !other.chained
+      });
+      if let Some(consumer) = consumer {
+        warnings.push(format!(
+          "{}: [{}] {} is shadowed, its modifier {} is consumed by [{}] {}",
+          file_name, binding.table, binding.raw, modifier, consumer.table, consumer.raw
+        ));
+      }
+    }
+  }
+
+  // Chained bindings match any set of held modifiers, so they win over movements on the same input.
+  for binding in bindings.iter().filter(|binding| binding.table == "movements" && !binding.modifiers.is_empty()) {
+    if let Some(chained) = bindings.iter().find(|other| other.table == "remap" && other.chained && other.input == binding.input) {
+      warnings.push(format!(
+        "{}: [movements] {} is shadowed by the chained binding [remap] {}",
+        file_name, binding.raw, chained.raw
+      ));
+    }
+  }
+
+  let mut custom_modifiers: Vec<&String> = bindings.iter()
+    .flat_map(|binding| binding.modifiers.iter())
+    .filter(|modifier| !DEFAULT_MODIFIERS.contains(&modifier.as_str()))
+    .collect();
+  custom_modifiers.sort();
+  custom_modifiers.dedup();
+  for modifier in custom_modifiers {
+    if let Some(plain) = bindings.iter().find(|binding| binding.table == "remap" && &binding.input == modifier && binding.modifiers.is_empty() && !binding.chained) {
+      warnings.push(format!(
+        "{}: {} is used as a custom modifier and also remapped on its own by [remap] {}",
+        file_name, modifier, plain.raw
+      ));
+    }
+  }
+
+  for binding in bindings.iter().filter(|binding| binding.table == "rubies") {
+    let script = Path::new(scripts_directory).join(format!("{}.rb", binding.target));
+    if !script.exists() {
+      warnings.push(format!(
+        "{}: [rubies] {} references {}, which doesn't exist",
+        file_name, binding.raw, script.display()
+      ));
+    }
+  }
+
+  warnings
+}
+
+pub fn run(config_directory: &str, scripts_directory: &str) {
+  let files: Vec<String> = match reload::config_files(Path::new(config_directory)) {
+    Ok(paths) => paths
+      .iter()
+      .map(|path| path.strip_prefix(config_directory).unwrap_or(path).display().to_string())
+      .collect(),
+    Err(e) => {
+      eprintln!("Unable to read {}: {}", config_directory, e);
+      std::process::exit(1);
+    }
+  };
+
+  let mut warnings = Vec::new();
+  let mut names: HashMap<String, &String> = HashMap::new();
+  for file_name in &files {
+    let name = Path::new(file_name).file_name().unwrap_or_default().to_string_lossy().to_string();
+    if name == reload::DEFAULTS_FILE { continue; }
+    if let Some(first) = names.insert(name, file_name) {
+      warnings.push(format!("{}: configures the same device as {}, the config file is skipped", file_name, first));
+    }
+  }
+  for file_name in &files {
+    let content = fs::read_to_string(Path::new(config_directory).join(file_name)).unwrap_or_default();
+    match RawConfig::from_str(&content) {
+      Ok(raw_config) => warnings.extend(lint_config(file_name, &raw_config, scripts_directory)),
+      Err(e) => warnings.push(format!("{}: unable to parse: {}", file_name, e)),
+    }
+  }
+
+  for warning in &warnings {
+    println!("{}", warning);
+  }
+  println!("Checked {} config file(s), found {} problem(s).", files.len(), warnings.len());
+  if !warnings.is_empty() {
+    std::process::exit(1);
+  }
+}
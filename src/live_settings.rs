@@ -0,0 +1,96 @@
+use crate::config::{Associations, Config};
+use crate::schema;
+use lazy_static::lazy_static;
+use std::{
+  collections::HashMap,
+  sync::atomic::{AtomicU64, Ordering},
+  sync::Mutex,
+};
+
+lazy_static! {
+  static ref CONFIGURED: Mutex<HashMap<String, HashMap<String, String>>> = Mutex::new(HashMap::new());
+  // Keyed by device name, None applying to every device.
+  static ref OVERRIDES: Mutex<HashMap<Option<String>, HashMap<String, String>>> = Mutex::new(HashMap::new());
+}
+
+// Bumped on every change so readers know when to rebuild their settings.
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+// The settings readers pick up again when changed, checked like every other setting with schema::check_setting.
+const ADJUSTABLE: [&str; 14] = [
+  "CURSOR_SPEED",
+  "SCROLL_SPEED",
+  "CURSOR_ACCEL",
+  "SCROLL_ACCEL",
+  "LSTICK_DEADZONE",
+  "RSTICK_DEADZONE",
+  "LSTICK_SENSITIVITY",
+  "RSTICK_SENSITIVITY",
+  "CURSOR_SPEED_PRESETS",
+  "WHEEL_MOVEMENT_SENSITIVITY",
+  "WHEEL_MOVEMENT_ACCEL",
+  "WHEEL_MULTIPLIER",
+  "LSTICK",
+  "RSTICK",
+];
+
+pub fn init(configs: &[Config]) {
+  let mut configured = CONFIGURED.lock().unwrap();
+  for config in configs.iter().filter(|config| config.associations == Associations::default()) {
+    configured.insert(config.name.clone(), config.settings.clone());
+  }
+}
+
+pub fn generation() -> u64 {
+  GENERATION.load(Ordering::Relaxed)
+}
+
+pub fn validate(setting: &str, value: &str) -> Result<(), String> {
+  if !ADJUSTABLE.contains(&setting) {
+    return Err(format!("{} can't be changed at runtime", setting));
+  }
+  schema::check_setting(setting, value)
+}
+
+pub fn set(device: Option<String>, setting: &str, value: &str) -> Result<(), String> {
+  if let Some(device) = &device {
+    if !CONFIGURED.lock().unwrap().contains_key(device) {
+      return Err(format!("no config for device {}", device));
+    }
+  }
+  validate(setting, value)?;
+  OVERRIDES.lock().unwrap().entry(device).or_default().insert(setting.to_string(), value.to_string());
+  GENERATION.fetch_add(1, Ordering::Relaxed);
+  Ok(())
+}
+
+// The runtime value if one was set, otherwise the one from the device's config file.
+pub fn get(device: Option<&str>, setting: &str) -> Option<String> {
+  let overrides = OVERRIDES.lock().unwrap();
+  let device_override = device.and_then(|device| overrides.get(&Some(device.to_string()))).and_then(|map| map.get(setting));
+  let global_override = overrides.get(&None).and_then(|map| map.get(setting));
+  let configured = device.and_then(|device| CONFIGURED.lock().unwrap().get(device).and_then(|map| map.get(setting)).cloned());
+  device_override.or(global_override).cloned().or(configured)
+}
+
+pub fn overrides_for(device: &str) -> HashMap<String, String> {
+  let overrides = OVERRIDES.lock().unwrap();
+  let mut merged = overrides.get(&None).cloned().unwrap_or_default();
+  merged.extend(overrides.get(&Some(device.to_string())).cloned().unwrap_or_default());
+  merged
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn validates_values_like_the_readers_parse_them() {
+    assert!(validate("LSTICK_SENSITIVITY", "8").is_ok());
+    assert!(validate("LSTICK_SENSITIVITY", "-1").is_err());
+    assert!(validate("RSTICK_DEADZONE", "-1").is_ok());
+    assert!(validate("CURSOR_SPEED_PRESETS", "0.5, 1.0").is_ok());
+    assert!(validate("LSTICK", "sideways").is_err());
+    assert!(validate("GRAB_DEVICE", "true").is_err());
+  }
+}
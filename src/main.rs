@@ -1,22 +1,138 @@
-mod active_client;
-mod config;
-mod ruby_runtime;
-mod udev_monitor;
-mod virtual_devices;
-mod input_event_handling;
-
-use crate::udev_monitor::*;
-use config::Config;
-use std::{env, thread};
+use makita_core::udev_monitor::*;
+use makita_core::{check, cli, config_watcher, controller_db, doctor, event_tap, hidraw, history, importer, ipc, layouts, lint, live_settings, merge, notifier, profiles, reload, remote, ruby_runtime, scheduler, schema, session, Config};
+use std::env;
 use std::sync::{Arc, Mutex};
 use tokio;
-use crate::input_event_handling::event_sender::EventSender;
-use crate::ruby_runtime::RubyService;
-use crate::virtual_devices::VirtualDevices;
+use makita_core::input_event_handling::event_sender::EventSender;
+use makita_core::ruby_runtime::RubyService;
+use makita_core::virtual_devices::{Target, VirtualDevices};
 
 #[tokio::main]
 async fn main() {
-  let config_directory = match env::var("MAKITA_CONFIG") {
+  let args: Vec<String> = env::args().collect();
+  let command = match cli::parse(&args[1..]) {
+    Ok(command) => command,
+    Err(e) => {
+      eprintln!("{}\n\n{}", e, cli::USAGE);
+      std::process::exit(2);
+    }
+  };
+  match &command {
+    cli::Command::Help => {
+      println!("{}", cli::USAGE);
+      return;
+    }
+    cli::Command::Version => {
+      println!("makita {}", env!("CARGO_PKG_VERSION"));
+      return;
+    }
+    cli::Command::Import(args) => {
+      importer::run(args);
+      return;
+    }
+    cli::Command::Schema => {
+      schema::run();
+      return;
+    }
+    _ => {}
+  }
+
+  let config_directory = get_config_directory();
+  match command {
+    cli::Command::Lint => {
+      lint::run(&config_directory, &get_ruby_scripts_directory(&config_directory));
+      return;
+    }
+    cli::Command::Check => {
+      check::run(&config_directory);
+      return;
+    }
+    cli::Command::Doctor => {
+      doctor::run(&config_directory, &get_ruby_scripts_directory(&config_directory));
+      return;
+    }
+    _ => {}
+  }
+
+  let configs: Vec<Config> = match reload::load_configs(&config_directory) {
+    Ok((configs, errors)) => {
+      for error in errors {
+        println!("[Config] Skipping {}.", error);
+      }
+      configs
+    }
+    _ => {
+      println!("Config directory not found, exiting Makita.");
+      std::process::exit(1);
+    }
+  };
+  if command == cli::Command::ListDevices {
+    list_devices(&configs);
+    return;
+  }
+
+  reload::init(&config_directory, &configs);
+  history::init();
+  profiles::init(&config_directory);
+  layouts::init(&config_directory);
+  controller_db::init(&config_directory);
+  live_settings::init(&configs);
+  notifier::init(&configs);
+  hidraw::start_hidraw_bridges(&configs);
+  merge::start_merged_devices(&configs);
+
+  let ruby_scripts_directory = get_ruby_scripts_directory(&config_directory);
+
+  let rubies = ruby_runtime::configured_scripts(&configs, &ruby_scripts_directory);
+  let ruby_service = start_ruby_service(rubies, get_ruby_library_directory(&ruby_scripts_directory));
+  // Devices with virtual devices of their own also get their own virtual gamepad.
+  let wayland = |config: &Config| config.settings.get("OUTPUT_BACKEND").is_some_and(|backend| backend == "wayland");
+  let shared_xpad = configs.iter().any(|config| {
+    config.settings.get("VIRTUAL_GAMEPAD").is_some_and(|mode| mode == "xpad")
+      && config.settings.get("SEPARATE_VIRTUAL_DEVICES").is_none_or(|value| value != "true")
+      && !wayland(config)
+  });
+  let shared_flat_pointer = configs.iter().any(|config| {
+    config.settings.get("FLAT_POINTER").is_some_and(|value| value == "true")
+      && config.settings.get("SEPARATE_VIRTUAL_DEVICES").is_none_or(|value| value != "true")
+      && !wayland(config)
+  });
+  // When no device emits through uinput, which might not be available then, scripts go through the compositor too.
+  let wayland_only = configs.iter().filter(|config| !config.name.contains("::")).all(wayland);
+  let virtual_devices = match (wayland_only, shared_xpad) {
+    (true, _) => VirtualDevices::wayland("Makita"),
+    (false, true) => VirtualDevices::new().with_xpad(),
+    (false, false) => VirtualDevices::new(),
+  };
+  let virtual_devices = match shared_flat_pointer && !wayland_only {
+    true => virtual_devices.with_flat_pointer(format!("{} (flat)", Target::Pointer.device_name())),
+    false => virtual_devices,
+  };
+
+  println!("Creating EventSender...");
+  let release_modifiers = configs.iter().any(|config| config.settings.get("RUBY_RELEASE_MODIFIERS").is_some_and(|value| value == "true"));
+  EventSender::new(ruby_runtime::synthetic_event_receiver(), virtual_devices.clone())
+    .release_modifiers(release_modifiers)
+    .spawn();
+
+  tokio::spawn(ipc::serve());
+  tokio::spawn(config_watcher::run(config_directory.clone()));
+  remote::start(&configs, virtual_devices.clone());
+  if configs.iter().any(|config| config.settings.get("EVENT_TAP").is_some_and(|tap| tap != "off")) {
+    tokio::spawn(event_tap::serve());
+  }
+  if configs.iter().any(|config| config.settings.contains_key("LOCK_SCREEN_MODE")) {
+    tokio::spawn(session::monitor_lock_signals());
+  }
+  let schedule = scheduler::parse_schedule(&configs);
+  if !schedule.is_empty() {
+    tokio::spawn(scheduler::run(schedule));
+  }
+  start_monitoring_udev(configs, virtual_devices, ruby_service).await;
+}
+
+fn get_config_directory() -> String {
+  match env::var("MAKITA_CONFIG") {
     Ok(directory) => {
       println!("MAKITA_CONFIG set to {}.", directory);
       directory
@@ -34,63 +150,33 @@ async fn main() {
       println!("MAKITA_CONFIG environment variable is not set, defaulting to {}.", directory);
       directory
     }
-  };
-
-  let mut configs: Vec<Config> = Vec::new();
-  match std::fs::read_dir(config_directory.clone()) {
-    Ok(directory_iterator) => {
-      for file in directory_iterator {
-        let filename: String = file.as_ref().unwrap().file_name().into_string().unwrap();
-
-        if filename.ends_with(".toml") && !filename.starts_with(".") {
-          let name: String = filename.split(".toml").collect::<Vec<&str>>()[0].to_string();
-          let config_file: Config = Config::new_from_file(file.unwrap().path().to_str().unwrap(), name);
-          configs.push(config_file);
-        }
-      }
-    },
-    _ => {
-      println!("Config directory not found, exiting Makita.");
-      std::process::exit(1);
-    }
   }
+}
 
-  let ruby_scripts_directory = match env::var("MAKITA_RUBY_SCRIPTS") {
+fn get_ruby_scripts_directory(config_directory: &str) -> String {
+  match env::var("MAKITA_RUBY_SCRIPTS") {
     Ok(directory) => directory,
     _ => {
       let directory = format!("{}/{}", config_directory, "scripts");
       println!("MAKITA_RUBY_SCRIPTS environment variable is not set, defaulting to {}", directory);
       directory
     }
-  };
-
-  let mut rubies = Vec::new();
-  for config in configs.clone() {
-    for (_event, modifier_map) in config.bindings.rubies {
-      for (_modifiers, script_name) in modifier_map {
-        let script_path = format!("{}/{}.rb", ruby_scripts_directory, script_name);
-        rubies.push((script_name, script_path));
-      }
-    }
   }
+}
 
-  let ruby_service = start_ruby_service(rubies);
-  let virtual_devices = Arc::new(Mutex::new(VirtualDevices::new()));
-
-  if let Some(service) = ruby_service.clone() {
-    println!("Creating EventSender...");
-    let event_sender = EventSender::new(service.lock().unwrap().get_synthetic_event_receiver(), virtual_devices.clone());
-    thread::spawn(move || { start_event_sender(event_sender); });
+fn get_ruby_library_directory(ruby_scripts_directory: &str) -> String {
+  match env::var("MAKITA_RUBY_LIB") {
+    Ok(directory) => directory,
+    _ => format!("{}/{}", ruby_scripts_directory, "lib"),
   }
-
-  start_monitoring_udev(configs, virtual_devices, ruby_service).await;
 }
 
-fn start_ruby_service(rubies: Vec<(String, String)>) -> Option<Arc<Mutex<RubyService>>> {
+fn start_ruby_service(rubies: Vec<(String, String)>, library_directory: String) -> Option<Arc<Mutex<RubyService>>> {
   if rubies.is_empty() { return None }
 
   println!("Initializing Ruby service...");
   let service = RubyService::new().expect("Failed to create Ruby service");
+  service.use_library(library_directory);
 
   for ruby in rubies {
     println!("Loading Ruby script: {}", ruby.0);
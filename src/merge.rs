@@ -0,0 +1,206 @@
+use crate::config::{Config, Merge};
+use evdev::{
+  uinput::{VirtualDevice, VirtualDeviceBuilder},
+  AbsInfo, AbsoluteAxisType, AttributeSet, Device, EventType, InputEvent, Key, RelativeAxisType, UinputAbsSetup,
+};
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+  thread,
+  time::Duration,
+};
+
+// Several physical devices, e.g. a pair of Joy-Cons, are grabbed and re-emitted on a single virtual device
+// named after the config file, so the regular udev pipeline maps them as one controller.
+pub fn start_merged_devices(configs: &[Config]) {
+  for config in configs {
+    let Some(merge) = config.merge.clone() else { continue };
+    let name = config.name.clone();
+
+    println!("[Merge] Merging {} into \"{}\".", merge.devices.join(", "), name);
+    thread::spawn(move || {
+      if let Err(e) = run_merge(&name, &merge) {
+        eprintln!("[Merge] Merged device \"{}\" stopped: {}", name, e);
+      }
+    });
+  }
+}
+
+fn find_device(name: &str) -> Option<Device> {
+  evdev::enumerate().map(|(_, device)| device).find(|device| device.name() == Some(name))
+}
+
+fn run_merge(name: &str, merge: &Merge) -> std::io::Result<()> {
+  let mut announced = false;
+  let sources: Vec<Device> = loop {
+    if let Some(sources) = merge.devices.iter().map(|device| find_device(device)).collect() {
+      break sources;
+    }
+    if !announced {
+      println!("[Merge] Waiting for every device of \"{}\" to connect.", name);
+      announced = true;
+    }
+    thread::sleep(Duration::from_secs(2));
+  };
+
+  let mut keys: AttributeSet<Key> = AttributeSet::new();
+  let mut relative_axes: AttributeSet<RelativeAxisType> = AttributeSet::new();
+  let mut absolute_axes: HashMap<u16, AbsInfo> = HashMap::new();
+  let mut transforms = Vec::new();
+
+  for (device_name, device) in merge.devices.iter().zip(&sources) {
+    if let Some(supported) = device.supported_keys() {
+      for key in supported.iter() { keys.insert(key); }
+    }
+    if let Some(supported) = device.supported_relative_axes() {
+      for axis in supported.iter() { relative_axes.insert(axis); }
+    }
+
+    let transform = StickTransform::new(device_name, merge, device)?;
+    if let Some(supported) = device.supported_absolute_axes() {
+      let state = device.get_abs_state()?;
+      for axis in supported.iter() {
+        let info = state[axis.0 as usize];
+        absolute_axes.insert(
+          transform.target_axis(axis.0),
+          AbsInfo::new(info.value, info.minimum, info.maximum, info.fuzz, info.flat, info.resolution),
+        );
+      }
+    }
+    transforms.push(transform);
+  }
+
+  let mut builder = VirtualDeviceBuilder::new()?.name(name);
+  if keys.iter().next().is_some() { builder = builder.with_keys(&keys)?; }
+  if relative_axes.iter().next().is_some() { builder = builder.with_relative_axes(&relative_axes)?; }
+  for (code, info) in absolute_axes {
+    builder = builder.with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType(code), info))?;
+  }
+  let virtual_device = Arc::new(Mutex::new(builder.build()?));
+
+  let mut threads = Vec::new();
+  for ((device_name, device), transform) in merge.devices.iter().cloned().zip(sources).zip(transforms) {
+    let virtual_device = virtual_device.clone();
+    threads.push(thread::spawn(move || forward(device_name, device, transform, virtual_device)));
+  }
+  for thread in threads {
+    let _ = thread.join();
+  }
+  Ok(())
+}
+
+// Reconnects to the source by name when it drops, which is common for Bluetooth controllers.
+fn forward(device_name: String, mut device: Device, mut transform: StickTransform, virtual_device: Arc<Mutex<VirtualDevice>>) {
+  loop {
+    if let Err(e) = device.grab() {
+      println!("[Merge] Unable to grab {}: {}", device_name, e);
+    }
+    while let Ok(events) = device.fetch_events() {
+      let events: Vec<InputEvent> = events
+        .filter(|event| event.event_type() != EventType::SYNCHRONIZATION)
+        .flat_map(|event| transform.apply(event))
+        .collect();
+      if !events.is_empty() {
+        let _ = virtual_device.lock().unwrap().emit(&events);
+      }
+    }
+
+    println!("[Merge] {} disconnected, waiting for it to reconnect.", device_name);
+    device = loop {
+      thread::sleep(Duration::from_secs(2));
+      if let Some(device) = find_device(&device_name) { break device; }
+    };
+    println!("[Merge] {} reconnected.", device_name);
+  }
+}
+
+// Moves a source's stick onto the left or right stick of the merged device and undoes the rotation of
+// controllers that report their stick sideways.
+struct StickTransform {
+  source: Option<(u16, u16)>,
+  target: (u16, u16),
+  rotation: i32,
+  centers: [i32; 2],
+  offsets: [i32; 2],
+}
+
+impl StickTransform {
+  fn new(device_name: &str, merge: &Merge, device: &Device) -> std::io::Result<Self> {
+    let supported = device.supported_absolute_axes();
+    let has_axis = |axis: AbsoluteAxisType| supported.is_some_and(|axes| axes.contains(axis));
+    let source = if has_axis(AbsoluteAxisType::ABS_X) && has_axis(AbsoluteAxisType::ABS_Y) {
+      Some((AbsoluteAxisType::ABS_X.0, AbsoluteAxisType::ABS_Y.0))
+    } else if has_axis(AbsoluteAxisType::ABS_RX) && has_axis(AbsoluteAxisType::ABS_RY) {
+      Some((AbsoluteAxisType::ABS_RX.0, AbsoluteAxisType::ABS_RY.0))
+    } else {
+      None
+    };
+
+    // hid-nintendo names them "Nintendo Switch Right Joy-Con", hid-generic "Joy-Con (R)".
+    let right_joycon = merge.joycon && (device_name.contains("Right") || device_name.contains("(R)"));
+    let left_joycon = merge.joycon && (device_name.contains("Left") || device_name.contains("(L)"));
+    let target = match source {
+      _ if right_joycon => (AbsoluteAxisType::ABS_RX.0, AbsoluteAxisType::ABS_RY.0),
+      _ if left_joycon => (AbsoluteAxisType::ABS_X.0, AbsoluteAxisType::ABS_Y.0),
+      Some(source) => source,
+      None => (AbsoluteAxisType::ABS_X.0, AbsoluteAxisType::ABS_Y.0),
+    };
+
+    let mut centers = [0, 0];
+    let mut offsets = [0, 0];
+    if let Some((x, y)) = source {
+      let state = device.get_abs_state()?;
+      for (index, code) in [x, y].into_iter().enumerate() {
+        let info = state[code as usize];
+        centers[index] = (info.minimum + info.maximum) / 2;
+        offsets[index] = info.value - centers[index];
+      }
+    }
+
+    Ok(Self {
+      source,
+      target,
+      rotation: merge.rotate.get(device_name).copied().unwrap_or(0).rem_euclid(360),
+      centers,
+      offsets,
+    })
+  }
+
+  fn target_axis(&self, code: u16) -> u16 {
+    match self.source {
+      Some((x, _)) if code == x => self.target.0,
+      Some((_, y)) if code == y => self.target.1,
+      _ => code,
+    }
+  }
+
+  fn apply(&mut self, event: InputEvent) -> Vec<InputEvent> {
+    let Some((source_x, source_y)) = self.source else { return vec![event] };
+    if event.event_type() != EventType::ABSOLUTE {
+      return vec![event];
+    }
+    let index = match event.code() {
+      code if code == source_x => 0,
+      code if code == source_y => 1,
+      _ => return vec![event],
+    };
+    if self.rotation == 0 {
+      let code = if index == 0 { self.target.0 } else { self.target.1 };
+      return vec![InputEvent::new(EventType::ABSOLUTE, code, event.value())];
+    }
+
+    self.offsets[index] = event.value() - self.centers[index];
+    let [x, y] = self.offsets;
+    // Clockwise quarter turns, with Y pointing down as evdev reports it.
+    let (x, y) = match self.rotation {
+      90 => (-y, x),
+      180 => (-x, -y),
+      270 => (y, -x),
+      _ => (x, y),
+    };
+    vec![
+      InputEvent::new(EventType::ABSOLUTE, self.target.0, x + self.centers[0]),
+      InputEvent::new(EventType::ABSOLUTE, self.target.1, y + self.centers[1]),
+    ]
+  }
+}
@@ -0,0 +1,185 @@
+use crate::config::{Config, NOTIFIERS};
+use crate::keymap;
+use crate::session;
+use crate::udev_monitor::{Environment, Server};
+use lazy_static::lazy_static;
+use std::{collections::HashMap, process::Stdio, sync::Mutex, thread};
+use zbus::blocking::{connection, Connection};
+use zbus::zvariant::Value;
+
+lazy_static! {
+  static ref BACKEND: Mutex<Backend> = Mutex::new(Backend::default());
+  // For scripts, which have no device to take it from.
+  static ref ENVIRONMENT: Mutex<Option<Environment>> = Mutex::new(None);
+}
+
+// How notifications are shown: over D-Bus, falling back to NOTIFY_COMMAND when no notification daemon answers, or
+// only through NOTIFY_COMMAND, a command template with {message} in it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+  #[default]
+  Dbus,
+  DbusOr(String),
+  Command(String),
+}
+
+// Like RUBY_RELEASE_MODIFIERS, the first config that sets NOTIFIER or NOTIFY_COMMAND picks the backend of all of them.
+pub fn backend(configs: &[Config]) -> Backend {
+  let mut configs: Vec<&Config> = configs.iter().collect();
+  configs.sort_by(|a, b| a.name.cmp(&b.name));
+  let setting = |name: &str| configs.iter().find_map(|config| config.settings.get(name).cloned());
+  match (setting("NOTIFIER").as_deref(), setting("NOTIFY_COMMAND")) {
+    (Some("command"), Some(command)) => Backend::Command(command),
+    (Some("command"), None) => {
+      println!("[Notifier] Warning: NOTIFIER is command but NOTIFY_COMMAND isn't set, using dbus.");
+      Backend::Dbus
+    }
+    (notifier, command) => {
+      if let Some(notifier) = notifier.filter(|notifier| !NOTIFIERS.contains(notifier)) {
+        println!("[Notifier] Warning: invalid NOTIFIER {}, using dbus.", notifier);
+      }
+      command.map_or(Backend::Dbus, Backend::DbusOr)
+    }
+  }
+}
+
+pub fn init(configs: &[Config]) {
+  *BACKEND.lock().unwrap() = backend(configs);
+}
+
+pub fn set_environment(environment: &Environment) {
+  *ENVIRONMENT.lock().unwrap() = Some(environment.clone());
+}
+
+// Shows a desktop notification, as the user of the active session when Makita runs as root.
+pub fn notify(environment: &Environment, message: &str) {
+  match BACKEND.lock().unwrap().clone() {
+    Backend::Command(template) => run(environment, &notify_command(&template, message)),
+    backend => {
+      let (environment, message) = (environment.clone(), message.to_string());
+      // Waits for the call to find out whether a notification daemon answered.
+      thread::spawn(move || {
+        let Err(e) = notify_over_dbus(&environment, &message) else { return };
+        match backend {
+          Backend::DbusOr(template) => run(&environment, &notify_command(&template, &message)),
+          _ => println!("[Notifier] Unable to show a notification over D-Bus, set NOTIFY_COMMAND to show them another way: {}", e),
+        }
+      });
+    }
+  }
+}
+
+// For Makita.notify in scripts.
+pub fn notify_from_script(message: &str) {
+  match ENVIRONMENT.lock().unwrap().clone() {
+    Some(environment) => notify(&environment, message),
+    None => println!("[Notifier] Unable to show a notification before the devices are set up: {}", message),
+  }
+}
+
+fn quote(text: &str) -> String {
+  format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+// org.freedesktop.Notifications.Notify(app_name, replaces_id, app_icon, summary, body, actions, hints, timeout) on the
+// session bus of the desktop user.
+fn notify_over_dbus(environment: &Environment, message: &str) -> zbus::Result<()> {
+  let connection = match session::session_bus_address(environment) {
+    Some(address) => connection::Builder::address(address.as_str())?.build()?,
+    None => Connection::session()?,
+  };
+  let hints: HashMap<&str, Value> = HashMap::new();
+  connection.call_method(
+    Some("org.freedesktop.Notifications"),
+    "/org/freedesktop/Notifications",
+    Some("org.freedesktop.Notifications"),
+    "Notify",
+    &("Makita", 0u32, "", "Makita", message, Vec::<&str>::new(), hints, 1500i32),
+  )?;
+  Ok(())
+}
+
+pub fn notify_command(template: &str, message: &str) -> String {
+  template.replace("{message}", &quote(message))
+}
+
+// Reports the active layout and profile of a device, as a notification and/or through OSD_COMMAND, a command
+// template for on-screen displays like wob, eww or dunstify with {device}, {layout} and {profile} in it.
+pub fn show_layer(environment: &Environment, notify_layer: bool, osd_command: Option<&str>, device: &str, layout: &str, profile: &str) {
+  if notify_layer {
+    notify(environment, &format!("{}: layout {}, profile {}", device, layout, profile));
+  }
+  if let Some(template) = osd_command {
+    run(environment, &layer_command(template, device, layout, profile));
+  }
+}
+
+// Profile names come from makitactl and scripts too, so they are quoted like notification messages.
+pub fn layer_command(template: &str, device: &str, layout: &str, profile: &str) -> String {
+  template
+    .replace("{device}", &quote(device))
+    .replace("{layout}", &quote(layout))
+    .replace("{profile}", &quote(profile))
+}
+
+// Switches the keyboard layout of the compositor to the XKB layout at that index of its list.
+pub fn switch_xkb_layout(environment: &Environment, index: u32) {
+  let Server::Connected(server) = &environment.server else { return };
+  match xkb_layout_command(server, index) {
+    Some(command) => {
+      keymap::layout_switched(index);
+      run(environment, &command);
+    }
+    None => println!("[Notifier] Unable to switch the XKB layout on {}, only Hyprland, sway, KDE and niri are supported.", server),
+  }
+}
+
+pub fn xkb_layout_command(server: &str, index: u32) -> Option<String> {
+  match server {
+    "Hyprland" => Some(format!("hyprctl switchxkblayout all {}", index)),
+    "sway" => Some(format!("swaymsg input type:keyboard xkb_switch_layout {}", index)),
+    "niri" => Some(format!("niri msg action switch-layout {}", index)),
+    "KDE" => Some(format!("dbus-send --session --type=method_call --dest=org.kde.keyboard /Layouts org.kde.KeyboardLayouts.setLayout uint32:{}", index)),
+    _ => None,
+  }
+}
+
+fn run(environment: &Environment, command: &str) {
+  let result = session::desktop_command(environment, command).stderr(Stdio::null()).spawn();
+  if let Err(e) = result {
+    println!("[Notifier] Unable to run {}: {}", command.split_whitespace().next().unwrap_or_default(), e);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn picks_the_notification_backend() {
+    let config = |name: &str, content: &str| Config::parse(content, name.to_string()).unwrap();
+    assert_eq!(backend(&[config("Mouse", "")]), Backend::Dbus);
+    assert_eq!(
+      backend(&[config("Mouse", ""), config("Keyboard", "[settings]\nNOTIFY_COMMAND = \"dunstify {message}\"\n")]),
+      Backend::DbusOr("dunstify {message}".to_string())
+    );
+    assert_eq!(
+      backend(&[config("Keyboard", "[settings]\nNOTIFIER = \"command\"\nNOTIFY_COMMAND = \"dunstify {message}\"\n")]),
+      Backend::Command("dunstify {message}".to_string())
+    );
+    assert_eq!(notify_command("dunstify -t 1500 {message}", "Caps Lock's on"), "dunstify -t 1500 'Caps Lock'\\''s on'");
+  }
+
+  #[test]
+  fn quotes_what_the_osd_command_is_given() {
+    assert_eq!(layer_command("wob {layout} {profile}", "Keyboard", "1", "$(reboot)"), "wob '1' '$(reboot)'");
+    assert_eq!(layer_command("osd 'Layout {layout}'", "Keyboard", "1", "work"), "osd 'Layout '1''");
+  }
+
+  #[test]
+  fn switches_the_xkb_layout_through_the_compositor() {
+    assert_eq!(xkb_layout_command("Hyprland", 1).unwrap(), "hyprctl switchxkblayout all 1");
+    assert_eq!(xkb_layout_command("sway", 2).unwrap(), "swaymsg input type:keyboard xkb_switch_layout 2");
+    assert!(xkb_layout_command("GNOME", 1).is_none());
+  }
+}
@@ -0,0 +1,144 @@
+use crate::config::Event;
+use crate::event_tap::event_name;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::time::{timeout, Duration};
+
+// How long sending to an OSC target or an MQTT broker may take, resolving its name, connecting and waiting for the
+// broker to answer included, so that one that doesn't answer doesn't leave a task behind for every key press.
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Fills in {device}, {event} and {value} in an OSC or MQTT payload.
+pub fn render(template: &str, device: &str, event: &Event, value: i32) -> String {
+  template
+    .replace("{device}", device)
+    .replace("{event}", &event_name(event))
+    .replace("{value}", &value.to_string())
+}
+
+// Sets a sysfs LED or backlight, given either its directory (e.g. /sys/class/leds/input3::scrolllock) or its
+// brightness file.
+pub fn set_led(path: &str, brightness: u32) {
+  let directory = std::path::Path::new(path);
+  let file = if directory.is_dir() { directory.join("brightness") } else { directory.to_path_buf() };
+  if let Err(e) = std::fs::write(&file, brightness.to_string()) {
+    println!("[Outputs] Unable to set {} to {}: {}", file.display(), brightness, e);
+  }
+}
+
+fn push_osc_string(buffer: &mut Vec<u8>, string: &str) {
+  buffer.extend_from_slice(string.as_bytes());
+  buffer.push(0);
+  while !buffer.len().is_multiple_of(4) { buffer.push(0); }
+}
+
+// Each whitespace-separated word of the payload becomes an int, float or string argument.
+fn encode_osc(address: &str, payload: &str) -> Vec<u8> {
+  let mut type_tags = String::from(",");
+  let mut arguments = Vec::new();
+  for word in payload.split_whitespace() {
+    if let Ok(int) = word.parse::<i32>() {
+      type_tags.push('i');
+      arguments.extend_from_slice(&int.to_be_bytes());
+    } else if let Ok(float) = word.parse::<f32>() {
+      type_tags.push('f');
+      arguments.extend_from_slice(&float.to_be_bytes());
+    } else {
+      type_tags.push('s');
+      push_osc_string(&mut arguments, word.trim_matches('"'));
+    }
+  }
+
+  let mut message = Vec::new();
+  push_osc_string(&mut message, address);
+  push_osc_string(&mut message, &type_tags);
+  message.extend(arguments);
+  message
+}
+
+pub async fn send_osc(target: &str, address: &str, payload: &str) -> std::io::Result<()> {
+  within_timeout(send_osc_message(target, address, payload)).await
+}
+
+async fn within_timeout(send: impl std::future::Future<Output = std::io::Result<()>>) -> std::io::Result<()> {
+  timeout(SEND_TIMEOUT, send).await
+    .unwrap_or_else(|_| Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "no answer in time")))
+}
+
+async fn send_osc_message(target: &str, address: &str, payload: &str) -> std::io::Result<()> {
+  let socket = UdpSocket::bind("0.0.0.0:0").await?;
+  socket.send_to(&encode_osc(address, payload), target).await?;
+  Ok(())
+}
+
+fn push_mqtt_string(buffer: &mut Vec<u8>, string: &str) {
+  buffer.extend_from_slice(&(string.len() as u16).to_be_bytes());
+  buffer.extend_from_slice(string.as_bytes());
+}
+
+fn mqtt_packet(header: u8, body: Vec<u8>) -> Vec<u8> {
+  let mut packet = vec![header];
+  let mut length = body.len();
+  loop {
+    let mut byte = (length % 128) as u8;
+    length /= 128;
+    if length > 0 { byte |= 0x80; }
+    packet.push(byte);
+    if length == 0 { break; }
+  }
+  packet.extend(body);
+  packet
+}
+
+// Publishes a single QoS 0 message over MQTT 3.1.1. The broker is "[user:password@]host[:port]".
+pub async fn publish_mqtt(broker: &str, topic: &str, payload: &str) -> std::io::Result<()> {
+  within_timeout(publish_mqtt_message(broker, topic, payload)).await
+}
+
+async fn publish_mqtt_message(broker: &str, topic: &str, payload: &str) -> std::io::Result<()> {
+  let (credentials, host) = match broker.rsplit_once('@') {
+    Some((credentials, host)) => (Some(credentials.split_once(':').unwrap_or((credentials, ""))), host),
+    None => (None, broker),
+  };
+  let host = if host.contains(':') { host.to_string() } else { format!("{}:1883", host) };
+  let mut stream = TcpStream::connect(host).await?;
+
+  let mut connect = Vec::new();
+  push_mqtt_string(&mut connect, "MQTT");
+  connect.push(4);
+  connect.push(match credentials { Some(_) => 0xC2, None => 0x02 });
+  connect.extend_from_slice(&30u16.to_be_bytes());
+  push_mqtt_string(&mut connect, &format!("makita-{}", std::process::id()));
+  if let Some((user, password)) = credentials {
+    push_mqtt_string(&mut connect, user);
+    push_mqtt_string(&mut connect, password);
+  }
+  stream.write_all(&mqtt_packet(0x10, connect)).await?;
+
+  let mut connack = [0u8; 4];
+  stream.read_exact(&mut connack).await?;
+  if connack[0] != 0x20 || connack[3] != 0 {
+    return Err(std::io::Error::other(format!("broker refused the connection (code {})", connack[3])));
+  }
+
+  let mut publish = Vec::new();
+  push_mqtt_string(&mut publish, topic);
+  publish.extend_from_slice(payload.as_bytes());
+  stream.write_all(&mqtt_packet(0x30, publish)).await?;
+  stream.write_all(&mqtt_packet(0xE0, Vec::new())).await?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use tokio::net::TcpListener;
+
+  #[tokio::test(start_paused = true)]
+  async fn gives_up_on_brokers_that_dont_answer() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let broker = listener.local_addr().unwrap().to_string();
+    let error = publish_mqtt(&broker, "makita", "KEY_A").await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+  }
+}
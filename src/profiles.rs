@@ -0,0 +1,36 @@
+use crate::state_files;
+use std::{path::PathBuf, sync::atomic::{AtomicBool, Ordering}, sync::Mutex};
+
+lazy_static::lazy_static! {
+  static ref ACTIVE_PROFILE: Mutex<Option<String>> = Mutex::new(None);
+}
+
+// Only the daemon remembers the active profile, not frontends embedding the engine.
+static PERSIST: AtomicBool = AtomicBool::new(false);
+
+pub fn init(config_directory: &str) {
+  if let Some((_, profile)) = state_files::read("profile", Some(&PathBuf::from(config_directory).join(".profile"))) {
+    let profile = profile.trim().to_string();
+    if !profile.is_empty() {
+      println!("[Profiles] Restoring profile {}.", profile);
+      *ACTIVE_PROFILE.lock().unwrap() = Some(profile);
+    }
+  }
+  PERSIST.store(true, Ordering::Relaxed);
+}
+
+pub fn active_profile() -> Option<String> {
+  ACTIVE_PROFILE.lock().unwrap().clone()
+}
+
+pub fn set_active_profile(profile: Option<String>) {
+  let profile = profile.filter(|profile| !profile.is_empty() && profile != "default");
+  println!("[Profiles] Switching to profile {}.", profile.as_deref().unwrap_or("default"));
+  *ACTIVE_PROFILE.lock().unwrap() = profile.clone();
+
+  if PERSIST.load(Ordering::Relaxed) {
+    if let Err(e) = state_files::write("profile", &profile.unwrap_or_default()) {
+      eprintln!("[Profiles] Unable to save the active profile to {}: {}", state_files::state_directory().display(), e);
+    }
+  }
+}
@@ -0,0 +1,393 @@
+use crate::config::{BindingInfo, Config, RawConfig};
+use crate::live_settings;
+use crate::notifier;
+use crate::ruby_runtime;
+use crate::schema;
+use lazy_static::lazy_static;
+use std::{
+  collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+  fs, io,
+  path::{Path, PathBuf},
+  str::FromStr,
+  sync::Mutex,
+};
+use tokio::sync::broadcast;
+
+lazy_static! {
+  static ref CONFIG_DIRECTORY: Mutex<Option<String>> = Mutex::new(None);
+  static ref CONFIGS: Mutex<Vec<Config>> = Mutex::new(Vec::new());
+  static ref RELOADS: broadcast::Sender<()> = broadcast::channel(4).0;
+  static ref RELAUNCHES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+  // Held for a whole reload, as the config watcher, makitactl and SIGHUP can each start one.
+  static ref RELOADING: Mutex<()> = Mutex::new(());
+  static ref STOPPED_READERS: broadcast::Sender<String> = broadcast::channel(16).0;
+  // The event nodes each device is read from, devices can have several, hence several readers.
+  static ref READERS: Mutex<HashMap<String, HashSet<String>>> = Mutex::new(HashMap::new());
+}
+
+pub fn init(config_directory: &str, configs: &[Config]) {
+  *CONFIG_DIRECTORY.lock().unwrap() = Some(config_directory.to_string());
+  *CONFIGS.lock().unwrap() = configs.to_vec();
+}
+
+// The [settings] of this file apply to every config of its directory and subdirectories that doesn't set them.
+pub const DEFAULTS_FILE: &str = "defaults.toml";
+
+// The .toml files of the config directory and of its subdirectories, e.g. keyboards/ and mice/, sorted by path.
+// Directories starting with a dot are masked like dotfiles.
+pub fn config_files(config_directory: &Path) -> io::Result<Vec<PathBuf>> {
+  let mut files: Vec<PathBuf> = Vec::new();
+  for entry in fs::read_dir(config_directory)?.flatten() {
+    let filename = entry.file_name().to_string_lossy().to_string();
+    if filename.starts_with('.') { continue; }
+    if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+      match config_files(&entry.path()) {
+        Ok(nested) => files.extend(nested),
+        Err(e) => eprintln!("[Config] Unable to read {}: {}", entry.path().display(), e),
+      }
+    } else if filename.ends_with(".toml") {
+      files.push(entry.path());
+    }
+  }
+  files.sort();
+  Ok(files)
+}
+
+// Parses every config file of the directory, along with the errors of the ones that couldn't be.
+pub fn load_configs(config_directory: &str) -> io::Result<(Vec<Config>, Vec<String>)> {
+  let root = Path::new(config_directory);
+  let mut configs: Vec<Config> = Vec::new();
+  let mut errors: Vec<String> = Vec::new();
+  let mut defaults: HashMap<PathBuf, HashMap<String, String>> = HashMap::new();
+  for path in config_files(root)? {
+    let filename = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let relative_path = path.strip_prefix(root).unwrap_or(&path).display().to_string();
+    if filename == DEFAULTS_FILE { continue; }
+    let name = filename.split(".toml").next().unwrap_or_default().to_string();
+    if let Some(config) = configs.iter().find(|config| config.name == name) {
+      errors.push(format!("{}: another file already configures {}", relative_path, config.name));
+      continue;
+    }
+    let default_settings = directory_defaults(root, path.parent().unwrap_or(root), &mut defaults, &mut errors);
+    match Config::new_from_file_with_defaults(path.to_str().unwrap(), name, &default_settings) {
+      Ok(config) => configs.push(config),
+      Err(e) => errors.push(format!("{}: {}", relative_path, e)),
+    }
+  }
+  Ok((configs, errors))
+}
+
+// The defaults of a directory on top of those of its parents, up to the config directory.
+fn directory_defaults(
+  root: &Path,
+  directory: &Path,
+  defaults: &mut HashMap<PathBuf, HashMap<String, String>>,
+  errors: &mut Vec<String>,
+) -> HashMap<String, String> {
+  if let Some(settings) = defaults.get(directory) {
+    return settings.clone();
+  }
+  let mut settings = match directory.parent() {
+    Some(parent) if directory != root && directory.starts_with(root) => directory_defaults(root, parent, defaults, errors),
+    _ => HashMap::new(),
+  };
+  let path = directory.join(DEFAULTS_FILE);
+  if path.exists() {
+    let relative_path = path.strip_prefix(root).unwrap_or(&path).display().to_string();
+    match fs::read_to_string(&path).map_err(|e| e.to_string()).and_then(|content| RawConfig::from_str(&content).map_err(|e| e.to_string())) {
+      Ok(raw_config) => settings.extend(raw_config.settings),
+      Err(e) => errors.push(format!("{}: unable to parse: {}", relative_path, e.trim_end())),
+    }
+  }
+  defaults.insert(directory.to_path_buf(), settings.clone());
+  settings
+}
+
+// Settings used when a device connects, which its reader can't take on while it runs.
+pub const CONNECT_SETTINGS: [&str; 5] = ["GRAB_DEVICE", "SEPARATE_VIRTUAL_DEVICES", "OUTPUT_BACKEND", "VIRTUAL_GAMEPAD", "FLAT_POINTER"];
+
+// Settings of what Makita only starts along with itself: the event tap, the profile scheduler, the remote bridge and
+// the sender of script events.
+pub const STARTUP_SETTINGS: [&str; 6] = ["EVENT_TAP", "PROFILE_SCHEDULE", "REMOTE_LISTEN", "REMOTE_TARGET", "REMOTE_SECRET", "RUBY_RELEASE_MODIFIERS"];
+
+pub fn config_directory() -> Option<String> {
+  CONFIG_DIRECTORY.lock().unwrap().clone()
+}
+
+pub fn configs() -> Vec<Config> {
+  CONFIGS.lock().unwrap().clone()
+}
+
+pub fn reloads() -> broadcast::Receiver<()> {
+  RELOADS.subscribe()
+}
+
+// Reads the config directory again and hands the new configs to the readers, unless one of them doesn't parse or
+// sets a value the readers can't take.
+// Returns the changes, which are logged too so an edit can be confirmed to have taken effect.
+pub fn reload() -> Result<Vec<String>, String> {
+  let _reloading = RELOADING.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+  let config_directory = config_directory().ok_or("no config directory to reload from")?;
+  let (configs, errors) = load_configs(&config_directory).map_err(|e| format!("unable to read {}: {}", config_directory, e))?;
+  if !errors.is_empty() {
+    println!("[Reload] Keeping the current configs, some don't parse: {}.", errors.join(", "));
+    return Err(format!("keeping the current configs, some don't parse: {}", errors.join(", ")));
+  }
+  if let Err(e) = check_settings(&configs) {
+    println!("[Reload] Keeping the current configs, {}.", e);
+    return Err(format!("keeping the current configs, {}", e));
+  }
+
+  let mut changes = diff(&CONFIGS.lock().unwrap(), &configs);
+  if changes.is_empty() {
+    println!("[Reload] No changes.");
+    return Ok(changes);
+  }
+  let restart = restart_needed(&CONFIGS.lock().unwrap(), &configs);
+  if !restart.is_empty() {
+    changes.push(format!("restart Makita for the changes to {} to apply", restart.join(", ")));
+  }
+  for change in &changes {
+    println!("[Reload] {}", change);
+  }
+  live_settings::init(&configs);
+  notifier::init(&configs);
+  // Devices an earlier reload relaunches, and that haven't stopped yet, still have to.
+  let mut pending = RELAUNCHES.lock().unwrap();
+  for device in relaunches(&CONFIGS.lock().unwrap(), &configs) {
+    if !pending.contains(&device) { pending.push(device); }
+  }
+  drop(pending);
+  *CONFIGS.lock().unwrap() = configs;
+  let _ = RELOADS.send(());
+  Ok(changes)
+}
+
+// Readers panic on settings they can't parse, the merged settings of app and layout configs coming from these too.
+fn check_settings(configs: &[Config]) -> Result<(), String> {
+  configs.iter()
+    .try_for_each(|config| schema::check_settings(&config.settings).map_err(|e| format!("{} has an {}", config.name, e)))
+}
+
+// The devices whose readers stop and start again for the new configs to apply: those that have no config anymore and
+// those whose connect-time settings changed.
+pub fn relaunches(old: &[Config], new: &[Config]) -> Vec<String> {
+  let devices = |configs: &[Config]| configs.iter()
+    .map(|config| config.name.split("::").next().unwrap_or_default().to_string())
+    .collect::<BTreeSet<String>>();
+  let connect_settings = |configs: &[Config], device: &str| -> Vec<Option<String>> {
+    let settings = configs.iter().find(|config| config.name == device).map(|config| config.settings.clone()).unwrap_or_default();
+    CONNECT_SETTINGS.iter().map(|setting| settings.get(*setting).cloned()).collect()
+  };
+  let new_devices = devices(new);
+  devices(old).into_iter()
+    .filter(|device| !new_devices.contains(device) || connect_settings(old, device) != connect_settings(new, device))
+    .collect()
+}
+
+// What only applies once Makita starts again: the Ruby scripts, which the Ruby service loads when it starts, and the
+// STARTUP_SETTINGS.
+pub fn restart_needed(old: &[Config], new: &[Config]) -> Vec<String> {
+  let scripts = |configs: &[Config]| ruby_runtime::configured_scripts(configs, "")
+    .into_iter()
+    .map(|(name, _)| name)
+    .collect::<BTreeSet<String>>();
+  let setting = |configs: &[Config], name: &str| configs.iter()
+    .filter_map(|config| Some((config.name.clone(), config.settings.get(name)?.clone())))
+    .collect::<BTreeMap<String, String>>();
+  let mut restart = Vec::new();
+  if scripts(old) != scripts(new) { restart.push("Ruby scripts".to_string()); }
+  restart.extend(STARTUP_SETTINGS.iter().filter(|name| setting(old, name) != setting(new, name)).map(|name| name.to_string()));
+  restart
+}
+
+// Whether the reader of the device has to stop after the last reload.
+pub fn relaunching(device: &str) -> bool {
+  RELAUNCHES.lock().unwrap().iter().any(|relaunch| relaunch == device)
+}
+
+// Whether the device was waiting for its reader to stop, which it no longer does.
+pub fn take_relaunch(device: &str) -> bool {
+  let mut relaunches = RELAUNCHES.lock().unwrap();
+  let waiting = relaunches.iter().any(|relaunch| relaunch == device);
+  relaunches.retain(|relaunch| relaunch != device);
+  waiting
+}
+
+pub fn stopped_readers() -> broadcast::Receiver<String> {
+  STOPPED_READERS.subscribe()
+}
+
+pub fn reader_started(device: &str, event_device: &str) {
+  READERS.lock().unwrap().entry(device.to_string()).or_default().insert(event_device.to_string());
+}
+
+// Tells once the last reader of the device stopped, and let go of it.
+pub fn reader_stopped(device: &str, event_device: &str) {
+  let mut readers = READERS.lock().unwrap();
+  let nodes = readers.entry(device.to_string()).or_default();
+  nodes.remove(event_device);
+  if nodes.is_empty() {
+    readers.remove(device);
+    let _ = STOPPED_READERS.send(device.to_string());
+  }
+}
+
+// Whether a reader is running for the event node, which launching readers again leaves alone.
+pub fn reading(event_device: &str) -> bool {
+  READERS.lock().unwrap().values().any(|nodes| nodes.contains(event_device))
+}
+
+// Bindings are matched by their table, input and modifiers, and changed when their output differs.
+pub fn diff(old: &[Config], new: &[Config]) -> Vec<String> {
+  let by_name = |configs: &[Config]| configs.iter().map(|config| (config.name.clone(), config.clone())).collect::<BTreeMap<String, Config>>();
+  let (old, new) = (by_name(old), by_name(new));
+  let mut changes: Vec<String> = Vec::new();
+
+  for (name, config) in &new {
+    let Some(previous) = old.get(name) else {
+      changes.push(format!("{}: added, with {} bindings", name, config.binding_list.len()));
+      continue;
+    };
+    let (bindings, previous_bindings) = (binding_outputs(&config.binding_list), binding_outputs(&previous.binding_list));
+    for (binding, output) in &bindings {
+      match previous_bindings.get(binding) {
+        None => changes.push(format!("{}: added {} = {}", name, binding, output)),
+        Some(previous_output) if previous_output != output => {
+          changes.push(format!("{}: changed {} from {} to {}", name, binding, previous_output, output))
+        }
+        _ => {}
+      }
+    }
+    for (binding, output) in previous_bindings.iter().filter(|(binding, _)| !bindings.contains_key(*binding)) {
+      changes.push(format!("{}: removed {} = {}", name, binding, output));
+    }
+
+    let settings: BTreeMap<&String, &String> = config.settings.iter().collect();
+    for (setting, value) in &settings {
+      match previous.settings.get(*setting) {
+        None => changes.push(format!("{}: set {} to {}", name, setting, value)),
+        Some(previous_value) if previous_value != *value => {
+          changes.push(format!("{}: changed {} from {} to {}", name, setting, previous_value, value))
+        }
+        _ => {}
+      }
+    }
+    let mut unset: Vec<&String> = previous.settings.keys().filter(|setting| !config.settings.contains_key(*setting)).collect();
+    unset.sort();
+    changes.extend(unset.into_iter().map(|setting| format!("{}: unset {}", name, setting)));
+  }
+  for name in old.keys().filter(|name| !new.contains_key(*name)) {
+    changes.push(format!("{}: removed", name));
+  }
+  changes
+}
+
+fn binding_outputs(bindings: &[BindingInfo]) -> BTreeMap<String, String> {
+  bindings.iter().map(|binding| {
+    let mut modifiers = binding.modifiers.clone();
+    modifiers.sort();
+    modifiers.push(binding.input.clone());
+    (format!("[{}] {}", binding.table, modifiers.join("-")), binding.output.clone())
+  }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn diffs_reloaded_configs() {
+    let old = Config::parse("[remap]\nKEY_A = [\"KEY_B\"]\nctrl-KEY_C = [\"KEY_D\"]\n[settings]\nCURSOR_SPEED = \"5\"\n", "Test Device".to_string()).unwrap();
+    let new = Config::parse("[remap]\nKEY_A = [\"KEY_E\"]\n[actions]\nKEY_F1 = \"cycle_cursor_speed\"\n[settings]\nGRAB_DEVICE = \"false\"\n", "Test Device".to_string()).unwrap();
+    let other = Config::parse("", "Other Device".to_string()).unwrap();
+    assert_eq!(diff(&[old, other], &[new]), vec![
+      "Test Device: added [actions] KEY_F1 = cycle_cursor_speed",
+      "Test Device: changed [remap] KEY_A from KEY_B to KEY_E",
+      "Test Device: removed [remap] KEY_LEFTCTRL-KEY_C = KEY_D",
+      "Test Device: set GRAB_DEVICE to false",
+      "Test Device: unset CURSOR_SPEED",
+      "Other Device: removed",
+    ]);
+  }
+
+  #[test]
+  fn rejects_settings_the_readers_cant_parse() {
+    let config = |name: &str, content: &str| Config::parse(content, name.to_string()).unwrap();
+    assert_eq!(check_settings(&[config("Mouse", "[settings]\nCURSOR_SPEED = \"5\"\n")]), Ok(()));
+    assert_eq!(
+      check_settings(&[config("Mouse", ""), config("Mouse::firefox", "[settings]\nCURSOR_SPEED = \"fast\"\n")]),
+      Err("Mouse::firefox has an invalid value fast for CURSOR_SPEED, use an integer".to_string()),
+    );
+  }
+
+  #[test]
+  fn tells_once_every_event_node_of_a_device_stopped_being_read() {
+    let mut stopped = stopped_readers();
+    reader_started("Reload Test Pad", "/dev/input/event90");
+    reader_started("Reload Test Pad", "/dev/input/event91");
+    assert!(reading("/dev/input/event90"));
+    reader_stopped("Reload Test Pad", "/dev/input/event90");
+    assert!(!reading("/dev/input/event90"));
+    assert!(stopped.try_recv().is_err());
+    reader_stopped("Reload Test Pad", "/dev/input/event91");
+    assert_eq!(stopped.try_recv().unwrap(), "Reload Test Pad");
+  }
+
+  #[test]
+  fn relaunches_devices_whose_connect_settings_changed() {
+    let config = |name: &str, content: &str| Config::parse(content, name.to_string()).unwrap();
+    let old = [
+      config("Keyboard", "[settings]\nGRAB_DEVICE = \"true\"\n"),
+      config("Mouse", "[settings]\nCURSOR_SPEED = \"5\"\n"),
+      config("Mouse::firefox", ""),
+      config("Pad", ""),
+    ];
+    let new = [
+      config("Keyboard", "[settings]\nGRAB_DEVICE = \"false\"\n"),
+      config("Mouse", "[settings]\nCURSOR_SPEED = \"8\"\n"),
+      config("Trackball", ""),
+    ];
+    assert_eq!(relaunches(&old, &new), vec!["Keyboard", "Pad"]);
+  }
+
+  #[test]
+  fn tells_what_only_applies_after_a_restart() {
+    let config = |name: &str, content: &str| Config::parse(content, name.to_string()).unwrap();
+    let old = [config("Keyboard", "[rubies]\nKEY_F1 = \"greet\"\n[settings]\nEVENT_TAP = \"bound\"\n")];
+    assert!(restart_needed(&old, &[config("Keyboard", "[rubies]\nKEY_F2 = \"greet\"\n[settings]\nEVENT_TAP = \"bound\"\n")]).is_empty());
+    assert_eq!(
+      restart_needed(&old, &[config("Keyboard", "[rubies]\nKEY_F1 = \"wave\"\n"), config("Mouse", "[settings]\nREMOTE_TARGET = \"pc:7331\"\n")]),
+      vec!["Ruby scripts", "EVENT_TAP", "REMOTE_TARGET"],
+    );
+  }
+
+  #[test]
+  fn loads_configs_from_subdirectories() {
+    let directory = std::env::temp_dir().join(format!("makita-configs-{}", std::process::id()));
+    let write = |path: &str, content: &str| {
+      let path = directory.join(path);
+      fs::create_dir_all(path.parent().unwrap()).unwrap();
+      fs::write(path, content).unwrap();
+    };
+    write("defaults.toml", "[settings]\nGRAB_DEVICE = \"false\"\nTAPPING_TERM = \"150\"\n");
+    write("keyboards/defaults.toml", "[settings]\nTAPPING_TERM = \"180\"\n");
+    write("keyboards/Keyboard.toml", "[settings]\nGRAB_DEVICE = \"true\"\n");
+    write("keyboards/laptop/Laptop Keyboard.toml", "");
+    write("mice/Mouse.toml", "");
+    write("mice/.old/Mouse.toml", "");
+    write("pads/Keyboard.toml", "");
+
+    let (configs, errors) = load_configs(directory.to_str().unwrap()).unwrap();
+    fs::remove_dir_all(&directory).unwrap();
+    let settings = |name: &str| {
+      let config = configs.iter().find(|config| config.name == name).unwrap();
+      (config.settings["GRAB_DEVICE"].as_str(), config.settings["TAPPING_TERM"].as_str())
+    };
+    assert_eq!(configs.len(), 3);
+    assert_eq!(settings("Keyboard"), ("true", "180"));
+    assert_eq!(settings("Laptop Keyboard"), ("false", "180"));
+    assert_eq!(settings("Mouse"), ("false", "150"));
+    assert_eq!(errors, vec!["pads/Keyboard.toml: another file already configures Keyboard"]);
+  }
+}
@@ -0,0 +1,339 @@
+use crate::config::{Config, RemoteCommand};
+use crate::state;
+use crate::state_files;
+use crate::virtual_devices::{Target, VirtualDevices};
+use evdev::{EventType, InputEvent};
+use hmac_sha256::HMAC;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+  io::{self, Read},
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex,
+  },
+  time::Duration,
+};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+static FORWARDING: AtomicBool = AtomicBool::new(false);
+
+// How long either side waits for the other through the handshake, and how long a line of it may be, so that a
+// connection that never proves anything can't be held open or make Makita buffer without end.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+const HANDSHAKE_LINE_LIMIT: u64 = 1024;
+
+lazy_static! {
+  static ref OUTBOX: Mutex<Option<UnboundedSender<String>>> = Mutex::new(None);
+  // Keys pressed on the other machine, released there when forwarding stops.
+  static ref REMOTE_KEYS: Mutex<Vec<u16>> = Mutex::new(Vec::new());
+}
+
+#[derive(Serialize, Deserialize)]
+struct Batch {
+  target: Target,
+  events: Vec<(u16, u16, i32)>,
+}
+
+// Software KVM: the Makita of another machine listens with REMOTE_LISTEN, and this one connects to it with
+// REMOTE_TARGET. While the remote(on|toggle) action has turned forwarding on, everything Makita emits goes to the
+// other machine instead of the local virtual devices. Both sides prove they know REMOTE_SECRET without sending it,
+// the sender before the listener tells it anything and the listener before the sender forwards anything, but the
+// events themselves aren't encrypted, so anything but a trusted network needs a tunnel.
+pub fn start(configs: &[Config], virtual_devices: VirtualDevices) {
+  let setting = |name: &str| configs.iter().find_map(|config| config.settings.get(name).cloned());
+  let (listen, target) = (setting("REMOTE_LISTEN"), setting("REMOTE_TARGET"));
+  if listen.is_none() && target.is_none() { return; }
+  let Some(secret) = setting("REMOTE_SECRET").filter(|secret| !secret.is_empty()) else {
+    eprintln!("[Remote] REMOTE_LISTEN and REMOTE_TARGET need a REMOTE_SECRET, not starting the remote bridge.");
+    return;
+  };
+  if let Some(address) = listen {
+    tokio::spawn(listen_for_remotes(address, secret.clone(), virtual_devices));
+  }
+  if let Some(address) = target {
+    let (outbox, batches) = mpsc::unbounded_channel();
+    *OUTBOX.lock().unwrap() = Some(outbox);
+    tokio::spawn(forward_batches(address, secret, batches));
+  }
+}
+
+// Turns forwarding on or off, releasing the keys held on the side that's left so none of them stays stuck.
+pub fn set_forwarding(command: RemoteCommand, virtual_devices: &VirtualDevices) {
+  if OUTBOX.lock().unwrap().is_none() {
+    println!("[Remote] No REMOTE_TARGET to forward to.");
+    return;
+  }
+  let forwarding = match command {
+    RemoteCommand::On => true,
+    RemoteCommand::Off => false,
+    RemoteCommand::Toggle => !FORWARDING.load(Ordering::SeqCst),
+  };
+  if forwarding == FORWARDING.load(Ordering::SeqCst) { return; }
+  if forwarding {
+    let releases: Vec<InputEvent> = state::virtual_keys().iter().map(|key| InputEvent::new_now(EventType::KEY, key.code(), 0)).collect();
+    if !releases.is_empty() { virtual_devices.emit(Target::Keyboard, &releases); }
+  } else {
+    let releases: Vec<InputEvent> = REMOTE_KEYS.lock().unwrap().iter().map(|code| InputEvent::new_now(EventType::KEY, *code, 0)).collect();
+    if !releases.is_empty() { forward(Target::Keyboard, &releases); }
+  }
+  FORWARDING.store(forwarding, Ordering::SeqCst);
+  println!("[Remote] Forwarding {}.", if forwarding { "on" } else { "off" });
+}
+
+// Called by VirtualDevices::emit, true if the events went to the other machine instead.
+pub fn forward_if_enabled(target: Target, events: &[InputEvent]) -> bool {
+  if !FORWARDING.load(Ordering::SeqCst) { return false; }
+  forward(target, events);
+  true
+}
+
+fn forward(target: Target, events: &[InputEvent]) {
+  let mut remote_keys = REMOTE_KEYS.lock().unwrap();
+  for event in events.iter().filter(|event| event.event_type() == EventType::KEY) {
+    remote_keys.retain(|code| *code != event.code());
+    if event.value() != 0 { remote_keys.push(event.code()); }
+  }
+  let batch = Batch { target, events: events.iter().map(|event| (event.event_type().0, event.code(), event.value())).collect() };
+  if let (Some(outbox), Ok(line)) = (OUTBOX.lock().unwrap().as_ref(), serde_json::to_string(&batch)) {
+    let _ = outbox.send(line);
+  }
+}
+
+fn challenge() -> io::Result<String> {
+  let mut bytes = [0u8; 32];
+  std::fs::File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+  Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+fn respond(challenge: &str, secret: &str) -> String {
+  HMAC::mac(challenge, secret).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn verify(challenge: &str, secret: &str, response: &str) -> bool {
+  let mut mac = [0u8; 32];
+  if response.len() != 64 || !response.is_ascii() { return false; }
+  for (i, byte) in mac.iter_mut().enumerate() {
+    let Ok(value) = u8::from_str_radix(&response[i * 2..i * 2 + 2], 16) else { return false };
+    *byte = value;
+  }
+  HMAC::verify(challenge, secret, &mac)
+}
+
+fn invalid(message: &str) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+async fn read_handshake_line<R: AsyncBufRead + Unpin>(reader: &mut R) -> io::Result<serde_json::Value> {
+  let mut line = String::new();
+  if (&mut *reader).take(HANDSHAKE_LINE_LIMIT).read_line(&mut line).await? == 0 {
+    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the other machine hung up"));
+  }
+  if !line.ends_with('\n') { return Err(invalid("handshake line too long")); }
+  Ok(serde_json::from_str(&line)?)
+}
+
+async fn within_handshake_timeout<T>(handshake: impl std::future::Future<Output = io::Result<T>>) -> io::Result<T> {
+  tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake).await
+    .unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::TimedOut, "no handshake in time")))
+}
+
+// Each side answers for its own role only, so that what one side says can't be sent back to it as an answer.
+async fn accept_sender<R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin>(reader: &mut R, writer: &mut W, secret: &str) -> io::Result<()> {
+  let challenge = challenge()?;
+  writer.write_all(format!("{}\n", json!({ "challenge": challenge })).as_bytes()).await?;
+  let hello = read_handshake_line(reader).await?;
+  if !hello["response"].as_str().is_some_and(|response| verify(&format!("sender:{}", challenge), secret, response)) {
+    writer.write_all(format!("{}\n", json!({ "status": "error", "message": "wrong secret" })).as_bytes()).await?;
+    return Err(invalid("wrong secret"));
+  }
+  let sender_challenge = hello["challenge"].as_str().ok_or_else(|| invalid("no challenge from the other machine"))?;
+  let response = respond(&format!("listener:{}", sender_challenge), secret);
+  writer.write_all(format!("{}\n", json!({ "status": "ok", "response": response })).as_bytes()).await
+}
+
+async fn connect_to_listener<R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin>(reader: &mut R, writer: &mut W, secret: &str) -> io::Result<()> {
+  let greeting = read_handshake_line(reader).await?;
+  let listener_challenge = greeting["challenge"].as_str().ok_or_else(|| invalid("no challenge from the other machine"))?;
+  let challenge = challenge()?;
+  let response = respond(&format!("sender:{}", listener_challenge), secret);
+  writer.write_all(format!("{}\n", json!({ "response": response, "challenge": challenge })).as_bytes()).await?;
+  let answer = read_handshake_line(reader).await?;
+  if answer["status"] != "ok" {
+    return Err(invalid(answer["message"].as_str().unwrap_or("refused")));
+  }
+  if !answer["response"].as_str().is_some_and(|response| verify(&format!("listener:{}", challenge), secret, response)) {
+    return Err(invalid("the other machine doesn't know REMOTE_SECRET"));
+  }
+  Ok(())
+}
+
+// Addresses are a socket path or "tcp:host:port", like MAKITA_EVENT_TAP.
+async fn listen_for_remotes(address: String, secret: String, virtual_devices: VirtualDevices) {
+  if let Some(tcp_address) = address.strip_prefix("tcp:") {
+    let listener = match TcpListener::bind(tcp_address).await {
+      Ok(listener) => listener,
+      Err(e) => {
+        eprintln!("[Remote] Unable to listen on {}: {}", tcp_address, e);
+        return;
+      }
+    };
+    println!("[Remote] Listening for remote input on tcp://{}.", tcp_address);
+    loop {
+      if let Ok((stream, peer)) = listener.accept().await {
+        tokio::spawn(receive_events(stream, peer.to_string(), secret.clone(), virtual_devices.clone()));
+      }
+    }
+  }
+
+  state_files::remove_stale_socket(&address);
+  let listener = match UnixListener::bind(&address) {
+    Ok(listener) => listener,
+    Err(e) => {
+      eprintln!("[Remote] Unable to bind {}: {}", address, e);
+      return;
+    }
+  };
+  println!("[Remote] Listening for remote input on {}.", address);
+  loop {
+    if let Ok((stream, _)) = listener.accept().await {
+      tokio::spawn(receive_events(stream, address.clone(), secret.clone(), virtual_devices.clone()));
+    }
+  }
+}
+
+async fn receive_events<S: AsyncRead + AsyncWrite + Unpin>(stream: S, peer: String, secret: String, virtual_devices: VirtualDevices) {
+  let mut held: Vec<u16> = Vec::new();
+  match receive_batches(stream, &secret, &virtual_devices, &mut held).await {
+    Ok(()) => println!("[Remote] {} disconnected.", peer),
+    Err(e) => println!("[Remote] {} disconnected: {}", peer, e),
+  }
+  let releases: Vec<InputEvent> = held.iter().map(|code| InputEvent::new_now(EventType::KEY, *code, 0)).collect();
+  if !releases.is_empty() { virtual_devices.emit(Target::Keyboard, &releases); }
+}
+
+async fn receive_batches<S: AsyncRead + AsyncWrite + Unpin>(
+  stream: S,
+  secret: &str,
+  virtual_devices: &VirtualDevices,
+  held: &mut Vec<u16>,
+) -> io::Result<()> {
+  let (reader, mut writer) = tokio::io::split(stream);
+  let mut reader = BufReader::new(reader);
+  within_handshake_timeout(accept_sender(&mut reader, &mut writer, secret)).await?;
+  println!("[Remote] Receiving input from the other machine.");
+
+  let mut lines = reader.lines();
+  while let Some(line) = lines.next_line().await? {
+    let batch: Batch = serde_json::from_str(&line)?;
+    let events: Vec<InputEvent> = batch.events.iter().map(|(event_type, code, value)| InputEvent::new(EventType(*event_type), *code, *value)).collect();
+    for event in events.iter().filter(|event| event.event_type() == EventType::KEY) {
+      held.retain(|code| *code != event.code());
+      if event.value() != 0 { held.push(event.code()); }
+    }
+    virtual_devices.emit(batch.target, &events);
+  }
+  Ok(())
+}
+
+// Reconnects until it works, batches forwarded while disconnected are dropped rather than replayed late.
+async fn forward_batches(address: String, secret: String, mut batches: UnboundedReceiver<String>) {
+  // Only logged once until the connection works again.
+  let mut failing = false;
+  loop {
+    let result = match address.strip_prefix("tcp:") {
+      Some(tcp_address) => match TcpStream::connect(tcp_address).await {
+        Ok(stream) => send_batches(stream, &secret, &mut batches, &mut failing).await,
+        Err(e) => Err(e),
+      },
+      None => match UnixStream::connect(&address).await {
+        Ok(stream) => send_batches(stream, &secret, &mut batches, &mut failing).await,
+        Err(e) => Err(e),
+      },
+    };
+    if let Err(e) = result {
+      if !failing { eprintln!("[Remote] Unable to forward to {}: {}, retrying.", address, e); }
+      failing = true;
+    }
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    while batches.try_recv().is_ok() {}
+  }
+}
+
+async fn send_batches<S: AsyncRead + AsyncWrite + Unpin>(
+  stream: S,
+  secret: &str,
+  batches: &mut UnboundedReceiver<String>,
+  failing: &mut bool,
+) -> io::Result<()> {
+  let (reader, mut writer) = tokio::io::split(stream);
+  let mut reader = BufReader::new(reader);
+  within_handshake_timeout(connect_to_listener(&mut reader, &mut writer, secret)).await?;
+  while batches.try_recv().is_ok() {}
+  *failing = false;
+  println!("[Remote] Connected to the other machine.");
+
+  while let Some(line) = batches.recv().await {
+    writer.write_all(format!("{}\n", line).as_bytes()).await?;
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  async fn handshake(listener_secret: &str, sender_secret: &str) -> (io::Result<()>, io::Result<()>) {
+    let (listener, sender) = tokio::io::duplex(4096);
+    let (listener_reader, mut listener_writer) = tokio::io::split(listener);
+    let (sender_reader, mut sender_writer) = tokio::io::split(sender);
+    let (mut listener_reader, mut sender_reader) = (BufReader::new(listener_reader), BufReader::new(sender_reader));
+    tokio::join!(
+      accept_sender(&mut listener_reader, &mut listener_writer, listener_secret),
+      connect_to_listener(&mut sender_reader, &mut sender_writer, sender_secret),
+    )
+  }
+
+  #[tokio::test]
+  async fn both_sides_prove_they_know_the_secret() {
+    let (listener, sender) = handshake("secret", "secret").await;
+    assert!(listener.is_ok() && sender.is_ok());
+    let (listener, sender) = handshake("secret", "guess").await;
+    assert_eq!(listener.unwrap_err().to_string(), "wrong secret");
+    assert_eq!(sender.unwrap_err().to_string(), "wrong secret");
+  }
+
+  #[tokio::test]
+  async fn doesnt_forward_to_a_listener_that_doesnt_know_the_secret() {
+    let (listener, sender) = tokio::io::duplex(4096);
+    let (sender_reader, mut sender_writer) = tokio::io::split(sender);
+    let mut sender_reader = BufReader::new(sender_reader);
+    // Stands in for the listener, accepting whatever the sender answers.
+    let impostor = async {
+      let (reader, mut writer) = tokio::io::split(listener);
+      let mut reader = BufReader::new(reader);
+      writer.write_all(format!("{}\n", json!({ "challenge": challenge()? })).as_bytes()).await?;
+      read_handshake_line(&mut reader).await?;
+      writer.write_all(format!("{}\n", json!({ "status": "ok", "response": "00".repeat(32) })).as_bytes()).await
+    };
+    let (impostor, sender) = tokio::join!(impostor, connect_to_listener(&mut sender_reader, &mut sender_writer, "secret"));
+    impostor.unwrap();
+    assert_eq!(sender.unwrap_err().to_string(), "the other machine doesn't know REMOTE_SECRET");
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn drops_connections_that_dont_complete_the_handshake() {
+    let (listener, mut client) = tokio::io::duplex(4096);
+    let (reader, mut writer) = tokio::io::split(listener);
+    let mut reader = BufReader::new(reader);
+    let error = within_handshake_timeout(accept_sender(&mut reader, &mut writer, "secret")).await.unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+
+    client.write_all(&[b'a'; HANDSHAKE_LINE_LIMIT as usize + 1]).await.unwrap();
+    let error = read_handshake_line(&mut reader).await.unwrap_err();
+    assert_eq!(error.to_string(), "handshake line too long");
+  }
+}
@@ -5,19 +5,62 @@ use std::os::fd::{AsRawFd, OwnedFd};
 use crossbeam_channel::{unbounded, Sender, Receiver};
 use magnus::{embed, Ruby, Error as MagnusError, define_global_function, function, RHash, RString, Value, RArray};
 use serde::{Deserialize, Serialize};
-use evdev::EventType;
+use evdev::{EventType, InputEvent};
+use std::collections::HashMap;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use std::str::FromStr;
+use crate::virtual_devices::Target;
+use crate::config::{Config, TapHoldCommand};
 use nix::libc::pathconf;
 use nix::unistd;
 
 #[derive(Debug)]
 enum RubyCommand {
   LoadScript { name: String, path: String },
+  UseLibrary { path: String },
   StartEventLoop,
 }
 
+// Which device an event comes from, so that one script can be shared by several devices. The id stays the
+// same across reconnects and reboots, unlike the path, as long as the device is plugged into the same port.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DeviceIdentity {
+  pub name: String,
+  pub path: String,
+  pub id: String,
+}
+
+impl DeviceIdentity {
+  pub fn new(device: &evdev::Device, path: &str) -> Self {
+    let input_id = device.input_id();
+    let mut id = format!("{:04x}:{:04x}:{:04x}", input_id.bus_type().0, input_id.vendor(), input_id.product());
+    match (device.unique_name(), device.physical_path()) {
+      (Some(unique), _) if !unique.is_empty() => id.push_str(&format!(":{}", unique)),
+      (_, Some(physical)) if !physical.is_empty() => id.push_str(&format!(":{}", physical)),
+      _ => {}
+    }
+    Self { name: device.name().unwrap_or_default().to_string(), path: path.to_string(), id }
+  }
+}
+
+lazy_static::lazy_static! {
+  static ref PASSTHROUGH_SENDERS: Mutex<HashMap<String, UnboundedSender<InputEvent>>> = Mutex::new(HashMap::new());
+}
+
+// Every reader registers where events passed through by scripts should go, by device path. Readers of
+// disconnected devices drop their receiver, which is how their entry gets cleaned up.
+pub fn register_passthrough(device_path: &str) -> UnboundedReceiver<InputEvent> {
+  let (sender, receiver) = unbounded_channel();
+  let mut senders = PASSTHROUGH_SENDERS.lock().unwrap();
+  senders.retain(|_, sender| !sender.is_closed());
+  senders.insert(device_path.to_string(), sender);
+  receiver
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PhysicalEvent {
   pub script: String,
+  pub device: DeviceIdentity,
   pub event_type: u16,
   pub code: u16,
   pub value: i32,
@@ -30,6 +73,7 @@ pub struct SyntheticEvent {
   pub event_type: u16,
   pub code: u16,
   pub value: i32,
+  pub target: Option<Target>,
 }
 
 lazy_static::lazy_static! {
@@ -129,6 +173,12 @@ impl RubyService {
             std::process::exit(1);
           }
         }
+        RubyCommand::UseLibrary { path } => {
+          let script = format!("$makita_runtime.use_library('{}')", path);
+          if let Err(e) = ruby.eval::<Value>(&script) {
+            eprintln!("[RubyRuntime] Failed to load the library in {}: {}", path, e);
+          }
+        }
         RubyCommand::StartEventLoop => {
           let _ = ruby.eval::<Value>("$makita_runtime.start_event_loop");
         }
@@ -140,7 +190,21 @@ impl RubyService {
     define_global_function("makita_get_signal_pipe_read_fd", function!(ruby_get_signal_pipe_read_fd, 0));
     define_global_function("makita_log", function!(ruby_log_message, 2));
     define_global_function("makita_send_synthetic_event", function!(ruby_send_synthetic_event, 3));
+    define_global_function("makita_send_synthetic_event_to", function!(ruby_send_synthetic_event_to, 4));
+    define_global_function("makita_virtual_devices", function!(ruby_virtual_devices, 0));
+    define_global_function("makita_passthrough", function!(ruby_passthrough, 4));
     define_global_function("makita_get_events", function!(ruby_get_events, 0));
+    define_global_function("makita_get_profile", function!(ruby_get_profile, 0));
+    define_global_function("makita_set_profile", function!(ruby_set_profile, 1));
+    define_global_function("makita_get_setting", function!(ruby_get_setting, 1));
+    define_global_function("makita_set_setting", function!(ruby_set_setting, 2));
+    define_global_function("makita_record_script", function!(ruby_record_script, 2));
+    define_global_function("makita_get_stats", function!(ruby_get_stats, 0));
+    define_global_function("makita_inhibit", function!(ruby_inhibit, 1));
+    define_global_function("makita_inhibited", function!(ruby_inhibited, 0));
+    define_global_function("makita_lock", function!(ruby_lock, 1));
+    define_global_function("makita_set_lock", function!(ruby_set_lock, 2));
+    define_global_function("makita_notify", function!(ruby_notify, 1));
 
     let _: Value = ruby.eval(include_str!("../ruby/fiber_scheduler/compatibility.rb"))?;
     let _: Value = ruby.eval(include_str!("../ruby/fiber_scheduler/selector.rb"))?;
@@ -156,6 +220,7 @@ impl RubyService {
     let _: Value = ruby.eval(format!("Makita.const_set(:EVENT_TYPE_KEY, {})", EventType::KEY.0).as_str())?;
     let _: Value = ruby.eval(format!("Makita.const_set(:EVENT_TYPE_RELATIVE, {})", EventType::RELATIVE.0).as_str())?;
     let _: Value = ruby.eval(format!("Makita.const_set(:EVENT_TYPE_ABSOLUTE, {})", EventType::ABSOLUTE.0).as_str())?;
+    let _: Value = ruby.eval(format!("Makita.const_set(:EVENT_TYPE_MISC, {})", EventType::MISC.0).as_str())?;
     let _: Value = ruby.eval(format!("Makita.const_set(:EVENT_TYPE_SWITCH, {})", EventType::SWITCH.0).as_str())?;
     let _: Value = ruby.eval(format!("Makita.const_set(:EVENT_TYPE_LED, {})", EventType::LED.0).as_str())?;
     let _: Value = ruby.eval(format!("Makita.const_set(:EVENT_TYPE_SOUND, {})", EventType::SOUND.0).as_str())?;
@@ -171,8 +236,18 @@ impl RubyService {
     COMMAND_SENDER.send(RubyCommand::StartEventLoop).expect("failed to start event loop");
   }
 
+  // Puts the directory on $LOAD_PATH and requires the files directly in it, before any script loads.
+  pub fn use_library(&self, path: String) {
+    println!("[RubyRuntime] Using library directory {}", path);
+    COMMAND_SENDER.send(RubyCommand::UseLibrary { path }).expect("failed to use library");
+  }
+
   pub fn load_script(&self, name: String, path: String) {
     println!("[RubyRuntime] Loading script: {} from {}", name, path);
+    // A reloaded script starts over, so keys held by its previous run are let go first.
+    for key in crate::state::synthetic_keys().iter().rev() {
+      SYNTHETIC_EVENT_SENDER.send(SyntheticEvent { event_type: EventType::KEY.0, code: key.code(), value: 0, target: None }).unwrap();
+    }
     COMMAND_SENDER.send(RubyCommand::LoadScript { name, path }).expect("failed to load script");
   }
 
@@ -191,6 +266,73 @@ impl RubyService {
   }
 }
 
+// The EventSender also emits the events injected with makitactl emit, so it runs with or without Ruby scripts.
+pub fn synthetic_event_receiver() -> Receiver<SyntheticEvent> {
+  let _ = SYNTHETIC_EVENT_SENDER.len();
+  SYNTHETIC_EVENT_RECEIVER.get()
+}
+
+pub fn send_synthetic_events(events: impl IntoIterator<Item = SyntheticEvent>) {
+  for event in events {
+    SYNTHETIC_EVENT_SENDER.send(event).unwrap();
+  }
+}
+
+// Taps the lock key on the virtual keyboard unless the lock already is as asked, None toggling it.
+pub fn set_lock(led: evdev::LedType, on: Option<bool>) {
+  let Some(key) = crate::state::lock_tap(led, on) else { return };
+  let event = |event_type: EventType, code: u16, value: i32| SyntheticEvent { event_type: event_type.0, code, value, target: None };
+  send_synthetic_events([
+    event(EventType::KEY, key.code(), 1),
+    event(EventType::SYNCHRONIZATION, 0, 0),
+    event(EventType::KEY, key.code(), 0),
+    event(EventType::SYNCHRONIZATION, 0, 0),
+  ]);
+}
+
+// The scripts the configs bind in [rubies] and [tap_hold], by name and path.
+pub fn configured_scripts(configs: &[Config], scripts_directory: &str) -> Vec<(String, String)> {
+  let mut scripts = Vec::new();
+  for config in configs {
+    for modifier_map in config.bindings.rubies.values() {
+      for script_name in modifier_map.values() {
+        scripts.push((script_name.clone(), format!("{}/{}.rb", scripts_directory, script_name)));
+      }
+    }
+    for tap_hold in config.bindings.tap_hold.values() {
+      for command in [&tap_hold.tap_command, &tap_hold.hold_command] {
+        if let Some(TapHoldCommand::Ruby(script_name)) = command {
+          scripts.push((script_name.clone(), format!("{}/{}.rb", scripts_directory, script_name)));
+        }
+      }
+    }
+  }
+  scripts
+}
+
+// Boots Ruby on a thread of its own, like the Ruby service does, and compiles the scripts without running them, for
+// makita doctor. Returns the Ruby version and the errors of the scripts, or why Ruby didn't boot.
+pub fn self_test(scripts: Vec<(String, String)>) -> Result<(String, Vec<String>), String> {
+  let boot = thread::spawn(move || {
+    let cleanup = unsafe { embed::init() };
+    let ruby = &*cleanup;
+    RubyService::setup_ruby_environment(ruby).map_err(|e| e.to_string())?;
+    let version: String = ruby.eval("RUBY_VERSION").map_err(|e| e.to_string())?;
+    let mut errors = Vec::new();
+    for (name, path) in scripts {
+      if !std::path::Path::new(&path).is_file() {
+        errors.push(format!("{}: {} not found", name, path));
+        continue;
+      }
+      if let Err(e) = ruby.eval::<Value>(&format!("RubyVM::InstructionSequence.compile_file('{}')", path)) {
+        errors.push(format!("{}: {}", name, e));
+      }
+    }
+    Ok((version, errors))
+  });
+  boot.join().unwrap_or_else(|_| Err("the Ruby thread panicked".to_string()))
+}
+
 fn ruby_get_signal_pipe_read_fd() -> Result<i32, MagnusError> {
   Ok(PIPE_FDS.lock().unwrap().0.as_raw_fd())
 }
@@ -212,7 +354,35 @@ fn ruby_log_message(level: RString, message: RString) -> Result<(), MagnusError>
 
 fn ruby_send_synthetic_event(event_type: u16, code: u16, value: i32) {
   println!("[Ruby] Sending synthetic event: type={}, code={}, value={}", event_type, code, value);
-  SYNTHETIC_EVENT_SENDER.send(SyntheticEvent { event_type, code, value }).unwrap();
+  SYNTHETIC_EVENT_SENDER.send(SyntheticEvent { event_type, code, value, target: None }).unwrap();
+}
+
+// Like makita_send_synthetic_event, on the given virtual device. Returns an error message for Ruby to raise.
+fn ruby_send_synthetic_event_to(event_type: u16, code: u16, value: i32, target: String) -> Option<String> {
+  match Target::from_str(&target) {
+    Ok(target) => {
+      SYNTHETIC_EVENT_SENDER.send(SyntheticEvent { event_type, code, value, target: Some(target) }).unwrap();
+      None
+    }
+    Err(_) => Some(format!("unknown device {}, use keyboard, pointer, absolute, gamepad or tablet", target)),
+  }
+}
+
+fn ruby_virtual_devices() -> Result<RHash, MagnusError> {
+  let devices = RHash::new();
+  for (name, target) in Target::ALL {
+    devices.aset(name, target.device_name())?;
+  }
+  Ok(devices)
+}
+
+// Asks the reader of the device to emit an event its Ruby binding swallowed, for handlers that return
+// :passthrough. False if the device is gone.
+fn ruby_passthrough(device_path: String, event_type: u16, code: u16, value: i32) -> bool {
+  match PASSTHROUGH_SENDERS.lock().unwrap().get(&device_path) {
+    Some(sender) => sender.send(InputEvent::new(EventType(event_type), code, value)).is_ok(),
+    None => false,
+  }
 }
 
 fn ruby_get_events() -> Result<RArray, MagnusError> {
@@ -220,6 +390,11 @@ fn ruby_get_events() -> Result<RArray, MagnusError> {
   for event in PHYSICAL_EVENT_RECEIVER.get().try_iter() {
     let hash = RHash::new();
     hash.aset("script", event.script)?;
+    let device = RHash::new();
+    device.aset("name", event.device.name)?;
+    device.aset("path", event.device.path)?;
+    device.aset("id", event.device.id)?;
+    hash.aset("device", device)?;
     hash.aset("event_type", event.event_type)?;
     hash.aset("code", event.code)?;
     hash.aset("value", event.value)?;
@@ -229,3 +404,51 @@ fn ruby_get_events() -> Result<RArray, MagnusError> {
   }
   Ok(ruby_array)
 }
+
+fn ruby_get_profile() -> Option<String> {
+  crate::profiles::active_profile()
+}
+
+fn ruby_set_profile(profile: String) {
+  crate::profiles::set_active_profile(Some(profile));
+}
+
+fn ruby_get_setting(setting: String) -> Option<String> {
+  crate::live_settings::get(None, &setting)
+}
+
+fn ruby_set_setting(setting: String, value: String) -> Option<String> {
+  crate::live_settings::set(None, &setting, &value).err()
+}
+
+fn ruby_record_script(script: String, seconds: f64) {
+  crate::stats::record_script(&script, std::time::Duration::from_secs_f64(seconds.max(0.0)));
+}
+
+fn ruby_inhibit(minutes: Option<f64>) {
+  crate::state::inhibit(minutes);
+}
+
+fn ruby_inhibited() -> bool {
+  crate::state::inhibited()
+}
+
+fn ruby_lock(name: String) -> Option<bool> {
+  crate::state::parse_lock(&name).map(crate::state::lock_on)
+}
+
+fn ruby_set_lock(name: String, on: Option<bool>) -> Option<String> {
+  match crate::state::parse_lock(&name) {
+    Some(led) => { set_lock(led, on); None }
+    None => Some(format!("unknown lock {}, use num_lock, caps_lock or scroll_lock", name)),
+  }
+}
+
+fn ruby_notify(message: String) {
+  crate::notifier::notify_from_script(&message);
+}
+
+// JSON, like `makitactl stats` gets it.
+fn ruby_get_stats() -> String {
+  serde_json::to_string(&crate::state::query(crate::state::StateQuery::Stats)).unwrap_or_default()
+}
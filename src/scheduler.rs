@@ -0,0 +1,85 @@
+use crate::config::Config;
+use crate::profiles;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduleRule {
+  start: u32,
+  end: u32,
+  profile: String,
+}
+
+impl ScheduleRule {
+  fn contains(&self, minute: u32) -> bool {
+    if self.start <= self.end {
+      minute >= self.start && minute < self.end
+    } else {
+      minute >= self.start || minute < self.end
+    }
+  }
+}
+
+// Parses "22:00-07:00=quiet, 09:00-17:00=work" from the PROFILE_SCHEDULE setting.
+pub fn parse_schedule(configs: &[Config]) -> Vec<ScheduleRule> {
+  let mut rules: Vec<ScheduleRule> = Vec::new();
+  for config in configs {
+    let Some(schedule) = config.settings.get("PROFILE_SCHEDULE") else { continue };
+    for entry in schedule.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+      let rule = entry.split_once('=').and_then(|(window, profile)| {
+        let (start, end) = window.split_once('-')?;
+        Some(ScheduleRule {
+          start: parse_time(start.trim())?,
+          end: parse_time(end.trim())?,
+          profile: profile.trim().to_string(),
+        })
+      });
+      match rule {
+        Some(rule) if !rules.contains(&rule) => rules.push(rule),
+        Some(_) => {}
+        None => println!("[Scheduler] Invalid entry in PROFILE_SCHEDULE of {}: {}, ignoring.", config.name, entry),
+      }
+    }
+  }
+  rules
+}
+
+fn parse_time(time: &str) -> Option<u32> {
+  let (hours, minutes) = time.split_once(':')?;
+  let (hours, minutes): (u32, u32) = (hours.parse().ok()?, minutes.parse().ok()?);
+  if hours > 23 || minutes > 59 { return None; }
+  Some(hours * 60 + minutes)
+}
+
+fn local_minute_of_day() -> u32 {
+  let now = std::time::SystemTime::now()
+    .duration_since(std::time::UNIX_EPOCH)
+    .unwrap_or_default()
+    .as_secs() as nix::libc::time_t;
+  let mut local: nix::libc::tm = unsafe { std::mem::zeroed() };
+  unsafe { nix::libc::localtime_r(&now, &mut local) };
+  (local.tm_hour * 60 + local.tm_min) as u32
+}
+
+// Only acts when the scheduled profile changes, so manual switches stick until the next window starts or ends.
+pub async fn run(rules: Vec<ScheduleRule>) {
+  println!("[Scheduler] {} profile schedule rule(s) loaded.", rules.len());
+  let mut scheduled: Option<String> = None;
+  let mut first_tick = true;
+
+  loop {
+    let minute = local_minute_of_day();
+    let profile = rules.iter().find(|rule| rule.contains(minute)).map(|rule| rule.profile.clone());
+
+    if profile != scheduled {
+      match &profile {
+        Some(_) => profiles::set_active_profile(profile.clone()),
+        None if !first_tick && profiles::active_profile() == scheduled => profiles::set_active_profile(None),
+        None => {}
+      }
+      scheduled = profile;
+    }
+    first_tick = false;
+
+    tokio::time::sleep(Duration::from_secs(30)).await;
+  }
+}
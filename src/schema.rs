@@ -0,0 +1,396 @@
+// `makita schema` prints a JSON Schema of the config files, for editors that validate and complete TOML
+// through one (e.g. taplo or Even Better TOML with `#:schema makita.schema.json` at the top of a config).
+// Key, axis and movement names come from the same lists the config parser uses.
+use crate::config::{parse_modifiers, Axis, AXIS_MODES, KEY_MAX, AXIS_OUTPUTS, DEDICATED_ABS_AXES, EVENT_TAP_MODES, LOCK_SCREEN_MODES, MOUSE_STICK_MODES, MOVEMENT_NAMES, STICK_FUNCTIONS, TAP_HOLD_STRATEGIES, NOTIFIERS, OUTPUT_BACKENDS, VIRTUAL_GAMEPAD_MODES, WHEEL_BINDING_SOURCES};
+use evdev::{AbsoluteAxisType, Key};
+use std::{collections::HashMap, str::FromStr, time::Duration};
+use serde_json::{json, Map, Value};
+
+// Each kind is checked the way the readers parse it, so that a value makita check accepts can't take them down.
+enum Kind {
+  Bool,
+  Integer,
+  Unsigned,
+  Float,
+  // A float the readers turn into a Duration, of minutes or seconds.
+  Duration,
+  // A float greater than 0.0 and up to 1.0.
+  Fraction,
+  FloatList,
+  IntegerList,
+  Choice(&'static [&'static str]),
+  Key,
+  Modifiers,
+  // <width>x<height>.
+  Size,
+  // Comma separated ABS_<NAME>:<percent> pairs.
+  AxisDeadzones,
+  // Comma separated <key>:<milliseconds> pairs.
+  KeyWindows,
+  Text,
+}
+
+const SETTINGS: [(&str, Kind, &str); 72] = [
+  ("GRAB_DEVICE", Kind::Bool, "Grab the device so that only Makita reads it. Defaults to true."),
+  ("WAIT_FOR_DEVICE", Kind::Bool, "Wait for the device when it isn't connected at startup. Defaults to false."),
+  ("WAIT_FOR_DEVICE_TIMEOUT", Kind::Unsigned, "Seconds to wait for the device, without a timeout by default."),
+  ("SEPARATE_VIRTUAL_DEVICES", Kind::Bool, "Give the device virtual devices of its own, named after it. Defaults to false."),
+  ("FLAT_POINTER", Kind::Bool, "Move the cursor of sticks, movements and [axes] with a virtual pointer of its own, to give it a flat acceleration profile. Defaults to false."),
+  ("OUTPUT_BACKEND", Kind::Choice(&OUTPUT_BACKENDS), "Emit keys and pointer events through uinput or the Wayland compositor. Defaults to uinput."),
+  ("DEVICE_NOTIFY", Kind::Bool, "Notify when the device is grabbed, lost or can't be grabbed. Defaults to false."),
+  ("LAYER_NOTIFY", Kind::Bool, "Notify when the layout or profile changes. Defaults to false."),
+  ("NOTIFIER", Kind::Choice(&NOTIFIERS), "Show notifications over D-Bus, or only through NOTIFY_COMMAND. Applies to every config as soon as one sets it. Defaults to dbus."),
+  ("NOTIFY_COMMAND", Kind::Text, "Command that shows a notification, with {message} replaced by it, when D-Bus isn't available or NOTIFIER is command."),
+  ("OSD_COMMAND", Kind::Text, "Command run when the layout or profile changes, with {device}, {layout} and {profile} replaced."),
+  ("IDLE_TIMEOUT", Kind::Duration, "Minutes of inactivity after which held keys and modifiers are released."),
+  ("GRAB_CHECK_INTERVAL", Kind::Unsigned, "Seconds between checks that the device is still grabbed and read, 0 to turn them off. Defaults to 5."),
+  ("IDLE_UNGRAB", Kind::Bool, "Also ungrab the device while idle. Defaults to false."),
+  ("MODIFIER_TIMEOUT", Kind::Duration, "Seconds without other input after which held modifiers are released."),
+  ("MOVEMENT_TIMEOUT", Kind::Unsigned, "Milliseconds after which a movement key that wasn't refreshed is checked for a lost release. Defaults to 250."),
+  ("LOCK_SCREEN_MODE", Kind::Choice(&LOCK_SCREEN_MODES), "What to do while the session is locked. Defaults to remap."),
+  ("EVENT_TAP", Kind::Choice(&EVENT_TAP_MODES), "Which events to stream to the event tap socket. Defaults to off."),
+  ("REMOTE_LISTEN", Kind::Text, "Socket path or tcp:host:port to receive remote input from another Makita on."),
+  ("REMOTE_TARGET", Kind::Text, "Socket path or tcp:host:port of the Makita the remote action forwards input to."),
+  ("REMOTE_SECRET", Kind::Text, "Shared secret both machines of the remote bridge authenticate with."),
+  ("LSTICK", Kind::Choice(&STICK_FUNCTIONS), "Function of the left analog stick. Defaults to cursor."),
+  ("RSTICK", Kind::Choice(&STICK_FUNCTIONS), "Function of the right analog stick. Defaults to scroll."),
+  ("FLICK_STICK_FULL_TURN", Kind::Float, "Mouse movement of a 360° turn for the flick stick. Defaults to 3000."),
+  ("FLICK_STICK_THRESHOLD", Kind::Float, "How far the stick has to be tilted to flick, 0.0 to 1.0. Defaults to 0.9."),
+  ("LSTICK_SENSITIVITY", Kind::Unsigned, "Left stick sensitivity, lower is faster. 0 disables movement."),
+  ("RSTICK_SENSITIVITY", Kind::Unsigned, "Right stick sensitivity, lower is faster. 0 disables movement."),
+  ("LSTICK_DEADZONE", Kind::Integer, "Left stick deadzone, 0 to 128. Defaults to 5."),
+  ("RSTICK_DEADZONE", Kind::Integer, "Right stick deadzone, 0 to 128. Defaults to 5."),
+  ("AXIS_DEADZONES", Kind::AxisDeadzones, "Comma separated ABS_<NAME>:<percent> deadzones of the axes bound as ABS_<NAME>_MINUS/PLUS. Defaults to 10."),
+  ("INVERT_CURSOR_AXIS", Kind::Bool, "Invert the stick that moves the cursor. Defaults to false."),
+  ("INVERT_SCROLL_AXIS", Kind::Bool, "Invert the stick that scrolls. Defaults to false."),
+  ("LSTICK_ACTIVATION_MODIFIERS", Kind::Modifiers, "Modifiers that have to be held for the left stick to move."),
+  ("RSTICK_ACTIVATION_MODIFIERS", Kind::Modifiers, "Modifiers that have to be held for the right stick to move."),
+  ("CURSOR_SPEED", Kind::Integer, "Pixels per 5ms of cursor movements. Defaults to 0."),
+  ("SCROLL_SPEED", Kind::Integer, "Speed of scroll movements. Defaults to 0."),
+  ("CURSOR_ACCEL", Kind::Float, "Fraction of CURSOR_SPEED gained every 5ms, 0.0 to 1.0. Defaults to 1.0."),
+  ("SCROLL_ACCEL", Kind::Float, "Fraction of SCROLL_SPEED gained every 5ms, 0.0 to 1.0. Defaults to 1.0."),
+  ("CURSOR_SPEED_PRESETS", Kind::FloatList, "Comma separated pointer speed multipliers for cycle_cursor_speed. Defaults to 1.0."),
+  ("CURSOR_SPEED_NOTIFY", Kind::Bool, "Show a notification when the cursor speed changes. Defaults to false."),
+  ("WHEEL_MOVEMENT_SENSITIVITY", Kind::Float, "Distance of a wheel detent bound to a movement. Defaults to 1.0."),
+  ("WHEEL_MOVEMENT_ACCEL", Kind::Float, "Extra distance for detents in quick succession. Defaults to 0.0."),
+  ("WHEEL_INVERT", Kind::Bool, "Invert the direction of the wheels, e.g. for natural scrolling in an app-specific config. Defaults to false."),
+  ("WHEEL_MULTIPLIER", Kind::Float, "Multiplier of the distance the wheels scroll. Defaults to 1.0."),
+  ("WHEEL_BINDING_SOURCE", Kind::Choice(&WHEEL_BINDING_SOURCES), "Which of REL_WHEEL and REL_WHEEL_HI_RES fires wheel bindings. Defaults to auto."),
+  ("BUTTON_DEBOUNCE", Kind::KeyWindows, "Comma separated BTN_<NAME>:<milliseconds> windows within which a release and press of the button are dropped, for worn switches that double-click."),
+  ("SCREEN_SIZE", Kind::Size, "Size of the desktop as <width>x<height>, for EDGE_* and CORNER_* bindings outside Hyprland, e.g. 2560x1440."),
+  ("PEN_TILT_THRESHOLD", Kind::Fraction, "Fraction of the tilt range that triggers TILT_* events. Defaults to 0.5."),
+  ("VIRTUAL_GAMEPAD", Kind::Choice(&VIRTUAL_GAMEPAD_MODES), "xpad adds a virtual Xbox 360 controller for games. Defaults to none."),
+  ("MOUSE_STICK", Kind::Choice(&MOUSE_STICK_MODES), "Turns mouse motion into the left or right stick of the virtual gamepad. Defaults to none."),
+  ("MOUSE_STICK_SENSITIVITY", Kind::Float, "How far mouse motion tilts the mouse stick. Defaults to 1.0."),
+  ("MOUSE_STICK_DECAY", Kind::Unsigned, "Milliseconds for the mouse stick to return halfway to the center. Defaults to 50."),
+  ("16_BIT_AXIS", Kind::Bool, "Needed by Xbox controllers and Joy-Cons. Defaults to false."),
+  ("CUSTOM_MODIFIERS", Kind::Modifiers, "Keys that act as modifiers, separated by -."),
+  ("STADIA", Kind::Bool, "Needed by Stadia controllers. Defaults to false."),
+  ("INHERIT_BINDINGS", Kind::Bool, "App, layout and profile configs only replace the bindings they declare. Defaults to false."),
+  ("CHAIN_ONLY", Kind::Bool, "Only fire chained bindings with other modifiers held. Defaults to true."),
+  ("MACRO_PAD", Kind::Bool, "Turn every unbound key into one of F13-F24 and KEY_MACRO1-30. Defaults to false."),
+  ("HOME_ROW_MODS", Kind::Bool, "Turn the home row into tap-hold modifiers. Defaults to false."),
+  ("AUTOSHIFT_LETTERS", Kind::Unsigned, "Milliseconds a letter has to be held to be shifted."),
+  ("AUTOSHIFT_NUMBERS", Kind::Unsigned, "Milliseconds a number has to be held to be shifted."),
+  ("AUTOSHIFT_SYMBOLS", Kind::Unsigned, "Milliseconds a symbol has to be held to be shifted."),
+  ("TAPPING_TERM", Kind::Unsigned, "Default tapping term of [tap_hold] entries in milliseconds. Defaults to 200."),
+  ("CHORD_TIMEOUT", Kind::Unsigned, "Milliseconds the inputs of a [chords] entry have to be pressed within. Defaults to 50."),
+  ("TAP_HOLD_STRATEGY", Kind::Choice(&TAP_HOLD_STRATEGIES), "Default strategy of [tap_hold] entries. Defaults to permissive_hold."),
+  ("RUBY_RELEASE_MODIFIERS", Kind::Bool, "Release held modifiers while Ruby scripts emit keys. Defaults to false."),
+  ("LAYOUT_SWITCHER", Kind::Key, "Key that cycles through the layouts. Defaults to BTN_0."),
+  ("LAYOUT_NAMES", Kind::Text, "Comma separated names of layouts 0, 1, 2 and so on."),
+  ("LAYOUT_LED", Kind::Text, "Sysfs LED or backlight set when the layout changes, e.g. /sys/class/leds/input3::scrolllock."),
+  ("LAYOUT_LED_BRIGHTNESS", Kind::IntegerList, "Comma separated brightness of LAYOUT_LED for layouts 0 to 3. Defaults to 0,1,1,1."),
+  ("XKB_LAYOUTS", Kind::IntegerList, "Comma separated XKB layout indices the compositor switches to along with layouts 0, 1, 2 and so on."),
+  ("PROFILE_SCHEDULE", Kind::Text, "Profiles by time of day, e.g. \"22:00-07:00=quiet, 09:00-17:00=work\"."),
+];
+
+const ACTIONS: &str = r"^\s*(profile(\(.*\))?|layout\(.+\)|osc\(.+\)|mqtt\(.+\)|wheel\(.+\)|hold\(.+\)|toggle\(.+\)|lock\(\s*(num_lock|caps_lock|scroll_lock)\s*(,\s*(on|off|toggle)\s*)?\)|cycle_cursor_speed|toggle_remapping|inhibit(\(\s*\d*\s*\))?|remote(\(\s*(on|off|toggle)?\s*\))?|paste_clipboard(\(\s*\d*\s*\))?)\s*$";
+
+pub fn run() {
+  println!("{}", serde_json::to_string_pretty(&schema()).unwrap());
+}
+
+pub fn schema() -> Value {
+  let settings: Map<String, Value> = SETTINGS.iter()
+    .map(|(name, kind, description)| {
+      let mut setting = setting_schema(kind);
+      setting["description"] = json!(description);
+      (name.to_string(), setting)
+    })
+    .collect();
+
+  json!({
+    "$schema": "http://json-schema.org/draft-07/schema#",
+    "title": "Makita config",
+    "type": "object",
+    "additionalProperties": false,
+    "properties": {
+      "remap": binding_table(json!({ "$ref": "#/definitions/keys" }), "Events to remap to keys."),
+      "overrides": binding_table(json!({ "$ref": "#/definitions/keys" }), "Bindings that fire even while other keys are held."),
+      "movements": binding_table(json!({ "type": "string", "enum": MOVEMENT_NAMES }), "Events that move the cursor or scroll."),
+      "actions": binding_table(json!({ "type": "string", "pattern": ACTIONS }), "Events that run an action."),
+      "rubies": binding_table(json!({ "type": "string" }), "Events that run a Ruby script from the scripts directory."),
+      "settings": {
+        "type": "object",
+        "properties": settings,
+        "additionalProperties": false,
+      },
+      "vars": {
+        "type": "object",
+        "description": "Variables that ${name} in actions, scripts and settings is replaced with.",
+        "additionalProperties": { "type": ["string", "number", "boolean"] },
+      },
+      "tap_hold": {
+        "type": "object",
+        "description": "Keys that do one thing when tapped and another when held.",
+        "additionalProperties": {
+          "type": "object",
+          "allOf": [
+            { "oneOf": [{ "required": ["tap"] }, { "required": ["tap_action"] }, { "required": ["tap_ruby"] }] },
+            { "oneOf": [{ "required": ["hold"] }, { "required": ["hold_action"] }, { "required": ["hold_ruby"] }] },
+          ],
+          "additionalProperties": false,
+          "properties": {
+            "tap": { "$ref": "#/definitions/key" },
+            "hold": { "$ref": "#/definitions/key" },
+            "tap_action": { "type": "string", "pattern": ACTIONS },
+            "hold_action": { "type": "string", "pattern": ACTIONS },
+            "tap_ruby": { "type": "string" },
+            "hold_ruby": { "type": "string" },
+            "tapping_term": { "type": "integer", "minimum": 0 },
+            "strategy": { "type": "string", "enum": TAP_HOLD_STRATEGIES },
+            "description": { "type": "string" },
+          },
+        },
+      },
+      "hidraw": {
+        "type": "object",
+        "description": "Buttons read from a hidraw report, for devices without key events.",
+        "required": ["device"],
+        "additionalProperties": false,
+        "properties": {
+          "device": { "type": "string" },
+          "report_id": { "type": "integer", "minimum": 0, "maximum": 255 },
+          "buttons": {
+            "type": "object",
+            "propertyNames": { "pattern": r"^\d+(:[0-7])?$" },
+            "additionalProperties": { "$ref": "#/definitions/key" },
+          },
+        },
+      },
+      "axes": {
+        "type": "object",
+        "description": "Absolute axes that move the cursor or scroll, by their ABS_ name.",
+        "additionalProperties": {
+          "type": "object",
+          "required": ["output"],
+          "additionalProperties": false,
+          "properties": {
+            "output": { "type": "string", "enum": AXIS_OUTPUTS },
+            "scale": { "type": "number" },
+            "invert": { "type": "boolean" },
+            "mode": { "type": "string", "enum": AXIS_MODES },
+            "deadzone": { "type": "number", "minimum": 0, "maximum": 100 },
+          },
+        },
+      },
+      "chords": {
+        "type": "object",
+        "description": "Inputs joined with + that press keys when pressed together, e.g. BTN_SELECT+BTN_START.",
+        "additionalProperties": { "$ref": "#/definitions/keys" },
+      },
+      "merge": {
+        "type": "object",
+        "description": "Devices read as one, e.g. a pair of Joy-Cons.",
+        "required": ["devices"],
+        "additionalProperties": false,
+        "properties": {
+          "devices": { "type": "array", "items": { "type": "string" } },
+          "joycon": { "type": "boolean" },
+          "rotate": { "type": "object", "additionalProperties": { "type": "integer" } },
+        },
+      },
+    },
+    "definitions": {
+      "key": {
+        "anyOf": [
+          { "type": "string", "enum": key_names() },
+          { "type": "string", "pattern": r"^(\d+|[^A-Z]+)$", "description": "A shorthand like ctrl or a, or a raw key code." },
+        ],
+      },
+      "keys": { "type": "array", "items": { "$ref": "#/definitions/key" } },
+      "event": {
+        "anyOf": [
+          { "type": "string", "enum": event_names() },
+          { "type": "string", "pattern": r"^(\d+|[^A-Z]+)$" },
+        ],
+      },
+      "binding": {
+        "anyOf": [
+          { "$ref": "#/definitions/event" },
+          { "type": "string", "pattern": r"^-?([^-]+-)*[^-]+$" },
+        ],
+      },
+    },
+  })
+}
+
+// Bindings are either the output alone or { output = ..., description = "...", rate_limit_ms = ..., when = "..." }.
+fn binding_table(output: Value, description: &str) -> Value {
+  json!({
+    "type": "object",
+    "description": description,
+    "propertyNames": { "$ref": "#/definitions/binding" },
+    "additionalProperties": {
+      "anyOf": [
+        output,
+        {
+          "type": "object",
+          "required": ["output"],
+          "additionalProperties": false,
+          "properties": {
+            "output": output,
+            "description": { "type": "string" },
+            "rate_limit_ms": { "type": "integer", "minimum": 0 },
+            "when": { "type": "string" },
+          },
+        },
+      ],
+    },
+  })
+}
+
+// Setting values are always TOML strings.
+// For `makita check`, which validates the values the editor would, without a JSON Schema validator.
+pub fn check_setting(name: &str, value: &str) -> Result<(), String> {
+  let Some((_, kind, _)) = SETTINGS.iter().find(|(setting, _, _)| *setting == name) else {
+    return Err(format!("unknown setting {}", name));
+  };
+  check_value(name, kind, value)
+}
+
+// Checks the values of every setting the readers know, before they are handed configs they would panic on.
+// Unknown settings are ignored by the readers and left to makita check.
+pub fn check_settings(settings: &HashMap<String, String>) -> Result<(), String> {
+  let mut names: Vec<&String> = settings.keys().collect();
+  names.sort();
+  for name in names {
+    if let Some((_, kind, _)) = SETTINGS.iter().find(|(setting, _, _)| setting == name) {
+      check_value(name, kind, &settings[name])?;
+    }
+  }
+  Ok(())
+}
+
+fn check_value(name: &str, kind: &Kind, value: &str) -> Result<(), String> {
+  let pairs = |parse_pair: &dyn Fn(&str, &str) -> bool| value.split(',')
+    .filter(|entry| !entry.trim().is_empty())
+    .all(|entry| entry.split_once(':').is_some_and(|(name, value)| parse_pair(name.trim(), value.trim())));
+  let valid = match kind {
+    Kind::Bool => value == "true" || value == "false",
+    Kind::Integer => value.parse::<i32>().is_ok(),
+    Kind::Unsigned => value.parse::<u64>().is_ok(),
+    Kind::Float => value.parse::<f64>().is_ok(),
+    // IDLE_TIMEOUT is in minutes, so it has to fit a Duration once turned into seconds.
+    Kind::Duration => value.parse::<f64>().is_ok_and(|amount| Duration::try_from_secs_f64(amount * 60.0).is_ok()),
+    Kind::Fraction => value.parse::<f64>().is_ok_and(|fraction| fraction > 0.0 && fraction <= 1.0),
+    Kind::FloatList => value.split(',').all(|entry| entry.trim().parse::<f64>().is_ok()),
+    Kind::IntegerList => value.split(',').all(|entry| entry.trim().parse::<u32>().is_ok()),
+    Kind::Choice(choices) => choices.contains(&value),
+    Kind::Key => Key::from_str(value).is_ok(),
+    Kind::Modifiers => parse_modifiers(&HashMap::from([(name.to_string(), value.to_string())]), name).is_ok(),
+    Kind::Size => value.split_once('x')
+      .is_some_and(|(width, height)| width.trim().parse::<f64>().is_ok() && height.trim().parse::<f64>().is_ok()),
+    Kind::AxisDeadzones => pairs(&|axis, deadzone| AbsoluteAxisType::from_str(axis).is_ok() && deadzone.parse::<f64>().is_ok()),
+    Kind::KeyWindows => pairs(&|key, window| Key::from_str(key).is_ok() && window.parse::<u64>().is_ok()),
+    Kind::Text => true,
+  };
+  if valid {
+    return Ok(());
+  }
+  let expected = match kind {
+    Kind::Bool => "true or false".to_string(),
+    Kind::Integer => "an integer".to_string(),
+    Kind::Unsigned => "an integer of 0 or more".to_string(),
+    Kind::Float => "a number".to_string(),
+    Kind::Duration => "a number of 0 or more".to_string(),
+    Kind::Fraction => "a number greater than 0.0 and up to 1.0".to_string(),
+    Kind::FloatList => "comma separated numbers".to_string(),
+    Kind::IntegerList => "comma separated integers".to_string(),
+    Kind::Choice(choices) => format!("one of {}", choices.join(", ")),
+    Kind::Key => "a key name, e.g. KEY_F13".to_string(),
+    Kind::Modifiers => "keys separated by -".to_string(),
+    Kind::Size => "<width>x<height>, e.g. 2560x1440".to_string(),
+    Kind::AxisDeadzones => "comma separated ABS_<NAME>:<percent> pairs".to_string(),
+    Kind::KeyWindows => "comma separated BTN_<NAME>:<milliseconds> pairs".to_string(),
+    Kind::Text => unreachable!(),
+  };
+  Err(format!("invalid value {} for {}, use {}", value, name, expected))
+}
+
+fn setting_schema(kind: &Kind) -> Value {
+  match kind {
+    Kind::Bool => json!({ "type": "string", "enum": ["true", "false"] }),
+    Kind::Integer => json!({ "type": "string", "pattern": r"^-?\d+$" }),
+    Kind::Unsigned => json!({ "type": "string", "pattern": r"^\d+$" }),
+    Kind::Float => json!({ "type": "string", "pattern": r"^-?\d+(\.\d+)?$" }),
+    Kind::Duration => json!({ "type": "string", "pattern": r"^\d+(\.\d+)?$" }),
+    Kind::Fraction => json!({ "type": "string", "pattern": r"^(0\.\d*[1-9]\d*|1(\.0+)?)$" }),
+    Kind::FloatList => json!({ "type": "string", "pattern": r"^\s*-?\d+(\.\d+)?\s*(,\s*-?\d+(\.\d+)?\s*)*$" }),
+    Kind::IntegerList => json!({ "type": "string", "pattern": r"^\s*\d+\s*(,\s*\d+\s*)*$" }),
+    Kind::Choice(choices) => json!({ "type": "string", "enum": choices }),
+    Kind::Key => json!({ "$ref": "#/definitions/key" }),
+    Kind::Modifiers => json!({ "type": "string", "pattern": r"^[^-]+(-[^-]+)*$" }),
+    Kind::Size => json!({ "type": "string", "pattern": r"^\s*\d+(\.\d+)?\s*x\s*\d+(\.\d+)?\s*$" }),
+    Kind::AxisDeadzones => json!({ "type": "string", "pattern": r"^\s*(ABS_[A-Z0-9_]+\s*:\s*\d+(\.\d+)?\s*(,\s*|$))*$" }),
+    Kind::KeyWindows => json!({ "type": "string", "pattern": r"^\s*((KEY|BTN)_[A-Z0-9_]+\s*:\s*\d+\s*(,\s*|$))*$" }),
+    Kind::Text => json!({ "type": "string" }),
+  }
+}
+
+// Every key evdev has a name for, up to KEY_MAX.
+fn key_names() -> Vec<String> {
+  (0..=KEY_MAX)
+    .map(|code| format!("{:?}", Key(code)))
+    .filter(|name| name.starts_with("KEY_") || name.starts_with("BTN_"))
+    .collect()
+}
+
+fn event_names() -> Vec<String> {
+  let mut names = key_names();
+  for axis in Axis::ALL.iter().map(|axis| format!("{:?}", axis)) {
+    if !names.contains(&axis) { names.push(axis); }
+  }
+  for axis in (0..0x40).map(AbsoluteAxisType).filter(|axis| !DEDICATED_ABS_AXES.contains(axis)) {
+    if format!("{:?}", axis).starts_with("ABS_") {
+      names.extend([Axis::ABS_MINUS(axis.0).name(), Axis::ABS_PLUS(axis.0).name()]);
+    }
+  }
+  names
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn checks_setting_values_like_the_readers_parse_them() {
+    assert!(check_setting("LSTICK_SENSITIVITY", "8").is_ok());
+    assert_eq!(check_setting("LSTICK_SENSITIVITY", "-1"), Err("invalid value -1 for LSTICK_SENSITIVITY, use an integer of 0 or more".to_string()));
+    assert!(check_setting("CHORD_TIMEOUT", "-50").is_err());
+    assert!(check_setting("CURSOR_SPEED", "3000000000").is_err());
+    assert!(check_setting("IDLE_TIMEOUT", "-1").is_err());
+    assert!(check_setting("PEN_TILT_THRESHOLD", "1.5").is_err());
+    assert!(check_setting("SCREEN_SIZE", "2560x1440").is_ok());
+    assert!(check_setting("SCREEN_SIZE", "2560").is_err());
+    assert!(check_setting("AXIS_DEADZONES", "ABS_THROTTLE:5, ABS_RUDDER:12.5").is_ok());
+    assert!(check_setting("AXIS_DEADZONES", "ABS_THROTTLE").is_err());
+    assert!(check_setting("BUTTON_DEBOUNCE", "BTN_LEFT:30").is_ok());
+    assert!(check_setting("BUTTON_DEBOUNCE", "BTN_LEFT:-30").is_err());
+
+    let settings = HashMap::from([("CURSOR_SPEED".to_string(), "fast".to_string()), ("UNKNOWN".to_string(), "1".to_string())]);
+    assert_eq!(check_settings(&settings), Err("invalid value fast for CURSOR_SPEED, use an integer".to_string()));
+    assert!(check_settings(&HashMap::from([("UNKNOWN".to_string(), "1".to_string())])).is_ok());
+  }
+}
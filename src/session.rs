@@ -0,0 +1,246 @@
+use crate::udev_monitor::Environment;
+use lazy_static::lazy_static;
+use nix::unistd::{geteuid, User};
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+
+static LOCKED: AtomicBool = AtomicBool::new(false);
+static REFRESHING: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+  static ref ACTIVE_USER: Mutex<Option<(Instant, Option<SessionUser>)>> = Mutex::new(None);
+  static ref SESSION_ENVIRONMENT: Mutex<Option<(Instant, u32, Variables)>> = Mutex::new(None);
+}
+
+type Variables = Vec<(String, String)>;
+
+// What the compositor and X server of a session are reached through.
+const SESSION_VARIABLES: [&str; 6] = ["WAYLAND_DISPLAY", "DISPLAY", "XAUTHORITY", "HYPRLAND_INSTANCE_SIGNATURE", "SWAYSOCK", "NIRI_SOCKET"];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SessionUser {
+  pub name: String,
+  pub uid: u32,
+}
+
+// The user of the session in the foreground of seat0 as logind reports it, so that switching users on a shared
+// machine is followed. Greeter sessions don't count. Window lookups ask for it on every event from the readers'
+// tasks, so only the first lookup waits for loginctl: later ones get the last known user, and once that's a second
+// old it's looked up again on a thread of its own.
+pub fn active_user() -> Option<SessionUser> {
+  let mut cached = ACTIVE_USER.lock().unwrap();
+  match cached.as_ref() {
+    Some((checked, user)) => {
+      if checked.elapsed() >= Duration::from_secs(1) && !REFRESHING.swap(true, Ordering::SeqCst) {
+        thread::spawn(|| {
+          let user = query_active_user();
+          *ACTIVE_USER.lock().unwrap() = Some((Instant::now(), user));
+          REFRESHING.store(false, Ordering::SeqCst);
+        });
+      }
+      user.clone()
+    }
+    None => {
+      let user = query_active_user();
+      *cached = Some((Instant::now(), user.clone()));
+      user
+    }
+  }
+}
+
+fn loginctl(args: &[&str]) -> Option<String> {
+  let output = std::process::Command::new("loginctl").args(args).stderr(Stdio::null()).output().ok()?;
+  output.status.success().then(|| String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+fn active_session() -> Option<String> {
+  let session = loginctl(&["show-seat", "seat0", "--property=ActiveSession", "--value"])?.trim().to_string();
+  (!session.is_empty()).then_some(session)
+}
+
+// The object path logind sends the signals of a session from: bytes other than letters and digits, and a leading
+// digit, are escaped as _ followed by their hex value, e.g. /org/freedesktop/login1/session/_32 for session 2.
+fn session_path(session: &str) -> String {
+  let mut path = String::from("/org/freedesktop/login1/session/");
+  for (index, byte) in session.bytes().enumerate() {
+    match byte.is_ascii_alphanumeric() && !(index == 0 && byte.is_ascii_digit()) {
+      true => path.push(byte as char),
+      false => path.push_str(&format!("_{:02x}", byte)),
+    }
+  }
+  path
+}
+
+fn query_active_user() -> Option<SessionUser> {
+  let session = active_session()?;
+  let properties = loginctl(&["show-session", &session, "--property=Name", "--property=User", "--property=Class"])?;
+  let property = |name: &str| properties.lines().find_map(|line| line.strip_prefix(&format!("{}=", name))).map(str::to_string);
+  if property("Class").as_deref() != Some("user") { return None; }
+  Some(SessionUser { name: property("Name")?, uid: property("User")?.parse().ok()? })
+}
+
+// Who commands for the desktop run as when Makita runs as root: the user of the active session, or the one that
+// started it through sudo.
+fn desktop_user(environment: &Environment) -> Option<SessionUser> {
+  if !geteuid().is_root() { return None; }
+  active_user().or_else(|| {
+    let name = environment.sudo_user.clone().ok()?;
+    let uid = User::from_name(&name).ok().flatten().map_or(0, |user| user.uid.as_raw());
+    Some(SessionUser { name, uid })
+  })
+}
+
+// The SESSION_VARIABLES and XDG_RUNTIME_DIR of the desktop user when Makita runs as root, read from the environment
+// of one of their processes, preferring those of the active session and then the newest. Empty otherwise, Makita's
+// own environment being the session's. Looked up again once five seconds old or when the user changes.
+pub fn session_environment(environment: &Environment) -> Vec<(String, String)> {
+  let Some(user) = desktop_user(environment).filter(|user| user.uid != 0) else { return Vec::new() };
+  let mut cached = SESSION_ENVIRONMENT.lock().unwrap();
+  if let Some((checked, uid, variables)) = cached.as_ref() {
+    if *uid == user.uid && checked.elapsed() < Duration::from_secs(5) { return variables.clone(); }
+  }
+  let mut variables = vec![("XDG_RUNTIME_DIR".to_string(), format!("/run/user/{}", user.uid))];
+  variables.extend(process_environment(user.uid, active_session().as_deref()));
+  *cached = Some((Instant::now(), user.uid, variables.clone()));
+  variables
+}
+
+// A variable the compositor or X server of the desktop is reached through, from the active session when Makita runs
+// as root, or from Makita's own environment.
+pub fn session_variable(environment: &Environment, name: &str) -> Option<String> {
+  match desktop_user(environment).filter(|user| user.uid != 0) {
+    Some(_) => session_environment(environment).into_iter().find(|(variable, _)| variable == name).map(|(_, value)| value),
+    None => std::env::var(name).ok(),
+  }
+}
+
+fn process_environment(uid: u32, session: Option<&str>) -> Vec<(String, String)> {
+  let scope = session.map(|session| format!("/session-{}.scope", session));
+  let Ok(entries) = fs::read_dir("/proc") else { return Vec::new() };
+  let mut processes: Vec<(bool, u32)> = entries
+    .flatten()
+    .filter_map(|entry| Some((entry.file_name().to_str()?.parse::<u32>().ok()?, entry.metadata().ok()?)))
+    .filter(|(_, metadata)| metadata.uid() == uid)
+    .map(|(pid, _)| {
+      let cgroup = fs::read_to_string(format!("/proc/{}/cgroup", pid)).unwrap_or_default();
+      (scope.as_ref().is_some_and(|scope| cgroup.contains(scope.as_str())), pid)
+    })
+    .collect();
+  processes.sort_unstable_by(|a, b| b.cmp(a));
+  processes
+    .iter()
+    .filter_map(|(_, pid)| fs::read(format!("/proc/{}/environ", pid)).ok())
+    .map(|environ| session_variables(&environ))
+    .find(|variables| variables.iter().any(|(name, _)| name == "WAYLAND_DISPLAY" || name == "DISPLAY"))
+    .unwrap_or_default()
+}
+
+// The SESSION_VARIABLES set in the NUL separated contents of /proc/<pid>/environ.
+fn session_variables(environ: &[u8]) -> Vec<(String, String)> {
+  environ
+    .split(|&byte| byte == 0)
+    .filter_map(|entry| std::str::from_utf8(entry).ok()?.split_once('='))
+    .filter(|(name, value)| SESSION_VARIABLES.contains(name) && !value.is_empty())
+    .map(|(name, value)| (name.to_string(), value.to_string()))
+    .collect()
+}
+
+// The session bus of the desktop user when Makita runs as root, None when it's Makita's own.
+pub fn session_bus_address(environment: &Environment) -> Option<String> {
+  desktop_user(environment).filter(|user| user.uid != 0).map(|user| format!("unix:path=/run/user/{}/bus", user.uid))
+}
+
+// A shell command that runs as the desktop user, on their session bus and with the variables of their session, or
+// as Makita's own user otherwise.
+pub fn desktop_command(environment: &Environment, command: &str) -> std::process::Command {
+  match desktop_user(environment) {
+    Some(user) => {
+      let mut runuser = std::process::Command::new("runuser");
+      runuser.arg(&user.name).arg("-c").arg(command);
+      if let Some(address) = session_bus_address(environment) {
+        runuser.envs(session_environment(environment)).env("DBUS_SESSION_BUS_ADDRESS", address);
+      }
+      runuser
+    }
+    None => {
+      let mut sh = std::process::Command::new("sh");
+      sh.arg("-c").arg(command);
+      sh
+    }
+  }
+}
+
+pub fn is_locked() -> bool {
+  LOCKED.load(Ordering::Relaxed)
+}
+
+// Follows logind's Lock/Unlock session signals through dbus-monitor.
+pub async fn monitor_lock_signals() {
+  let child = Command::new("dbus-monitor")
+    .arg("--system")
+    .arg("type='signal',interface='org.freedesktop.login1.Session'")
+    .stdout(Stdio::piped())
+    .stderr(Stdio::null())
+    .kill_on_drop(true)
+    .spawn();
+
+  let mut child = match child {
+    Ok(child) => child,
+    Err(e) => {
+      println!("[Session] Unable to run dbus-monitor, screen lock awareness disabled: {}", e);
+      return;
+    }
+  };
+
+  let Some(stdout) = child.stdout.take() else { return };
+  let mut lines = BufReader::new(stdout).lines();
+  while let Ok(Some(line)) = lines.next_line().await {
+    if !line.contains("member=Lock") && !line.contains("member=Unlock") { continue; }
+    // Only the lock screen of the session in the foreground counts, not those of other seats or users. Without
+    // logind telling which one that is, every session does.
+    let path = line.split("path=").nth(1).and_then(|rest| rest.split(';').next()).unwrap_or_default();
+    let active = tokio::task::spawn_blocking(active_session).await.ok().flatten();
+    if active.is_some_and(|session| session_path(&session) != path) { continue; }
+    if line.contains("member=Lock") {
+      println!("[Session] Session locked.");
+      LOCKED.store(true, Ordering::Relaxed);
+    } else if line.contains("member=Unlock") {
+      println!("[Session] Session unlocked.");
+      LOCKED.store(false, Ordering::Relaxed);
+    }
+  }
+
+  println!("[Session] dbus-monitor exited, screen lock awareness disabled.");
+  LOCKED.store(false, Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn escapes_session_paths_like_logind() {
+    assert_eq!(session_path("2"), "/org/freedesktop/login1/session/_32");
+    assert_eq!(session_path("c1"), "/org/freedesktop/login1/session/c1");
+    assert_eq!(session_path("12"), "/org/freedesktop/login1/session/_312");
+  }
+
+  #[test]
+  fn reads_the_variables_of_a_session_process() {
+    let environ = b"HOME=/home/ann\0WAYLAND_DISPLAY=wayland-1\0SWAYSOCK=/run/user/1000/sway-ipc.1000.812.sock\0DISPLAY=\0";
+    assert_eq!(
+      session_variables(environ),
+      vec![
+        ("WAYLAND_DISPLAY".to_string(), "wayland-1".to_string()),
+        ("SWAYSOCK".to_string(), "/run/user/1000/sway-ipc.1000.812.sock".to_string()),
+      ]
+    );
+  }
+}
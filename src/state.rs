@@ -0,0 +1,531 @@
+use crate::config::{BindingInfo, Event, LayoutTarget};
+use crate::profiles;
+use crate::stats::{self, BindingStats, ScriptStats};
+use evdev::{Key, LedType};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::{
+  collections::{HashMap, HashSet, VecDeque},
+  sync::{Arc, Mutex},
+  time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::broadcast;
+
+lazy_static! {
+  static ref MODIFIERS: Mutex<Option<Arc<Mutex<Vec<Event>>>>> = Mutex::new(None);
+  static ref TOGGLES: Mutex<HashMap<String, Vec<Key>>> = Mutex::new(HashMap::new());
+  static ref BINDINGS: Mutex<HashMap<String, Vec<BindingInfo>>> = Mutex::new(HashMap::new());
+  static ref SYNTHETIC_KEYS: Mutex<Vec<Key>> = Mutex::new(Vec::new());
+  static ref LAST_INPUT: Mutex<Instant> = Mutex::new(Instant::now());
+  static ref RELEASE_REQUESTS: broadcast::Sender<()> = broadcast::channel(4).0;
+  static ref DISABLED_DEVICES: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+  static ref ENABLED_CHANGES: broadcast::Sender<()> = broadcast::channel(16).0;
+  static ref LAYOUTS: Mutex<HashMap<String, DeviceLayouts>> = Mutex::new(HashMap::new());
+  static ref LAYOUT_REQUESTS: broadcast::Sender<(String, LayoutTarget)> = broadcast::channel(16).0;
+  static ref DEVICES: Mutex<HashMap<String, DeviceStatus>> = Mutex::new(HashMap::new());
+  static ref DEVICE_PROBLEMS: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
+  static ref PHYSICAL_KEYS: Mutex<HashMap<String, Vec<Key>>> = Mutex::new(HashMap::new());
+  static ref VIRTUAL_KEYS: Mutex<Vec<Key>> = Mutex::new(Vec::new());
+  static ref RECENT_EVENTS: Mutex<VecDeque<RecentEvent>> = Mutex::new(VecDeque::new());
+  static ref INHIBITED_UNTIL: Mutex<Option<SystemTime>> = Mutex::new(None);
+  static ref LOCK_KEY_PRESSES: broadcast::Sender<LedType> = broadcast::channel(16).0;
+  static ref LOCKS: Mutex<Option<Vec<LedType>>> = Mutex::new(None);
+}
+
+// How many physical key events `makitactl state` shows.
+const RECENT_EVENT_COUNT: usize = 50;
+// How long do-not-disturb lasts when no duration is given.
+pub const DEFAULT_INHIBIT_MINUTES: u64 = 60;
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum StateQuery {
+  Modifiers,
+  Toggles,
+  Bindings,
+  SyntheticKeys,
+  Stats,
+  Layouts,
+  Devices,
+  Locks,
+  Snapshot,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum StateResponse {
+  ModifierState { modifiers: Vec<HeldModifier> },
+  ToggleState { toggles: HashMap<String, Vec<u16>> },
+  BindingList { bindings: HashMap<String, Vec<BindingInfo>> },
+  SyntheticKeyState { keys: Vec<u16> },
+  Statistics { bindings: HashMap<String, Vec<BindingStats>>, scripts: Vec<ScriptStats> },
+  LayoutState { layouts: HashMap<String, DeviceLayouts> },
+  DeviceList { devices: HashMap<String, DeviceStatus>, disabled: Vec<String>, problems: HashMap<String, String> },
+  LockState { num_lock: bool, caps_lock: bool, scroll_lock: bool },
+  Snapshot(Box<Snapshot>),
+}
+
+// A held modifier by name, e.g. { "kind": "key", "name": "KEY_LEFTCTRL", "code": 29 }, or { "kind": "axis",
+// "name": "BTN_TL2" } for the custom modifiers that aren't keys, which have no key code.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct HeldModifier {
+  pub kind: ModifierKind,
+  pub name: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub code: Option<u16>,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ModifierKind {
+  Key,
+  Axis,
+}
+
+impl HeldModifier {
+  pub fn new(modifier: &Event) -> Option<Self> {
+    match modifier {
+      Event::Key(key) => Some(Self { kind: ModifierKind::Key, name: format!("{:?}", key), code: Some(key.code()) }),
+      Event::Axis(axis) => Some(Self { kind: ModifierKind::Axis, name: axis.name(), code: None }),
+      Event::Hold => None,
+    }
+  }
+}
+
+// Everything that can leave a key stuck, for `makitactl state`. Keys are given by name.
+#[derive(Serialize, Debug)]
+pub struct Snapshot {
+  pub profile: Option<String>,
+  pub modifiers: Vec<String>,
+  pub virtual_keys: Vec<String>,
+  pub synthetic_keys: Vec<String>,
+  pub physical_keys: HashMap<String, Vec<String>>,
+  pub toggles: HashMap<String, Vec<String>>,
+  pub layers: HashMap<String, String>,
+  pub recent_events: Vec<RecentEvent>,
+  pub inhibited_until: Option<u64>,
+}
+
+// A key event read from a physical device, with when it was read in milliseconds since the Unix epoch.
+#[derive(Serialize, Clone, Debug)]
+pub struct RecentEvent {
+  pub device: String,
+  pub key: String,
+  pub value: i32,
+  pub time: u64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Layout {
+  pub number: u16,
+  pub name: String,
+}
+
+// The layouts a device's configs declare, and the one it's on.
+#[derive(Serialize, Clone, Debug)]
+pub struct DeviceLayouts {
+  pub active: u16,
+  pub layouts: Vec<Layout>,
+}
+
+// Whether a configured device is plugged in. Devices with WAIT_FOR_DEVICE are waiting until they connect
+// or until their timeout passes, and absent afterwards.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceStatus {
+  Waiting,
+  Connected,
+  Absent,
+}
+
+// The modifiers are shared by every device, so the udev monitor registers them once, before launching any reader.
+pub fn register_modifiers(modifiers: Arc<Mutex<Vec<Event>>>) {
+  *MODIFIERS.lock().unwrap() = Some(modifiers);
+}
+
+pub fn held_modifiers() -> Vec<Event> {
+  MODIFIERS.lock().unwrap().as_ref().map(|modifiers| modifiers.lock().unwrap().clone()).unwrap_or_default()
+}
+
+// When any device last sent an event, since modifiers are shared by every device.
+pub fn touch_input() {
+  *LAST_INPUT.lock().unwrap() = Instant::now();
+}
+
+pub fn last_input() -> Instant {
+  *LAST_INPUT.lock().unwrap()
+}
+
+// Asks every reader to release what it holds, for `makitactl release-all`. Returns how many readers got it.
+pub fn request_release_all() -> usize {
+  RELEASE_REQUESTS.send(()).unwrap_or_default()
+}
+
+pub fn release_requests() -> broadcast::Receiver<()> {
+  RELEASE_REQUESTS.subscribe()
+}
+
+// Devices whose config is paused: they're ungrabbed and their input goes through as is until enabled again.
+pub fn set_device_enabled(device: &str, enabled: bool) {
+  let changed = match enabled {
+    true => DISABLED_DEVICES.lock().unwrap().remove(device),
+    false => DISABLED_DEVICES.lock().unwrap().insert(device.to_string()),
+  };
+  if changed { let _ = ENABLED_CHANGES.send(()); }
+}
+
+pub fn device_enabled(device: &str) -> bool {
+  !DISABLED_DEVICES.lock().unwrap().contains(device)
+}
+
+pub fn enabled_changes() -> broadcast::Receiver<()> {
+  ENABLED_CHANGES.subscribe()
+}
+
+// Do-not-disturb, e.g. while sharing the screen: [actions], [rubies] and tap-hold commands are bypassed, and their
+// keys only remap, for the given number of minutes. None is DEFAULT_INHIBIT_MINUTES, 0 or less stops it.
+pub fn inhibit(minutes: Option<f64>) {
+  let minutes = minutes.unwrap_or(DEFAULT_INHIBIT_MINUTES as f64);
+  let mut until = INHIBITED_UNTIL.lock().unwrap();
+  match minutes > 0.0 {
+    true => {
+      *until = Some(SystemTime::now() + Duration::from_secs_f64(minutes * 60.0));
+      println!("[State] Bypassing actions and scripts for {} minute(s).", minutes);
+    }
+    false => {
+      if until.take().is_some() { println!("[State] Running actions and scripts again."); }
+    }
+  }
+}
+
+// Expires on its own once the time is up.
+pub fn inhibited_until() -> Option<SystemTime> {
+  let mut until = INHIBITED_UNTIL.lock().unwrap();
+  if until.is_some_and(|until| until <= SystemTime::now()) {
+    *until = None;
+    println!("[State] Do-not-disturb expired, running actions and scripts again.");
+  }
+  *until
+}
+
+pub fn inhibited() -> bool {
+  inhibited_until().is_some()
+}
+
+// Keys held down on the virtual keyboard by Ruby scripts and hold() actions rather than by a physical key.
+pub fn set_synthetic_key(key: Key, value: i32) {
+  let mut keys = SYNTHETIC_KEYS.lock().unwrap();
+  match value {
+    0 => keys.retain(|&held| held != key),
+    1 if !keys.contains(&key) => keys.push(key),
+    _ => {}
+  }
+}
+
+pub fn synthetic_keys() -> Vec<Key> {
+  SYNTHETIC_KEYS.lock().unwrap().clone()
+}
+
+pub fn take_synthetic_keys() -> Vec<Key> {
+  std::mem::take(&mut *SYNTHETIC_KEYS.lock().unwrap())
+}
+
+pub fn set_toggles(device: &str, keys: &[Key]) {
+  let mut toggles = TOGGLES.lock().unwrap();
+  if keys.is_empty() {
+    toggles.remove(device);
+  } else {
+    toggles.insert(device.to_string(), keys.to_vec());
+  }
+}
+
+pub fn bindings() -> HashMap<String, Vec<BindingInfo>> {
+  BINDINGS.lock().unwrap().clone()
+}
+
+pub fn set_layouts(device: &str, layouts: Option<DeviceLayouts>) {
+  match layouts {
+    Some(layouts) => LAYOUTS.lock().unwrap().insert(device.to_string(), layouts),
+    None => LAYOUTS.lock().unwrap().remove(device),
+  };
+}
+
+// Switches the device, or every device that has it, to a layout picked by number or by name, like a layout() action.
+pub fn request_layout(device: Option<&str>, layout: &str) -> Result<(), String> {
+  let target = match layout.parse::<u16>() {
+    Ok(number) => LayoutTarget::Number(number),
+    Err(_) => LayoutTarget::Name(layout.to_string()),
+  };
+  let layouts = LAYOUTS.lock().unwrap();
+  let has_layout = |device_layouts: &DeviceLayouts| device_layouts.layouts.iter().any(|candidate| match &target {
+    LayoutTarget::Number(number) => candidate.number == *number,
+    LayoutTarget::Name(name) => candidate.name == *name,
+  });
+  let mut devices: Vec<&String> = layouts.iter()
+    .filter(|(name, device_layouts)| device.is_none_or(|device| device == name.as_str()) && has_layout(device_layouts))
+    .map(|(name, _)| name)
+    .collect();
+  devices.sort();
+  match (devices.is_empty(), device) {
+    (true, Some(device)) if !layouts.contains_key(device) => return Err(format!("{} isn't connected", device)),
+    (true, Some(device)) => return Err(format!("{} has no layout {}", device, layout)),
+    (true, None) => return Err(format!("no connected device has a layout {}", layout)),
+    _ => {}
+  }
+  for device in devices {
+    let _ = LAYOUT_REQUESTS.send((device.clone(), target.clone()));
+  }
+  Ok(())
+}
+
+pub fn layout_requests() -> broadcast::Receiver<(String, LayoutTarget)> {
+  LAYOUT_REQUESTS.subscribe()
+}
+
+// Returns the previous status, if the device had one.
+pub fn set_device_status(device: &str, status: DeviceStatus) -> Option<DeviceStatus> {
+  DEVICES.lock().unwrap().insert(device.to_string(), status)
+}
+
+pub fn device_status(device: &str) -> Option<DeviceStatus> {
+  DEVICES.lock().unwrap().get(device).copied()
+}
+
+pub fn connected_devices() -> Vec<String> {
+  let mut devices: Vec<String> = DEVICES.lock().unwrap().iter()
+    .filter(|(_, status)| **status == DeviceStatus::Connected)
+    .map(|(device, _)| device.clone())
+    .collect();
+  devices.sort();
+  devices
+}
+
+// What's wrong with a connected device, e.g. a lost grab. Returns whether that changed.
+pub fn set_device_problem(device: &str, problem: Option<String>) -> bool {
+  let mut problems = DEVICE_PROBLEMS.lock().unwrap();
+  match problem {
+    Some(problem) => problems.insert(device.to_string(), problem.clone()).as_ref() != Some(&problem),
+    None => problems.remove(device).is_some(),
+  }
+}
+
+// Keys held on the physical devices, and the last presses and releases, to tell a key Makita holds down from one
+// whose release never came from the device.
+pub fn record_physical_key(device: &str, key: Key, value: i32) {
+  let mut physical_keys = PHYSICAL_KEYS.lock().unwrap();
+  let keys = physical_keys.entry(device.to_string()).or_default();
+  match value {
+    0 => keys.retain(|&held| held != key),
+    1 if !keys.contains(&key) => keys.push(key),
+    _ => {}
+  }
+  if value == 2 { return; }
+  let mut recent_events = RECENT_EVENTS.lock().unwrap();
+  if recent_events.len() == RECENT_EVENT_COUNT { recent_events.pop_front(); }
+  let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+  recent_events.push_back(RecentEvent { device: device.to_string(), key: format!("{:?}", key), value, time });
+}
+
+pub fn clear_physical_keys(device: &str) {
+  PHYSICAL_KEYS.lock().unwrap().remove(device);
+}
+
+// Keys held on any physical device.
+pub fn held_physical_keys() -> Vec<Key> {
+  PHYSICAL_KEYS.lock().unwrap().values().flatten().copied().collect()
+}
+
+// The compositor can't set the LEDs of grabbed devices, so readers follow the lock keys pressed on the virtual
+// keyboard and set them instead.
+pub fn lock_key_presses() -> broadcast::Receiver<LedType> {
+  LOCK_KEY_PRESSES.subscribe()
+}
+
+pub fn lock_key(led: LedType) -> Option<Key> {
+  match led {
+    LedType::LED_NUML => Some(Key::KEY_NUMLOCK),
+    LedType::LED_CAPSL => Some(Key::KEY_CAPSLOCK),
+    LedType::LED_SCROLLL => Some(Key::KEY_SCROLLLOCK),
+    _ => None,
+  }
+}
+
+pub fn parse_lock(name: &str) -> Option<LedType> {
+  match name {
+    "num_lock" => Some(LedType::LED_NUML),
+    "caps_lock" => Some(LedType::LED_CAPSL),
+    "scroll_lock" => Some(LedType::LED_SCROLLL),
+    _ => None,
+  }
+}
+
+// The lock state of the session, which Makita tracks itself since grabbed devices don't hear about it anymore.
+// It's the LEDs of the first device to connect until a lock key is pressed on the virtual keyboard.
+pub fn locks() -> Vec<LedType> {
+  LOCKS.lock().unwrap().clone().unwrap_or_default()
+}
+
+pub fn lock_on(led: LedType) -> bool {
+  locks().contains(&led)
+}
+
+// Called with the LEDs of a device as it connects. Returns the state to set them to, so that a device plugged in
+// again shows the locks as they are rather than as they were.
+pub fn sync_locks(device_leds: Vec<LedType>) -> Vec<LedType> {
+  LOCKS.lock().unwrap().get_or_insert(device_leds).clone()
+}
+
+// The compositor sets the LEDs of devices that aren't grabbed.
+pub fn set_lock(led: LedType, on: bool) {
+  let mut locks = LOCKS.lock().unwrap();
+  let locks = locks.get_or_insert_with(Vec::new);
+  locks.retain(|&lit| lit != led);
+  if on { locks.push(led); }
+}
+
+// The lock key to tap for the lock to be on, off, or toggled with None, if it isn't already.
+pub fn lock_tap(led: LedType, on: Option<bool>) -> Option<Key> {
+  lock_key(led).filter(|_| on.is_none_or(|on| on != lock_on(led)))
+}
+
+// Keys held down on the virtual devices, whatever pressed them.
+pub fn set_virtual_key(key: Key, value: i32) {
+  let led = [LedType::LED_NUML, LedType::LED_CAPSL, LedType::LED_SCROLLL].into_iter().find(|&led| lock_key(led) == Some(key));
+  if let Some(led) = led.filter(|_| value == 1) {
+    set_lock(led, !lock_on(led));
+    let _ = LOCK_KEY_PRESSES.send(led);
+  }
+  let mut keys = VIRTUAL_KEYS.lock().unwrap();
+  match value {
+    0 => keys.retain(|&held| held != key),
+    1 if !keys.contains(&key) => keys.push(key),
+    _ => {}
+  }
+}
+
+pub fn virtual_keys() -> Vec<Key> {
+  VIRTUAL_KEYS.lock().unwrap().clone()
+}
+
+// Bindings of the config each device is currently using.
+pub fn set_bindings(device: &str, bindings: &[BindingInfo]) {
+  let mut all_bindings = BINDINGS.lock().unwrap();
+  if bindings.is_empty() {
+    all_bindings.remove(device);
+  } else {
+    all_bindings.insert(device.to_string(), bindings.to_vec());
+  }
+}
+
+pub fn query(query: StateQuery) -> StateResponse {
+  match query {
+    StateQuery::Modifiers => StateResponse::ModifierState { modifiers: held_modifiers().iter().filter_map(HeldModifier::new).collect() },
+    StateQuery::Toggles => StateResponse::ToggleState {
+      toggles: TOGGLES.lock().unwrap().iter()
+        .map(|(device, keys)| (device.clone(), keys.iter().map(|key| key.code()).collect()))
+        .collect(),
+    },
+    StateQuery::Bindings => StateResponse::BindingList { bindings: BINDINGS.lock().unwrap().clone() },
+    StateQuery::SyntheticKeys => StateResponse::SyntheticKeyState { keys: synthetic_keys().iter().map(|key| key.code()).collect() },
+    StateQuery::Stats => StateResponse::Statistics { bindings: stats::binding_stats(), scripts: stats::script_stats() },
+    StateQuery::Layouts => StateResponse::LayoutState { layouts: LAYOUTS.lock().unwrap().clone() },
+    StateQuery::Devices => StateResponse::DeviceList {
+      devices: DEVICES.lock().unwrap().clone(),
+      disabled: DISABLED_DEVICES.lock().unwrap().iter().cloned().collect(),
+      problems: DEVICE_PROBLEMS.lock().unwrap().clone(),
+    },
+    StateQuery::Locks => StateResponse::LockState {
+      num_lock: lock_on(LedType::LED_NUML),
+      caps_lock: lock_on(LedType::LED_CAPSL),
+      scroll_lock: lock_on(LedType::LED_SCROLLL),
+    },
+    StateQuery::Snapshot => snapshot(),
+  }
+}
+
+fn key_names(keys: &[Key]) -> Vec<String> {
+  keys.iter().map(|key| format!("{:?}", key)).collect()
+}
+
+fn snapshot() -> StateResponse {
+  let modifiers = held_modifiers().iter().filter_map(HeldModifier::new).map(|modifier| modifier.name).collect();
+  let layers = LAYOUTS.lock().unwrap().iter().map(|(device, layouts)| {
+    let name = layouts.layouts.iter().find(|layout| layout.number == layouts.active).map(|layout| layout.name.clone());
+    (device.clone(), name.unwrap_or(layouts.active.to_string()))
+  }).collect();
+  StateResponse::Snapshot(Box::new(Snapshot {
+    profile: profiles::active_profile(),
+    modifiers,
+    virtual_keys: key_names(&VIRTUAL_KEYS.lock().unwrap()),
+    synthetic_keys: key_names(&synthetic_keys()),
+    physical_keys: PHYSICAL_KEYS.lock().unwrap().iter().map(|(device, keys)| (device.clone(), key_names(keys))).collect(),
+    toggles: TOGGLES.lock().unwrap().iter().map(|(device, keys)| (device.clone(), key_names(keys))).collect(),
+    layers,
+    recent_events: RECENT_EVENTS.lock().unwrap().iter().cloned().collect(),
+    inhibited_until: inhibited_until().map(|until| until.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64),
+  }))
+}
+
+// The test binary runs tests in parallel, so those that go through the statics above hold this, which also
+// clears what earlier ones left in them.
+#[cfg(test)]
+pub(crate) fn isolated() -> std::sync::MutexGuard<'static, ()> {
+  static TESTS: Mutex<()> = Mutex::new(());
+  let guard = TESTS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+  LAYOUTS.lock().unwrap().clear();
+  DEVICES.lock().unwrap().clear();
+  DEVICE_PROBLEMS.lock().unwrap().clear();
+  VIRTUAL_KEYS.lock().unwrap().clear();
+  *LOCKS.lock().unwrap() = None;
+  guard
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::config::Axis;
+  use evdev::AbsoluteAxisType;
+
+  #[test]
+  fn names_held_modifiers() {
+    let key = HeldModifier::new(&Event::Key(Key::KEY_LEFTCTRL)).unwrap();
+    assert_eq!((key.kind, key.name.as_str(), key.code), (ModifierKind::Key, "KEY_LEFTCTRL", Some(29)));
+    let axis = HeldModifier::new(&Event::Axis(Axis::BTN_TL2)).unwrap();
+    assert_eq!((axis.kind, axis.name.as_str(), axis.code), (ModifierKind::Axis, "BTN_TL2", None));
+    assert_eq!(HeldModifier::new(&Event::Axis(Axis::ABS_PLUS(AbsoluteAxisType::ABS_THROTTLE.0))).unwrap().name, "ABS_THROTTLE_PLUS");
+    assert_eq!(serde_json::to_string(&axis).unwrap(), r#"{"kind":"axis","name":"BTN_TL2"}"#);
+  }
+
+  #[test]
+  fn tracks_lock_state_across_devices() {
+    let _state = isolated();
+    // The first keyboard tells the state, which the virtual keyboard's lock keys flip and later keyboards are set to.
+    assert_eq!(sync_locks(vec![LedType::LED_NUML]), vec![LedType::LED_NUML]);
+    set_virtual_key(Key::KEY_CAPSLOCK, 1);
+    set_virtual_key(Key::KEY_CAPSLOCK, 0);
+    assert_eq!(sync_locks(Vec::new()), vec![LedType::LED_NUML, LedType::LED_CAPSL]);
+    assert_eq!(lock_tap(LedType::LED_CAPSL, Some(true)), None);
+    assert_eq!(lock_tap(LedType::LED_CAPSL, Some(false)), Some(Key::KEY_CAPSLOCK));
+    assert_eq!(lock_tap(LedType::LED_SCROLLL, None), Some(Key::KEY_SCROLLLOCK));
+  }
+
+  #[test]
+  fn requests_layouts_by_number_or_name() {
+    let _state = isolated();
+    let layouts = |names: &[&str]| DeviceLayouts {
+      active: 0,
+      layouts: names.iter().enumerate().map(|(number, name)| Layout { number: number as u16, name: name.to_string() }).collect(),
+    };
+    set_layouts("Pad", Some(layouts(&["base", "gaming"])));
+    set_layouts("Keyboard", Some(layouts(&["base", "symbols"])));
+    let mut requests = layout_requests();
+
+    request_layout(None, "gaming").unwrap();
+    assert_eq!(requests.try_recv().unwrap(), ("Pad".to_string(), LayoutTarget::Name("gaming".to_string())));
+    request_layout(Some("Keyboard"), "1").unwrap();
+    assert_eq!(requests.try_recv().unwrap(), ("Keyboard".to_string(), LayoutTarget::Number(1)));
+    assert_eq!(request_layout(Some("Keyboard"), "gaming"), Err("Keyboard has no layout gaming".to_string()));
+    assert_eq!(request_layout(Some("Mouse"), "0"), Err("Mouse isn't connected".to_string()));
+    assert_eq!(request_layout(None, "navigation"), Err("no connected device has a layout navigation".to_string()));
+  }
+}
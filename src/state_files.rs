@@ -0,0 +1,115 @@
+use nix::unistd::{chown, geteuid, User};
+use std::{env, fs, io, os::unix::fs::FileTypeExt, path::Path, path::PathBuf};
+
+// The user Makita runs for when it was started through sudo, whose files it shouldn't leave owned by root.
+fn sudo_user() -> Option<User> {
+  if !geteuid().is_root() { return None; }
+  User::from_name(&env::var("SUDO_USER").ok()?).ok().flatten()
+}
+
+// Hands a file or directory Makita created over to the user that started it through sudo.
+pub fn give_to_user(path: &Path) {
+  if let Some(user) = sudo_user() {
+    if let Err(e) = chown(path, Some(user.uid), Some(user.gid)) {
+      eprintln!("[StateFiles] Unable to give {} to {}: {}", path.display(), user.name, e);
+    }
+  }
+}
+
+// What Makita remembers across restarts goes to $XDG_STATE_HOME/makita, ~/.local/state/makita by default,
+// of the user that started it, including through sudo.
+pub fn state_directory() -> PathBuf {
+  if let Ok(directory) = env::var("XDG_STATE_HOME") {
+    return PathBuf::from(directory).join("makita");
+  }
+  home().join(".local/state/makita")
+}
+
+fn home() -> PathBuf {
+  match (sudo_user(), env::var("HOME")) {
+    (Some(user), _) => user.dir,
+    (None, Ok(home)) => PathBuf::from(home),
+    (None, Err(_)) => PathBuf::from("/root"),
+  }
+}
+
+// Sockets go to $XDG_RUNTIME_DIR, or to /run when Makita runs as root.
+pub fn runtime_path(name: &str) -> PathBuf {
+  match env::var("XDG_RUNTIME_DIR") {
+    Ok(runtime_directory) if !geteuid().is_root() => PathBuf::from(runtime_directory).join(name),
+    _ => PathBuf::from("/run").join(name),
+  }
+}
+
+// Removes the socket a previous run left behind before binding to its path again. Anything else there, e.g. a file
+// an address was mistyped as, stays, and binding fails instead.
+pub fn remove_stale_socket(path: impl AsRef<Path>) {
+  let path = path.as_ref();
+  if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+    let _ = fs::remove_file(path);
+  }
+}
+
+// Reads a state file, falling back to where older versions kept it so nothing is lost when upgrading.
+pub fn read(name: &str, legacy_path: Option<&Path>) -> Option<(PathBuf, String)> {
+  let path = state_directory().join(name);
+  match fs::read_to_string(&path) {
+    Ok(contents) => Some((path, contents)),
+    Err(_) => legacy_path.and_then(|legacy_path| Some((legacy_path.to_path_buf(), fs::read_to_string(legacy_path).ok()?))),
+  }
+}
+
+// Creates the state directory as needed, owned by the user like the files in it. So are the parents created along
+// with it in the home directory, e.g. ~/.local/state on a fresh account, which other programs write to too.
+pub fn create(name: &str) -> io::Result<(PathBuf, fs::File)> {
+  let directory = state_directory();
+  let missing = missing_directories(&directory);
+  fs::create_dir_all(&directory)?;
+  let home = home();
+  for created in missing.iter().rev().filter(|created| **created == directory || (created.starts_with(&home) && **created != home)) {
+    give_to_user(created);
+  }
+  let path = directory.join(name);
+  let file = fs::File::create(&path)?;
+  give_to_user(&path);
+  Ok((path, file))
+}
+
+// The directory and those of its parents that don't exist yet, deepest first.
+fn missing_directories(directory: &Path) -> Vec<PathBuf> {
+  directory.ancestors().take_while(|ancestor| !ancestor.exists()).map(Path::to_path_buf).collect()
+}
+
+pub fn write(name: &str, contents: &str) -> io::Result<PathBuf> {
+  let (path, mut file) = create(name)?;
+  io::Write::write_all(&mut file, contents.as_bytes())?;
+  Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn finds_the_parents_that_have_to_be_created() {
+    let root = env::temp_dir().join(format!("makita-state-files-{}", std::process::id()));
+    fs::create_dir_all(root.join("home")).unwrap();
+    let directory = root.join("home/.local/state/makita");
+    assert_eq!(missing_directories(&directory), vec![directory.clone(), root.join("home/.local/state"), root.join("home/.local")]);
+    fs::remove_dir_all(&root).unwrap();
+  }
+
+  #[test]
+  fn only_removes_sockets_before_binding() {
+    let root = env::temp_dir().join(format!("makita-sockets-{}", std::process::id()));
+    fs::create_dir_all(&root).unwrap();
+    let (socket, file) = (root.join("makita.sock"), root.join("notes.txt"));
+    drop(std::os::unix::net::UnixListener::bind(&socket).unwrap());
+    fs::write(&file, "keep me").unwrap();
+    remove_stale_socket(&socket);
+    remove_stale_socket(&file);
+    assert!(!socket.exists());
+    assert_eq!(fs::read_to_string(&file).unwrap(), "keep me");
+    fs::remove_dir_all(&root).unwrap();
+  }
+}
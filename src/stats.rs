@@ -0,0 +1,123 @@
+use crate::config::Event;
+use crate::event_tap::event_name;
+use crate::state;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use std::{
+  collections::HashMap,
+  sync::Mutex,
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+// Tables whose bindings fire through the event reader, so that bindings of other tables (e.g. tap_hold)
+// aren't reported as dead.
+const COUNTED_TABLES: [&str; 5] = ["remap", "overrides", "movements", "actions", "rubies"];
+
+#[derive(Default, Clone, Debug)]
+struct Counter {
+  count: u64,
+  last_fired: u64,
+  total_latency: Duration,
+}
+
+impl Counter {
+  fn record(&mut self, latency: Duration) {
+    self.count += 1;
+    self.last_fired = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+    self.total_latency += latency;
+  }
+
+  fn average_latency_us(&self) -> Option<u64> {
+    (self.count > 0).then(|| (self.total_latency / self.count as u32).as_micros() as u64)
+  }
+}
+
+// (device, table, input, modifiers)
+type BindingKey = (String, String, String, Vec<String>);
+
+lazy_static! {
+  static ref BINDINGS: Mutex<HashMap<BindingKey, Counter>> = Mutex::new(HashMap::new());
+  static ref SCRIPTS: Mutex<HashMap<String, Counter>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct BindingStats {
+  pub table: String,
+  pub input: String,
+  pub modifiers: Vec<String>,
+  pub count: u64,
+  pub last_fired: Option<u64>,
+  pub average_latency_us: Option<u64>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ScriptStats {
+  pub script: String,
+  pub count: u64,
+  pub last_fired: Option<u64>,
+  pub average_latency_us: Option<u64>,
+}
+
+// Chained bindings are held under the HOLD modifier but written as "-KEY", so they're counted without it.
+// Modifier names are sorted so that they match however the binding was written.
+pub fn record_binding(device: &str, table: &str, event: &Event, modifiers: &[Event], latency: Duration) {
+  let mut modifiers: Vec<String> = modifiers.iter().filter(|modifier| **modifier != Event::Hold).map(event_name).collect();
+  modifiers.sort();
+  let key = (device.to_string(), table.to_string(), event_name(event), modifiers);
+  BINDINGS.lock().unwrap().entry(key).or_default().record(latency);
+}
+
+pub fn record_script(script: &str, latency: Duration) {
+  SCRIPTS.lock().unwrap().entry(script.to_string()).or_default().record(latency);
+}
+
+// Bindings of the configs in use, fired or not, followed by bindings that fired under a config that's no
+// longer in use.
+pub fn binding_stats() -> HashMap<String, Vec<BindingStats>> {
+  let counters = BINDINGS.lock().unwrap().clone();
+  let stats = |counter: Option<&Counter>, table: &str, input: &str, modifiers: &[String]| {
+    let counter = counter.cloned().unwrap_or_default();
+    BindingStats {
+      table: table.to_string(),
+      input: input.to_string(),
+      modifiers: modifiers.to_vec(),
+      count: counter.count,
+      last_fired: (counter.count > 0).then_some(counter.last_fired),
+      average_latency_us: counter.average_latency_us(),
+    }
+  };
+
+  let mut devices: HashMap<String, Vec<BindingStats>> = HashMap::new();
+  for (device, bindings) in state::bindings() {
+    let list = devices.entry(device.clone()).or_default();
+    for binding in bindings.iter().filter(|binding| COUNTED_TABLES.contains(&binding.table.as_str())) {
+      let mut modifiers = binding.modifiers.clone();
+      modifiers.sort();
+      let key = (device.clone(), binding.table.clone(), binding.input.clone(), modifiers);
+      list.push(stats(counters.get(&key), &binding.table, &binding.input, &binding.modifiers));
+    }
+  }
+  for ((device, table, input, modifiers), counter) in counters.iter() {
+    let list = devices.entry(device.clone()).or_default();
+    let listed = list.iter().any(|binding| {
+      let mut listed_modifiers = binding.modifiers.clone();
+      listed_modifiers.sort();
+      (&binding.table, &binding.input, &listed_modifiers) == (table, input, modifiers)
+    });
+    if !listed {
+      list.push(stats(Some(counter), table, input, modifiers));
+    }
+  }
+  devices
+}
+
+pub fn script_stats() -> Vec<ScriptStats> {
+  let mut scripts: Vec<ScriptStats> = SCRIPTS.lock().unwrap().iter().map(|(script, counter)| ScriptStats {
+    script: script.clone(),
+    count: counter.count,
+    last_fired: Some(counter.last_fired),
+    average_latency_us: counter.average_latency_us(),
+  }).collect();
+  scripts.sort_by(|a, b| a.script.cmp(&b.script));
+  scripts
+}
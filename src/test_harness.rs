@@ -0,0 +1,145 @@
+// End-to-end test support: a fake physical device is created through uinput, picked up by the same
+// device matching the daemon uses, and whatever the EventReader emits on Makita's virtual devices is
+// recorded so that tests can assert on it. Needs write access to /dev/uinput and read access to
+// /dev/input, and tests should run with --test-threads=1 since modifiers and profiles are global.
+use crate::state;
+use crate::udev_monitor::{launch_tasks, Environment, Server};
+use crate::virtual_devices::{Target, VirtualDevices};
+use crate::Config;
+use evdev::{
+  uinput::{VirtualDevice, VirtualDeviceBuilder},
+  AttributeSet, Device, EventType, InputEvent, Key, RelativeAxisType,
+};
+use std::{
+  env, fs, io,
+  path::PathBuf,
+  sync::{mpsc, Arc, Mutex},
+  thread::{self, JoinHandle},
+  time::{Duration, Instant},
+};
+
+pub struct Harness {
+  source: VirtualDevice,
+  output: mpsc::Receiver<InputEvent>,
+  config_directory: PathBuf,
+  _virtual_devices: VirtualDevices,
+  _tasks: Vec<JoinHandle<()>>,
+}
+
+impl Harness {
+  // Starts a reader for a fake device called `name`, mapped by the given config file content.
+  pub fn new(name: &str, config: &str) -> io::Result<Self> {
+    let config_directory = env::temp_dir().join(format!("makita-harness-{}-{}", std::process::id(), name.replace(' ', "_")));
+    fs::create_dir_all(&config_directory)?;
+    let config_path = config_directory.join(format!("{}.toml", name));
+    fs::write(&config_path, config)?;
+    let config = Config::new_from_file(config_path.to_str().unwrap(), name.to_string())
+      .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let mut keys = AttributeSet::new();
+    for code in 1..334 { keys.insert(Key(code)); }
+    let mut relative_axes = AttributeSet::new();
+    for axis in [RelativeAxisType::REL_X, RelativeAxisType::REL_Y, RelativeAxisType::REL_WHEEL, RelativeAxisType::REL_HWHEEL] {
+      relative_axes.insert(axis);
+    }
+    let mut source = VirtualDeviceBuilder::new()?
+      .name(name)
+      .with_keys(&keys)?
+      .with_relative_axes(&relative_axes)?
+      .build()?;
+    wait_for_nodes(|| Ok(source.enumerate_dev_nodes_blocking()?.filter_map(Result::ok).collect()))?;
+
+    let virtual_devices = VirtualDevices::new();
+    let (sender, output) = mpsc::channel();
+    let keyboard = wait_for_nodes(|| virtual_devices.dev_nodes(Target::Keyboard))?;
+    for path in keyboard.into_iter().chain(wait_for_nodes(|| virtual_devices.dev_nodes(Target::Pointer))?) {
+      let mut device = Device::open(path)?;
+      let sender = sender.clone();
+      thread::spawn(move || {
+        while let Ok(events) = device.fetch_events() {
+          for event in events.filter(|event| event.event_type() != EventType::SYNCHRONIZATION) {
+            if sender.send(event).is_err() { return; }
+          }
+        }
+      });
+    }
+
+    let environment = Environment {
+      user: env::var("USER"),
+      sudo_user: Err(env::VarError::NotPresent),
+      server: Server::Unsupported,
+    };
+    let mut tasks = Vec::new();
+    let modifiers = Arc::new(Mutex::new(Vec::new()));
+    state::register_modifiers(modifiers.clone());
+    launch_tasks(&[config], &mut tasks, virtual_devices.clone(), None, environment, modifiers, Arc::new(Mutex::new(true)));
+    if tasks.is_empty() {
+      return Err(io::Error::new(io::ErrorKind::NotFound, format!("no reader started for {}", name)));
+    }
+    // Gives the reader time to grab the device before the first event.
+    thread::sleep(Duration::from_millis(200));
+
+    Ok(Self {
+      source,
+      output,
+      config_directory,
+      _virtual_devices: virtual_devices,
+      _tasks: tasks,
+    })
+  }
+
+  pub fn emit(&mut self, event_type: EventType, code: u16, value: i32) {
+    self.source.emit(&[InputEvent::new(event_type, code, value)]).unwrap();
+  }
+
+  pub fn press(&mut self, key: Key) {
+    self.emit(EventType::KEY, key.code(), 1);
+  }
+
+  pub fn release(&mut self, key: Key) {
+    self.emit(EventType::KEY, key.code(), 0);
+  }
+
+  pub fn tap(&mut self, key: Key) {
+    self.press(key);
+    self.release(key);
+  }
+
+  // Everything emitted until nothing new arrives for `quiet`, as (type, code, value).
+  pub fn collect(&self, quiet: Duration) -> Vec<(EventType, u16, i32)> {
+    let mut events = Vec::new();
+    while let Ok(event) = self.output.recv_timeout(quiet) {
+      events.push((event.event_type(), event.code(), event.value()));
+    }
+    events
+  }
+
+  // Only the key events, as (key, value).
+  pub fn collect_keys(&self, quiet: Duration) -> Vec<(Key, i32)> {
+    self.collect(quiet).into_iter()
+      .filter(|(event_type, _, _)| *event_type == EventType::KEY)
+      .map(|(_, code, value)| (Key(code), value))
+      .collect()
+  }
+}
+
+impl Drop for Harness {
+  fn drop(&mut self) {
+    let _ = fs::remove_dir_all(&self.config_directory);
+  }
+}
+
+// uinput nodes show up asynchronously, after udev has processed them.
+fn wait_for_nodes(mut dev_nodes: impl FnMut() -> io::Result<Vec<PathBuf>>) -> io::Result<Vec<PathBuf>> {
+  let started = Instant::now();
+  loop {
+    let nodes: Vec<PathBuf> = dev_nodes()?;
+    if !nodes.is_empty() && nodes.iter().all(|node| Device::open(node).is_ok()) {
+      return Ok(nodes);
+    }
+    if started.elapsed() > Duration::from_secs(5) {
+      return Err(io::Error::new(io::ErrorKind::TimedOut, "virtual device node didn't appear"));
+    }
+    thread::sleep(Duration::from_millis(50));
+  }
+}
@@ -1,13 +1,21 @@
-use crate::config::{Associations, Event};
+use crate::capabilities::{unproducible_events, Capabilities};
+use crate::config::{Associations, Event, OUTPUT_BACKENDS};
+use crate::controller_db;
+use crate::event_tap;
 use crate::input_event_handling::event_reader::EventReader;
-use crate::input_event_handling::event_sender::EventSender;
-use crate::virtual_devices::VirtualDevices;
+use crate::input_event_handling::event_sender::release_synthetic_keys;
+use crate::history;
+use crate::notifier;
+use crate::reload;
+use crate::state::{self, DeviceStatus};
+use crate::virtual_devices::{Target, VirtualDevices};
 use crate::Config;
 use evdev::{Device, EventStream};
-use std::{env, path::Path, process, process::Command, sync::Arc, sync::Mutex, thread};
+use std::{env, path::Path, process, process::Command, sync::Arc, sync::Mutex, thread, time::Duration};
 use std::thread::JoinHandle;
 use tokio_stream::StreamExt;
 use tokio::signal;
+use tokio::time::Instant;
 use crate::ruby_runtime::RubyService;
 
 #[derive(Debug, Default, Eq, PartialEq, Hash, Clone)]
@@ -24,6 +32,9 @@ pub enum Server {
   Failed,
 }
 
+// The XDG_CURRENT_DESKTOP of the Wayland compositors whose active window Makita can follow.
+pub const SUPPORTED_COMPOSITORS: [&str; 4] = ["Hyprland", "sway", "KDE", "niri"];
+
 #[derive(Clone)]
 pub struct Environment {
   pub user: Result<String, env::VarError>,
@@ -32,13 +43,22 @@ pub struct Environment {
 }
 
 pub async fn start_monitoring_udev(
-  config_files: Vec<Config>,
-  virtual_devices: Arc<Mutex<VirtualDevices>>,
+  mut config_files: Vec<Config>,
+  virtual_devices: VirtualDevices,
   ruby_service: Option<Arc<Mutex<RubyService>>>
 ) {
   let environment = set_environment();
+  notifier::set_environment(&environment);
+  // Every reader shares the held modifiers, those launched by later udev events and reloads included.
+  let modifiers: Arc<Mutex<Vec<Event>>> = Arc::new(Mutex::new(Default::default()));
+  let modifier_was_activated: Arc<Mutex<bool>> = Arc::new(Mutex::new(true));
+  state::register_modifiers(modifiers.clone());
   let mut tasks: Vec<JoinHandle<()>> = Vec::new();
-  launch_tasks(&config_files, &mut tasks, virtual_devices.clone(), ruby_service.clone(), environment.clone());
+  let waits = wait_deadlines(&config_files);
+  for (device, _) in &waits {
+    state::set_device_status(device, DeviceStatus::Waiting);
+  }
+  launch_tasks(&config_files, &mut tasks, virtual_devices.clone(), ruby_service.clone(), environment.clone(), modifiers.clone(), modifier_was_activated.clone());
 
   let mut monitor = tokio_udev::AsyncMonitorSocket::new(
     tokio_udev::MonitorBuilder::new()
@@ -50,8 +70,16 @@ pub async fn start_monitoring_udev(
   ).unwrap();
 
   let mut sigint = signal::unix::signal(signal::unix::SignalKind::interrupt()).expect("Failed to register SIGINT handler");
+  let mut sighup = signal::unix::signal(signal::unix::SignalKind::hangup()).expect("Failed to register SIGHUP handler");
+  let mut sigusr1 = signal::unix::signal(signal::unix::SignalKind::user_defined1()).expect("Failed to register SIGUSR1 handler");
+  let mut reloads = reload::reloads();
+  let mut stopped_readers = reload::stopped_readers();
 
   loop {
+    let wait_deadline = waits.iter()
+      .filter(|(device, _)| state::device_status(device) == Some(DeviceStatus::Waiting))
+      .filter_map(|(_, deadline)| *deadline)
+      .min();
     tokio::select! {
       // Handle udev events
       event = monitor.next() => {
@@ -60,7 +88,7 @@ pub async fn start_monitoring_udev(
             if is_mapped(&event.device(), &config_files) {
               println!("[UdevMonitor] Reinitializing...");
               tasks.clear();
-              launch_tasks(&config_files, &mut tasks, virtual_devices.clone(), ruby_service.clone(), environment.clone())
+              launch_tasks(&config_files, &mut tasks, virtual_devices.clone(), ruby_service.clone(), environment.clone(), modifiers.clone(), modifier_was_activated.clone())
             }
           }
           Some(Err(e)) => {
@@ -73,8 +101,65 @@ pub async fn start_monitoring_udev(
         }
       }
 
+      _ = tokio::time::sleep_until(wait_deadline.unwrap_or_else(Instant::now)), if wait_deadline.is_some() => {
+        for (device, deadline) in &waits {
+          if deadline.is_some_and(|deadline| deadline <= Instant::now()) && state::device_status(device) == Some(DeviceStatus::Waiting) {
+            println!("[UdevMonitor] {} didn't connect within its WAIT_FOR_DEVICE_TIMEOUT.", device);
+            update_device_status(device, DeviceStatus::Absent);
+          }
+        }
+        let configured = configured_devices(&config_files);
+        if !configured.iter().any(|device| matches!(state::device_status(device), Some(DeviceStatus::Connected | DeviceStatus::Waiting))) {
+          println!("[UdevMonitor] None of the configured devices is connected or awaited, exiting.");
+          release_synthetic_keys(&virtual_devices);
+          virtual_devices.wait_until_written();
+          process::exit(1);
+        }
+      }
+
+      _ = sighup.recv() => {
+        println!("[UdevMonitor] Received SIGHUP, reloading configs...");
+        let _ = reload::reload();
+      }
+
+      _ = sigusr1.recv() => {
+        match history::dump("requested with SIGUSR1") {
+          Ok(path) => println!("[UdevMonitor] Wrote the last events to {}.", path.display()),
+          Err(e) => eprintln!("[UdevMonitor] Unable to write the last events: {}", e),
+        }
+      }
+
+      // Readers pick up the reloaded configs themselves, devices that connect later get them from here. Devices
+      // that just got a config are looked for now.
+      Ok(()) = reloads.recv() => {
+        let previous = configured_devices(&config_files);
+        config_files = reload::configs();
+        let added: Vec<String> = configured_devices(&config_files).into_iter().filter(|device| !previous.contains(device)).collect();
+        if !added.is_empty() {
+          println!("[UdevMonitor] Looking for {}...", added.join(", "));
+          let configs: Vec<Config> = config_files.iter().filter(|config| added.contains(&device_of(config))).cloned().collect();
+          launch_tasks(&configs, &mut tasks, virtual_devices.clone(), ruby_service.clone(), environment.clone(), modifiers.clone(), modifier_was_activated.clone());
+        }
+      }
+
+      // Readers stop when a reload changes how their device connects, or removes its configs.
+      Ok(device) = stopped_readers.recv() => {
+        if reload::take_relaunch(&device) {
+          config_files = reload::configs();
+          let configs: Vec<Config> = config_files.iter().filter(|config| device_of(config) == device).cloned().collect();
+          if configs.is_empty() {
+            update_device_status(&device, DeviceStatus::Absent);
+          } else {
+            println!("[UdevMonitor] Reconnecting {}...", device);
+            launch_tasks(&configs, &mut tasks, virtual_devices.clone(), ruby_service.clone(), environment.clone(), modifiers.clone(), modifier_was_activated.clone());
+          }
+        }
+      }
+
       _ = sigint.recv() => {
         println!("[UdevMonitor] Received SIGINT, shutting down...");
+        release_synthetic_keys(&virtual_devices);
+        virtual_devices.wait_until_written();
         process::exit(0);
       }
     }
@@ -82,14 +167,14 @@ pub async fn start_monitoring_udev(
 }
 
 pub fn launch_tasks(
-  config_files: &Vec<Config>,
+  config_files: &[Config],
   tasks: &mut Vec<JoinHandle<()>>,
-  virtual_devices: Arc<Mutex<VirtualDevices>>,
+  virtual_devices: VirtualDevices,
   ruby_service: Option<Arc<Mutex<RubyService>>>,
   environment: Environment,
+  modifiers: Arc<Mutex<Vec<Event>>>,
+  modifier_was_activated: Arc<Mutex<bool>>,
 ) {
-  let modifiers: Arc<Mutex<Vec<Event>>> = Arc::new(Mutex::new(Default::default()));
-  let modifier_was_activated: Arc<Mutex<bool>> = Arc::new(Mutex::new(true));
   let user_has_access = match Command::new("groups").output() {
     Ok(groups) if std::str::from_utf8(&groups.stdout.as_slice()).unwrap().contains("input") => {
       println!("[UdevMonitor] Evdev permissions available. Scanning for event devices with a matching config file...");
@@ -112,90 +197,234 @@ pub fn launch_tasks(
 
   let devices: evdev::EnumerateDevices = evdev::enumerate();
   let mut devices_found = 0;
+  let mut connected: Vec<String> = Vec::new();
   for device in devices {
     let actual_device_name = device.1.name().unwrap();
-    let mut config_list: Vec<Config> = Vec::new();
-
-    for config in config_files {
-      let split_config_name = config.name.split("::").collect::<Vec<&str>>();
-      let configured_device_name = split_config_name[0];
-
-      if configured_device_name == actual_device_name.replace("/", "") {
-        let (window_class, layout) = match split_config_name.len() {
-          1 => (Client::Default, 0),
-          2 => {
-            if let Ok(layout) = split_config_name[1].parse::<u16>() {
-              (Client::Default, layout)
-            } else {
-              (Client::Class(split_config_name[1].to_string()), 0)
-            }
-          }
-          3 => {
-            if let Ok(layout) = split_config_name[1].parse::<u16>() {
-              (Client::Class(split_config_name[2].to_string()), layout)
-            } else if let Ok(layout) = split_config_name[2].parse::<u16>() {
-              (Client::Class(split_config_name[1].to_string()), layout)
-            } else {
-              println!("[UdevMonitor] Warning: unable to parse layout number in {}, treating it as default.", config.name);
-              (Client::Default, 0)
-            }
-          }
-          _ => {
-            println!("[UdevMonitor] Warning: too many arguments in config file name {}, treating it as default.", config.name);
-            (Client::Default, 0)
-          }
-        };
-
-        let mut device_config = config.clone();
-        device_config.associations.client = window_class;
-        device_config.associations.layout = layout;
-        config_list.push(device_config);
-      };
-    }
-
-    if config_list.len() > 0 && !config_list.iter().any(|x| x.associations == Associations::default()) {
-      config_list.push(Config::new_empty(actual_device_name.to_string()));
-    }
+    let mut config_list = device_configs(config_files, actual_device_name);
 
     let event_device = device.0.as_path().to_str().unwrap().to_string();
     if config_list.len() != 0 {
-      let stream = Arc::new(Mutex::new(get_event_stream(
-        Path::new(&event_device),
-        config_list.clone(),
-      )));
+      // Every udev event launches readers again, for the event nodes that aren't read yet.
+      if reload::reading(&event_device) {
+        connected.push(actual_device_name.replace("/", ""));
+        continue;
+      }
+      let translations = controller_db::device_translations(&device.1);
+      for config in config_list.iter_mut() {
+        config.translate_events(&translations);
+      }
+      let default_settings = &config_list.iter().find(|&x| x.associations == Associations::default()).unwrap().settings;
+      let device_notify = default_settings.get("DEVICE_NOTIFY").is_some_and(|value| value == "true");
+      let stream = match get_event_stream(Path::new(&event_device), config_list.clone()) {
+        Ok(stream) => Arc::new(Mutex::new(stream)),
+        Err(e) => {
+          println!("[UdevMonitor] Unable to grab {} ({}): {}. Is another instance of Makita running?", device.0.to_str().unwrap(), actual_device_name, e);
+          if device_notify {
+            notifier::notify(&environment, &format!("Unable to grab {}", actual_device_name));
+          }
+          continue;
+        }
+      };
+      if device_notify {
+        notifier::notify(&environment, &format!("{} connected", actual_device_name));
+      }
       println!("[UdevMonitor] Constructing reader for {} ({})...", device.0.to_str().unwrap(), actual_device_name);
+      let capabilities = Capabilities::of(&device.1);
+      for config in &config_list {
+        let events = unproducible_events(config, default_settings, &capabilities);
+        if !events.is_empty() {
+          let names: Vec<String> = events.iter().map(event_tap::event_name).collect();
+          println!("[UdevMonitor] Warning: {} binds {}, which {} doesn't report, those bindings will never fire.", config.name, names.join(", "), actual_device_name);
+        }
+      }
+      let own_virtual_devices = match default_settings.get("OUTPUT_BACKEND").map(String::as_str) {
+        Some("wayland") => {
+          println!("[UdevMonitor] Emitting the output of {} through the Wayland compositor.", actual_device_name);
+          Some(VirtualDevices::wayland(&actual_device_name.replace("/", "")))
+        }
+        backend => {
+          if let Some(backend) = backend.filter(|backend| !OUTPUT_BACKENDS.contains(backend)) {
+            println!("[UdevMonitor] Warning: invalid OUTPUT_BACKEND {} for {}, using uinput.", backend, actual_device_name);
+          }
+          default_settings.get("SEPARATE_VIRTUAL_DEVICES").is_some_and(|value| value == "true").then(|| {
+            println!("[UdevMonitor] Creating virtual devices of its own for {}.", actual_device_name);
+            VirtualDevices::for_device(&actual_device_name.replace("/", ""))
+          })
+        }
+      };
+      let uinput = default_settings.get("OUTPUT_BACKEND").is_none_or(|backend| backend != "wayland");
+      let reader_virtual_devices = match own_virtual_devices {
+        Some(mut own) => {
+          if default_settings.get("VIRTUAL_GAMEPAD").is_some_and(|mode| mode == "xpad") {
+            own = own.with_xpad();
+          }
+          if uinput && default_settings.get("FLAT_POINTER").is_some_and(|value| value == "true") {
+            own = own.with_flat_pointer(format!("{} (flat)", Target::Pointer.device_name_for(&actual_device_name.replace("/", ""))));
+          }
+          own
+        }
+        None => virtual_devices.clone(),
+      };
       let reader = EventReader::new(
         config_list.clone(),
-        virtual_devices.clone(),
+        reader_virtual_devices,
         stream,
         modifiers.clone(),
         modifier_was_activated.clone(),
         environment.clone(),
         ruby_service.clone(),
+        &event_device,
       );
 
-      tasks.push(thread::spawn(move || { start_reader(reader); }));
+      let device_name = actual_device_name.replace("/", "");
+      reload::reader_started(&device_name, &event_device);
+      tasks.push(thread::spawn(move || {
+        start_reader(reader);
+        reload::reader_stopped(&device_name, &event_device);
+      }));
       devices_found += 1;
+      connected.push(actual_device_name.replace("/", ""));
     }
   }
 
-  if devices_found == 0 && !user_has_access {
+  let mut waiting: Vec<String> = Vec::new();
+  for device in configured_devices(config_files) {
+    if connected.contains(&device) {
+      update_device_status(&device, DeviceStatus::Connected);
+    } else if state::device_status(&device) == Some(DeviceStatus::Waiting) {
+      waiting.push(device);
+    } else {
+      update_device_status(&device, DeviceStatus::Absent);
+    }
+  }
+
+  if !waiting.is_empty() {
+    println!("[UdevMonitor] Waiting for {} to connect...", waiting.join(", "));
+  } else if devices_found == 0 && !user_has_access {
     println!("[UdevMonitor] No matching devices found. Note: make sure that your user has access to event devices.");
   } else if devices_found == 0 && user_has_access {
     println!("[UdevMonitor] No matching devices found. Note: double-check that your device and its associated config file have the same name, as reported by 'evtest'.");
   }
 }
 
-pub fn start_reader(reader: EventReader) {
-  reader.start();
+// For `makita list-devices`: the event devices Makita can open and the configs each would get, without grabbing them.
+pub fn list_devices(config_files: &[Config]) {
+  let mut devices: Vec<(std::path::PathBuf, Device)> = evdev::enumerate().collect();
+  devices.sort_by(|(a, _), (b, _)| a.cmp(b));
+  for (path, device) in &devices {
+    let name = device.name().unwrap_or_default();
+    let configs: Vec<String> = device_configs(config_files, name).into_iter().map(|config| config.name).collect();
+    match configs.is_empty() {
+      true => println!("{}  {}  (no config)", path.display(), name),
+      false => println!("{}  {}  ({})", path.display(), name, configs.join(", ")),
+    }
+  }
+  if devices.is_empty() {
+    println!("No event devices found. Note: make sure that your user has access to event devices.");
+  }
+}
+
+// The configs of a device: its default config, the layout, application and profile ones, and an empty default config
+// when it only has the others.
+pub fn device_configs(config_files: &[Config], device_name: &str) -> Vec<Config> {
+  let mut config_list: Vec<Config> = Vec::new();
+
+  for config in config_files {
+    let mut split_config_name = config.name.split("::").collect::<Vec<&str>>();
+    let profile = split_config_name.iter()
+      .position(|x| x.starts_with('@'))
+      .map(|index| split_config_name.remove(index)[1..].to_string());
+    let configured_device_name = split_config_name[0];
+
+    if configured_device_name == device_name.replace("/", "") {
+      let (window_class, layout) = match split_config_name.len() {
+        1 => (Client::Default, 0),
+        2 => {
+          if let Ok(layout) = split_config_name[1].parse::<u16>() {
+            (Client::Default, layout)
+          } else {
+            (Client::Class(split_config_name[1].to_string()), 0)
+          }
+        }
+        3 => {
+          if let Ok(layout) = split_config_name[1].parse::<u16>() {
+            (Client::Class(split_config_name[2].to_string()), layout)
+          } else if let Ok(layout) = split_config_name[2].parse::<u16>() {
+            (Client::Class(split_config_name[1].to_string()), layout)
+          } else {
+            println!("[UdevMonitor] Warning: unable to parse layout number in {}, treating it as default.", config.name);
+            (Client::Default, 0)
+          }
+        }
+        _ => {
+          println!("[UdevMonitor] Warning: too many arguments in config file name {}, treating it as default.", config.name);
+          (Client::Default, 0)
+        }
+      };
+
+      let mut device_config = config.clone();
+      device_config.associations.client = window_class;
+      device_config.associations.layout = layout;
+      device_config.associations.profile = profile;
+      config_list.push(device_config);
+    };
+  }
+
+  if !config_list.is_empty() && !config_list.iter().any(|x| x.associations == Associations::default()) {
+    config_list.push(Config::new_empty(device_name.to_string()));
+  }
+
+  if let Some(default_config) = config_list.iter().find(|x| x.associations == Associations::default()).cloned() {
+    if default_config.settings.get("INHERIT_BINDINGS").is_some_and(|value| value == "true") {
+      for config in config_list.iter_mut().filter(|x| x.associations != Associations::default()) {
+        config.inherit_bindings(&default_config);
+      }
+    }
+  }
+  config_list
 }
 
-pub fn start_event_sender(event_sender: EventSender) {
-  if let Err(e) = event_sender.start() {
-    eprintln!("[UdevMonitor] EventSender error: {}", e);
+// The devices that have a config, by their name as reported by evdev without slashes.
+fn device_of(config: &Config) -> String {
+  config.name.split("::").next().unwrap_or_default().to_string()
+}
+
+pub fn configured_devices(config_files: &[Config]) -> Vec<String> {
+  let mut devices: Vec<String> = config_files.iter().map(device_of).collect();
+  devices.sort();
+  devices.dedup();
+  devices
+}
+
+// Devices whose default config sets WAIT_FOR_DEVICE, with when to stop waiting for them if they have a timeout.
+fn wait_deadlines(config_files: &[Config]) -> Vec<(String, Option<Instant>)> {
+  config_files.iter()
+    .filter(|config| !config.name.contains("::"))
+    .filter(|config| config.settings.get("WAIT_FOR_DEVICE").is_some_and(|value| value == "true"))
+    .map(|config| {
+      let timeout = config.settings.get("WAIT_FOR_DEVICE_TIMEOUT").and_then(|seconds| match seconds.parse::<u64>() {
+        Ok(seconds) => Some(Duration::from_secs(seconds)),
+        Err(_) => {
+          println!("[UdevMonitor] Warning: invalid WAIT_FOR_DEVICE_TIMEOUT {} for {}, waiting without a timeout.", seconds, config.name);
+          None
+        }
+      });
+      (config.name.clone(), timeout.map(|timeout| Instant::now() + timeout))
+    })
+    .collect()
+}
+
+fn update_device_status(device: &str, status: DeviceStatus) {
+  let previous = state::set_device_status(device, status);
+  if let Some(previous) = previous.filter(|previous| *previous != status) {
+    println!("[UdevMonitor] {} went from {:?} to {:?}.", device, previous, status);
   }
 }
 
+pub fn start_reader(reader: EventReader) {
+  reader.start();
+}
+
 fn set_environment() -> Environment {
   match env::var("DBUS_SESSION_BUS_ADDRESS") {
     Ok(_) => copy_variables(),
@@ -217,7 +446,7 @@ fn set_environment() -> Environment {
     env::set_var("XDG_SESSION_TYPE", "wayland")
   }
 
-  let supported_compositors = vec!["Hyprland", "sway", "KDE", "niri"]
+  let supported_compositors = SUPPORTED_COMPOSITORS
     .into_iter()
     .map(|str| String::from(str))
     .collect::<Vec<String>>();
@@ -285,18 +514,18 @@ fn copy_variables() {
   }
 }
 
-pub fn get_event_stream(path: &Path, config: Vec<Config>) -> EventStream {
-  let mut device: Device = Device::open(path).expect("Couldn't open device path.");
+pub fn get_event_stream(path: &Path, config: Vec<Config>) -> std::io::Result<EventStream> {
+  let mut device: Device = Device::open(path)?;
   match config.iter().find(|&x| x.associations == Associations::default()).unwrap().settings.get("GRAB_DEVICE") {
     Some(value) => {
       if value == &true.to_string() {
-        device.grab().expect("Unable to grab device. Is another instance of Makita running?")
+        device.grab()?
       }
     }
-    None => device.grab().expect("Unable to grab device. Is another instance of Makita running?"),
+    None => device.grab()?,
   }
 
-  device.into_event_stream().unwrap()
+  device.into_event_stream()
 }
 
 pub fn is_mapped(udev_device: &tokio_udev::Device, config_files: &Vec<Config>) -> bool {
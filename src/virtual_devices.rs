@@ -1,49 +1,528 @@
+use crate::history;
+use crate::remote;
+use crate::state;
+use crate::wayland_output::WaylandOutput;
 use evdev::{
   uinput::{VirtualDevice, VirtualDeviceBuilder},
-  Key,
+  AbsInfo, AbsoluteAxisType, BusType, Device, EventType, InputEvent, InputId, Key, UinputAbsSetup,
 };
+use crossbeam_channel::{Receiver, Sender};
+use nix::libc::{input_event, suseconds_t, time_t, timeval};
+use std::{
+  io,
+  path::PathBuf,
+  str::FromStr,
+  thread,
+  time::{Duration, SystemTime},
+};
+
+// (axis, minimum, maximum) of the absolute device, wide enough for both gamepad sticks and tablet values.
+const ABSOLUTE_AXES: [(AbsoluteAxisType, i32, i32); 13] = [
+  (AbsoluteAxisType::ABS_X, -32768, 32767),
+  (AbsoluteAxisType::ABS_Y, -32768, 32767),
+  (AbsoluteAxisType::ABS_Z, -32768, 32767),
+  (AbsoluteAxisType::ABS_RX, -32768, 32767),
+  (AbsoluteAxisType::ABS_RY, -32768, 32767),
+  (AbsoluteAxisType::ABS_RZ, -32768, 32767),
+  (AbsoluteAxisType::ABS_HAT0X, -1, 1),
+  (AbsoluteAxisType::ABS_HAT0Y, -1, 1),
+  (AbsoluteAxisType::ABS_THROTTLE, 0, 255),
+  (AbsoluteAxisType::ABS_WHEEL, 0, 71),
+  (AbsoluteAxisType::ABS_PRESSURE, 0, 4095),
+  (AbsoluteAxisType::ABS_TILT_X, -90, 90),
+  (AbsoluteAxisType::ABS_TILT_Y, -90, 90),
+];
+
+// What the xpad driver exposes for a wired Xbox 360 controller, which SDL and Proton have mappings for.
+const XPAD_NAME: &str = "Microsoft X-Box 360 pad";
+const XPAD_ID: (u16, u16, u16) = (0x045e, 0x028e, 0x0110);
+const XPAD_BUTTONS: [Key; 11] = [
+  Key::BTN_SOUTH, Key::BTN_EAST, Key::BTN_NORTH, Key::BTN_WEST, Key::BTN_TL, Key::BTN_TR,
+  Key::BTN_SELECT, Key::BTN_START, Key::BTN_MODE, Key::BTN_THUMBL, Key::BTN_THUMBR,
+];
+// (axis, minimum, maximum, fuzz, flat)
+const XPAD_AXES: [(AbsoluteAxisType, i32, i32, i32, i32); 8] = [
+  (AbsoluteAxisType::ABS_X, -32768, 32767, 16, 128),
+  (AbsoluteAxisType::ABS_Y, -32768, 32767, 16, 128),
+  (AbsoluteAxisType::ABS_Z, 0, 255, 0, 0),
+  (AbsoluteAxisType::ABS_RX, -32768, 32767, 16, 128),
+  (AbsoluteAxisType::ABS_RY, -32768, 32767, 16, 128),
+  (AbsoluteAxisType::ABS_RZ, 0, 255, 0, 0),
+  (AbsoluteAxisType::ABS_HAT0X, -1, 1, 0, 0),
+  (AbsoluteAxisType::ABS_HAT0Y, -1, 1, 0, 0),
+];
+
+// The keys of a macro pad are turned into, in order: F13 to F24, then KEY_MACRO1 (0x290) to KEY_MACRO30,
+// which evdev doesn't name yet. Keyboards don't have them, so other software can bind them freely.
+pub fn macro_pad_bank() -> Vec<Key> {
+  (Key::KEY_F13.code()..=Key::KEY_F24.code()).chain(0x290..0x290 + 30).map(Key).collect()
+}
+
+// The virtual device an event is emitted on. Scripts can pick one, otherwise it follows from the event type.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub enum Target {
+  Keyboard,
+  Pointer,
+  Absolute,
+  Gamepad,
+}
+
+impl Target {
+  pub const ALL: [(&'static str, Target); 4] = [
+    ("keyboard", Target::Keyboard),
+    ("pointer", Target::Pointer),
+    ("absolute", Target::Absolute),
+    ("gamepad", Target::Gamepad),
+  ];
+
+  pub fn for_event_type(event_type: EventType) -> Target {
+    match event_type {
+      EventType::RELATIVE => Target::Pointer,
+      EventType::ABSOLUTE | EventType::MISC => Target::Absolute,
+      _ => Target::Keyboard,
+    }
+  }
+
+  pub fn device_name(&self) -> &'static str {
+    match self {
+      Target::Keyboard => "Makita Virtual Keyboard/Mouse",
+      Target::Pointer => "Makita Virtual Pointer",
+      Target::Absolute => "Makita Virtual Absolute",
+      Target::Gamepad => XPAD_NAME,
+    }
+  }
+
+  // The name of a virtual device of its own for a physical device, e.g. "Makita: G502 Pointer".
+  pub fn device_name_for(&self, device: &str) -> String {
+    match self {
+      Target::Keyboard => format!("Makita: {} Keyboard/Mouse", device),
+      Target::Pointer => format!("Makita: {} Pointer", device),
+      Target::Absolute => format!("Makita: {} Absolute", device),
+      Target::Gamepad => XPAD_NAME.to_string(),
+    }
+  }
+}
+
+impl FromStr for Target {
+  type Err = String;
+  fn from_str(s: &str) -> Result<Target, Self::Err> {
+    match s {
+      "keyboard" => Ok(Target::Keyboard),
+      "pointer" => Ok(Target::Pointer),
+      "absolute" | "tablet" => Ok(Target::Absolute),
+      "gamepad" => Ok(Target::Gamepad),
+      _ => Err(s.to_string()),
+    }
+  }
+}
+
+enum Command {
+  Emit(Vec<InputEvent>),
+  DevNodes(Sender<io::Result<Vec<PathBuf>>>),
+  Sync(Sender<()>),
+}
+
+// A virtual device written to by a thread of its own, so that emitting only means queueing the events and never
+// waits on uinput or on a lock. Readers, scripts and the stick loops each emit through clones of it, so the writer
+// thread stamps every batch as it takes it, never earlier than the batch before it, and writes batches in the order
+// they were queued in.
+#[derive(Clone)]
+struct Writer {
+  commands: Sender<Command>,
+}
+
+impl Writer {
+  fn spawn(target: Target, name: String) -> io::Result<Self> {
+    let device = create_device(target, &name)?;
+    let (commands, receiver) = crossbeam_channel::unbounded();
+    thread::spawn(move || write_loop(name.clone(), Some(device), move || create_device(target, &name), receiver));
+    Ok(Self { commands })
+  }
+
+  // The compositor may not be up yet when Makita starts, so it's connected to with the first batch, and again
+  // with the next ones until that works.
+  fn spawn_wayland(name: String) -> Self {
+    let (commands, receiver) = crossbeam_channel::unbounded();
+    thread::spawn(move || write_loop(name, None, WaylandOutput::connect, receiver));
+    Self { commands }
+  }
+
+  fn submit(&self, events: &[InputEvent]) {
+    let _ = self.commands.send(Command::Emit(events.to_vec()));
+  }
+
+  fn request<T>(&self, command: impl FnOnce(Sender<T>) -> Command) -> Option<T> {
+    let (reply, response) = crossbeam_channel::bounded(1);
+    self.commands.send(command(reply)).ok()?;
+    response.recv().ok()
+  }
+}
+
+// Where a writer thread writes its batches to.
+trait Sink {
+  fn emit(&mut self, events: &[InputEvent]) -> io::Result<()>;
+  fn dev_nodes(&mut self) -> io::Result<Vec<PathBuf>>;
+}
+
+impl Sink for VirtualDevice {
+  fn emit(&mut self, events: &[InputEvent]) -> io::Result<()> {
+    VirtualDevice::emit(self, events)
+  }
+
+  fn dev_nodes(&mut self) -> io::Result<Vec<PathBuf>> {
+    self.enumerate_dev_nodes_blocking().map(|nodes| nodes.filter_map(Result::ok).collect())
+  }
+}
+
+impl Sink for WaylandOutput {
+  fn emit(&mut self, events: &[InputEvent]) -> io::Result<()> {
+    WaylandOutput::emit(self, events)
+  }
+
+  fn dev_nodes(&mut self) -> io::Result<Vec<PathBuf>> {
+    Ok(Vec::new())
+  }
+}
+
+// Write errors are logged instead of taking the daemon down. When the uinput node went away, e.g. because udev
+// rules were reloaded, or the compositor restarted, the device is created again with the keys that were held
+// pressed again, and the batch is written once more. The batches queued meanwhile follow in order.
+fn write_loop<S: Sink>(name: String, mut device: Option<S>, create: impl Fn() -> io::Result<S>, commands: Receiver<Command>) {
+  let mut held: Vec<u16> = Vec::new();
+  let mut creation_failed = false;
+  let mut last_stamp = Duration::ZERO;
+  for command in commands {
+    let batch = match command {
+      Command::Emit(batch) => stamp(&batch, &mut last_stamp),
+      Command::DevNodes(reply) => {
+        let _ = reply.send(device.as_mut().map_or_else(|| Ok(Vec::new()), Sink::dev_nodes));
+        continue;
+      }
+      Command::Sync(reply) => {
+        let _ = reply.send(());
+        continue;
+      }
+    };
+    write_batch(&name, &mut device, &create, &mut creation_failed, &held, &batch);
+    // Whether or not the batch was written, so that keys released meanwhile aren't pressed again with the device.
+    for event in batch.iter().filter(|event| event.event_type() == EventType::KEY) {
+      held.retain(|code| *code != event.code());
+      if event.value() != 0 { held.push(event.code()); }
+    }
+  }
+}
+
+fn stamp(events: &[InputEvent], last_stamp: &mut Duration) -> Vec<InputEvent> {
+  let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+  *last_stamp = (*last_stamp).max(now);
+  let time = timeval { tv_sec: last_stamp.as_secs() as time_t, tv_usec: last_stamp.subsec_micros() as suseconds_t };
+  events.iter().map(|event| {
+    InputEvent::from(input_event { time, type_: event.event_type().0, code: event.code(), value: event.value() })
+  }).collect()
+}
+
+fn write_batch<S: Sink>(name: &str, device: &mut Option<S>, create: impl Fn() -> io::Result<S>, creation_failed: &mut bool, held: &[u16], batch: &[InputEvent]) {
+  let emitted = match device.as_mut() {
+    Some(device) => device.emit(batch).map_err(|e| eprintln!("[VirtualDevices] Unable to emit on {}: {}, creating it again.", name, e)),
+    None => Err(()),
+  };
+  if emitted.is_ok() {
+    return;
+  }
+  let mut created = match create() {
+    Ok(created) => created,
+    Err(e) => {
+      // Only once until it works again, it's tried with every batch.
+      if !*creation_failed { eprintln!("[VirtualDevices] Unable to create {}: {}", name, e); }
+      *device = None;
+      *creation_failed = true;
+      return;
+    }
+  };
+  *creation_failed = false;
+  println!("[VirtualDevices] Created {}, pressing {} held key(s) again.", name, held.len());
+  let presses: Vec<InputEvent> = held.iter().map(|code| InputEvent::new_now(EventType::KEY, *code, 1)).collect();
+  let result = created.emit(&presses).and_then(|_| created.emit(batch));
+  *device = Some(created);
+  if let Err(e) = result {
+    eprintln!("[VirtualDevices] Unable to emit on {}: {}", name, e);
+  }
+}
 
+// Cloning it gives another handle on the same devices.
+#[derive(Clone)]
 pub struct VirtualDevices {
-  pub keys: VirtualDevice,
-  pub axis: VirtualDevice,
+  keys: Writer,
+  axis: Writer,
+  abs: Option<Writer>,
+  gamepad: Option<Writer>,
+  // Cursor motion Makita makes up from sticks, movements and [axes], which the compositor can give a flat
+  // acceleration profile apart from the motion of real mice.
+  flat_pointer: Option<Writer>,
 }
 
 impl VirtualDevices {
   pub fn new() -> Self {
-    let mut key_capabilities = evdev::AttributeSet::new();
-    for i in 1..334 { key_capabilities.insert(Key(i)); }
+    Self::build([Target::Keyboard, Target::Pointer, Target::Absolute].map(|target| target.device_name().to_string()))
+  }
 
-    let mut axis_capabilities = evdev::AttributeSet::new();
-    for i in 0..13 { axis_capabilities.insert(evdev::RelativeAxisType(i)); }
+  // Virtual devices for a single physical device, named after it so that libinput quirks and compositor
+  // settings can target them.
+  pub fn for_device(device: &str) -> Self {
+    Self::build([Target::Keyboard, Target::Pointer, Target::Absolute].map(|target| target.device_name_for(device)))
+  }
 
-    let mut tablet_capabilities = evdev::AttributeSet::new();
-    for i in 272..277 { tablet_capabilities.insert(evdev::Key(i)); }
-    for i in 320..325 { tablet_capabilities.insert(evdev::Key(i)); }
-    for i in 326..328 { tablet_capabilities.insert(evdev::Key(i)); }
-    for i in 330..333 { tablet_capabilities.insert(evdev::Key(i)); }
+  fn build(names: [String; 3]) -> Self {
+    let [keys, axis, abs] = names;
+    let spawn = |target: Target, name: String| Writer::spawn(target, name)
+      .expect("Unable to create virtual device through uinput. Take a look at the Troubleshooting section for more info.");
+    Self {
+      keys: spawn(Target::Keyboard, keys),
+      axis: spawn(Target::Pointer, axis),
+      abs: Some(spawn(Target::Absolute, abs)),
+      gamepad: None,
+      flat_pointer: None,
+    }
+  }
 
-    let mut tab_rel = evdev::AttributeSet::new();
-    tab_rel.insert(evdev::RelativeAxisType(8));
+  // Keys and pointer events go to a virtual keyboard and pointer of the Wayland compositor instead, for sessions
+  // without access to /dev/uinput. Absolute events still need uinput and are dropped without it.
+  pub fn wayland(device: &str) -> Self {
+    let keys = Writer::spawn_wayland(format!("the Wayland virtual keyboard and pointer of {}", device));
+    let abs_name = Target::Absolute.device_name_for(device);
+    let abs = Writer::spawn(Target::Absolute, abs_name.clone())
+      .map_err(|e| eprintln!("[VirtualDevices] Unable to create {}, absolute events are dropped: {}", abs_name, e))
+      .ok();
+    Self { axis: keys.clone(), keys, abs, gamepad: None, flat_pointer: None }
+  }
 
-    let mut tab_msc = evdev::AttributeSet::new();
-    tab_msc.insert(evdev::MiscType(0));
+  // Adds a virtual gamepad with the exact capabilities, axis ranges and IDs of an Xbox 360 controller, for
+  // games (e.g. through Wine or Proton) that only recognize controllers they know.
+  pub fn with_xpad(mut self) -> Self {
+    self.gamepad = Some(Writer::spawn(Target::Gamepad, XPAD_NAME.to_string())
+      .expect("Unable to create virtual device through uinput. Take a look at the Troubleshooting section for more info."));
+    self
+  }
 
-    let keys_builder = VirtualDeviceBuilder::new()
-      .expect("Unable to create virtual device through uinput. Take a look at the Troubleshooting section for more info.")
-      .name("Makita Virtual Keyboard/Mouse")
-      .with_keys(&key_capabilities).unwrap();
+  // Adds a second virtual pointer, e.g. "Makita Virtual Pointer (flat)", for the cursor motion of emit_motion.
+  pub fn with_flat_pointer(mut self, name: String) -> Self {
+    self.flat_pointer = Some(Writer::spawn(Target::Pointer, name)
+      .expect("Unable to create virtual device through uinput. Take a look at the Troubleshooting section for more info."));
+    self
+  }
 
-    let axis_builder = VirtualDeviceBuilder::new()
-      .expect("Unable to create virtual device through uinput. Take a look at the Troubleshooting section for more info.")
-      .name("Makita Virtual Pointer")
-      .with_relative_axes(&axis_capabilities).unwrap();
+  // Without the xpad gamepad, gamepad events go to the absolute device.
+  fn writer(&self, target: Target) -> Option<&Writer> {
+    match target {
+      Target::Keyboard => Some(&self.keys),
+      Target::Pointer => Some(&self.axis),
+      Target::Absolute => self.abs.as_ref(),
+      Target::Gamepad => self.gamepad.as_ref().or(self.abs.as_ref()),
+    }
+  }
 
-    let virtual_device_keys = keys_builder.build().unwrap();
-    let virtual_device_axis = axis_builder.build().unwrap();
+  pub fn emit(&self, target: Target, events: &[InputEvent]) {
+    self.emit_on(target, false, events);
+  }
 
-    Self {
-      keys: virtual_device_keys,
-      axis: virtual_device_axis,
+  // Cursor motion that doesn't come from a mouse, which goes to the flat pointer when there is one.
+  pub fn emit_motion(&self, events: &[InputEvent]) {
+    self.emit_on(Target::Pointer, true, events);
+  }
+
+  fn emit_on(&self, target: Target, flat: bool, events: &[InputEvent]) {
+    history::record_output(target, events);
+    if remote::forward_if_enabled(target, events) { return; }
+    for event in events.iter().filter(|event| event.event_type() == EventType::KEY) {
+      state::set_virtual_key(Key(event.code()), event.value());
+    }
+    let writer = match flat && self.flat_pointer.is_some() {
+      true => self.flat_pointer.as_ref(),
+      false => self.writer(target),
+    };
+    if let Some(writer) = writer {
+      writer.submit(events);
     }
   }
+
+  // The /dev/input nodes of a virtual device, once udev has created them.
+  pub fn dev_nodes(&self, target: Target) -> io::Result<Vec<PathBuf>> {
+    self.writer(target).and_then(|writer| writer.request(Command::DevNodes)).unwrap_or_else(|| Ok(Vec::new()))
+  }
+
+  // Blocks until the events emitted so far are written, e.g. before exiting.
+  pub fn wait_until_written(&self) {
+    for writer in [&self.keys, &self.axis].into_iter().chain(&self.abs).chain(&self.gamepad).chain(&self.flat_pointer) {
+      writer.request(Command::Sync);
+    }
+  }
+
+  // Where an event a reader passes through unchanged goes: gamepad buttons and axes to the xpad gamepad when
+  // there is one, absolute events nowhere otherwise.
+  pub fn passthrough_target(&self, event: &InputEvent) -> Option<Target> {
+    match (event.event_type(), self.gamepad.is_some()) {
+      (EventType::KEY, true) if XPAD_BUTTONS.contains(&Key(event.code())) => Some(Target::Gamepad),
+      (EventType::KEY, _) => Some(Target::Keyboard),
+      (EventType::RELATIVE, _) => Some(Target::Pointer),
+      (EventType::ABSOLUTE, true) => Some(Target::Gamepad),
+      _ => None,
+    }
+  }
+}
+
+fn create_device(target: Target, name: &str) -> std::io::Result<VirtualDevice> {
+  match target {
+    Target::Keyboard => {
+      let mut key_capabilities = evdev::AttributeSet::new();
+      for i in 1..334 { key_capabilities.insert(Key(i)); }
+      for key in macro_pad_bank() { key_capabilities.insert(key); }
+      VirtualDeviceBuilder::new()?
+        .name(name)
+        .with_keys(&key_capabilities)?
+        .build()
+    }
+    Target::Pointer => {
+      let mut axis_capabilities = evdev::AttributeSet::new();
+      for i in 0..13 { axis_capabilities.insert(evdev::RelativeAxisType(i)); }
+      VirtualDeviceBuilder::new()?
+        .name(name)
+        .with_relative_axes(&axis_capabilities)?
+        .build()
+    }
+    Target::Absolute => {
+      let mut tablet_capabilities = evdev::AttributeSet::new();
+      for i in 272..277 { tablet_capabilities.insert(evdev::Key(i)); }
+      for i in 320..325 { tablet_capabilities.insert(evdev::Key(i)); }
+      for i in 326..328 { tablet_capabilities.insert(evdev::Key(i)); }
+      for i in 330..333 { tablet_capabilities.insert(evdev::Key(i)); }
+
+      let mut tab_rel = evdev::AttributeSet::new();
+      tab_rel.insert(evdev::RelativeAxisType(8));
+
+      let mut tab_msc = evdev::AttributeSet::new();
+      tab_msc.insert(evdev::MiscType(0));
+
+      let mut abs_builder = VirtualDeviceBuilder::new()?
+        .name(name)
+        .with_keys(&tablet_capabilities)?
+        .with_relative_axes(&tab_rel)?
+        .with_msc(&tab_msc)?;
+      for (axis, minimum, maximum) in ABSOLUTE_AXES {
+        abs_builder = abs_builder.with_absolute_axis(&UinputAbsSetup::new(axis, AbsInfo::new(0, minimum, maximum, 0, 0, 0)))?;
+      }
+      abs_builder.build()
+    }
+    Target::Gamepad => create_xpad(),
+  }
+}
+
+fn create_xpad() -> std::io::Result<VirtualDevice> {
+  let mut buttons = evdev::AttributeSet::new();
+  for button in XPAD_BUTTONS { buttons.insert(button); }
+  let mut builder = VirtualDeviceBuilder::new()?
+    .name(XPAD_NAME)
+    .input_id(InputId::new(BusType::BUS_USB, XPAD_ID.0, XPAD_ID.1, XPAD_ID.2))
+    .with_keys(&buttons)?;
+  for (axis, minimum, maximum, fuzz, flat) in XPAD_AXES {
+    builder = builder.with_absolute_axis(&UinputAbsSetup::new(axis, AbsInfo::new(0, minimum, maximum, fuzz, flat, 0)))?;
+  }
+  builder.build()
+}
+
+// The range of an axis on the virtual absolute device.
+pub fn absolute_range(axis: AbsoluteAxisType) -> Option<(i32, i32)> {
+  ABSOLUTE_AXES.iter().find(|(candidate, _, _)| *candidate == axis).map(|(_, minimum, maximum)| (*minimum, *maximum))
+}
+
+impl Default for VirtualDevices {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+// A virtual device with the same capabilities, properties and axis ranges as the physical one,
+// for devices whose events are passed through as they are, like pens and touchscreens.
+pub fn copy_of(name: &str, device: &Device) -> std::io::Result<VirtualDevice> {
+  let mut builder = VirtualDeviceBuilder::new()?
+    .name(name)
+    .with_properties(device.properties())?;
+  if let Some(keys) = device.supported_keys() { builder = builder.with_keys(keys)?; }
+  if let Some(axes) = device.supported_relative_axes() { builder = builder.with_relative_axes(axes)?; }
+  if let Some(misc) = device.misc_properties() { builder = builder.with_msc(misc)?; }
+  if let Some(axes) = device.supported_absolute_axes() {
+    let state = device.get_abs_state()?;
+    for axis in axes.iter() {
+      let info = state[axis.0 as usize];
+      builder = builder.with_absolute_axis(&UinputAbsSetup::new(
+        axis,
+        AbsInfo::new(info.value, info.minimum, info.maximum, info.fuzz, info.flat, info.resolution),
+      ))?;
+    }
+  }
+  builder.build()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+  };
+
+  #[derive(Clone, Default)]
+  struct Recorder {
+    written: Arc<Mutex<Vec<(u16, i32)>>>,
+    gone: Arc<AtomicBool>,
+  }
+
+  impl Sink for Recorder {
+    fn emit(&mut self, events: &[InputEvent]) -> io::Result<()> {
+      if self.gone.load(Ordering::SeqCst) {
+        return Err(io::ErrorKind::NotFound.into());
+      }
+      self.written.lock().unwrap().extend(events.iter().map(|event| (event.code(), event.value())));
+      Ok(())
+    }
+
+    fn dev_nodes(&mut self) -> io::Result<Vec<PathBuf>> {
+      Ok(Vec::new())
+    }
+  }
+
+  #[test]
+  fn presses_again_only_the_keys_still_held_once_the_device_is_back() {
+    let recorder = Recorder::default();
+    let create = {
+      let recorder = recorder.clone();
+      move || match recorder.gone.load(Ordering::SeqCst) {
+        true => Err(io::ErrorKind::NotFound.into()),
+        false => Ok(recorder.clone()),
+      }
+    };
+    let (commands, receiver) = crossbeam_channel::unbounded();
+    let writer = thread::spawn({
+      let recorder = recorder.clone();
+      move || write_loop("test".to_string(), Some(recorder), create, receiver)
+    });
+    let emit = |events: &[(Key, i32)]| {
+      commands.send(Command::Emit(events.iter().map(|(key, value)| InputEvent::new(EventType::KEY, key.code(), *value)).collect())).unwrap();
+      let (reply, done) = crossbeam_channel::bounded(1);
+      commands.send(Command::Sync(reply)).unwrap();
+      done.recv().unwrap();
+    };
+
+    emit(&[(Key::KEY_A, 1), (Key::KEY_B, 1)]);
+    // The release of A is lost while the device can't be created, but A isn't pressed again once it's back.
+    recorder.gone.store(true, Ordering::SeqCst);
+    emit(&[(Key::KEY_A, 0)]);
+    recorder.gone.store(false, Ordering::SeqCst);
+    emit(&[(Key::KEY_C, 1)]);
+    drop(commands);
+    writer.join().unwrap();
+
+    let written = recorder.written.lock().unwrap().clone();
+    let (a, b, c) = (Key::KEY_A.code(), Key::KEY_B.code(), Key::KEY_C.code());
+    assert_eq!(written, vec![(a, 1), (b, 1), (b, 1), (c, 1)]);
+  }
 }
@@ -0,0 +1,202 @@
+use crate::session;
+use evdev::{EventType, InputEvent, Key, RelativeAxisType};
+use nix::sys::memfd::{memfd_create, MFdFlags};
+use std::{
+  env,
+  fs::File,
+  io::{self, Write},
+  os::{fd::AsFd, unix::net::UnixStream},
+  path::PathBuf,
+  time::UNIX_EPOCH,
+};
+use wayland_client::{
+  delegate_noop,
+  globals::{registry_queue_init, GlobalListContents},
+  protocol::{wl_pointer, wl_registry::{self, WlRegistry}, wl_seat::WlSeat},
+  Connection, Dispatch, EventQueue, QueueHandle,
+};
+use wayland_protocols_misc::zwp_virtual_keyboard_v1::client::{
+  zwp_virtual_keyboard_manager_v1::ZwpVirtualKeyboardManagerV1, zwp_virtual_keyboard_v1::ZwpVirtualKeyboardV1,
+};
+use wayland_protocols_wlr::virtual_pointer::v1::client::{
+  zwlr_virtual_pointer_manager_v1::ZwlrVirtualPointerManagerV1, zwlr_virtual_pointer_v1::ZwlrVirtualPointerV1,
+};
+
+// WL_KEYBOARD_KEYMAP_FORMAT_XKB_V1
+const XKB_V1: u32 = 1;
+// libinput reports a wheel notch as 15 degrees, which is what clients expect from a scroll step.
+const NOTCH: f64 = 15.0;
+
+struct State;
+
+impl Dispatch<WlRegistry, GlobalListContents> for State {
+  fn event(_: &mut Self, _: &WlRegistry, _: wl_registry::Event, _: &GlobalListContents, _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+delegate_noop!(State: ignore WlSeat);
+delegate_noop!(State: ZwpVirtualKeyboardManagerV1);
+delegate_noop!(State: ZwpVirtualKeyboardV1);
+delegate_noop!(State: ZwlrVirtualPointerManagerV1);
+delegate_noop!(State: ZwlrVirtualPointerV1);
+
+// A virtual keyboard and pointer of the compositor, through the zwp_virtual_keyboard_v1 and zwlr_virtual_pointer_v1
+// protocols, for sessions where /dev/uinput isn't available. Mouse buttons go to the pointer, other keys to the
+// keyboard, and the compositor handles key repeat itself.
+pub struct WaylandOutput {
+  connection: Connection,
+  queue: EventQueue<State>,
+  keyboard: ZwpVirtualKeyboardV1,
+  pointer: ZwlrVirtualPointerV1,
+  layout: String,
+  held_modifiers: Vec<Key>,
+  locked_modifiers: u32,
+}
+
+fn other_error(e: impl std::fmt::Display) -> io::Error {
+  io::Error::other(e.to_string())
+}
+
+// The compositor of the active session when Makita runs as root without the user's environment.
+fn socket_path() -> PathBuf {
+  let display = env::var("WAYLAND_DISPLAY").unwrap_or_else(|_| "wayland-0".to_string());
+  let runtime_directory = match (env::var("XDG_RUNTIME_DIR"), session::active_user()) {
+    (Ok(runtime_directory), _) => PathBuf::from(runtime_directory),
+    (Err(_), Some(user)) => PathBuf::from(format!("/run/user/{}", user.uid)),
+    (Err(_), None) => PathBuf::from("/run/user/0"),
+  };
+  runtime_directory.join(display)
+}
+
+// Keycodes are evdev codes plus 8 in XKB, and the compositor resolves the includes like it does for its own
+// keymap. The layout is the first of XKB_DEFAULT_LAYOUT, us by default.
+fn keymap(layout: &str) -> String {
+  let variant = env::var("XKB_DEFAULT_VARIANT").ok()
+    .and_then(|variants| variants.split(',').next().map(str::to_string))
+    .filter(|variant| !variant.is_empty())
+    .map(|variant| format!("({})", variant))
+    .unwrap_or_default();
+  format!(
+    "xkb_keymap {{\n  xkb_keycodes {{ include \"evdev+aliases(qwerty)\" }};\n  xkb_types {{ include \"complete\" }};\n  \
+     xkb_compat {{ include \"complete\" }};\n  xkb_symbols {{ include \"pc+{}{}+inet(evdev)\" }};\n}};\n",
+    layout, variant
+  )
+}
+
+// The modifier masks of that keymap, the protocol leaves it to the client to send them along with the keys.
+fn modifier_mask(key: Key, layout: &str) -> u32 {
+  match key {
+    Key::KEY_LEFTSHIFT | Key::KEY_RIGHTSHIFT => 1 << 0,
+    Key::KEY_LEFTCTRL | Key::KEY_RIGHTCTRL => 1 << 2,
+    Key::KEY_LEFTALT => 1 << 3,
+    // Right Alt is AltGr, on Mod5, in most layouts but us.
+    Key::KEY_RIGHTALT if layout == "us" => 1 << 3,
+    Key::KEY_RIGHTALT => 1 << 7,
+    Key::KEY_LEFTMETA | Key::KEY_RIGHTMETA => 1 << 6,
+    _ => 0,
+  }
+}
+
+fn lock_mask(key: Key) -> u32 {
+  match key {
+    Key::KEY_CAPSLOCK => 1 << 1,
+    Key::KEY_NUMLOCK => 1 << 4,
+    _ => 0,
+  }
+}
+
+impl WaylandOutput {
+  pub fn connect() -> io::Result<Self> {
+    let connection = Connection::from_socket(UnixStream::connect(socket_path())?).map_err(other_error)?;
+    let (globals, mut queue) = registry_queue_init::<State>(&connection).map_err(other_error)?;
+    let handle = queue.handle();
+    let seat: WlSeat = globals.bind(&handle, 1..=1, ()).map_err(other_error)?;
+    let keyboard_manager: ZwpVirtualKeyboardManagerV1 = globals.bind(&handle, 1..=1, ())
+      .map_err(|_| other_error("the compositor doesn't support zwp_virtual_keyboard_v1"))?;
+    let pointer_manager: ZwlrVirtualPointerManagerV1 = globals.bind(&handle, 1..=2, ())
+      .map_err(|_| other_error("the compositor doesn't support zwlr_virtual_pointer_v1"))?;
+    let keyboard = keyboard_manager.create_virtual_keyboard(&seat, &handle, ());
+    let pointer = pointer_manager.create_virtual_pointer(Some(&seat), &handle, ());
+
+    let layout = env::var("XKB_DEFAULT_LAYOUT").ok()
+      .and_then(|layouts| layouts.split(',').next().map(str::to_string))
+      .filter(|layout| !layout.is_empty())
+      .unwrap_or_else(|| "us".to_string());
+    let mut keymap = keymap(&layout).into_bytes();
+    keymap.push(0);
+    let mut keymap_file = File::from(memfd_create("makita-keymap", MFdFlags::MFD_CLOEXEC).map_err(other_error)?);
+    keymap_file.write_all(&keymap)?;
+    keyboard.keymap(XKB_V1, keymap_file.as_fd(), keymap.len() as u32);
+    queue.roundtrip(&mut State).map_err(other_error)?;
+
+    Ok(Self { connection, queue, keyboard, pointer, layout, held_modifiers: Vec::new(), locked_modifiers: 0 })
+  }
+
+  pub fn emit(&mut self, events: &[InputEvent]) -> io::Result<()> {
+    let Some(last) = events.last() else { return Ok(()) };
+    let time = |event: &InputEvent| event.timestamp().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u32;
+    let mut pointer_frame = false;
+    let (mut motion, mut wheel, mut hi_res_wheel) = ((0.0, 0.0), (0, 0), (0, 0));
+    for event in events {
+      match (event.event_type(), event.value()) {
+        // Repeats are up to the compositor.
+        (EventType::KEY, 2) => {}
+        (EventType::KEY, value) if (Key::BTN_LEFT.code()..=Key::BTN_TASK.code()).contains(&event.code()) => {
+          let state = match value {
+            0 => wl_pointer::ButtonState::Released,
+            _ => wl_pointer::ButtonState::Pressed,
+          };
+          self.pointer.button(time(event), event.code() as u32, state);
+          pointer_frame = true;
+        }
+        (EventType::KEY, value) => {
+          self.keyboard.key(time(event), event.code() as u32, value.min(1) as u32);
+          self.update_modifiers(Key(event.code()), value != 0);
+        }
+        (EventType::RELATIVE, value) => match RelativeAxisType(event.code()) {
+          RelativeAxisType::REL_X => motion.0 += value as f64,
+          RelativeAxisType::REL_Y => motion.1 += value as f64,
+          RelativeAxisType::REL_HWHEEL => wheel.0 += value,
+          RelativeAxisType::REL_WHEEL => wheel.1 += value,
+          RelativeAxisType::REL_HWHEEL_HI_RES => hi_res_wheel.0 += value,
+          RelativeAxisType::REL_WHEEL_HI_RES => hi_res_wheel.1 += value,
+          _ => {}
+        },
+        _ => {}
+      }
+    }
+
+    if motion != (0.0, 0.0) {
+      self.pointer.motion(time(last), motion.0, motion.1);
+      pointer_frame = true;
+    }
+    // evdev scrolls up with positive values and Wayland down. Batches that have both only count the notches.
+    for (axis, steps, hi_res) in [
+      (wl_pointer::Axis::HorizontalScroll, wheel.0, hi_res_wheel.0),
+      (wl_pointer::Axis::VerticalScroll, -wheel.1, -hi_res_wheel.1),
+    ] {
+      if steps == 0 && hi_res == 0 { continue; }
+      self.pointer.axis_source(wl_pointer::AxisSource::Wheel);
+      match steps {
+        0 => self.pointer.axis(time(last), axis, hi_res as f64 * NOTCH / 120.0),
+        _ => self.pointer.axis_discrete(time(last), axis, steps as f64 * NOTCH, steps),
+      }
+      pointer_frame = true;
+    }
+    if pointer_frame {
+      self.pointer.frame();
+    }
+
+    self.queue.dispatch_pending(&mut State).map_err(other_error)?;
+    self.connection.flush().map_err(other_error)
+  }
+
+  fn update_modifiers(&mut self, key: Key, pressed: bool) {
+    let (mask, lock) = (modifier_mask(key, &self.layout), lock_mask(key));
+    if mask == 0 && lock == 0 { return; }
+    if pressed { self.locked_modifiers ^= lock; }
+    self.held_modifiers.retain(|held| *held != key);
+    if pressed && mask != 0 { self.held_modifiers.push(key); }
+    let depressed = self.held_modifiers.iter().fold(0, |mods, held| mods | modifier_mask(*held, &self.layout));
+    self.keyboard.modifiers(depressed, 0, self.locked_modifiers, 0);
+  }
+}
@@ -0,0 +1,269 @@
+use evdev::{AbsoluteAxisType, AttributeSet, Key, LedType};
+use makita_core::config::{parse_binding, Action, Axis, Config, ConfigError, Event, LayoutTarget, RemoteCommand, TapHoldCommand};
+use makita_core::capabilities::{unproducible_events, Capabilities};
+use proptest::prelude::*;
+use std::collections::{BTreeSet, HashMap};
+use std::time::Duration;
+
+const MODIFIERS: [(&str, Key); 6] = [
+  ("ctrl", Key::KEY_LEFTCTRL),
+  ("KEY_LEFTSHIFT", Key::KEY_LEFTSHIFT),
+  ("alt", Key::KEY_LEFTALT),
+  ("KEY_RIGHTCTRL", Key::KEY_RIGHTCTRL),
+  ("meta", Key::KEY_LEFTMETA),
+  ("BTN_SIDE", Key::BTN_SIDE),
+];
+
+const KEYS: [(&str, Key); 6] = [
+  ("KEY_A", Key::KEY_A),
+  ("b", Key::KEY_B),
+  ("f5", Key::KEY_F5),
+  ("KEY_SPACE", Key::KEY_SPACE),
+  ("mouse1", Key::BTN_LEFT),
+  ("48", Key::KEY_B),
+];
+
+fn parse(content: &str) -> Result<Config, ConfigError> {
+  Config::parse(content, "Test Device".to_string())
+}
+
+fn binding() -> impl Strategy<Value = (Vec<usize>, usize, Vec<usize>)> {
+  (
+    proptest::sample::subsequence((0..MODIFIERS.len()).collect::<Vec<usize>>(), 0..=3).prop_shuffle(),
+    0..KEYS.len(),
+    proptest::collection::vec(0..KEYS.len(), 1..4),
+  )
+}
+
+proptest! {
+  // Modifiers can be written in any order and with any alias, they end up sorted the way
+  // the held modifiers are compared at runtime, next to the other bindings of the same key.
+  #[test]
+  fn remap_round_trips(bindings in proptest::collection::vec(binding(), 1..12)) {
+    let mut expected: HashMap<(Key, Vec<Event>), Vec<Key>> = HashMap::new();
+    let mut content = String::from("[remap]\n");
+    let mut seen = BTreeSet::new();
+    for (modifiers, key, outputs) in bindings {
+      let mut sorted: Vec<Event> = modifiers.iter().map(|&index| Event::Key(MODIFIERS[index].1)).collect();
+      sorted.sort();
+      if !seen.insert((KEYS[key].1, sorted.clone())) { continue; }
+
+      let names: Vec<&str> = modifiers.iter().map(|&index| MODIFIERS[index].0).chain([KEYS[key].0]).collect();
+      let output_names: Vec<String> = outputs.iter().map(|&index| format!("\"{}\"", KEYS[index].0)).collect();
+      content.push_str(&format!("\"{}\" = [{}]\n", names.join("-"), output_names.join(", ")));
+      expected.insert((KEYS[key].1, sorted), outputs.iter().map(|&index| KEYS[index].1).collect());
+    }
+
+    let config = parse(&content).unwrap();
+    let parsed: usize = config.bindings.remap.values().map(HashMap::len).sum();
+    prop_assert_eq!(parsed, expected.len());
+    for ((key, modifiers), outputs) in expected {
+      prop_assert_eq!(config.bindings.remap.get(&Event::Key(key)).and_then(|map| map.get(&modifiers)), Some(&outputs));
+    }
+  }
+
+  // Whatever is written in the tables, parsing returns an error instead of panicking.
+  #[test]
+  fn parsing_never_panics(
+    input in "[-A-Za-z0-9_]{0,16}",
+    output in "[ -~]{0,24}",
+    table in prop_oneof![Just("remap"), Just("overrides"), Just("movements"), Just("actions"), Just("rubies"), Just("settings")],
+  ) {
+    let mut entries = toml::map::Map::new();
+    let value = match table {
+      "remap" | "overrides" => toml::Value::Array(vec![toml::Value::String(output)]),
+      _ => toml::Value::String(output),
+    };
+    entries.insert(input, value);
+    let mut config = toml::map::Map::new();
+    config.insert(table.to_string(), toml::Value::Table(entries));
+    let _ = parse(&toml::to_string(&toml::Value::Table(config)).unwrap());
+  }
+}
+
+#[test]
+fn reports_unknown_keys() {
+  assert_eq!(
+    parse("[remap]\nKEY_NOPE = [\"KEY_A\"]\n").unwrap_err(),
+    ConfigError::UnknownEvent { binding: "KEY_NOPE".to_string(), name: "KEY_NOPE".to_string() },
+  );
+  assert_eq!(
+    parse("[remap]\nctrl-KEY_NOPE-KEY_A = [\"KEY_B\"]\n").unwrap_err(),
+    ConfigError::UnknownEvent { binding: "ctrl-KEY_NOPE-KEY_A".to_string(), name: "KEY_NOPE".to_string() },
+  );
+  assert!(matches!(parse("[remap]\nKEY_A = [\"KEY_NOPE\"]\n"), Err(ConfigError::Toml(_))));
+}
+
+#[test]
+fn reports_invalid_outputs() {
+  assert_eq!(
+    parse("[movements]\nKEY_A = \"CURSOR_SIDEWAYS\"\n").unwrap_err(),
+    ConfigError::InvalidMovement { binding: "KEY_A".to_string(), movement: "CURSOR_SIDEWAYS".to_string() },
+  );
+  assert_eq!(
+    parse("[actions]\nKEY_A = \"explode\"\n").unwrap_err(),
+    ConfigError::InvalidAction { binding: "KEY_A".to_string(), action: "explode".to_string() },
+  );
+}
+
+#[test]
+fn reports_invalid_settings() {
+  assert_eq!(
+    parse("[settings]\nTAPPING_TERM = \"soon\"\n").unwrap_err(),
+    ConfigError::InvalidSetting { setting: "TAPPING_TERM".to_string(), value: "soon".to_string() },
+  );
+  assert_eq!(
+    parse("[settings]\nCUSTOM_MODIFIERS = \"BTN_SIDE-KEY_NOPE\"\n").unwrap_err(),
+    ConfigError::InvalidSetting { setting: "CUSTOM_MODIFIERS".to_string(), value: "BTN_SIDE-KEY_NOPE".to_string() },
+  );
+  assert!(matches!(parse("[settings\n"), Err(ConfigError::Toml(_))));
+}
+
+#[test]
+fn parses_binding_names() {
+  assert_eq!(parse_binding("-KEY_A"), Ok((Event::Key(Key::KEY_A), vec![Event::Hold])));
+  assert_eq!(
+    parse_binding("shift-ctrl-KEY_A"),
+    Ok((Event::Key(Key::KEY_A), vec![Event::Key(Key::KEY_LEFTCTRL), Event::Key(Key::KEY_LEFTSHIFT)])),
+  );
+  assert_eq!(parse_binding("-"), Ok((Event::Key(Key::KEY_MINUS), vec![])));
+  assert_eq!(parse_binding("ctrl--"), Ok((Event::Key(Key::KEY_MINUS), vec![Event::Key(Key::KEY_LEFTCTRL)])));
+  assert_eq!(parse_binding("--"), Ok((Event::Key(Key::KEY_MINUS), vec![Event::Hold])));
+  assert_eq!(parse_binding("767"), Ok((Event::Key(Key(767)), vec![])));
+  assert!(parse_binding("768").is_err());
+}
+
+#[test]
+fn schema_names_every_event() {
+  let schema = makita_core::schema::schema();
+  let events = schema["definitions"]["event"]["anyOf"][0]["enum"].as_array().unwrap();
+  for name in ["KEY_A", "BTN_SOUTH", "LSTICK_UP", "SWIPE_FROM_LEFT", "TAP_3_FINGERS"] {
+    assert!(events.iter().any(|event| event == name), "{} is missing", name);
+  }
+  assert_eq!(schema["properties"]["settings"]["properties"]["TAP_HOLD_STRATEGY"]["enum"][0], "hold_on_timeout");
+}
+
+#[test]
+fn inherits_undeclared_bindings() {
+  let default_config = parse("[remap]\nKEY_A = [\"KEY_B\"]\nctrl-KEY_A = [\"KEY_C\"]\n[actions]\nKEY_F5 = \"cycle_cursor_speed\"\n").unwrap();
+  let mut app_config = parse("[remap]\nKEY_F5 = [\"KEY_F6\"]\nKEY_A = [\"KEY_D\"]\n").unwrap();
+  app_config.inherit_bindings(&default_config);
+
+  let remap = &app_config.bindings.remap;
+  assert_eq!(remap[&Event::Key(Key::KEY_A)][&vec![]], vec![Key::KEY_D]);
+  assert_eq!(remap[&Event::Key(Key::KEY_A)][&vec![Event::Key(Key::KEY_LEFTCTRL)]], vec![Key::KEY_C]);
+  assert!(!app_config.bindings.actions.contains_key(&Event::Key(Key::KEY_F5)));
+  assert_eq!(app_config.binding_list.len(), 3);
+}
+
+#[test]
+fn substitutes_vars() {
+  let config = parse("[vars]\nbroker = \"localhost:1883\"\n[actions]\nKEY_F1 = \"mqtt(${broker}, lights, on)\"\n").unwrap();
+  assert_eq!(config.binding_list[0].output, "mqtt(localhost:1883, lights, on)");
+  assert!(matches!(parse("[vars]\nhost = \"a\"\n[actions]\nKEY_F1 = \"mqtt(${broker}, lights, on)\"\n"), Err(ConfigError::Toml(_))));
+}
+
+#[test]
+fn parses_layout_actions() {
+  let config = parse("[actions]\nKEY_F1 = \"layout(2)\"\nKEY_F2 = \"layout(\\\"gaming\\\")\"\n").unwrap();
+  let actions = &config.bindings.actions;
+  assert_eq!(actions[&Event::Key(Key::KEY_F1)][&vec![]], Action::Layout(LayoutTarget::Number(2)));
+  assert_eq!(actions[&Event::Key(Key::KEY_F2)][&vec![]], Action::Layout(LayoutTarget::Name("gaming".to_string())));
+  assert!(parse("[actions]\nKEY_F1 = \"layout()\"\n").is_err());
+}
+
+#[test]
+fn parses_remote_actions() {
+  let config = parse("[actions]\nKEY_F1 = \"remote\"\nKEY_F2 = \"remote(off)\"\n").unwrap();
+  let actions = &config.bindings.actions;
+  assert_eq!(actions[&Event::Key(Key::KEY_F1)][&vec![]], Action::Remote(RemoteCommand::Toggle));
+  assert_eq!(actions[&Event::Key(Key::KEY_F2)][&vec![]], Action::Remote(RemoteCommand::Off));
+  assert!(parse("[actions]\nKEY_F1 = \"remote(sideways)\"\n").is_err());
+}
+
+#[test]
+fn parses_inhibit_actions() {
+  let config = parse("[actions]\nKEY_F1 = \"inhibit\"\nKEY_F2 = \"inhibit(30)\"\n").unwrap();
+  let actions = &config.bindings.actions;
+  assert_eq!(actions[&Event::Key(Key::KEY_F1)][&vec![]], Action::Inhibit(None));
+  assert_eq!(actions[&Event::Key(Key::KEY_F2)][&vec![]], Action::Inhibit(Some(30)));
+  assert!(parse("[actions]\nKEY_F1 = \"inhibit(soon)\"\n").is_err());
+}
+
+#[test]
+fn parses_lock_actions() {
+  let config = parse("[actions]\nKEY_F1 = \"lock(caps_lock)\"\nKEY_F2 = \"lock(num_lock, off)\"\n").unwrap();
+  let actions = &config.bindings.actions;
+  assert_eq!(actions[&Event::Key(Key::KEY_F1)][&vec![]], Action::Lock(LedType::LED_CAPSL, None));
+  assert_eq!(actions[&Event::Key(Key::KEY_F2)][&vec![]], Action::Lock(LedType::LED_NUML, Some(false)));
+  assert!(parse("[actions]\nKEY_F1 = \"lock(shift_lock)\"\n").is_err());
+}
+
+#[test]
+fn parses_rate_limits() {
+  let config = parse(r#"
+    [rubies]
+    ctrl-SCROLL_WHEEL_UP = { output = "volume_up", rate_limit_ms = 100 }
+    KEY_A = "type_a"
+  "#).unwrap();
+  let (event, modifiers) = parse_binding("KEY_LEFTCTRL-SCROLL_WHEEL_UP").unwrap();
+  assert_eq!(config.bindings.rate_limits.get(&(event, modifiers)), Some(&Duration::from_millis(100)));
+  assert_eq!(config.bindings.rate_limits.len(), 1);
+}
+
+#[test]
+fn parses_tap_hold_commands() {
+  let config = parse(r#"
+    [tap_hold]
+    KEY_SYSRQ = { tap_ruby = "screenshot_region", hold_action = "profile(gaming)" }
+  "#).unwrap();
+  let tap_hold = &config.bindings.tap_hold[&Key::KEY_SYSRQ];
+  assert_eq!(tap_hold.tap_command, Some(TapHoldCommand::Ruby("screenshot_region".to_string())));
+  assert_eq!(tap_hold.hold_command, Some(TapHoldCommand::Action(Action::Profile(Some("gaming".to_string())))));
+  assert!(tap_hold.hold.is_empty());
+  assert!(matches!(parse("[tap_hold]\nKEY_A = { tap = \"KEY_A\", tap_ruby = \"a\", hold = \"KEY_B\" }\n"), Err(ConfigError::InvalidTapHold { .. })));
+  assert!(matches!(parse("[tap_hold]\nKEY_A = { tap = \"KEY_A\" }\n"), Err(ConfigError::InvalidTapHold { .. })));
+}
+
+#[test]
+fn binds_the_extra_hats() {
+  let config = parse("[remap]\nHAT1_UP = [\"KEY_F1\"]\nBTN_TRIGGER-HAT3_LEFT = [\"KEY_F2\"]\n").unwrap();
+  assert_eq!(config.bindings.remap[&Event::Axis(Axis::HAT1_UP)][&vec![]], vec![Key::KEY_F1]);
+  assert_eq!(config.bindings.remap[&Event::Axis(Axis::HAT3_LEFT)][&vec![Event::Key(Key::BTN_TRIGGER)]], vec![Key::KEY_F2]);
+
+  let mut capabilities = Capabilities { keys: AttributeSet::new(), relative_axes: AttributeSet::new(), absolute_axes: AttributeSet::new() };
+  capabilities.keys.insert(Key::BTN_TRIGGER);
+  capabilities.absolute_axes.insert(AbsoluteAxisType::ABS_HAT1Y);
+  assert_eq!(unproducible_events(&config, &HashMap::new(), &capabilities), vec![Event::Axis(Axis::HAT3_LEFT)]);
+}
+
+#[test]
+fn binds_generic_absolute_axes() {
+  let config = parse("[remap]\nABS_THROTTLE_PLUS = [\"KEY_W\"]\nBTN_TRIGGER-ABS_RUDDER_MINUS = [\"KEY_Q\"]\n").unwrap();
+  let throttle = Event::Axis(Axis::ABS_PLUS(AbsoluteAxisType::ABS_THROTTLE.0));
+  assert_eq!(config.bindings.remap[&throttle][&vec![]], vec![Key::KEY_W]);
+  assert_eq!(config.bindings.remap[&Event::Axis(Axis::ABS_MINUS(AbsoluteAxisType::ABS_RUDDER.0))][&vec![Event::Key(Key::BTN_TRIGGER)]], vec![Key::KEY_Q]);
+  assert_eq!(Axis::ABS_PLUS(AbsoluteAxisType::ABS_THROTTLE.0).name(), "ABS_THROTTLE_PLUS");
+  // Axes with events of their own keep them.
+  assert!(parse("[remap]\nABS_X_PLUS = [\"KEY_A\"]\n").is_err());
+
+  let mut capabilities = Capabilities { keys: AttributeSet::new(), relative_axes: AttributeSet::new(), absolute_axes: AttributeSet::new() };
+  capabilities.keys.insert(Key::BTN_TRIGGER);
+  capabilities.absolute_axes.insert(AbsoluteAxisType::ABS_THROTTLE);
+  assert_eq!(unproducible_events(&config, &HashMap::new(), &capabilities), vec![Event::Axis(Axis::ABS_MINUS(AbsoluteAxisType::ABS_RUDDER.0))]);
+}
+
+#[test]
+fn parses_axes_chords_and_pointer_edges() {
+  let error = parse("[axes]\nABS_THROTTLE = { output = \"REL_DIAL\" }\n").unwrap_err();
+  assert_eq!(error.to_string(), "invalid [axes] entry ABS_THROTTLE: output REL_DIAL isn't one of REL_X, REL_Y, REL_WHEEL, REL_HWHEEL");
+
+  let config = parse("[chords]\n\"BTN_SELECT+BTN_START\" = [\"KEY_ESC\"]\n\"BTN_TL2+BTN_TR2\" = [\"KEY_LEFTCTRL\", \"KEY_S\"]\n").unwrap();
+  assert_eq!(config.bindings.chords[&vec![Event::Key(Key::BTN_SELECT), Event::Key(Key::BTN_START)]], vec![Key::KEY_ESC]);
+  assert_eq!(config.bindings.chords[&vec![Event::Axis(Axis::BTN_TL2), Event::Axis(Axis::BTN_TR2)]], vec![Key::KEY_LEFTCTRL, Key::KEY_S]);
+  let error = parse("[chords]\nBTN_SELECT = [\"KEY_ESC\"]\n").unwrap_err();
+  assert_eq!(error.to_string(), "invalid [chords] entry BTN_SELECT: a chord needs two or more different inputs");
+
+  let config = parse("[remap]\nCORNER_TOP_LEFT = [\"KEY_LEFTMETA\"]\n").unwrap();
+  assert!(config.bindings.binds(&Event::Axis(Axis::CORNER_TOP_LEFT)));
+}
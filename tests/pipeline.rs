@@ -0,0 +1,88 @@
+// End-to-end binding tests through uinput, run as root with:
+// cargo test --features test-harness --test pipeline -- --test-threads=1
+#![cfg(feature = "test-harness")]
+
+use evdev::{EventType, Key, RelativeAxisType};
+use makita_core::test_harness::Harness;
+use std::time::Duration;
+
+const QUIET: Duration = Duration::from_millis(150);
+
+#[test]
+fn remaps_a_key() {
+  let mut harness = Harness::new("Makita Test Remap", "[remap]\nKEY_A = [\"KEY_B\"]\n").unwrap();
+  harness.tap(Key::KEY_A);
+  assert_eq!(harness.collect_keys(QUIET), vec![(Key::KEY_B, 1), (Key::KEY_B, 0)]);
+}
+
+#[test]
+fn passes_unbound_keys_through() {
+  let mut harness = Harness::new("Makita Test Passthrough", "[remap]\nKEY_A = [\"KEY_B\"]\n").unwrap();
+  harness.tap(Key::KEY_C);
+  assert_eq!(harness.collect_keys(QUIET), vec![(Key::KEY_C, 1), (Key::KEY_C, 0)]);
+}
+
+#[test]
+fn remaps_a_key_sequence() {
+  let mut harness = Harness::new("Makita Test Sequence", "[remap]\nKEY_CAPSLOCK = [\"KEY_LEFTCTRL\", \"KEY_C\"]\n").unwrap();
+  harness.tap(Key::KEY_CAPSLOCK);
+  assert_eq!(
+    harness.collect_keys(QUIET),
+    vec![(Key::KEY_LEFTCTRL, 1), (Key::KEY_C, 1), (Key::KEY_LEFTCTRL, 0), (Key::KEY_C, 0)],
+  );
+}
+
+#[test]
+fn remaps_with_a_modifier() {
+  let mut harness = Harness::new("Makita Test Modifier", "[remap]\nKEY_LEFTCTRL-KEY_J = [\"KEY_DOWN\"]\n").unwrap();
+  harness.press(Key::KEY_LEFTCTRL);
+  harness.tap(Key::KEY_J);
+  harness.release(Key::KEY_LEFTCTRL);
+  let keys = harness.collect_keys(QUIET);
+  assert!(keys.contains(&(Key::KEY_DOWN, 1)) && keys.contains(&(Key::KEY_DOWN, 0)));
+  assert!(!keys.iter().any(|(key, _)| *key == Key::KEY_J));
+}
+
+#[test]
+fn times_replayed_tap_hold_keys_from_their_press() {
+  let config = "[tap_hold]\n\
+    KEY_F = { tap = \"KEY_F\", hold = \"KEY_LEFTSHIFT\", tapping_term = 200, strategy = \"hold_on_timeout\" }\n\
+    KEY_J = { tap = \"KEY_J\", hold = \"KEY_LEFTCTRL\", tapping_term = 200, strategy = \"hold_on_timeout\" }\n";
+  let mut harness = Harness::new("Makita Test Replay", config).unwrap();
+  // J is held back until F resolves, but its own tapping term runs from when it was pressed.
+  harness.press(Key::KEY_F);
+  std::thread::sleep(Duration::from_millis(50));
+  harness.press(Key::KEY_J);
+  std::thread::sleep(Duration::from_millis(250));
+  harness.release(Key::KEY_J);
+  harness.release(Key::KEY_F);
+  let keys = harness.collect_keys(QUIET);
+  assert!(keys.contains(&(Key::KEY_LEFTSHIFT, 1)) && keys.contains(&(Key::KEY_LEFTCTRL, 1)));
+  assert!(!keys.iter().any(|(key, _)| *key == Key::KEY_J));
+}
+
+#[test]
+fn moves_the_cursor_while_a_movement_key_is_held() {
+  let config = "[movements]\nKEY_L = \"CURSOR_RIGHT\"\n[settings]\nCURSOR_SPEED = \"4\"\n";
+  let mut harness = Harness::new("Makita Test Movement", config).unwrap();
+  harness.press(Key::KEY_L);
+  std::thread::sleep(Duration::from_millis(50));
+  harness.release(Key::KEY_L);
+  let events = harness.collect(QUIET);
+  assert!(events.iter().any(|(event_type, code, value)| *event_type == EventType::RELATIVE && *code == RelativeAxisType::REL_X.0 && *value == 4));
+  assert!(!events.iter().any(|(event_type, code, _)| *event_type == EventType::KEY && *code == Key::KEY_L.code()));
+}
+
+#[test]
+fn inverts_and_multiplies_the_wheel() {
+  let config = "[settings]\nWHEEL_INVERT = \"true\"\nWHEEL_MULTIPLIER = \"1.5\"\n";
+  let mut harness = Harness::new("Makita Test Wheel", config).unwrap();
+  for _ in 0..2 {
+    harness.emit(EventType::RELATIVE, RelativeAxisType::REL_WHEEL.0, 1);
+  }
+  let wheel: Vec<i32> = harness.collect(QUIET).into_iter()
+    .filter(|(event_type, code, _)| *event_type == EventType::RELATIVE && *code == RelativeAxisType::REL_WHEEL.0)
+    .map(|(_, _, value)| value)
+    .collect();
+  assert_eq!(wheel, vec![-1, -2]);
+}